@@ -1,11 +1,10 @@
-use anilang::function::AnilangFn;
-use anilang::{Diagnostics, Evaluator, Lexer, Lowerer, Parser, SourceText};
+use anilang::{Diagnostics, Evaluator, Lexer, Lowerer, OptLevel, Parser, SourceText};
 use criterion::{criterion_group, criterion_main, Criterion};
 
 macro_rules! rand {
     ($min:expr, $max:expr; $t:ty) => {{
         let min = $min;
-        (rand!() * ($max - min) as f64).trunc() as $t + min
+        (rand!() * ($max as f64 - min as f64)).trunc() as $t + min
     }};
 
     ($min:expr, $max:expr; $t:ty; nonzero) => {{
@@ -39,8 +38,8 @@ fn perform_test(c: &mut Criterion, id: &str, src: &str, allow_err: bool) {
         b.iter(|| {
             let tokens = Lexer::lex(&src, &diagnostics);
             let ast = Parser::parse(tokens, &src, &diagnostics);
-            let bytecode = Lowerer::lower(ast.clone(), &diagnostics, false);
-            Evaluator::evaluate(&bytecode[..], &diagnostics)
+            let bytecode = Lowerer::lower(ast.clone(), &diagnostics, OptLevel::O0);
+            Evaluator::evaluate(&bytecode, &diagnostics)
         });
     });
 
@@ -48,8 +47,8 @@ fn perform_test(c: &mut Criterion, id: &str, src: &str, allow_err: bool) {
         b.iter(|| {
             let tokens = Lexer::lex(&src, &diagnostics);
             let ast = Parser::parse(tokens, &src, &diagnostics);
-            let bytecode = Lowerer::lower(ast.clone(), &diagnostics, true);
-            Evaluator::evaluate(&bytecode[..], &diagnostics)
+            let bytecode = Lowerer::lower(ast.clone(), &diagnostics, OptLevel::O1);
+            Evaluator::evaluate(&bytecode, &diagnostics)
         });
     });
 
@@ -62,28 +61,24 @@ fn perform_test(c: &mut Criterion, id: &str, src: &str, allow_err: bool) {
     let ast = Parser::parse(tokens, &src, &diagnostics);
 
     group.bench_function("lower-no_optimize", |b| {
-        b.iter(|| Lowerer::lower(ast.clone(), &diagnostics, false))
+        b.iter(|| Lowerer::lower(ast.clone(), &diagnostics, OptLevel::O0))
     });
-    let bytecode = Lowerer::lower(ast.clone(), &diagnostics, false);
-    // Bad hack to make sure iterations don't have the same scopes
-    let func = AnilangFn::new(vec![], bytecode);
+    let bytecode = Lowerer::lower(ast.clone(), &diagnostics, OptLevel::O0);
 
     group.bench_function("evaluate-no_optimize", |b| {
-        b.iter(|| Evaluator::evaluate(&func.duplicate_body()[..], &diagnostics))
+        b.iter(|| Evaluator::evaluate(&bytecode, &diagnostics))
     });
-    let v1 = Evaluator::evaluate(&func.body[..], &diagnostics);
+    let v1 = Evaluator::evaluate(&bytecode, &diagnostics);
 
     group.bench_function("lower-optimize", |b| {
-        b.iter(|| Lowerer::lower(ast.clone(), &diagnostics, true))
+        b.iter(|| Lowerer::lower(ast.clone(), &diagnostics, OptLevel::O1))
     });
-    let bytecode = Lowerer::lower(ast, &diagnostics, true);
-    // Bad hack to make sure iterations don't have the same scopes
-    let func = AnilangFn::new(vec![], bytecode);
+    let bytecode = Lowerer::lower(ast, &diagnostics, OptLevel::O1);
 
     group.bench_function("evaluate-optimize", |b| {
-        b.iter(|| Evaluator::evaluate(&func.duplicate_body()[..], &diagnostics))
+        b.iter(|| Evaluator::evaluate(&bytecode, &diagnostics))
     });
-    let v2 = Evaluator::evaluate(&func.body[..], &diagnostics);
+    let v2 = Evaluator::evaluate(&bytecode, &diagnostics);
 
     group.finish();
 
@@ -183,6 +178,62 @@ fn bench_loop(c: &mut Criterion) {
     )
 }
 
+fn nested_variables(c: &mut Criterion) {
+    perform_test(
+        c,
+        "nested_variables",
+        "let total = 0
+        let i = 0
+        let j = 0
+        while i < 100 {
+            j = 0
+            while j < 100 {
+                total += i * j
+                j += 1
+            }
+            i += 1
+        }
+        total == 24502500",
+        false,
+    )
+}
+
+fn arithmetic_loop(c: &mut Criterion) {
+    perform_test(
+        c,
+        "arithmetic_loop",
+        "let a = 0
+        let b = 1
+        let x = 0.0
+        let i = 0
+        while i < 1000 {
+            a = a + b * 2 - i
+            b = b * 3 - b - b
+            x = x * 0.5 + 1.5 - x
+            i = i + 1
+        }
+        a + i < x * 1000",
+        false,
+    )
+}
+
+fn deep_recursion(c: &mut Criterion) {
+    perform_test(
+        c,
+        "deep_recursion",
+        "fn sum(n) {
+            if n == 0 {
+                0
+            } else {
+                n + sum(n - 1)
+            }
+        }
+
+        sum(5000) == 12502500",
+        false,
+    )
+}
+
 criterion_group!(
     benches,
     basic,
@@ -190,6 +241,9 @@ criterion_group!(
     huge_arithmetic_float,
     factorial,
     many_blocks,
-    bench_loop
+    bench_loop,
+    nested_variables,
+    deep_recursion,
+    arithmetic_loop
 );
 criterion_main!(benches);
@@ -10,6 +10,7 @@ pub fn compile(
     output_file: PathBuf,
     show_ast: bool,
     show_bytecode: bool,
+    opt_level: anilang::OptLevel,
 ) -> Result<()> {
     let input = String::from_utf8(fs::read(input_file)?)?;
 
@@ -18,15 +19,19 @@ pub fn compile(
 
     let tokens = anilang::Lexer::lex(&src, &diagnostics);
     let root = anilang::Parser::parse(tokens, &src, &diagnostics);
+    anilang::Checker::check(&root, &diagnostics);
 
     if show_ast {
         root.prt();
     }
 
-    let bytecode = anilang::Lowerer::lower(root, &diagnostics, true);
+    // The std is provided when the compiled code is run, but the variables in it still need to be
+    // resolved
+    let std = crate::stdlib::make_std();
+    let bytecode = anilang::Lowerer::lower_with_std(root, &diagnostics, std, opt_level);
 
     if show_bytecode {
-        anilang::print_bytecode(&bytecode[..])?;
+        anilang::print_bytecode(&bytecode)?;
     }
 
     if !diagnostics.any() {
@@ -36,14 +41,14 @@ pub fn compile(
         let mut idents = HashSet::new();
 
         // Serialize scopes
-        count_scopes(&bytecode[..], &mut idents).serialize(&mut output_file)?;
+        count_scopes(&bytecode, &mut idents).serialize(&mut output_file)?;
         idents.len().serialize(&mut output_file)?;
 
         idents.clear();
 
-        serialize_scopes(&bytecode[..], &mut output_file, &mut idents)?;
+        serialize_scopes(&bytecode, &mut output_file, &mut idents)?;
 
-        // Serialize the Instructions
+        // Serialize the chunk
         bytecode.serialize(&mut output_file)?;
 
         println!("Compiled with {} warnings", diagnostics.num_warnings());
@@ -58,23 +63,25 @@ pub fn compile(
     Ok(())
 }
 
-use anilang::{Instruction, InstructionKind, Value};
+use anilang::{Chunk, Op, Value};
 
-fn count_scopes(bytecode: &[Instruction], idents: &mut HashSet<usize>) -> usize {
+fn count_scopes(bytecode: &Chunk, idents: &mut HashSet<usize>) -> usize {
     let mut num_scopes = 0;
 
-    for instr in bytecode {
-        match &instr.kind {
-            InstructionKind::PushVar { .. } => num_scopes += 1,
-            InstructionKind::Push { value } => {
-                if let Value::Function(func) = value {
+    for op in bytecode.ops.iter() {
+        match op.get() {
+            Op::PushVar { .. } => num_scopes += 1,
+            Op::Push { constant } => {
+                if let Value::Function(func) = &bytecode.constants[constant as usize] {
                     if let Some(func) = func.as_anilang_fn() {
-                        num_scopes += count_scopes(&func.body[..], idents)
+                        // The arguments are stored in slots, so their names are only found here
+                        idents.extend(func.args.iter().map(to_usize));
+                        num_scopes += count_scopes(&func.body, idents)
                     }
                 }
             }
-            InstructionKind::Load { ident, .. } | InstructionKind::Store { ident, .. } => {
-                let ident = to_usize(ident);
+            Op::Load { ident } | Op::Store { ident, .. } | Op::PopStore { ident, .. } => {
+                let ident = to_usize(&bytecode.idents[ident as usize]);
                 if !idents.contains(&ident) {
                     idents.insert(ident);
                 }
@@ -87,35 +94,34 @@ fn count_scopes(bytecode: &[Instruction], idents: &mut HashSet<usize>) -> usize
 }
 
 fn serialize_scopes(
-    bytecode: &[Instruction],
+    bytecode: &Chunk,
     output_file: &mut fs::File,
     idents: &mut HashSet<usize>,
 ) -> Result<()> {
-    for instr in bytecode {
-        match &instr.kind {
-            InstructionKind::PushVar { scope } => {
+    for op in bytecode.ops.iter() {
+        match op.get() {
+            Op::PushVar { scope } => {
+                let scope = &bytecode.scopes[scope as usize];
                 false.serialize(output_file)?;
+                scope.id.serialize(output_file)?;
                 if let Some(parent_id) = scope.parent_id() {
                     parent_id.serialize(output_file)?;
                 } else {
                     usize::MAX.serialize(output_file)?;
                 }
             }
-            InstructionKind::Push { value } => {
-                if let Value::Function(func) = value {
+            Op::Push { constant } => {
+                if let Value::Function(func) = &bytecode.constants[constant as usize] {
                     if let Some(func) = func.as_anilang_fn() {
-                        serialize_scopes(&func.body[..], output_file, idents)?;
+                        for arg in func.args.iter() {
+                            serialize_ident(arg, output_file, idents)?;
+                        }
+                        serialize_scopes(&func.body, output_file, idents)?;
                     }
                 }
             }
-            InstructionKind::Store { ident, .. } | InstructionKind::Load { ident } => {
-                let ident_usize = to_usize(ident);
-                if !idents.contains(&ident_usize) {
-                    true.serialize(output_file)?;
-                    ident_usize.serialize(output_file)?;
-                    ident[..].serialize(output_file)?;
-                    idents.insert(ident_usize);
-                }
+            Op::Store { ident, .. } | Op::PopStore { ident, .. } | Op::Load { ident } => {
+                serialize_ident(&bytecode.idents[ident as usize], output_file, idents)?;
             }
             _ => {}
         }
@@ -124,6 +130,22 @@ fn serialize_scopes(
     Ok(())
 }
 
+fn serialize_ident(
+    ident: &Rc<str>,
+    output_file: &mut fs::File,
+    idents: &mut HashSet<usize>,
+) -> Result<()> {
+    let ident_usize = to_usize(ident);
+    if !idents.contains(&ident_usize) {
+        true.serialize(output_file)?;
+        ident_usize.serialize(output_file)?;
+        ident[..].serialize(output_file)?;
+        idents.insert(ident_usize);
+    }
+
+    Ok(())
+}
+
 fn to_usize(rc: &Rc<str>) -> usize {
     Rc::as_ptr(rc) as *const u8 as usize
 }
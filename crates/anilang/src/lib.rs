@@ -1,12 +1,13 @@
+pub use checker::Checker;
 pub use diagnostics::Diagnostics;
 pub use evaluator::Evaluator;
 pub use intermediaries::TokenKind;
 pub use lexer::Lexer;
-pub use lowerer::Lowerer;
+pub use lowerer::{Lowerer, OptLevel};
 pub use parser::Parser;
 pub use serialize::{Deserialize, DeserializeCtx, Serialize};
 pub use source::SourceText;
 pub use vm::{
-    function, print_bytecode, Bytecode, DeserializationContext, Instruction, InstructionKind,
-    LabelNumber, Scope, Type, Value,
+    function, print_bytecode, Bytecode, Chunk, DeserializationContext, Instruction,
+    InstructionKind, LabelNumber, Op, Scope, Type, Value,
 };
@@ -1,3 +1,4 @@
+use anilang::OptLevel;
 use crossterm::style::Colorize;
 use crossterm::ErrorKind as CEK;
 use std::io::ErrorKind as IEK;
@@ -52,6 +53,11 @@ struct Opt {
     #[structopt(long, short = "b")]
     show_bytecode: bool,
 
+    /// The optimization level to compile or interpret with: 0, 1 or 2. Defaults to 1 when compiling
+    /// and 0 when interpreting.
+    #[structopt(short = "O", long = "opt-level")]
+    opt_level: Option<OptLevel>,
+
     /// Prints a quick guide through the syntax
     #[structopt(long, short)]
     syntax: bool,
@@ -69,29 +75,34 @@ fn main() {
             output_file
         });
 
-        compiler::compile(input_file, output_file, opt.show_ast, opt.show_bytecode).unwrap_or_else(
-            |e| {
-                print!("{} ", "ERROR".dark_red());
-                match e {
-                    CEK::Utf8Error(_) => {
-                        println!("Found non utf-8 bytes while trying to process a string.");
-                        println!(
+        compiler::compile(
+            input_file,
+            output_file,
+            opt.show_ast,
+            opt.show_bytecode,
+            opt.opt_level.unwrap_or(OptLevel::O1),
+        )
+        .unwrap_or_else(|e| {
+            print!("{} ", "ERROR".dark_red());
+            match e {
+                CEK::Utf8Error(_) => {
+                    println!("Found non utf-8 bytes while trying to process a string.");
+                    println!(
                         "Make sure the file you are trying to compile is a utf-8 encoded text file"
                     );
-                    }
-                    CEK::IoError(e) => {
-                        println!(
-                            "An error occurred while compiling the file\nError message: {}",
-                            e
-                        )
-                    }
-                    e => println!(
+                }
+                CEK::IoError(e) => {
+                    println!(
+                        "An error occurred while compiling the file\nError message: {}",
+                        e
+                    )
+                }
+                e => println!(
                     "An unknown error occurred, could not compile the program.\nError message: {}",
                     e
                 ),
-                }
-            },
-        );
+            }
+        });
     } else if let Some(bin_file) = opt.bin_file {
         // .into() doesn't work to convert io::Result to crossterm::Result
         runtime::run(bin_file, opt.show_bytecode).unwrap_or_else(|e| {
@@ -104,7 +115,13 @@ fn main() {
             println!("Error message: {}", e);
         });
     } else if let Some(file) = opt.interpret_file {
-        runtime::interpret(file, opt.show_ast, opt.show_bytecode).unwrap_or_else(|e| {
+        runtime::interpret(
+            file,
+            opt.show_ast,
+            opt.show_bytecode,
+            opt.opt_level.unwrap_or(OptLevel::O0),
+        )
+        .unwrap_or_else(|e| {
             print!("{} ", "ERROR".dark_red());
             match e {
                 CEK::Utf8Error(_) => {
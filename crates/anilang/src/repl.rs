@@ -60,6 +60,7 @@ pub fn run(mut show_ast: bool, mut show_bytecode: bool) {
 
         let tokens = anilang::Lexer::lex(&src, &diagnostics);
         let root = anilang::Parser::parse(tokens, &src, &diagnostics);
+        anilang::Checker::check(&root, &diagnostics);
         if show_ast {
             root.prt();
         }
@@ -68,17 +69,17 @@ pub fn run(mut show_ast: bool, mut show_bytecode: bool) {
             root,
             &diagnostics,
             Rc::clone(&global_scope),
-            false,
+            anilang::OptLevel::O0,
         );
         if show_bytecode {
-            anilang::print_bytecode(&bytecode[..]).unwrap_or_else(|e| {
+            anilang::print_bytecode(&bytecode).unwrap_or_else(|e| {
                 println!("{} Failed to print bytecode", "ERROR".dark_red());
                 println!("Error Message: {}", e)
             });
         }
 
         if !diagnostics.any() {
-            let value = anilang::Evaluator::evaluate(&bytecode[..], &diagnostics);
+            let value = anilang::Evaluator::evaluate(&bytecode, &diagnostics);
             match value {
                 anilang::Value::Null => {}
                 value if !diagnostics.any() => println!("{:?}", value),
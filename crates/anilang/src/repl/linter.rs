@@ -93,11 +93,15 @@ fn print_token(
     let colour = match token_kind {
         TokenKind::Number | TokenKind::Boolean => PURPLE,
         TokenKind::String => YELLOW,
+        TokenKind::Label => PURPLE,
 
         TokenKind::IfKeyword
         | TokenKind::ElseKeyword
         | TokenKind::FnKeyword
         | TokenKind::InterfaceKeyword
+        | TokenKind::ForKeyword
+        | TokenKind::InKeyword
+        | TokenKind::DeferKeyword
         | TokenKind::BreakKeyword
         | TokenKind::WhileKeyword
         | TokenKind::LoopKeyword
@@ -120,6 +124,9 @@ fn print_token(
         | TokenKind::GTOperator
         | TokenKind::LEOperator
         | TokenKind::GEOperator
+        | TokenKind::PipeOperator
+        | TokenKind::UnionOperator
+        | TokenKind::ArrowOperator
         | TokenKind::ColonColonOperator => RED,
 
         TokenKind::Comment => Color::DarkGrey,
@@ -15,7 +15,6 @@ pub fn run(bin_file: PathBuf, show_bytecode: bool) -> io::Result<()> {
     let num_idents = usize::deserialize(&mut bin)?;
 
     let mut ctx = anilang::DeserializationContext::new(num_scopes, num_idents, Some(std));
-    let mut i = 0;
 
     for _ in 0..(num_scopes + num_idents) {
         let is_ident = bool::deserialize(&mut bin)?;
@@ -24,6 +23,7 @@ pub fn run(bin_file: PathBuf, show_bytecode: bool) -> io::Result<()> {
             let ident = String::deserialize(&mut bin)?.into();
             ctx.add_ident(id, ident);
         } else {
+            let id = usize::deserialize(&mut bin)?;
             let parent_id = usize::deserialize(&mut bin)?;
 
             let parent_id = if parent_id != usize::MAX {
@@ -32,27 +32,31 @@ pub fn run(bin_file: PathBuf, show_bytecode: bool) -> io::Result<()> {
                 None
             };
 
-            ctx.add_scope(i, parent_id);
-            i += 1;
+            ctx.add_scope(id, parent_id);
         }
     }
 
-    let bytecode = Vec::deserialize_with_context(&mut bin, &mut ctx)?;
+    let bytecode = anilang::Chunk::deserialize_with_context(&mut bin, &mut ctx)?;
 
     if show_bytecode {
-        if let Some(e) = anilang::print_bytecode(&bytecode[..]).err() {
+        if let Some(e) = anilang::print_bytecode(&bytecode).err() {
             println!("{} Failed to print bytecode", "ERROR".dark_red());
             println!("Error Message: {}", e);
             return Ok(());
         }
     }
 
-    anilang::Evaluator::evaluate(&bytecode[..], &diagnostics);
+    anilang::Evaluator::evaluate(&bytecode, &diagnostics);
 
     Ok(())
 }
 
-pub fn interpret(file: PathBuf, show_ast: bool, show_bytecode: bool) -> crossterm::Result<()> {
+pub fn interpret(
+    file: PathBuf,
+    show_ast: bool,
+    show_bytecode: bool,
+    opt_level: anilang::OptLevel,
+) -> crossterm::Result<()> {
     let input = String::from_utf8(fs::read(file)?)?;
 
     let std = crate::stdlib::make_std();
@@ -62,19 +66,20 @@ pub fn interpret(file: PathBuf, show_ast: bool, show_bytecode: bool) -> crosster
 
     let tokens = anilang::Lexer::lex(&src, &diagnostics);
     let root = anilang::Parser::parse(tokens, &src, &diagnostics);
+    anilang::Checker::check(&root, &diagnostics);
 
     if show_ast {
         root.prt();
     }
 
-    let bytecode = anilang::Lowerer::lower_with_global(root, &diagnostics, std, false);
+    let bytecode = anilang::Lowerer::lower_with_global(root, &diagnostics, std, opt_level);
 
     if show_bytecode {
-        anilang::print_bytecode(&bytecode[..])?;
+        anilang::print_bytecode(&bytecode)?;
     }
 
     if !diagnostics.any() {
-        anilang::Evaluator::evaluate(&bytecode[..], &diagnostics);
+        anilang::Evaluator::evaluate(&bytecode, &diagnostics);
     }
 
     Ok(())
@@ -21,6 +21,9 @@ pub fn make_std() -> Rc<Scope> {
     declare_native_fn!(scope => print);
     declare_native_fn!(scope => input);
     declare_native_fn!(scope => assert);
+    declare_native_fn!(scope => gc);
+    declare_native_fn!(scope => map);
+    declare_native_fn!(scope => set);
 
     scope
 }
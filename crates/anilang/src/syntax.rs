@@ -27,6 +27,16 @@ pub fn print() {
     print_block(stdout, "let a = <val>");
     println!("\nOnce declared variables can be reassigned to any other value");
     print_block(stdout, "a = <val>");
+    println!("\nVariables and functions can optionally be annotated with types, which are checked");
+    println!("before the code is run");
+    print_block(
+        stdout,
+        r##"let a: int | float = <val>
+
+fn <func_name>(<arg>: string, ...) -> list {
+    ...
+}"##,
+    );
     println!();
 
     println!(
@@ -48,6 +58,7 @@ pub fn print() {
 │    >=    │ Greater than equal to                      │
 │     <    │ Less than                                  │
 │    <=    │ Less than equal to                         │
+│    in    │ Membership                                 │
 └──────────┴────────────────────────────────────────────┘
 
 There are also conditionals"##,
@@ -77,6 +88,16 @@ while <cond> {
     print_line(stdout, "loop".to_owned());
     println!(" provides an infinite loop");
 
+    println!("\nLoops return the value they break with, and can be labeled to break outer loops");
+    print_block(
+        stdout,
+        r##"let pair = 'outer: loop {
+    loop {
+        break 'outer [i, j]
+    }
+}"##,
+    );
+
     println!("\nFunctions can be declared in the following ways:");
     print_block(
         stdout,
@@ -105,6 +126,31 @@ fn <func_name>(<args>...) {
 }"##,
     );
 
+    print!("\nA statement can be run when the current block is exited, even through a");
+    print!(" return or break, with ");
+    print_line(stdout, "defer".to_owned());
+    println!();
+    print_block(
+        stdout,
+        r##"let file = open(path)
+defer close(file)"##,
+    );
+
+    println!("\nCalls can be chained with |>, which passes the left value as the first argument");
+    print_block(
+        stdout,
+        r##"xs |> filter(f) |> sum() |> print()
+// is the same as
+print(sum(filter(xs, f)))"##,
+    );
+
+    println!("\nLists and objects can be built with comprehensions");
+    print_block(
+        stdout,
+        r##"[x * x for x in xs if x % 2 == 0]
+{k: v for k, v in pairs}"##,
+    );
+
     println!("\nStrings can be indexed using []");
     print_block(stdout, "\"string\"[1]\nvariable[2]");
 
@@ -76,3 +76,83 @@ fn boolean_algebra() {
     assert_eq!(execute("12 == 12").unwrap(), v::b(true));
     assert_eq!(execute("12 == 42").unwrap(), v::b(false));
 }
+
+#[test]
+fn specialized_algebra() {
+    // The operations are specialized for the types seen in the first few iterations, and have to
+    // go back to handling every type when they see another one
+    assert_eq!(
+        execute("[x + x for x in [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0.5, 11, \"a\"]]").unwrap(),
+        v::l(vec![
+            v::i(2),
+            v::i(4),
+            v::i(6),
+            v::i(8),
+            v::i(10),
+            v::i(12),
+            v::i(14),
+            v::i(16),
+            v::i(18),
+            v::i(20),
+            v::f(1.0),
+            v::i(22),
+            v::s("aa"),
+        ])
+    );
+    assert_eq!(
+        execute("[x * 0.5 - 1.0 for x in [2.0, 4.0, 6.0, 8.0, 10.0, 12.0, 14.0, 16.0, 18.0, 20]]")
+            .unwrap(),
+        v::l((0..10).map(|i| v::f(i as f64)).collect())
+    );
+    assert_eq!(
+        execute("[x < 9 for x in [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 8.5]]").unwrap(),
+        v::l(
+            [true, true, true, true, true, true, true, true, false, false, true]
+                .iter()
+                .map(|b| v::b(*b))
+                .collect()
+        )
+    );
+}
+
+#[test]
+fn big_int_algebra() {
+    assert_eq!(
+        execute("9223372036854775807 + 1").unwrap(),
+        v::big("9223372036854775808")
+    );
+    assert_eq!(
+        execute("-9223372036854775807 - 2").unwrap(),
+        v::big("-9223372036854775809")
+    );
+    assert_eq!(
+        execute("2 ^ 100").unwrap(),
+        v::big("1267650600228229401496703205376")
+    );
+    assert_eq!(
+        execute("123456789012345678901234567890 % 1000").unwrap(),
+        v::i(890)
+    );
+    assert_eq!(execute("2 ^ 64 / 2 ^ 62").unwrap(), v::i(4));
+    assert_eq!(execute("2 ^ 64 > 2 ^ 63").unwrap(), v::b(true));
+    assert_eq!(
+        execute("2 ^ 64 == 18446744073709551616").unwrap(),
+        v::b(true)
+    );
+    assert!(execute("[1, 2][2 ^ 64]").is_err());
+
+    // Specialized ops go back to the generic op when they overflow
+    assert_eq!(
+        execute(
+            "let x = 1
+            let i = 0
+            while i < 70 {
+                x = x * 2
+                i += 1
+            }
+            x"
+        )
+        .unwrap(),
+        v::big("1180591620717411303424")
+    );
+}
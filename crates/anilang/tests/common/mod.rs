@@ -12,16 +12,35 @@ fn base_scope() -> Rc<anilang::Scope> {
         )
         .expect("Could not declare assert");
     scope
+        .declare(
+            "gc".into(),
+            anilang::Value::Function(Rc::new(Function::native_fn(native::gc))),
+        )
+        .expect("Could not declare gc");
+    scope
+        .declare(
+            "map".into(),
+            anilang::Value::Function(Rc::new(Function::native_fn(native::map))),
+        )
+        .expect("Could not declare map");
+    scope
+        .declare(
+            "set".into(),
+            anilang::Value::Function(Rc::new(Function::native_fn(native::set))),
+        )
+        .expect("Could not declare set");
+    scope
 }
 
 #[allow(dead_code)]
-fn _execute(code: &str, scope: Rc<anilang::Scope>) -> Result {
+fn _execute(code: &str, scope: Rc<anilang::Scope>, opt_level: anilang::OptLevel) -> Result {
     let src = anilang::SourceText::new(code);
     let diagnostics = anilang::Diagnostics::new(&src).no_print();
 
     let tokens = anilang::Lexer::lex(&src, &diagnostics);
     let root = anilang::Parser::parse(tokens, &src, &diagnostics);
-    let bytecode = anilang::Lowerer::lower_with_global(root, &diagnostics, scope, false);
+    anilang::Checker::check(&root, &diagnostics);
+    let bytecode = anilang::Lowerer::lower_with_global(root, &diagnostics, scope, opt_level);
     let value = anilang::Evaluator::evaluate(&bytecode, &diagnostics);
 
     if diagnostics.any() {
@@ -33,7 +52,13 @@ fn _execute(code: &str, scope: Rc<anilang::Scope>) -> Result {
 #[allow(dead_code)]
 /// Executes one statement
 pub fn execute(code: &str) -> Result {
-    _execute(code, base_scope())
+    _execute(code, base_scope(), anilang::OptLevel::O0)
+}
+
+#[allow(dead_code)]
+/// Executes one statement, with the given optimizations
+pub fn execute_at(code: &str, opt_level: anilang::OptLevel) -> Result {
+    _execute(code, base_scope(), opt_level)
 }
 
 #[allow(dead_code)]
@@ -41,7 +66,7 @@ pub fn execute(code: &str) -> Result {
 pub fn execute_many(code: Vec<&str>) -> Vec<Result> {
     let scope = base_scope();
     code.iter()
-        .map(|code| _execute(code, Rc::clone(&scope)))
+        .map(|code| _execute(code, Rc::clone(&scope), anilang::OptLevel::O0))
         .collect()
 }
 
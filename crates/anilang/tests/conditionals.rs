@@ -88,3 +88,134 @@ fn while_loops() {
         v::i(10)
     );
 }
+
+#[test]
+fn loop_values() {
+    assert_eq!(
+        execute(
+            "let i = 0
+            loop {
+                i += 1
+                if i == 10 {
+                    break i * 2
+                }
+            }"
+        )
+        .unwrap(),
+        v::i(20)
+    );
+
+    assert_eq!(execute("loop { break }").unwrap(), v::n());
+    assert_eq!(execute("while false {}").unwrap(), v::n());
+    assert_eq!(
+        execute("let a = loop { break 'done' }\na").unwrap(),
+        v::s("done")
+    );
+}
+
+#[test]
+fn labeled_loops() {
+    assert_eq!(
+        execute(
+            "let i = 0
+            let j = 0
+            'outer: loop {
+                j = 0
+                loop {
+                    if i * j == 6 {
+                        break 'outer [i, j]
+                    }
+                    if j == 5 {
+                        break
+                    }
+                    j += 1
+                }
+                i += 1
+            }"
+        )
+        .unwrap(),
+        v::l(vec![v::i(2), v::i(3)])
+    );
+
+    assert_eq!(
+        execute(
+            "let c = 0
+            'outer: while true {
+                while true {
+                    c += 1
+                    break 'outer
+                }
+                c += 10
+            }
+            c"
+        )
+        .unwrap(),
+        v::i(1)
+    );
+
+    // A bare break doesn't take the next statement as its value
+    assert_eq!(
+        execute(
+            "let c = 0
+            loop {
+                break
+                c = 1
+            }
+            c"
+        )
+        .unwrap(),
+        v::i(0)
+    );
+    assert_eq!(
+        execute(
+            "let c = 0
+            'outer: loop {
+                loop {
+                    break 'outer
+                    c = 1
+                }
+            }
+            c"
+        )
+        .unwrap(),
+        v::i(0)
+    );
+    assert_eq!(
+        execute("[loop { break 1 }, 2]").unwrap(),
+        v::l(vec![v::i(1), v::i(2)])
+    );
+
+    // A labeled loop is a value like any other loop
+    assert_eq!(
+        execute("let v = 'outer: loop { loop { break 'outer 5 } }\nv").unwrap(),
+        v::i(5)
+    );
+    assert_eq!(
+        execute(
+            "fn first_even(l) {
+                let i = 0
+                return 'search: while i < 3 {
+                    let j = 0
+                    while j < 3 {
+                        if l[i][j] % 2 == 0 {
+                            break 'search [i, j]
+                        }
+                        j += 1
+                    }
+                    i += 1
+                }
+            }
+            first_even([[1, 3, 5], [7, 8, 9], [2, 2, 2]])"
+        )
+        .unwrap(),
+        v::l(vec![v::i(1), v::i(1)])
+    );
+    assert_eq!(
+        execute("['a: loop { break 'a 1 }, 'b: loop { break 'b 'x' }]").unwrap(),
+        v::l(vec![v::i(1), v::s("x")])
+    );
+
+    assert!(execute("'a: loop { break 'b }").is_err());
+    assert!(execute("'a: loop {}\nloop { break 'a }").is_err());
+    assert!(execute("'a: loop { fn() { break 'a } }").is_err());
+}
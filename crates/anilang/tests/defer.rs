@@ -0,0 +1,131 @@
+mod common;
+use common::*;
+
+#[test]
+fn defer_at_block_end() {
+    assert_eq!(
+        execute(
+            "let log = []
+            {
+                defer log.push(1)
+                defer log.push(2)
+                log.push(0)
+            }
+            log"
+        )
+        .unwrap(),
+        v::l(vec![v::i(0), v::i(2), v::i(1)]),
+    );
+
+    // The value of the block is not changed by the deferred statements
+    assert_eq!(
+        execute(
+            "let a = 1
+            let b = {
+                defer a = 2
+                a
+            }
+            let c = [a, b]"
+        )
+        .unwrap(),
+        v::l(vec![v::i(2), v::i(1)]),
+    );
+
+    // Deferred statements are run in the scope they are declared in
+    assert_eq!(
+        execute(
+            "let log = []
+            {
+                let a = 'outer'
+                defer log.push(a)
+                {
+                    let a = 'inner'
+                    log.push(a)
+                }
+            }
+            log"
+        )
+        .unwrap(),
+        v::l(vec![v::s("inner"), v::s("outer")]),
+    );
+}
+
+#[test]
+fn defer_on_return() {
+    assert_eq!(
+        execute(
+            "let log = []
+            fn f(early) {
+                defer log.push('f')
+                if early {
+                    defer log.push('if')
+                    return 1
+                }
+                2
+            }
+            let a = f(true)
+            let b = f(false)
+            let c = [a, b, log]"
+        )
+        .unwrap(),
+        v::l(vec![
+            v::i(1),
+            v::i(2),
+            v::l(vec![v::s("if"), v::s("f"), v::s("f")]),
+        ]),
+    );
+
+    // Only statements deferred before the return are run
+    assert_eq!(
+        execute(
+            "let log = []
+            fn f() {
+                defer log.push(1)
+                return log
+                defer log.push(2)
+            }
+            f()"
+        )
+        .unwrap(),
+        v::l(vec![v::i(1)]),
+    );
+}
+
+#[test]
+fn defer_in_loops() {
+    assert_eq!(
+        execute(
+            "let log = []
+            let i = 0
+            while i < 3 {
+                defer log.push(i)
+                i += 1
+            }
+            log"
+        )
+        .unwrap(),
+        v::l(vec![v::i(1), v::i(2), v::i(3)]),
+    );
+
+    assert_eq!(
+        execute(
+            "let log = []
+            loop {
+                defer log.push('loop')
+                if true {
+                    defer log.push('if')
+                    break
+                }
+            }
+            log"
+        )
+        .unwrap(),
+        v::l(vec![v::s("if"), v::s("loop")]),
+    );
+}
+
+#[test]
+fn defer_cannot_jump() {
+    assert!(execute("loop { defer break }").is_err());
+    assert!(execute("fn f() { defer return 1 }").is_err());
+}
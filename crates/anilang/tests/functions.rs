@@ -151,3 +151,259 @@ fn anonymous_functions() {
 fn inbuilt_property_functions() {
     assert_eq!(execute("(fn(a, b) { a + b }).call(1, 2)").unwrap(), v::i(3))
 }
+
+#[test]
+fn pipe_functions() {
+    assert_eq!(
+        execute(
+            "fn sub(a, b) { a - b }
+            fn double(a) { a * 2 }
+            10 |> sub(3) |> double()"
+        )
+        .unwrap(),
+        v::i(14)
+    );
+    assert_eq!(
+        execute(
+            "let double = fn(a) { a * 2 }
+            1 + 2 |> double"
+        )
+        .unwrap(),
+        v::i(6)
+    );
+    assert_eq!(
+        execute(
+            "let append = fn(l, a) {
+                l.push(a)
+                l
+            }
+            [1, 2] |> append(3)"
+        )
+        .unwrap(),
+        v::l(vec![v::i(1), v::i(2), v::i(3)])
+    );
+}
+
+#[test]
+fn annotated_functions() {
+    assert_eq!(
+        execute(
+            "fn add(a: int | float, b: int | float) -> int | float { a + b }
+            add(1, 2.5)"
+        )
+        .unwrap(),
+        v::f(3.5)
+    );
+    assert_eq!(
+        execute(
+            "let obj = {
+                double(a: int) -> int { a * 2 }
+            }
+            obj.double(2)"
+        )
+        .unwrap(),
+        v::i(4)
+    );
+    assert_eq!(
+        execute(
+            "let f = fn(a: list) -> string { a + [1] }
+            f([0])"
+        )
+        .unwrap(),
+        v::l(vec![v::i(0), v::i(1)])
+    );
+}
+
+#[test]
+fn deep_recursion() {
+    assert_eq!(
+        execute(
+            "fn count(n) {
+                if n == 0 { 0 } else { 1 + count(n - 1) }
+            }
+            count(100000)"
+        )
+        .unwrap(),
+        v::i(100000)
+    );
+    assert_eq!(
+        execute(
+            "fn is_even(n) { if n == 0 { true } else { is_odd(n - 1) } }
+            fn is_odd(n) { if n == 0 { false } else { is_even(n - 1) } }
+            is_even(100000)"
+        )
+        .unwrap(),
+        v::b(true)
+    );
+}
+
+#[test]
+fn unbounded_recursion_overflows() {
+    assert!(execute(
+        "fn f(n) { 1 + f(n + 1) }
+        f(0)"
+    )
+    .is_err());
+}
+
+#[test]
+fn tail_recursion() {
+    assert_eq!(
+        execute(
+            "fn sum(n, total) {
+                if n == 0 { total } else { sum(n - 1, total + n) }
+            }
+            sum(100000, 0)"
+        )
+        .unwrap(),
+        v::i(5000050000)
+    );
+    assert_eq!(
+        execute(
+            "fn count_down(n) {
+                if n == 0 {
+                    return 0
+                }
+                return count_down(n - 1)
+            }
+            count_down(100000)"
+        )
+        .unwrap(),
+        v::i(0)
+    );
+}
+
+#[test]
+fn calls_have_their_own_variables() {
+    assert_eq!(
+        execute(
+            "fn make_adder(n) {
+                fn(x) { x + n }
+            }
+            let add2 = make_adder(2)
+            let add3 = make_adder(3)
+            add2(1) * 10 + add3(1)"
+        )
+        .unwrap(),
+        v::i(34)
+    );
+    assert_eq!(
+        execute(
+            "fn f(n) {
+                let a = n
+                if n > 0 {
+                    f(n - 1)
+                }
+                a
+            }
+            f(3)"
+        )
+        .unwrap(),
+        v::i(3)
+    );
+}
+
+#[test]
+fn inlined_calls() {
+    // Functions in the global scope aren't inlined, so the code is run inside a block
+    let programs = [
+        "{
+            fn sq(x) { x * x }
+            fn add(a, b) { a + b }
+            let total = 0
+            let i = 0
+            while i < 5 {
+                total = add(total, sq(i))
+                i += 1
+            }
+            total
+        }",
+        "{
+            let k = 5
+            fn add_k(x) { x + k }
+            let a = add_k(1)
+            {
+                let k = 100
+                a + add_k(1)
+            }
+        }",
+        "{
+            let calls = 0
+            fn early(x) {
+                defer calls += 1
+                if x > 2 { return x }
+                -x
+            }
+            early(1) + early(3) + calls
+        }",
+        "{
+            fn is_even(n) { if n == 0 { true } else { is_odd(n - 1) } }
+            fn is_odd(n) { if n == 0 { false } else { is_even(n - 1) } }
+            [is_even(4), is_odd(7)]
+        }",
+        "{
+            fn f() { 1 }
+            f = fn() { 2 }
+            f()
+        }",
+        "{
+            fn outer(y) {
+                fn inner(z) { z + y }
+                inner(4)
+            }
+            outer(3)
+        }",
+    ];
+
+    for program in programs.iter() {
+        assert_eq!(
+            execute_at(program, anilang::OptLevel::O2).unwrap(),
+            execute_at(program, anilang::OptLevel::O0).unwrap(),
+            "{}",
+            program
+        );
+    }
+}
+
+#[test]
+fn new_list_for_every_call() {
+    let programs = [
+        "fn f(n) {
+            let acc = []
+            acc.push(n)
+            acc
+        }
+        [f(1), f(2)]",
+        "fn f(n) {
+            let obj = { a: 0 }
+            obj.a += n
+            obj.a
+        }
+        [f(1), f(2)]",
+        "fn f(n) {
+            let acc = [n]
+            if n > 0 {
+                acc.push(f(n - 1))
+            }
+            acc
+        }
+        f(2)",
+        "{
+            fn f(n) { let acc = [] acc.push(n) acc }
+            [f(1), f(2)]
+        }",
+    ];
+
+    for program in programs.iter() {
+        assert_eq!(
+            execute_at(program, anilang::OptLevel::O2).unwrap(),
+            execute_at(program, anilang::OptLevel::O0).unwrap(),
+            "{}",
+            program
+        );
+    }
+    assert_eq!(
+        execute_at(programs[0], anilang::OptLevel::O2).unwrap(),
+        v::l(vec![v::l(vec![v::i(1)]), v::l(vec![v::i(2)])])
+    );
+}
@@ -0,0 +1,90 @@
+mod common;
+use common::*;
+
+#[test]
+fn collect_self_referencing_objects() {
+    assert_eq!(
+        execute(
+            "let make = fn() {
+                let o = {}
+                o.self = o
+                0
+            }
+            make()
+            make()
+            gc().collected"
+        )
+        .unwrap(),
+        v::i(2),
+    );
+}
+
+#[test]
+fn collect_list_cycles() {
+    assert_eq!(
+        execute(
+            "let make = fn() {
+                let a = []
+                let b = [a]
+                a.push(b)
+                0
+            }
+            make()
+            gc().collected"
+        )
+        .unwrap(),
+        v::i(2),
+    );
+}
+
+#[test]
+fn collect_closures() {
+    assert_eq!(
+        execute(
+            "let make = fn() {
+                let f = fn() { f }
+                0
+            }
+            make()
+            gc().collected"
+        )
+        .unwrap(),
+        v::i(2),
+    );
+}
+
+#[test]
+fn keep_reachable_cycles() {
+    assert_eq!(
+        execute(
+            "let o = {}
+            o.self = o
+            let collected = gc().collected
+            o.self.self.x = 1
+            collected + o.x"
+        )
+        .unwrap(),
+        v::i(1),
+    );
+}
+
+#[test]
+fn collect_automatically() {
+    assert_eq!(
+        execute(
+            "let make = fn() {
+                let o = {}
+                o.self = o
+            }
+            let i = 0
+            while i < 50000 {
+                make()
+                i = i + 1
+            }
+            let stats = gc()
+            stats.collections > 1 && stats.tracked < 100"
+        )
+        .unwrap(),
+        v::b(true),
+    );
+}
@@ -103,3 +103,52 @@ fn inbuilt_property_lists() {
         v::l(vec![v::i(1), v::i(2), v::i(3), v::i(4), v::i(5), v::i(6)]),
     );
 }
+
+#[test]
+fn list_comprehensions() {
+    assert_eq!(
+        execute("[x * x for x in [1, 2, 3, 4, 5, 6] if x % 2 == 0]").unwrap(),
+        v::l(vec![v::i(4), v::i(16), v::i(36)])
+    );
+    assert_eq!(
+        execute("[i for i in 0..3]").unwrap(),
+        v::l(vec![v::i(0), v::i(1), v::i(2)])
+    );
+    assert_eq!(
+        execute("[c + c for c in 'ab']").unwrap(),
+        v::l(vec![v::s("aa"), v::s("bb")])
+    );
+    assert_eq!(
+        execute("[a + b for a, b in [[1, 2], [3, 4]]]").unwrap(),
+        v::l(vec![v::i(3), v::i(7)])
+    );
+    assert_eq!(
+        execute("[[y for y in 0..x] for x in 1..3]").unwrap(),
+        v::l(vec![v::l(vec![v::i(0)]), v::l(vec![v::i(0), v::i(1)])])
+    );
+    assert_eq!(
+        execute(
+            "let x = [1, 2]
+            let y = [x for x in x]
+            y"
+        )
+        .unwrap(),
+        v::l(vec![v::i(1), v::i(2)])
+    );
+    assert!(execute("[x for x in 1]").is_err());
+    assert!(execute("loop { [break for x in 0..1] }").is_err());
+}
+
+#[test]
+fn in_lists() {
+    assert_eq!(execute("2 in [1, 2, 3]").unwrap(), v::b(true));
+    assert_eq!(execute("2.0 in [1, 2, 3]").unwrap(), v::b(true));
+    assert_eq!(execute("'2' in [1, 2, 3]").unwrap(), v::b(false));
+    assert_eq!(execute("[1] in [[1], [2]]").unwrap(), v::b(true));
+    assert_eq!(execute("!(4 in [1, 2, 3])").unwrap(), v::b(true));
+    assert_eq!(execute("1 in (0..2) && 2 in (0..2)").unwrap(), v::b(false));
+    assert_eq!(
+        execute("[x in [1, 3] for x in 0..4]").unwrap(),
+        v::l(vec![v::b(false), v::b(true), v::b(false), v::b(true)]),
+    );
+}
@@ -0,0 +1,130 @@
+mod common;
+use common::*;
+
+#[test]
+fn map_keys() {
+    assert_eq!(
+        execute(
+            "let m = map()
+            m[1] = 'int'
+            m[true] = 'bool'
+            m['s'] = 'string'
+            m[[1, 'a']] = 'list'
+            m.key = 'property'
+            let result = [m[1], m[true], m.s, m[[1, 'a']], m['key'], m.len]"
+        )
+        .unwrap(),
+        v::l(vec![
+            v::s("int"),
+            v::s("bool"),
+            v::s("string"),
+            v::s("list"),
+            v::s("property"),
+            v::i(5),
+        ])
+    );
+
+    // Lists are copied into the key, so changing them doesn't change the map
+    assert_eq!(
+        execute(
+            "let key = [1]
+            let m = map([[key, 'a']])
+            key.push(2)
+            let result = [m[[1]], [1, 2] in m]"
+        )
+        .unwrap(),
+        v::l(vec![v::s("a"), v::b(false)])
+    );
+
+    assert_eq!(
+        execute("map([[9223372036854775807 + 1, 'big']])[9223372036854775808]").unwrap(),
+        v::s("big")
+    );
+    assert!(execute("map()[1]").is_err());
+    assert!(execute("map()[1.5] = 1").is_err());
+    assert!(execute("map()[[{}]] = 1").is_err());
+    assert!(execute("map([1, 2])").is_err());
+    assert!(execute("let l = []\nl.push(l)\nlet m = map()\nm[l] = 1").is_err());
+    assert!(execute("let l = [1]\nl.push(l)\nl in set()").is_ok());
+}
+
+#[test]
+fn map_iteration() {
+    assert_eq!(
+        execute(
+            "let m = map({ b: 1, a: 2 })
+            m[0] = 3
+            m.remove('b')
+            let result = [[k, v] for k, v in m]"
+        )
+        .unwrap(),
+        v::l(vec![
+            v::l(vec![v::s("a"), v::i(2)]),
+            v::l(vec![v::i(0), v::i(3)]),
+        ])
+    );
+
+    assert_eq!(
+        execute("map([[1, 2], [3, 4]]) == map([[3, 4], [1, 2]])").unwrap(),
+        v::b(true)
+    );
+    assert!(execute("map().remove(1)").is_err());
+
+    // Keys don't hide the properties of a map
+    assert_eq!(
+        execute(
+            "let m = map([['len', 'a'], ['remove', 'b']])
+            let len = m.len
+            let removed = m.remove('len')
+            let result = [len, removed, 'len' in m, m.len]"
+        )
+        .unwrap(),
+        v::l(vec![v::i(2), v::s("a"), v::b(false), v::i(1)])
+    );
+    assert!(execute("let m = map()\nm.len = 1").is_err());
+}
+
+#[test]
+fn sets() {
+    assert_eq!(
+        execute(
+            "let s = set([3, 1, 3, 'a'])
+            s.add(2)
+            s.add(1)
+            let elements = [e for e in s]
+            let removed = s.remove('a')
+            let result = [elements, 2 in s, 4 in s, removed, s.remove('a'), s.len]"
+        )
+        .unwrap(),
+        v::l(vec![
+            v::l(vec![v::i(3), v::i(1), v::s("a"), v::i(2)]),
+            v::b(true),
+            v::b(false),
+            v::b(true),
+            v::b(false),
+            v::i(3),
+        ])
+    );
+
+    assert_eq!(execute("set('abca') == set('cba')").unwrap(), v::b(true));
+    assert!(execute("set([[]]).add({})").is_err());
+}
+
+#[test]
+fn set_operations() {
+    assert_eq!(
+        execute(
+            "let a = set([1, 2, 3])
+            let b = set([4, 3, 2])
+            let result = [a.union(b), a.intersection(b), a.difference(b)]"
+        )
+        .unwrap(),
+        v::l(vec![
+            v::st(vec![v::i(1), v::i(2), v::i(3), v::i(4)]),
+            v::st(vec![v::i(2), v::i(3)]),
+            v::st(vec![v::i(1)]),
+        ])
+    );
+
+    assert!(execute("set().union([1])").is_err());
+}
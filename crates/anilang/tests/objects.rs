@@ -38,6 +38,26 @@ fn nested_index_on_objects() {
     );
 }
 
+#[test]
+fn index_objects_of_different_shapes() {
+    assert_eq!(
+        execute(
+            "let objects = [{ a: 1, b: 2 }, { b: 3, a: 4 }, { a: 5 }, { a: 6, b: 7 }]
+            let sum = 0
+            let i = 0
+            while i < objects.len {
+                let o = objects[i]
+                o.c = o.a * 10
+                sum = sum + o.a + o.c
+                i = i + 1
+            }
+            sum"
+        )
+        .unwrap(),
+        v::i(176),
+    );
+}
+
 #[test]
 fn weird_object_declarations() {
     assert_eq!(
@@ -60,3 +80,54 @@ fn weird_object_declarations() {
         v::s("hello world b"),
     );
 }
+
+#[test]
+fn object_comprehensions() {
+    assert_eq!(
+        execute("{k: v * 2 for k, v in { a: 1, b: 2 }}").unwrap(),
+        v::o(vec![("a", v::i(2)), ("b", v::i(4))])
+    );
+    assert_eq!(
+        execute("{p[0]: p[1] for p in [['a', 1], ['b', 2]] if p[1] > 1}").unwrap(),
+        v::o(vec![("b", v::i(2))])
+    );
+    assert!(execute("{k: k for k in [1]}").is_err());
+}
+
+#[test]
+fn objects_keep_key_order() {
+    for opt_level in [anilang::OptLevel::O0, anilang::OptLevel::O1] {
+        assert_eq!(
+            execute_at("[k for k, v in { c: 1, a: 2, b: 3, a: 4 }]", opt_level).unwrap(),
+            v::l(vec![v::s("c"), v::s("a"), v::s("b")])
+        );
+    }
+
+    assert_eq!(
+        execute(
+            "let o = {}
+            let key = 'z'
+            let i = 0
+            while i < 50 {
+                o[key] = i
+                key = 'y' + key
+                i += 1
+            }
+            let keys = [k for k, v in o]
+            keys[0..3]"
+        )
+        .unwrap(),
+        v::l(vec![v::s("z"), v::s("yz"), v::s("yyz")])
+    );
+}
+
+#[test]
+fn in_objects() {
+    assert_eq!(execute("'a' in { a: 1 }").unwrap(), v::b(true));
+    assert_eq!(execute("'b' in { a: 1 }").unwrap(), v::b(false));
+    assert_eq!(
+        execute("let o = { key: false }\nlet k = 'key'\nk in o").unwrap(),
+        v::b(true)
+    );
+    assert!(execute("1 in { a: 1 }").is_err());
+}
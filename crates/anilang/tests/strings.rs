@@ -26,3 +26,60 @@ fn index_strings() {
 fn inbuilt_property_strings() {
     assert_eq!(execute(r#"'hello'.len"#).unwrap(), v::i(5));
 }
+
+#[test]
+fn in_strings() {
+    assert_eq!(execute("'ell' in 'hello'").unwrap(), v::b(true));
+    assert_eq!(execute("'' in 'hello'").unwrap(), v::b(true));
+    assert_eq!(execute("'hi' in 'hello'").unwrap(), v::b(false));
+    assert!(execute("1 in 'hello'").is_err());
+}
+
+#[test]
+fn strings_are_immutable() {
+    assert_eq!(
+        execute(
+            "let a = 'hello'
+            let b = a
+            a[0] = 'j'
+            let result = [a, b]"
+        )
+        .unwrap(),
+        v::l(vec![v::s("jello"), v::s("hello")])
+    );
+
+    assert_eq!(
+        execute(
+            "let a = ['hello', { name: 'world' }]
+            let b = a[0]
+            a[0][0] = 'j'
+            a[1].name[0] = 'W'
+            let result = [a, b]"
+        )
+        .unwrap(),
+        v::l(vec![
+            v::l(vec![v::s("jello"), v::o(vec![("name", v::s("World"))])]),
+            v::s("hello")
+        ])
+    );
+}
+
+#[test]
+fn strings_like_labels() {
+    assert_eq!(
+        execute("let s = 'note: while'\ns").unwrap(),
+        v::s("note: while")
+    );
+    assert_eq!(
+        execute("let s = ['a: loop', 'b']\ns[0]").unwrap(),
+        v::s("a: loop")
+    );
+    assert_eq!(
+        execute("loop { break 'hello world' }").unwrap(),
+        v::s("hello world")
+    );
+    assert_eq!(
+        execute("loop { break 'hello, ' + 'world' }").unwrap(),
+        v::s("hello, world")
+    );
+}
@@ -189,3 +189,153 @@ fn access_property_variables() {
         v::i(2)
     );
 }
+
+#[test]
+fn annotated_variables() {
+    assert_eq!(execute("let a: int = 123").unwrap(), v::i(123));
+    assert_eq!(
+        execute("let a: int | float = 1\na += 0.5").unwrap(),
+        v::f(1.5)
+    );
+    assert_eq!(
+        execute("{ let a: string = 'a'\na + 'b' }").unwrap(),
+        v::s("ab")
+    );
+
+    // Annotations aren't enforced when running
+    assert_eq!(execute("let a: int = 'a'").unwrap(), v::s("a"));
+}
+
+#[test]
+fn unknown_variables() {
+    assert!(execute("a").is_err());
+    assert!(execute("a = 1").is_err());
+    assert!(execute("{ let a = 1 }\na").is_err());
+    assert!(execute("a\nlet a = 1").is_err());
+
+    // Reported even if the code is never run
+    assert!(execute("if false { a }").is_err());
+    assert!(execute("fn f() { a }").is_err());
+
+    // Functions look up variables when called, so they can use variables declared later
+    assert_eq!(
+        execute(
+            "fn is_even(n) { if n == 0 { true } else { is_odd(n - 1) } }
+            fn is_odd(n) { if n == 0 { false } else { is_even(n - 1) } }
+            is_even(4)"
+        )
+        .unwrap(),
+        v::b(true)
+    );
+
+    // Variables from previous statements in the same global scope
+    assert!(execute_many(vec!["let a = 1", "a + 1", "b"])
+        .into_iter()
+        .eq(vec![Ok(v::i(1)), Ok(v::i(2)), Err(())]));
+}
+
+#[test]
+fn local_variables() {
+    // Functions in a block can use variables declared later in the block
+    assert_eq!(
+        execute(
+            "{
+                fn is_even(n) { if n == 0 { true } else { is_odd(n - 1) } }
+                fn is_odd(n) { if n == 0 { false } else { is_even(n - 1) } }
+                is_even(5)
+            }"
+        )
+        .unwrap(),
+        v::b(false)
+    );
+
+    // The outer variable is used until the inner one is declared
+    assert_eq!(
+        execute(
+            "let a = 1
+            {
+                let b = a
+                let a = 2
+                a + b
+            }"
+        )
+        .unwrap(),
+        v::i(3)
+    );
+
+    // Functions use the variables of the scope they were declared in
+    assert_eq!(
+        execute(
+            "fn counter() {
+                let count = 0
+                fn() { count += 1 }
+            }
+            let c = counter()
+            c()
+            c()"
+        )
+        .unwrap(),
+        v::i(2)
+    );
+
+    // Declared every iteration
+    assert_eq!(
+        execute(
+            "let i = 0
+            while i < 3 {
+                let j = i * 2
+                i += 1
+            }
+            i"
+        )
+        .unwrap(),
+        v::i(3)
+    );
+
+    assert!(execute("{ let a = 1\nlet a = 2 }").is_err());
+    assert!(execute("{ fn f() { g() }\nf()\nfn g() {} }").is_err());
+}
+
+#[test]
+fn constant_variables() {
+    let programs = [
+        "{
+            let n = 10
+            let m = n * 2 + 1
+            if m > n { m } else { n }
+        }",
+        "{
+            let n = 3
+            let i = 0
+            while i < n { i += 1 }
+            i * n
+        }",
+        "{
+            let n = 3
+            {
+                let n = 4
+                n
+            } + n
+        }",
+        "{
+            let n = 3
+            fn f(x) { x * n }
+            f(2)
+        }",
+        "{
+            let l = [1, 2]
+            let m = l
+            m[0] = 5
+            l[0]
+        }",
+    ];
+
+    for program in programs.iter() {
+        assert_eq!(
+            execute_at(program, anilang::OptLevel::O1).unwrap(),
+            execute_at(program, anilang::OptLevel::O0).unwrap(),
+            "{}",
+            program
+        );
+    }
+}
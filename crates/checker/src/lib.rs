@@ -0,0 +1,544 @@
+use diagnostics::Diagnostics;
+use enumflags2::BitFlags;
+use intermediaries::{node, SyntaxNode, TokenKind};
+use source::TextSpan;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use vm::Type;
+
+/// The annotations of a function declared with `fn <ident>(...)`
+struct Signature {
+    args: Vec<Option<BitFlags<Type>>>,
+    return_type: Option<BitFlags<Type>>,
+}
+
+struct Variable {
+    type_: BitFlags<Type>,
+    /// The type the variable was declared with, any value assigned to it later must match this
+    annotation: Option<BitFlags<Type>>,
+    signature: Option<Rc<Signature>>,
+}
+
+/// Performs a gradual type check on the AST, which is run between the parsing and lowering.
+///
+/// Types are inferred through the tree, starting from literals, annotations and the known results
+/// of operators. Whenever a type can't be known (for example the value of an unannotated function
+/// argument, or a variable which is reassigned), it is treated as being any type, and so anything
+/// done with it is allowed. Only operations which will error for every possible type of their
+/// operands, or values which can never match their annotation are reported.
+///
+/// Since the annotations are not enforced at runtime, everything found is reported as a warning,
+/// and so checking doesn't change whether or how the code is run.
+///
+/// # Examples
+/// ```no_run
+/// # use source::SourceText;
+/// # use diagnostics::Diagnostics;
+/// # use intermediaries::{Token, node::BlockNode};
+/// # struct Lexer;
+/// # impl Lexer { fn lex(_: &SourceText, _: &Diagnostics) -> Vec<Token> { vec![] } }
+/// # struct Parser;
+/// # impl Parser {
+/// #     fn parse(_: Vec<Token>, _: &SourceText, _: &Diagnostics) -> BlockNode {
+/// #         BlockNode::new(vec![], Default::default())
+/// #     }
+/// # }
+/// use checker::Checker;
+///
+/// let src = SourceText::new("let a: int = 'string'");
+/// let diagnostics = Diagnostics::new(&src);
+///
+/// let tokens = Lexer::lex(&src, &diagnostics);
+/// let root_node = Parser::parse(tokens, &src, &diagnostics);
+/// Checker::check(&root_node, &diagnostics);
+///
+/// assert_eq!(diagnostics.num_warnings(), 1);
+/// assert!(!diagnostics.any());
+/// ```
+pub struct Checker<'diagnostics, 'src> {
+    diagnostics: &'diagnostics Diagnostics<'src>,
+    /// The variables declared in each scope which is currently being checked, with the innermost
+    /// scope at the end
+    scopes: Vec<HashMap<Rc<str>, Variable>>,
+    /// All the variables which are assigned to (as a whole) somewhere in the tree. The type of these
+    /// can't be inferred from the value they are declared with.
+    reassigned: HashSet<Rc<str>>,
+    /// The return annotations of the functions currently being checked, with the innermost function
+    /// at the end
+    return_types: Vec<Option<BitFlags<Type>>>,
+}
+
+impl<'diagnostics, 'src> Checker<'diagnostics, 'src> {
+    pub fn check(root: &node::BlockNode, diagnostics: &'diagnostics Diagnostics<'src>) {
+        let mut checker = Self {
+            diagnostics,
+            scopes: Vec::new(),
+            reassigned: HashSet::new(),
+            return_types: Vec::new(),
+        };
+
+        checker.find_reassigned_in(&root.block);
+
+        checker.check_block(root);
+    }
+
+    /// Whether a value of type `got` can be of type `expected`, ints are allowed for floats as they
+    /// are implicitly casted
+    fn is_compatible(got: BitFlags<Type>, expected: BitFlags<Type>) -> bool {
+        got.intersects(expected) || (got.contains(Type::Int) && expected.contains(Type::Float))
+    }
+
+    fn check_annotation(
+        &self,
+        annotation: Option<BitFlags<Type>>,
+        got: BitFlags<Type>,
+        span: &TextSpan,
+    ) {
+        if let Some(expected) = annotation {
+            if !Self::is_compatible(got, expected) {
+                self.diagnostics.type_mismatch(expected, got, span.clone());
+            }
+        }
+    }
+
+    fn declare(&mut self, ident: Rc<str>, variable: Variable) {
+        // There is always at least one scope while nodes are being checked
+        self.scopes.last_mut().unwrap().insert(ident, variable);
+    }
+
+    fn get_variable(&self, ident: &str) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(ident))
+    }
+
+    fn find_reassigned_in(&mut self, nodes: &[SyntaxNode]) {
+        for node in nodes {
+            self.find_reassigned(node);
+        }
+    }
+
+    fn find_reassigned(&mut self, node: &SyntaxNode) {
+        match node {
+            SyntaxNode::AssignmentNode(node) => {
+                match node.indices {
+                    Some(ref indices) => self.find_reassigned_in(indices),
+                    None => {
+                        self.reassigned.insert(Rc::clone(&node.ident));
+                    }
+                }
+                self.find_reassigned(&node.value);
+            }
+            SyntaxNode::BinaryNode(node) => {
+                self.find_reassigned(&node.left);
+                self.find_reassigned(&node.right);
+            }
+            SyntaxNode::BlockNode(node) => self.find_reassigned_in(&node.block),
+            SyntaxNode::ComprehensionNode(node) => {
+                if let Some(ref key) = node.key {
+                    self.find_reassigned(key);
+                }
+                self.find_reassigned(&node.value);
+                self.find_reassigned(&node.iter);
+                if let Some(ref cond) = node.cond {
+                    self.find_reassigned(cond);
+                }
+            }
+            SyntaxNode::DeclarationNode(node) => self.find_reassigned(&node.value),
+            SyntaxNode::DeferNode(node) => self.find_reassigned(&node.statement),
+            SyntaxNode::FnCallNode(node) => {
+                self.find_reassigned(&node.child);
+                self.find_reassigned_in(&node.args);
+            }
+            SyntaxNode::FnDeclarationNode(node) => self.find_reassigned_in(&node.block.block),
+            SyntaxNode::IfNode(node) => {
+                self.find_reassigned(&node.cond);
+                self.find_reassigned_in(&node.if_block.block);
+                if let Some(ref else_block) = node.else_block {
+                    self.find_reassigned_in(&else_block.block);
+                }
+            }
+            SyntaxNode::IndexNode(node) => {
+                self.find_reassigned(&node.child);
+                self.find_reassigned(&node.index);
+            }
+            SyntaxNode::InterfaceNode(node) => {
+                for (_, value) in node.values.iter() {
+                    self.find_reassigned(value);
+                }
+            }
+            SyntaxNode::ListNode(node) => self.find_reassigned_in(&node.elements),
+            SyntaxNode::LoopNode(node) => self.find_reassigned_in(&node.block),
+            SyntaxNode::ObjectNode(node) => self.find_reassigned_in(&node.elements),
+            SyntaxNode::BreakNode(node::BreakNode { value, .. })
+            | SyntaxNode::ReturnNode(node::ReturnNode { value, .. }) => {
+                if let Some(ref value) = value {
+                    self.find_reassigned(value);
+                }
+            }
+            SyntaxNode::UnaryNode(node) => self.find_reassigned(&node.child),
+            SyntaxNode::LiteralNode(_) | SyntaxNode::VariableNode(_) | SyntaxNode::BadNode(_) => {}
+        }
+    }
+
+    /// Checks the node and gives the types its value can have
+    fn check_node(&mut self, node: &SyntaxNode) -> BitFlags<Type> {
+        match node {
+            SyntaxNode::BlockNode(node) => self.check_block(node),
+            SyntaxNode::LiteralNode(node) => node.value.type_().into(),
+            SyntaxNode::ListNode(node) => {
+                for element in node.elements.iter() {
+                    self.check_node(element);
+                }
+                Type::List.into()
+            }
+            SyntaxNode::ObjectNode(node) => {
+                for element in node.elements.iter() {
+                    self.check_node(element);
+                }
+                Type::Object.into()
+            }
+            SyntaxNode::InterfaceNode(node) => self.check_interface(node),
+            SyntaxNode::VariableNode(node) => self
+                .get_variable(&node.ident)
+                .map_or_else(BitFlags::all, |variable| variable.type_),
+            SyntaxNode::IndexNode(node) => {
+                self.check_node(&node.child);
+                self.check_node(&node.index);
+                BitFlags::all()
+            }
+            SyntaxNode::IfNode(node) => self.check_if(node),
+            SyntaxNode::LoopNode(node) => {
+                self.check_statements(&node.block);
+                BitFlags::all()
+            }
+            SyntaxNode::ComprehensionNode(node) => self.check_comprehension(node),
+            SyntaxNode::AssignmentNode(node) => self.check_assignment(node),
+            SyntaxNode::DeclarationNode(node) => self.check_declaration(node),
+            SyntaxNode::DeferNode(node) => {
+                self.check_node(&node.statement);
+                Type::Null.into()
+            }
+            SyntaxNode::FnDeclarationNode(node) => self.check_fn_declaration(node),
+            SyntaxNode::FnCallNode(node) => self.check_fn_call(node),
+            SyntaxNode::BinaryNode(node) => self.check_binary(node),
+            SyntaxNode::UnaryNode(node) => self.check_unary(node),
+            SyntaxNode::BreakNode(node) => {
+                if let Some(ref value) = node.value {
+                    self.check_node(value);
+                }
+                BitFlags::all()
+            }
+            SyntaxNode::ReturnNode(node) => self.check_return(node),
+            SyntaxNode::BadNode(_) => BitFlags::all(),
+        }
+    }
+
+    fn check_block(&mut self, block: &node::BlockNode) -> BitFlags<Type> {
+        self.check_statements(&block.block)
+    }
+
+    fn check_statements(&mut self, statements: &[SyntaxNode]) -> BitFlags<Type> {
+        self.scopes.push(HashMap::new());
+
+        let mut type_ = Type::Null.into();
+        for node in statements {
+            type_ = self.check_node(node);
+        }
+
+        self.scopes.pop();
+        type_
+    }
+
+    fn check_interface(&mut self, node: &node::InterfaceNode) -> BitFlags<Type> {
+        self.declare(
+            Rc::clone(&node.ident),
+            Variable {
+                type_: BitFlags::all(),
+                annotation: None,
+                signature: None,
+            },
+        );
+
+        self.scopes.push(HashMap::new());
+        for (_, value) in node.values.iter() {
+            self.check_node(value);
+        }
+        self.scopes.pop();
+
+        BitFlags::all()
+    }
+
+    fn check_if(&mut self, node: &node::IfNode) -> BitFlags<Type> {
+        self.check_node(&node.cond);
+        let if_type = self.check_block(&node.if_block);
+        let else_type = match node.else_block {
+            Some(ref else_block) => self.check_block(else_block),
+            None => Type::Null.into(),
+        };
+
+        if_type | else_type
+    }
+
+    fn check_comprehension(&mut self, node: &node::ComprehensionNode) -> BitFlags<Type> {
+        self.check_node(&node.iter);
+
+        self.scopes.push(HashMap::new());
+        for var in node.vars.iter() {
+            self.declare(
+                Rc::clone(var),
+                Variable {
+                    type_: BitFlags::all(),
+                    annotation: None,
+                    signature: None,
+                },
+            );
+        }
+
+        if let Some(ref cond) = node.cond {
+            self.check_node(cond);
+        }
+        if let Some(ref key) = node.key {
+            self.check_node(key);
+        }
+        self.check_node(&node.value);
+        self.scopes.pop();
+
+        if node.is_object() {
+            Type::Object.into()
+        } else {
+            Type::List.into()
+        }
+    }
+
+    fn check_assignment(&mut self, node: &node::AssignmentNode) -> BitFlags<Type> {
+        if let Some(ref indices) = node.indices {
+            for index in indices {
+                self.check_node(index);
+            }
+            return self.check_node(&node.value);
+        }
+
+        let type_ = self.check_node(&node.value);
+        let annotation = self
+            .get_variable(&node.ident)
+            .and_then(|variable| variable.annotation);
+        self.check_annotation(annotation, type_, node.value.span());
+
+        type_
+    }
+
+    fn check_declaration(&mut self, node: &node::DeclarationNode) -> BitFlags<Type> {
+        let type_ = self.check_node(&node.value);
+        self.check_annotation(node.annotation, type_, node.value.span());
+
+        let variable_type = match node.annotation {
+            Some(annotation) => annotation,
+            None if self.reassigned.contains(&node.ident) => BitFlags::all(),
+            None => type_,
+        };
+
+        self.declare(
+            Rc::clone(&node.ident),
+            Variable {
+                type_: variable_type,
+                annotation: node.annotation,
+                signature: None,
+            },
+        );
+
+        type_
+    }
+
+    fn check_fn_declaration(&mut self, node: &node::FnDeclarationNode) -> BitFlags<Type> {
+        if let Some(ref ident) = node.ident {
+            // A reassigned function may not be this declaration when it is called
+            let signature = if self.reassigned.contains(ident) {
+                None
+            } else {
+                Some(Rc::new(Signature {
+                    args: node.arg_annotations.clone(),
+                    return_type: node.return_annotation,
+                }))
+            };
+
+            // Declared before the body is checked so that recursive calls know the signature
+            self.declare(
+                Rc::clone(ident),
+                Variable {
+                    type_: Type::Function.into(),
+                    annotation: None,
+                    signature,
+                },
+            );
+        }
+
+        self.scopes.push(HashMap::new());
+        for (arg, annotation) in node.args.iter().zip(node.arg_annotations.iter()) {
+            self.declare(
+                Rc::clone(arg),
+                Variable {
+                    type_: annotation.unwrap_or_else(BitFlags::all),
+                    annotation: *annotation,
+                    signature: None,
+                },
+            );
+        }
+
+        self.return_types.push(node.return_annotation);
+        let type_ = self.check_block(&node.block);
+        self.return_types.pop();
+        self.scopes.pop();
+
+        let span = node
+            .block
+            .block
+            .last()
+            .map_or(&node.block.span, |n| n.span());
+        self.check_annotation(node.return_annotation, type_, span);
+
+        Type::Function.into()
+    }
+
+    fn check_fn_call(&mut self, node: &node::FnCallNode) -> BitFlags<Type> {
+        self.check_node(&node.child);
+        let arg_types: Vec<_> = node.args.iter().map(|arg| self.check_node(arg)).collect();
+
+        let signature = match *node.child {
+            SyntaxNode::VariableNode(ref variable) => self
+                .get_variable(&variable.ident)
+                .and_then(|variable| variable.signature.clone()),
+            _ => None,
+        };
+
+        let signature = match signature {
+            Some(signature) => signature,
+            None => return BitFlags::all(),
+        };
+
+        if signature.args.len() != node.args.len() {
+            self.diagnostics.arg_count_mismatch(
+                signature.args.len(),
+                node.args.len(),
+                node.span.clone(),
+            );
+        } else {
+            for ((annotation, type_), arg) in
+                signature.args.iter().zip(arg_types).zip(node.args.iter())
+            {
+                self.check_annotation(*annotation, type_, arg.span());
+            }
+        }
+
+        signature.return_type.unwrap_or_else(BitFlags::all)
+    }
+
+    fn check_return(&mut self, node: &node::ReturnNode) -> BitFlags<Type> {
+        let type_ = match node.value {
+            Some(ref value) => self.check_node(value),
+            None => Type::Null.into(),
+        };
+
+        if let Some(&annotation) = self.return_types.last() {
+            let span = node.value.as_ref().map_or(&node.span, |value| value.span());
+            self.check_annotation(annotation, type_, span);
+        }
+
+        // The return statement itself never produces a value
+        BitFlags::all()
+    }
+
+    fn check_binary(&mut self, node: &node::BinaryNode) -> BitFlags<Type> {
+        let left = self.check_node(&node.left);
+        let right = self.check_node(&node.right);
+
+        let mut type_ = BitFlags::empty();
+        for l in left.iter() {
+            for r in right.iter() {
+                if let Some(t) = binary_result(&node.operator, l, r) {
+                    type_ |= t;
+                }
+            }
+        }
+
+        if type_.is_empty() {
+            self.diagnostics.invalid_binary_operation(
+                &node.operator,
+                left,
+                right,
+                node.span.clone(),
+            );
+            // Already reported, so allow anything to prevent the same error from cascading
+            BitFlags::all()
+        } else {
+            type_
+        }
+    }
+
+    fn check_unary(&mut self, node: &node::UnaryNode) -> BitFlags<Type> {
+        let child = self.check_node(&node.child);
+
+        match node.operator {
+            TokenKind::NotOperator => Type::Bool.into(),
+            _ => {
+                let type_ = child & (Type::Int | Type::Float);
+                if type_.is_empty() {
+                    self.diagnostics.invalid_unary_operation(
+                        &node.operator,
+                        child,
+                        node.span.clone(),
+                    );
+                    BitFlags::all()
+                } else {
+                    type_
+                }
+            }
+        }
+    }
+}
+
+/// The type produced by a binary operation on the given types, `None` if it will always error at
+/// runtime. These mirror the binary operations defined in `vm/src/value/mod.rs`
+fn binary_result(operator: &TokenKind, left: Type, right: Type) -> Option<BitFlags<Type>> {
+    use Type::*;
+
+    let number = |left, right| match (left, right) {
+        (Int, Int) => Some(Int.into()),
+        (Int, Float) | (Float, Int) | (Float, Float) => Some(Float.into()),
+        _ => None,
+    };
+
+    let implicitly_castable = left == right || matches!((left, right), (Int, Float) | (Float, Int));
+
+    match operator {
+        TokenKind::PlusOperator => match (left, right) {
+            (String, String) => Some(String.into()),
+            (List, List) => Some(List.into()),
+            _ => number(left, right),
+        },
+        TokenKind::MinusOperator
+        | TokenKind::StarOperator
+        | TokenKind::SlashOperator
+        | TokenKind::ModOperator
+        | TokenKind::CaretOperator => number(left, right),
+        TokenKind::RangeOperator => match (left, right) {
+            (Int, Int) => Some(Range.into()),
+            _ => None,
+        },
+        // Either of the values can be given
+        TokenKind::OrOperator | TokenKind::AndOperator => Some(left | right),
+        TokenKind::EqOperator | TokenKind::NEOperator if implicitly_castable => Some(Bool.into()),
+        TokenKind::LTOperator
+        | TokenKind::GTOperator
+        | TokenKind::LEOperator
+        | TokenKind::GEOperator
+            if implicitly_castable && matches!(left, Int | Float | Bool | String | List) =>
+        {
+            Some(Bool.into())
+        }
+        TokenKind::InKeyword => match (left, right) {
+            (_, List) | (_, Map) | (_, Set) => Some(Bool.into()),
+            (String, Object) | (String, String) | (Int, Range) => Some(Bool.into()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
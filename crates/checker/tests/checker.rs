@@ -0,0 +1,106 @@
+use checker::Checker;
+use diagnostics::Diagnostics;
+use lexer::Lexer;
+use parser::Parser;
+use source::SourceText;
+
+/// Checks the code and gives the number of warnings produced
+fn check(code: &str) -> usize {
+    let src = SourceText::new(code);
+    let diagnostics = Diagnostics::new(&src).no_print();
+
+    let tokens = Lexer::lex(&src, &diagnostics);
+    let root = Parser::parse(tokens, &src, &diagnostics);
+    assert!(!diagnostics.any(), "failed to parse {}", code);
+
+    Checker::check(&root, &diagnostics);
+
+    // The checker should never stop the code from running
+    assert!(!diagnostics.any());
+    diagnostics.num_warnings()
+}
+
+#[test]
+fn check_declaration_annotations() {
+    assert_eq!(check("let a: int = 1"), 0);
+    assert_eq!(check("let a: float = 1"), 0);
+    assert_eq!(check("let a: int | string = 'a'"), 0);
+    assert_eq!(check("let a: any = [1, 2]"), 0);
+    assert_eq!(check("let a: null = {}"), 1);
+    assert_eq!(check("let a: int = 'a'"), 1);
+    assert_eq!(check("let a: int = 1.5"), 1);
+    assert_eq!(check("let a: list = 1..2"), 1);
+}
+
+#[test]
+fn check_assignment_annotations() {
+    assert_eq!(check("let a: int = 1\na = 2"), 0);
+    assert_eq!(check("let a: int = 1\na += 2"), 0);
+    assert_eq!(check("let a: int = 1\na = 'a'"), 1);
+    assert_eq!(check("let a: int = 1\n{ a = 'a' }"), 1);
+
+    // Shadowed by an unannotated variable
+    assert_eq!(check("let a: int = 1\n{ let a = 1\na = 'a' }"), 0);
+}
+
+#[test]
+fn check_inferred_types() {
+    assert_eq!(check("let a = 1\nlet b: int = a + 2"), 0);
+    assert_eq!(check("let a = 1\nlet b: string = a + 2"), 1);
+    assert_eq!(check("let a = 1.5\nlet b: int = a * 2"), 1);
+    assert_eq!(check("let a: int = if true { 1 } else { 'a' }"), 0);
+    assert_eq!(check("let a: int = if true { 'a' } else { [] }"), 1);
+    assert_eq!(check("let a: list = [a for a in 0..3]"), 0);
+
+    // `a` can be anything after being reassigned, so nothing can be inferred
+    assert_eq!(check("let a = 1\na = 'a'\nlet b: string = a"), 0);
+}
+
+#[test]
+fn check_operators() {
+    assert_eq!(check("1 + 2.5"), 0);
+    assert_eq!(check("'a' + 'b'"), 0);
+    assert_eq!(check("[1] + [2]"), 0);
+    assert_eq!(check("1 + 'a'"), 1);
+    assert_eq!(check("[] - []"), 1);
+    assert_eq!(check("'a'..2"), 1);
+    assert_eq!(check("1 == 1.0"), 0);
+    assert_eq!(check("1 == 'a'"), 1);
+    assert_eq!(check("{} < {}"), 1);
+    assert_eq!(check("1 in [1]"), 0);
+    assert_eq!(check("1 in 'a'"), 1);
+    assert_eq!(check("let s: set = set([1])\n1 in s"), 0);
+    assert_eq!(check("let m: map = map()\nlet k = [1, 'a']\nk in m"), 0);
+    assert_eq!(check("let m: map = map()\n1.5 in m"), 0);
+    assert_eq!(check("let s: set = set()\nlet t: set = set()\ns == t"), 0);
+    assert_eq!(check("let m: map = map()\nm != map()"), 0);
+    assert_eq!(check("let m: map = map()\nlet s: set = set()\nm == s"), 1);
+    assert_eq!(check("let s: set = set()\ns + s"), 1);
+    assert_eq!(check("-'a'"), 1);
+    assert_eq!(check("!'a'"), 0);
+
+    // Unknown values can be anything
+    assert_eq!(check("fn f(a) { a + 1 }"), 0);
+    assert_eq!(check("fn f(a: list) { a + 1 }"), 1);
+
+    // Only the first error is reported
+    assert_eq!(check("(1 + 'a') * 2"), 1);
+}
+
+#[test]
+fn check_functions() {
+    assert_eq!(check("fn f(a: int) -> int { a }\nf(1)"), 0);
+    assert_eq!(check("fn f(a: int) -> int { a }\nf('a')"), 1);
+    assert_eq!(check("fn f(a: int) -> int { a }\nf(1, 2)"), 1);
+    assert_eq!(check("fn f(a: int) -> int { a }\nlet b: string = f(1)"), 1);
+    assert_eq!(check("fn f() -> string { 1 }"), 1);
+    assert_eq!(check("fn f() -> string { return 1\n'a' }"), 1);
+    assert_eq!(check("fn f() -> string { if true { return 'a' }\n'b' }"), 0);
+    assert_eq!(check("fn f(a: int | float) -> float { a * 2 }"), 0);
+
+    // Recursive calls know the signature
+    assert_eq!(check("fn f(a: int) { f('a') }"), 1);
+
+    // The function may be something else when called
+    assert_eq!(check("fn f(a: int) { a }\nf = fn(a) { a }\nf('a')"), 0);
+}
@@ -1,9 +1,10 @@
 use crossterm::{queue, style};
+use enumflags2::BitFlags;
 use intermediaries::{Token, TokenKind};
 use source::{SourceText, TextBase, TextSpan};
 use std::cell::Cell;
 use std::io::{self, prelude::*};
-use vm::types::ToString;
+use vm::types::{ToString, Type};
 use vm::value;
 
 /// A general Error struct for printing errors raised during the
@@ -370,6 +371,107 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
         }
     }
 
+    /// Generated in the parser
+    ///
+    /// Is reported when a type annotation uses a name which isn't a type, see `parser/src/lib.rs`
+    /// Examples:
+    /// let a: integer = 123
+    ///        ^^^^^^^
+    /// The type is called `int`
+    pub fn unknown_type(&self, span: TextSpan) {
+        self.report_err(
+            format!("UnknownType: `{}` is not a type", &self.src[&span]),
+            span,
+        )
+    }
+
+    /// Generated in the checker
+    ///
+    /// Is reported when a value can never be of the type it is annotated with,
+    /// see `checker/src/lib.rs`
+    /// Examples:
+    /// let a: int = "123"
+    ///              ^^^^^
+    /// A string can't be stored in an int variable
+    ///
+    /// This is a warning since the annotations aren't enforced at runtime.
+    pub fn type_mismatch(&self, expected: BitFlags<Type>, got: BitFlags<Type>, span: TextSpan) {
+        self.report_warning(
+            format!(
+                "TypeMismatch: Expected <{}>, got <{}>",
+                expected.to_string(),
+                got.to_string()
+            ),
+            span,
+        )
+    }
+
+    /// Generated in the checker
+    ///
+    /// Is reported when the types of the operands can't be used with a binary operator,
+    /// see `checker/src/lib.rs`
+    /// Examples:
+    /// 123 + "123"
+    ///     ^
+    /// An int cannot be added to a string
+    pub fn invalid_binary_operation(
+        &self,
+        operator: &TokenKind,
+        left: BitFlags<Type>,
+        right: BitFlags<Type>,
+        span: TextSpan,
+    ) {
+        self.report_warning(
+            format!(
+                "InvalidOperation: {:?} cannot be applied to <{}> and <{}>",
+                operator,
+                left.to_string(),
+                right.to_string()
+            ),
+            span,
+        )
+    }
+
+    /// Generated in the checker
+    ///
+    /// Is reported when the type of the operand can't be used with a unary operator,
+    /// see `checker/src/lib.rs`
+    /// Examples:
+    /// -"123"
+    /// ^
+    /// A string cannot be negated
+    pub fn invalid_unary_operation(
+        &self,
+        operator: &TokenKind,
+        operand: BitFlags<Type>,
+        span: TextSpan,
+    ) {
+        self.report_warning(
+            format!(
+                "InvalidOperation: {:?} cannot be applied to <{}>",
+                operator,
+                operand.to_string()
+            ),
+            span,
+        )
+    }
+
+    /// Generated in the checker
+    ///
+    /// Is reported when a function with a known declaration is called with the wrong number of
+    /// arguments, see `checker/src/lib.rs`
+    /// Examples:
+    /// fn add(a, b) { a + b }
+    /// add(1)
+    /// ^^^^^^
+    /// `add` needs 2 arguments
+    pub fn arg_count_mismatch(&self, expected: usize, got: usize, span: TextSpan) {
+        self.report_warning(
+            format!("TypeError: expected {} args, got {} args", expected, got),
+            span,
+        )
+    }
+
     /// Generated in the lowerer
     ///
     /// Is reported when there is a break statement outside a loop.
@@ -382,6 +484,17 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
         )
     }
 
+    /// Generated in the lowerer
+    ///
+    /// Is reported when a break statement has a label which none of the enclosing loops have.
+    /// see `lowerer/src/lib.rs`
+    pub fn unknown_label(&self, label: &str, span: TextSpan) {
+        self.report_err(
+            format!("UnknownLabel: No enclosing loop is labeled `{}`", label),
+            span,
+        )
+    }
+
     /// Generated in the lowerer
     ///
     /// Is reported when there is a break statement outside a loop.
@@ -405,7 +518,7 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
     ///     a + 2131
     /// }
     /// Computing 1231 + 12313 is futile since it doesn't change a variable or get used anywhere.
-    /// Note this is only checked for when the should_optimize flag is enabled in the lowerer.
+    /// Note this is only checked for when optimizations are enabled in the lowerer.
     pub fn unused_statement(&self, span: TextSpan) {
         self.report_warning(
             "UnusedStatement: this statement has no side effects and the value produced by it is not used"
@@ -414,10 +527,10 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
         )
     }
 
-    /// Generated in the evaluator
+    /// Generated in the lowerer and evaluator
     ///
     /// Is reported when a variable is used without being previously declared,
-    /// see `evaluator/src/lib.rs`
+    /// see `lowerer/src/lib.rs` and `evaluator/src/lib.rs`
     /// Examples:
     /// let a = a + 123
     ///         ^
@@ -429,10 +542,10 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
         )
     }
 
-    /// Generated in the evaluator
+    /// Generated in the lowerer and evaluator
     ///
     /// Is reported when a variable is being redeclared while already being declared in the
-    /// current see `evaluator/src/lib.rs`
+    /// current see `lowerer/src/lib.rs` and `evaluator/src/lib.rs`
     /// Examples:
     /// let a = 123
     /// let a = a + 123
@@ -445,6 +558,23 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
         )
     }
 
+    /// Generated in the evaluator
+    ///
+    /// Is reported when a function uses a variable from an enclosing scope before the variable's
+    /// declaration has been run, see `evaluator/src/lib.rs`
+    /// Examples:
+    /// fn f() { g() }
+    ///          ^
+    /// f()
+    /// fn g() {}
+    /// `g` is only declared after `f` is called
+    pub fn uninitialized_variable(&self, span: TextSpan) {
+        self.report_err(
+            "UnknownReference: Variable used before it was declared".to_owned(),
+            span,
+        )
+    }
+
     /// Generated in the evaluator
     ///
     /// This is a general method to convert a `value::ErrorKind` to an printed error, these are
@@ -458,6 +588,9 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
     pub fn from_value_error(&self, err: value::ErrorKind, span: TextSpan) {
         let msg = match err {
             value::ErrorKind::DivideByZero => String::from("DivideByZero: Cannot divide by zero"),
+            value::ErrorKind::StackOverflow { depth } => {
+                format!("StackOverflow: stack overflow at depth {}", depth)
+            }
             value::ErrorKind::OutOfBounds { got, start, end } => format!(
                 "OutOfBounds: Value {} is out of the bounds {} <= x < {}",
                 got, start, end
@@ -492,21 +625,24 @@ impl<'a, T: TextBase> Diagnostics<'a, T> {
                 if let value::Value::Object(_) = val {
                     format!(
                         "InvalidProperty: property '{}' does not exist on object {}",
-                        property.borrow().as_str(),
+                        property.as_str(),
                         val,
                     )
                 } else {
                     format!(
                         "InvalidProperty: property '{}' does not exist on type <{}>",
-                        property.borrow().as_str(),
+                        property.as_str(),
                         val.type_()
                     )
                 }
             }
+            value::ErrorKind::KeyNotFound { key } => {
+                format!("KeyNotFound: key {:?} does not exist in map", key)
+            }
             value::ErrorKind::ReadonlyProperty { val, property } => {
                 format!(
                     "ReadonlyProperty: property '{}' is immutable for type <{}>",
-                    property.borrow().as_str(),
+                    property.as_str(),
                     val.type_()
                 )
             }
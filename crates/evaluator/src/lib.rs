@@ -1,9 +1,45 @@
 use diagnostics::Diagnostics;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::ops::Deref;
 use std::rc::Rc;
-use vm::value::ErrorKind;
-use vm::{Instruction, InstructionKind, LabelNumber, Type, Value};
+use vm::value::{ErrorKind, Object};
+use vm::{Chunk, Op, Type, Value};
+
+/// The maximum number of nested function calls allowed before evaluation is stopped with a stack
+/// overflow error.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1_000_000;
+
+/// The instructions currently being evaluated.
+#[derive(Clone)]
+enum Code<'bytecode> {
+    /// The bytecode the evaluator was started with
+    Root(&'bytecode Chunk),
+    /// The body of a called function
+    Function(Rc<Chunk>),
+}
+
+impl<'bytecode> Deref for Code<'bytecode> {
+    type Target = Chunk;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Code::Root(bytecode) => bytecode,
+            Code::Function(bytecode) => bytecode,
+        }
+    }
+}
+
+/// The record of a function call which is in progress.
+struct Frame<'bytecode> {
+    /// The caller's bytecode, which evaluation resumes in once the function returns
+    bytecode: Code<'bytecode>,
+    /// The instruction in the caller to resume from
+    return_address: usize,
+    /// The length of the value stack when the function was called. Anything above it belongs to
+    /// the function
+    stack_base: usize,
+    /// The number of scopes when the function was called. The function's own scopes are above it
+    scopes_base: usize,
+}
 
 /// Evaluates bytecode.
 ///
@@ -12,13 +48,14 @@ use vm::{Instruction, InstructionKind, LabelNumber, Type, Value};
 /// ```no_run
 /// # use source::SourceText;
 /// # use diagnostics::Diagnostics;
-/// # use vm::{Value, InstructionKind, Scope, Bytecode};
+/// # use vm::{Value, Scope, Chunk};
 /// # struct Lexer;
 /// # impl Lexer { fn lex(_: &SourceText, _: &Diagnostics) {} }
 /// # struct Parser;
 /// # impl Parser { fn parse(_: (), _: &SourceText, _: &Diagnostics) {} }
 /// # struct Lowerer;
-/// # impl Lowerer { fn lower(_: (), _: &Diagnostics, _: bool) -> Bytecode { vec![] } }
+/// # enum OptLevel { O0 }
+/// # impl Lowerer { fn lower(_: (), _: &Diagnostics, _: OptLevel) -> Chunk { Chunk::default() } }
 /// use evaluator::Evaluator;
 ///
 /// let src = SourceText::new("1 + 2 + 3");
@@ -26,8 +63,8 @@ use vm::{Instruction, InstructionKind, LabelNumber, Type, Value};
 ///
 /// let tokens = Lexer::lex(&src, &diagnostics);
 /// let root_node = Parser::parse(tokens, &src, &diagnostics);
-/// let bytecode = Lowerer::lower(root_node, &diagnostics, false);
-/// let value = Evaluator::evaluate(&bytecode[..], &diagnostics);
+/// let bytecode = Lowerer::lower(root_node, &diagnostics, OptLevel::O0);
+/// let value = Evaluator::evaluate(&bytecode, &diagnostics);
 ///
 /// assert_eq!(value, Value::Int(6));
 /// ```
@@ -36,15 +73,16 @@ use vm::{Instruction, InstructionKind, LabelNumber, Type, Value};
 /// ```no_run
 /// # use source::SourceText;
 /// # use diagnostics::Diagnostics;
-/// # use vm::{Value, InstructionKind, Scope, Bytecode};
+/// # use vm::{Value, Scope, Chunk};
 /// # struct Lexer;
 /// # impl Lexer { fn lex(_: &SourceText, _: &Diagnostics) {} }
 /// # struct Parser;
 /// # impl Parser { fn parse(_: (), _: &SourceText, _: &Diagnostics) {} }
 /// # struct Lowerer;
+/// # enum OptLevel { O0 }
 /// # impl Lowerer {
-/// #     fn lower_with_global(_: (), _: &Diagnostics, _: Rc<Scope>, _: bool) -> Bytecode {
-/// #         vec![]
+/// #     fn lower_with_global(_: (), _: &Diagnostics, _: Rc<Scope>, _: OptLevel) -> Chunk {
+/// #         Chunk::default()
 /// #     }
 /// # }
 /// use evaluator::Evaluator;
@@ -58,43 +96,54 @@ use vm::{Instruction, InstructionKind, LabelNumber, Type, Value};
 ///
 /// let tokens = Lexer::lex(&src, &diagnostics);
 /// let root_node = Parser::parse(tokens, &src, &diagnostics);
-/// let bytecode = Lowerer::lower_with_global(root_node, &diagnostics, scope, false);
-/// let value = Evaluator::evaluate(&bytecode[..], &diagnostics);
+/// let bytecode = Lowerer::lower_with_global(root_node, &diagnostics, scope, OptLevel::O0);
+/// let value = Evaluator::evaluate(&bytecode, &diagnostics);
 ///
 /// assert_eq!(value, Value::Int(6));
 /// ```
 pub struct Evaluator<'diagnostics, 'src, 'bytecode> {
     diagnostics: &'diagnostics Diagnostics<'src>,
-    /// The list of labels to instruction. Each index is the label number, and the usize stored is
-    /// one plus the index of the label in the bytecode
-    labels: Vec<usize>,
     /// This are the variable scopes, the root scope is at index 0, and subsequent scopes can check
     /// the scopes at a previous index, once a scope is over, it is popped of
     scopes: Vec<Rc<vm::Scope>>,
     /// The stack of values
     stack: Vec<Value>,
     /// The bytecode to execute
-    bytecode: &'bytecode [Instruction],
+    bytecode: Code<'bytecode>,
     /// The current instruction number
     instr_i: usize,
+    /// The function calls which are in progress, the innermost call is last
+    frames: Vec<Frame<'bytecode>>,
+    /// The maximum length `frames` can grow to
+    max_depth: usize,
 }
 
 impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     /// Given a root node and diagnostics to report to, this will execute the parsed AST
     pub fn evaluate(
-        bytecode: &'bytecode [Instruction],
+        bytecode: &'bytecode Chunk,
         diagnostics: &'diagnostics Diagnostics<'src>,
+    ) -> Value {
+        Self::evaluate_with_max_depth(bytecode, diagnostics, DEFAULT_MAX_CALL_DEPTH)
+    }
+
+    /// Same as `evaluate`, but reports a stack overflow once more than `max_depth` function calls
+    /// are nested
+    pub fn evaluate_with_max_depth(
+        bytecode: &'bytecode Chunk,
+        diagnostics: &'diagnostics Diagnostics<'src>,
+        max_depth: usize,
     ) -> Value {
         let mut evaluator = Self {
             diagnostics,
-            labels: Vec::new(),
-            bytecode,
+            bytecode: Code::Root(bytecode),
             instr_i: 0,
             stack: Vec::new(),
             scopes: Vec::new(),
+            frames: Vec::new(),
+            max_depth,
         };
 
-        evaluator.register_labels();
         evaluator.evaluate_bytecode();
         evaluator.stack.pop().unwrap_or(Value::Null)
     }
@@ -109,79 +158,259 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
             Ok(v) => v,
             Err(e) => {
                 self.diagnostics
-                    .from_value_error(e, self.bytecode[self.instr_i].span.clone());
+                    .from_value_error(e, self.bytecode.span(self.instr_i));
                 Value::Null
             }
         }
     }
 
     fn evaluate_bytecode(&mut self) {
-        while self.instr_i < self.bytecode.len() {
-            // Error has been reported to diagnostics, stop processing commands
-            if self.diagnostics.any() {
-                break;
-            }
+        'frames: loop {
+            // A cheap clone, which lets the instructions be borrowed while the evaluator is mutated
+            let bytecode = self.bytecode.clone();
 
-            match &self.bytecode[self.instr_i].kind {
-                InstructionKind::BinaryAdd => self.evaluate_binary_add(),
-                InstructionKind::BinarySubtract => self.evaluate_binary_subtract(),
-                InstructionKind::BinaryMultiply => self.evaluate_binary_multiply(),
-                InstructionKind::BinaryDivide => self.evaluate_binary_divide(),
-                InstructionKind::BinaryMod => self.evaluate_binary_mod(),
-                InstructionKind::BinaryPower => self.evaluate_binary_power(),
-                InstructionKind::BinaryOr => self.evaluate_binary_or(),
-                InstructionKind::BinaryAnd => self.evaluate_binary_and(),
-                InstructionKind::UnaryPositive => self.evaluate_unary_positive(),
-                InstructionKind::UnaryNegative => self.evaluate_unary_negative(),
-                InstructionKind::UnaryNot => self.evaluate_unary_not(),
-                InstructionKind::CompareLT => self.evaluate_compare_lt(),
-                InstructionKind::CompareLE => self.evaluate_compare_le(),
-                InstructionKind::CompareGT => self.evaluate_compare_gt(),
-                InstructionKind::CompareGE => self.evaluate_compare_ge(),
-                InstructionKind::CompareEQ => self.evaluate_compare_eq(),
-                InstructionKind::CompareNE => self.evaluate_compare_ne(),
-                InstructionKind::Pop => self.evaluate_pop(),
-                InstructionKind::Push { value } => self.evaluate_push(value.clone()),
-                InstructionKind::Store { ident, declaration } => {
-                    self.evaluate_store(Rc::clone(&ident), *declaration)
+            while self.instr_i < bytecode.ops.len() {
+                // Error has been reported to diagnostics, stop processing commands
+                if self.diagnostics.any() {
+                    return;
                 }
-                InstructionKind::Load { ident } => self.evaluate_load(ident),
-                InstructionKind::GetIndex => self.evaluate_get_index(),
-                InstructionKind::SetIndex => self.evaluate_set_index(),
-                InstructionKind::JumpTo { label } => self.evaluate_jump_to(*label),
-                InstructionKind::PopJumpIfTrue { label } => self.evaluate_pop_jump_if_true(*label),
-                InstructionKind::CallFunction { num_args } => {
-                    self.evaluate_call_function(*num_args)
+
+                match bytecode.ops[self.instr_i].get() {
+                    Op::BinaryAdd => self.evaluate_binary_add(),
+                    Op::BinarySubtract => self.evaluate_binary_subtract(),
+                    Op::BinaryMultiply => self.evaluate_binary_multiply(),
+                    Op::BinaryDivide => self.evaluate_binary_divide(),
+                    Op::BinaryMod => self.evaluate_binary_mod(),
+                    Op::BinaryPower => self.evaluate_binary_power(),
+                    Op::BinaryOr => self.evaluate_binary_or(),
+                    Op::BinaryAnd => self.evaluate_binary_and(),
+                    Op::UnaryPositive => self.evaluate_unary_positive(),
+                    Op::UnaryNegative => self.evaluate_unary_negative(),
+                    Op::UnaryNot => self.evaluate_unary_not(),
+                    Op::CompareLT => self.evaluate_compare_lt(),
+                    Op::CompareLE => self.evaluate_compare_le(),
+                    Op::CompareGT => self.evaluate_compare_gt(),
+                    Op::CompareGE => self.evaluate_compare_ge(),
+                    Op::CompareEQ => self.evaluate_compare_eq(),
+                    Op::CompareNE => self.evaluate_compare_ne(),
+                    Op::Pop => self.evaluate_pop(),
+                    Op::Push { constant } => {
+                        self.evaluate_push(&bytecode.constants[constant as usize])
+                    }
+                    Op::Store { ident, declaration } => self
+                        .evaluate_store(Rc::clone(&bytecode.idents[ident as usize]), declaration),
+                    Op::Load { ident } => self.evaluate_load(&bytecode.idents[ident as usize]),
+                    Op::GetIndex => self.evaluate_get_index(),
+                    Op::SetIndex => self.evaluate_set_index(),
+                    Op::SetNestedIndex { depth } => self.evaluate_set_nested_index(depth as usize),
+                    // Jumps go straight to the target instead of the instruction after it
+                    Op::JumpTo { target } => {
+                        self.instr_i = target as usize;
+                        continue;
+                    }
+                    Op::PopJumpIfTrue { target } => {
+                        if self.evaluate_pop_jump_if_true() {
+                            self.instr_i = target as usize;
+                            continue;
+                        }
+                    }
+                    Op::CallFunction { num_args } => {
+                        if self.evaluate_call_function(num_args as usize, false) {
+                            // Evaluation continues in the body of the called function
+                            continue 'frames;
+                        }
+                    }
+                    Op::PopJumpIfFalse { target } => {
+                        if !self.evaluate_pop_jump_if_true() {
+                            self.instr_i = target as usize;
+                            continue;
+                        }
+                    }
+                    Op::PopStore { ident, declaration } => self.evaluate_pop_store(
+                        Rc::clone(&bytecode.idents[ident as usize]),
+                        declaration,
+                    ),
+                    Op::PopStoreLocal {
+                        depth,
+                        slot,
+                        declaration,
+                    } => self.evaluate_pop_store_local(depth as usize, slot as usize, declaration),
+                    Op::TailCallFunction { num_args } => {
+                        if self.evaluate_call_function(num_args as usize, true) {
+                            continue 'frames;
+                        }
+                    }
+                    Op::MakeList { len } => self.evaluate_make_list(len as usize),
+                    Op::MakeObject { len } => self.evaluate_make_object(len as usize),
+                    Op::MakeRange => self.evaluate_make_range(),
+                    Op::PushVar { scope } => {
+                        self.evaluate_push_var(&bytecode.scopes[scope as usize])
+                    }
+                    Op::PopVar => self.evaluate_pop_var(),
+                    Op::MakeIter => self.evaluate_make_iter(),
+                    Op::ForIter { target } => {
+                        if self.evaluate_for_iter() {
+                            self.instr_i = target as usize;
+                            continue;
+                        }
+                    }
+                    Op::ListAppend { depth } => self.evaluate_list_append(depth as usize),
+                    Op::ObjectInsert { depth } => self.evaluate_object_insert(depth as usize),
+                    Op::BinaryIn => self.evaluate_binary_in(),
+                    Op::LoadLocal { depth, slot } => {
+                        self.evaluate_load_local(depth as usize, slot as usize)
+                    }
+                    Op::StoreLocal {
+                        depth,
+                        slot,
+                        declaration,
+                    } => self.evaluate_store_local(depth as usize, slot as usize, declaration),
+                    // A specialized op which gets operands of other types is replaced with the
+                    // generic op, which is then evaluated instead
+                    Op::AddIntInt => {
+                        if !self
+                            .evaluate_int_int(|left, right| left.checked_add(right).map(Value::Int))
+                        {
+                            continue;
+                        }
+                    }
+                    Op::SubIntInt => {
+                        if !self
+                            .evaluate_int_int(|left, right| left.checked_sub(right).map(Value::Int))
+                        {
+                            continue;
+                        }
+                    }
+                    Op::MulIntInt => {
+                        if !self
+                            .evaluate_int_int(|left, right| left.checked_mul(right).map(Value::Int))
+                        {
+                            continue;
+                        }
+                    }
+                    Op::AddFloatFloat => {
+                        if !self.evaluate_float_float(|left, right| left + right) {
+                            continue;
+                        }
+                    }
+                    Op::SubFloatFloat => {
+                        if !self.evaluate_float_float(|left, right| left - right) {
+                            continue;
+                        }
+                    }
+                    Op::MulFloatFloat => {
+                        if !self.evaluate_float_float(|left, right| left * right) {
+                            continue;
+                        }
+                    }
+                    Op::LtIntInt => {
+                        if !self.evaluate_int_int(|left, right| Some(Value::Bool(left < right))) {
+                            continue;
+                        }
+                    }
+                    Op::LeIntInt => {
+                        if !self.evaluate_int_int(|left, right| Some(Value::Bool(left <= right))) {
+                            continue;
+                        }
+                    }
+                    Op::GtIntInt => {
+                        if !self.evaluate_int_int(|left, right| Some(Value::Bool(left > right))) {
+                            continue;
+                        }
+                    }
+                    Op::GeIntInt => {
+                        if !self.evaluate_int_int(|left, right| Some(Value::Bool(left >= right))) {
+                            continue;
+                        }
+                    }
                 }
-                InstructionKind::Label { .. } => {}
-                InstructionKind::MakeList { len } => self.evaluate_make_list(*len),
-                InstructionKind::MakeObject { len } => self.evaluate_make_object(*len),
-                InstructionKind::MakeRange => self.evaluate_make_range(),
-                InstructionKind::PushVar { scope } => self.evaluate_push_var(Rc::clone(scope)),
-                InstructionKind::PopVar => self.evaluate_pop_var(),
+
+                self.instr_i += 1;
             }
 
-            self.instr_i += 1;
+            if !self.return_from_function() {
+                return;
+            }
         }
     }
 
-    fn register_labels(&mut self) {
-        for (i, instr) in self.bytecode.iter().enumerate() {
-            if let Instruction {
-                kind: InstructionKind::Label { number },
-                ..
-            } = instr
-            {
-                if *number >= self.labels.len() {
-                    self.labels.resize(*number + 1, usize::MAX);
-                }
+    /// Pops the innermost frame and passes the function's value back to the caller. Returns
+    /// false if there is no function to return from, which means the root bytecode is complete
+    fn return_from_function(&mut self) -> bool {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return false,
+        };
+
+        let value = if self.stack.len() > frame.stack_base {
+            self.stack.pop().unwrap()
+        } else {
+            Value::Null
+        };
+        self.stack.truncate(frame.stack_base);
+        self.scopes.truncate(frame.scopes_base);
 
-                self.labels[*number] = i;
+        self.bytecode = frame.bytecode;
+        self.instr_i = frame.return_address;
+        self.stack.push(value);
+        true
+    }
+
+    /// Records the types of the operands of the arithmetic or comparison op being evaluated, so
+    /// that it can be specialized if it is always evaluated with the same types
+    fn record_types(&self, int_int: Op, float_float: Option<Op>) {
+        let len = self.stack.len();
+        let specialized = match (&self.stack[len - 1], &self.stack[len - 2]) {
+            (Value::Int(_), Value::Int(_)) => Some(int_int),
+            (Value::Float(_), Value::Float(_)) => float_float,
+            _ => None,
+        };
+        self.bytecode.record_types(self.instr_i, specialized);
+    }
+
+    /// Evaluates an op specialized for two ints. Returns false if the operands aren't ints, or if
+    /// `op` returns `None` because the result overflows, in which case the op is deoptimized and
+    /// has to be evaluated again, promoting the result to a big int
+    #[inline]
+    fn evaluate_int_int(&mut self, op: impl FnOnce(i64, i64) -> Option<Value>) -> bool {
+        let len = self.stack.len();
+        let result = match (&self.stack[len - 1], &self.stack[len - 2]) {
+            (&Value::Int(left), &Value::Int(right)) => op(left, right),
+            _ => None,
+        };
+
+        match result {
+            Some(result) => {
+                self.stack.pop();
+                self.stack[len - 2] = result;
+                true
+            }
+            None => {
+                self.bytecode.deoptimize(self.instr_i);
+                false
+            }
+        }
+    }
+
+    /// Evaluates an op specialized for two floats, like `evaluate_int_int`
+    #[inline]
+    fn evaluate_float_float(&mut self, op: impl FnOnce(f64, f64) -> f64) -> bool {
+        let len = self.stack.len();
+        match (&self.stack[len - 1], &self.stack[len - 2]) {
+            (&Value::Float(left), &Value::Float(right)) => {
+                self.stack.pop();
+                self.stack[len - 2] = Value::Float(op(left, right));
+                true
+            }
+            _ => {
+                self.bytecode.deoptimize(self.instr_i);
+                false
             }
         }
     }
 
     fn evaluate_binary_add(&mut self) {
+        self.record_types(Op::AddIntInt, Some(Op::AddFloatFloat));
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left + right);
@@ -189,6 +418,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     }
 
     fn evaluate_binary_subtract(&mut self) {
+        self.record_types(Op::SubIntInt, Some(Op::SubFloatFloat));
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left - right);
@@ -196,6 +426,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     }
 
     fn evaluate_binary_multiply(&mut self) {
+        self.record_types(Op::MulIntInt, Some(Op::MulFloatFloat));
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left * right);
@@ -235,6 +466,13 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
         self.stack.push(left.and(right));
     }
 
+    fn evaluate_binary_in(&mut self) {
+        let left = self.stack.pop().expect("Expect 2 values on the stack");
+        let right = self.stack.pop().expect("Expect 2 values on the stack");
+        let v = self.handle_result(right.contains(left));
+        self.stack.push(v);
+    }
+
     fn evaluate_unary_positive(&mut self) {
         let value = self.stack.pop().expect("Expect value on the stack");
         let value = self.handle_result(value.plus());
@@ -253,6 +491,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     }
 
     fn evaluate_compare_lt(&mut self) {
+        self.record_types(Op::LtIntInt, None);
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left.lt(right));
@@ -260,6 +499,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     }
 
     fn evaluate_compare_gt(&mut self) {
+        self.record_types(Op::GtIntInt, None);
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left.gt(right));
@@ -267,6 +507,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     }
 
     fn evaluate_compare_le(&mut self) {
+        self.record_types(Op::LeIntInt, None);
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left.le(right));
@@ -274,6 +515,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
     }
 
     fn evaluate_compare_ge(&mut self) {
+        self.record_types(Op::GeIntInt, None);
         let left = self.stack.pop().expect("Expect 2 values on the stack");
         let right = self.stack.pop().expect("Expect 2 values on the stack");
         let v = self.handle_result(left.ge(right));
@@ -303,8 +545,16 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
         });
     }
 
-    fn evaluate_push(&mut self, value: Value) {
-        self.stack.push(value);
+    fn evaluate_push(&mut self, value: &Value) {
+        // A function can access the variables of the scope it is declared in, which is only known
+        // now since every call of the enclosing function has its own scopes
+        if let (Value::Function(f), Some(scope)) = (value, self.scopes.last()) {
+            if let Some(f) = f.declared_in(scope) {
+                self.stack.push(Value::new_function(f));
+                return;
+            }
+        }
+        self.stack.push(value.clone());
     }
 
     fn evaluate_store(&mut self, ident: Rc<str>, declaration: bool) {
@@ -313,14 +563,23 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
             .last()
             .expect("Expect value on stack to store")
             .clone();
+        self.store(ident, v, declaration);
+    }
+
+    fn evaluate_pop_store(&mut self, ident: Rc<str>, declaration: bool) {
+        let v = self.stack.pop().expect("Expect value on stack to store");
+        self.store(ident, v, declaration);
+    }
+
+    fn store(&mut self, ident: Rc<str>, v: Value, declaration: bool) {
         if declaration {
             if let Err(ident) = self.scope().declare(ident, v) {
                 self.diagnostics
-                    .already_declared(&ident, self.bytecode[self.instr_i].span.clone());
+                    .already_declared(&ident, self.bytecode.span(self.instr_i));
             }
         } else if let Err(ident) = self.scope().set(ident, v) {
             self.diagnostics
-                .unknown_reference(&ident, self.bytecode[self.instr_i].span.clone());
+                .unknown_reference(&ident, self.bytecode.span(self.instr_i));
         }
     }
 
@@ -329,14 +588,47 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
             self.stack.push(value);
         } else {
             self.diagnostics
-                .unknown_reference(ident, self.bytecode[self.instr_i].span.clone());
+                .unknown_reference(ident, self.bytecode.span(self.instr_i));
+        }
+    }
+
+    fn evaluate_load_local(&mut self, depth: usize, slot: usize) {
+        if let Some(value) = self.scope().ancestor(depth).get_slot(slot) {
+            self.stack.push(value);
+        } else {
+            self.diagnostics
+                .uninitialized_variable(self.bytecode.span(self.instr_i));
+        }
+    }
+
+    fn evaluate_store_local(&mut self, depth: usize, slot: usize, declaration: bool) {
+        let v = self
+            .stack
+            .last()
+            .expect("Expect value on stack to store")
+            .clone();
+        self.store_local(depth, slot, v, declaration);
+    }
+
+    fn evaluate_pop_store_local(&mut self, depth: usize, slot: usize, declaration: bool) {
+        let v = self.stack.pop().expect("Expect value on stack to store");
+        self.store_local(depth, slot, v, declaration);
+    }
+
+    fn store_local(&mut self, depth: usize, slot: usize, v: Value, declaration: bool) {
+        let scope = self.scope().ancestor(depth);
+        if declaration || scope.has_slot(slot) {
+            scope.set_slot(slot, v);
+        } else {
+            self.diagnostics
+                .uninitialized_variable(self.bytecode.span(self.instr_i));
         }
     }
 
     fn evaluate_get_index(&mut self) {
         let v = self.stack.pop().expect("Expect 2 values on stack");
         let index = self.stack.pop().expect("Expect 2 values on stack");
-        let v = self.handle_result(v.get_at(index));
+        let v = self.handle_result(v.get_at_cached(index, self.bytecode.cache(self.instr_i)));
         self.stack.push(v);
     }
 
@@ -344,22 +636,34 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
         let indexed = self.stack.pop().expect("Expect 3 values on stack");
         let index = self.stack.pop().expect("Expect 3 values on stack");
         let v = self.stack.pop().expect("Expect 3 values on stack");
-        let v = self.handle_result(indexed.set_at(index, v));
+        let v =
+            self.handle_result(indexed.set_at_cached(index, v, self.bytecode.cache(self.instr_i)));
         self.stack.push(v);
     }
 
-    fn evaluate_jump_to(&mut self, label: LabelNumber) {
-        self.instr_i = self.labels[label];
+    fn evaluate_set_nested_index(&mut self, depth: usize) {
+        let e_msg = "Expect depth + 2 values on stack";
+        let indexed = self.stack.pop().expect(e_msg);
+        let mut indices = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            indices.push(self.stack.pop().expect(e_msg));
+        }
+        let v = self.stack.pop().expect(e_msg);
+
+        let v = self.handle_result(indexed.set_at_nested(indices, v));
+        self.stack.push(v);
     }
 
-    fn evaluate_pop_jump_if_true(&mut self, label: LabelNumber) {
+    /// Returns whether the jump should be taken
+    fn evaluate_pop_jump_if_true(&mut self) -> bool {
         let v = self.stack.pop().expect("Expect a value on the stack");
-        if bool::from(v) {
-            self.instr_i = self.labels[label];
-        }
+        bool::from(v)
     }
 
-    fn evaluate_call_function(&mut self, mut num_args: usize) {
+    /// Calls the function on top of the stack. Returns true if an anilang function was called, in
+    /// which case evaluation continues from the start of its body. For a `tail` call, the frame of
+    /// the current function is given to the called function, so the call depth does not grow
+    fn evaluate_call_function(&mut self, mut num_args: usize, tail: bool) -> bool {
         let e_msg = |num_args| {
             panic!(
                 "Expect {} value{} on the stack",
@@ -374,9 +678,9 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
                     got: v.type_(),
                     expected: Type::Function.into(),
                 },
-                self.bytecode[self.instr_i].span.clone(),
+                self.bytecode.span(self.instr_i),
             );
-            return;
+            return false;
         }
 
         let func = v.into_rc_fn();
@@ -400,14 +704,14 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
                 Ok(v) => v,
                 Err(e) => {
                     self.diagnostics
-                        .from_value_error(e, self.bytecode[self.instr_i].span.clone());
-                    return;
+                        .from_value_error(e, self.bytecode.span(self.instr_i));
+                    return false;
                 }
             };
 
             self.stack.push(v);
 
-            return;
+            return false;
         }
 
         let func = func.as_anilang_fn().unwrap();
@@ -417,9 +721,9 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
                     got: num_args,
                     expected: func.args.len(),
                 },
-                self.bytecode[self.instr_i].span.clone(),
+                self.bytecode.span(self.instr_i),
             );
-            return;
+            return false;
         }
 
         // Is empty, nothing to execute
@@ -431,35 +735,50 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
                     panic!("Expected {} values on the stack", func.args.len())
                 }) = Value::Null;
             }
-            return;
+            return false;
         }
 
-        // An optimization of checking the strong count of the function to avoid copying (similar to
-        // the one for list and string in `vm/src/value/mod.rs`) cannot be made since functions
-        // can never be generated at execution time, so there will always be at least 2 references:
-        // The one on the stack, which we are using to execute, and the one in the bytecode
-        // instructions
-
-        let fn_body = func.duplicate_body();
-
-        let fn_scope = match &fn_body[0].kind {
-            InstructionKind::PushVar { scope } => scope,
-            _ => unreachable!("Function body must start with a PushVar"),
-        };
+        // The body is shared by every call, only the scope the arguments are stored in is new
+        let fn_scope = func.call_scope();
+        for slot in 0..func.args.len() {
+            fn_scope.set_slot(slot, self.stack.pop().unwrap_or_else(|| e_msg(num_args)));
+        }
+        let fn_body = Code::Function(Rc::clone(&func.body));
+
+        if tail {
+            if let Some(frame) = self.frames.last() {
+                // Whatever the current function left on the stack or in its scopes is no longer
+                // needed, since it would only have been removed once the called function returned
+                self.stack.truncate(frame.stack_base);
+                self.scopes.truncate(frame.scopes_base);
+                self.scopes.push(fn_scope);
+                self.bytecode = fn_body;
+                // The body's `PushVar` is skipped, since its scope has already been pushed
+                self.instr_i = 1;
+                return true;
+            }
+        }
 
-        for arg in func.args.iter() {
-            fn_scope
-                .declare(
-                    arg.clone(),
-                    self.stack.pop().unwrap_or_else(|| e_msg(num_args)),
-                )
-                // Since this is a cloned scope, it should be empty, so there shouldn't be any
-                // issues in declaring the variable
-                .unwrap();
+        if self.frames.len() >= self.max_depth {
+            self.diagnostics.from_value_error(
+                ErrorKind::StackOverflow {
+                    depth: self.frames.len(),
+                },
+                self.bytecode.span(self.instr_i),
+            );
+            return false;
         }
 
-        self.stack
-            .push(Evaluator::evaluate(&fn_body, self.diagnostics));
+        let caller = std::mem::replace(&mut self.bytecode, fn_body);
+        self.frames.push(Frame {
+            bytecode: caller,
+            return_address: self.instr_i + 1,
+            stack_base: self.stack.len(),
+            scopes_base: self.scopes.len(),
+        });
+        self.scopes.push(fn_scope);
+        self.instr_i = 1;
+        true
     }
 
     fn evaluate_make_list(&mut self, len: usize) {
@@ -476,7 +795,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
             list.push(self.stack.pop().unwrap_or_else(e_msg));
         }
 
-        self.stack.push(Value::List(Rc::new(RefCell::new(list))));
+        self.stack.push(Value::new_list(list));
     }
 
     fn evaluate_make_object(&mut self, len: usize) {
@@ -484,7 +803,7 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
             panic!("Expect {} values on the stack", len * 2,);
         };
 
-        let mut map = HashMap::with_capacity(len);
+        let mut object = Object::new();
         for _ in 0..len {
             let k = self.stack.pop().unwrap_or_else(e_msg);
             let v = self.stack.pop().unwrap_or_else(e_msg);
@@ -496,15 +815,15 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
                             k.type_()
                         ),
                     },
-                    self.bytecode[self.instr_i].span.clone(),
+                    self.bytecode.span(self.instr_i),
                 );
                 return;
             }
 
-            map.insert(k.into_str(), v);
+            object.insert(k.to_ref_str(), v);
         }
 
-        self.stack.push(Value::Object(Rc::new(RefCell::new(map))));
+        self.stack.push(Value::new_object(object));
     }
 
     fn evaluate_make_range(&mut self) {
@@ -514,11 +833,84 @@ impl<'diagnostics, 'src, 'bytecode> Evaluator<'diagnostics, 'src, 'bytecode> {
         self.stack.push(v);
     }
 
-    fn evaluate_push_var(&mut self, scope: Rc<vm::Scope>) {
+    fn evaluate_push_var(&mut self, scope: &Rc<vm::Scope>) {
+        // The root scope is used as is since it may be a global scope. Any other scope is created
+        // anew every time it is entered, so that for example recursive calls don't share variables
+        let scope = match self.scopes.last() {
+            Some(parent) => {
+                let scope = Rc::new(vm::Scope::new(scope.id, Some(Rc::clone(parent))));
+                vm::gc::track_scope(&scope);
+                scope
+            }
+            None => Rc::clone(scope),
+        };
         self.scopes.push(scope);
     }
 
     fn evaluate_pop_var(&mut self) {
         self.scopes.pop();
     }
+
+    fn evaluate_make_iter(&mut self) {
+        let v = self.stack.pop().expect("Expect value on the stack");
+        let v = self.handle_result(v.into_iterable());
+        self.stack.push(v);
+        self.stack.push(Value::Int(0));
+    }
+
+    /// Returns whether the iterator is exhausted, in which case the jump should be taken
+    fn evaluate_for_iter(&mut self) -> bool {
+        let len = self.stack.len();
+        let index = match self.stack[len - 1] {
+            Value::Int(index) => index as usize,
+            _ => unreachable!("Expect iterator index on the stack"),
+        };
+
+        match self.stack[len - 2].iter_at(index) {
+            Some(v) => {
+                self.stack[len - 1] = Value::Int(index as i64 + 1);
+                self.stack.push(v);
+                false
+            }
+            None => {
+                // Iterator is exhausted, remove it
+                self.stack.truncate(len - 2);
+                true
+            }
+        }
+    }
+
+    fn evaluate_list_append(&mut self, depth: usize) {
+        let v = self.stack.pop().expect("Expect value on the stack");
+        let i = self.stack.len() - 1 - depth;
+        match self.stack[i] {
+            Value::List(ref list) => list.borrow_mut().push(v),
+            _ => unreachable!("Expect list {} values deep in the stack", depth),
+        }
+    }
+
+    fn evaluate_object_insert(&mut self, depth: usize) {
+        let k = self.stack.pop().expect("Expect 2 values on the stack");
+        let v = self.stack.pop().expect("Expect 2 values on the stack");
+        if k.type_() != Type::String {
+            self.diagnostics.from_value_error(
+                ErrorKind::Other {
+                    message: format!(
+                        "IncorrectType: Object Keys must be of type <string>, got <{}>",
+                        k.type_()
+                    ),
+                },
+                self.bytecode.span(self.instr_i),
+            );
+            return;
+        }
+
+        let i = self.stack.len() - 1 - depth;
+        match self.stack[i] {
+            Value::Object(ref object) => {
+                object.borrow_mut().insert(k.to_ref_str(), v);
+            }
+            _ => unreachable!("Expect object {} values deep in the stack", depth),
+        }
+    }
 }
@@ -4,7 +4,7 @@ use source::SourceText;
 use std::rc::Rc;
 use vm::function::Function;
 use vm::test_helpers::*;
-use vm::{Bytecode, InstructionKind, Value};
+use vm::{finalize_bytecode, Bytecode, InstructionKind, Value};
 
 macro_rules! par {
     ($parent:expr) => {
@@ -33,19 +33,21 @@ fn eval(mut bytecode: Bytecode) -> Value {
         .into(),
     );
     bytecode.push(InstructionKind::PopVar.into());
+    let bytecode = finalize_bytecode(bytecode);
     // The source text is only needed in diagnostics, so can be ignored
     let src = SourceText::new("");
     let diagnostics = Diagnostics::new(&src).no_print();
-    Evaluator::evaluate(&bytecode[..], &diagnostics)
+    Evaluator::evaluate(&bytecode, &diagnostics)
 }
 
 fn eval_s(mut bytecode: Bytecode, scope: Rc<vm::Scope>) -> Value {
     bytecode.insert(0, InstructionKind::PushVar { scope }.into());
     bytecode.push(InstructionKind::PopVar.into());
+    let bytecode = finalize_bytecode(bytecode);
     // The source text is only needed in diagnostics, so can be ignored
     let src = SourceText::new("");
     let diagnostics = Diagnostics::new(&src).no_print();
-    Evaluator::evaluate(&bytecode[..], &diagnostics)
+    Evaluator::evaluate(&bytecode, &diagnostics)
 }
 
 #[test]
@@ -105,6 +107,38 @@ fn evaluate_variable_properly() {
     assert_eq!(eval(bytecode), i(0));
 }
 
+#[test]
+fn evaluate_local_variable_properly() {
+    let scope = gen_scope(0, None);
+    scope.set_slot(1, i(2));
+
+    let bytecode = vec![
+        InstructionKind::PushVar {
+            scope: gen_scope(1, par!(scope)),
+        }
+        .into(),
+        InstructionKind::Push { value: i(3) }.into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 0,
+            declaration: true,
+        }
+        .into(),
+        InstructionKind::LoadLocal { depth: 1, slot: 1 }.into(),
+        InstructionKind::BinaryAdd.into(),
+        InstructionKind::StoreLocal {
+            depth: 1,
+            slot: 1,
+            declaration: false,
+        }
+        .into(),
+        InstructionKind::PopVar.into(),
+    ];
+
+    assert_eq!(eval_s(bytecode, Rc::clone(&scope)), i(5));
+    assert_eq!(scope.get_slot(1), Some(i(5)));
+}
+
 #[test]
 fn evaluate_index_properly() {
     let scope = gen_scope(0, None);
@@ -142,6 +176,47 @@ fn evaluate_index_properly() {
 //     }
 // }
 // ```
+#[test]
+fn evaluate_fused_instructions_properly() {
+    let scope = gen_scope(0, None);
+    scope.declare("global".into(), i(0)).unwrap();
+
+    let bytecode = vec![
+        InstructionKind::PushVar {
+            scope: gen_scope(1, par!(scope)),
+        }
+        .into(),
+        InstructionKind::Push { value: i(3) }.into(),
+        InstructionKind::PopStoreLocal {
+            depth: 0,
+            slot: 0,
+            declaration: true,
+        }
+        .into(),
+        InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
+        InstructionKind::PopStore {
+            ident: "global".into(),
+            declaration: false,
+        }
+        .into(),
+        InstructionKind::Push { value: b(false) }.into(),
+        InstructionKind::PopJumpIfFalse { target: 0 }.into(),
+        InstructionKind::Push { value: i(1) }.into(),
+        InstructionKind::PopStoreLocal {
+            depth: 0,
+            slot: 0,
+            declaration: false,
+        }
+        .into(),
+        InstructionKind::Label { number: 0 }.into(),
+        InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
+        InstructionKind::PopVar.into(),
+    ];
+
+    assert_eq!(eval_s(bytecode, Rc::clone(&scope)), i(3));
+    assert_eq!(scope.try_get_value("global"), Some(i(3)));
+}
+
 #[test]
 fn evaluate_if_properly() {
     let root = gen_scope(0, None);
@@ -151,14 +226,14 @@ fn evaluate_if_properly() {
     let if_tree = |cond| {
         vec![
             InstructionKind::Push { value: b(cond) }.into(),
-            InstructionKind::PopJumpIfTrue { label: 0 }.into(),
+            InstructionKind::PopJumpIfTrue { target: 0 }.into(),
             InstructionKind::PushVar {
                 scope: Rc::clone(&else_scope),
             }
             .into(),
             InstructionKind::Push { value: i(1) }.into(),
             InstructionKind::PopVar.into(),
-            InstructionKind::JumpTo { label: 1 }.into(),
+            InstructionKind::JumpTo { target: 1 }.into(),
             InstructionKind::Label { number: 0 }.into(),
             InstructionKind::PushVar {
                 scope: Rc::clone(&if_scope),
@@ -193,13 +268,13 @@ fn evaluate_loop_properly() {
         InstructionKind::Push { value: i(100) }.into(),
         InstructionKind::Load { ident: "a".into() }.into(),
         InstructionKind::CompareGE.into(),
-        InstructionKind::PopJumpIfTrue { label: if_then }.into(),
+        InstructionKind::PopJumpIfTrue { target: if_then }.into(),
         InstructionKind::Push { value: n() }.into(),
-        InstructionKind::JumpTo { label: if_end }.into(),
+        InstructionKind::JumpTo { target: if_end }.into(),
         InstructionKind::Label { number: if_then }.into(),
         InstructionKind::PushVar { scope: if_scope }.into(),
         InstructionKind::PopVar.into(),
-        InstructionKind::JumpTo { label: loop_end }.into(),
+        InstructionKind::JumpTo { target: loop_end }.into(),
         InstructionKind::PopVar.into(),
         InstructionKind::Label { number: if_end }.into(),
         InstructionKind::Pop.into(),
@@ -212,7 +287,7 @@ fn evaluate_loop_properly() {
         }
         .into(),
         InstructionKind::Pop.into(),
-        InstructionKind::JumpTo { label: loop_start }.into(),
+        InstructionKind::JumpTo { target: loop_start }.into(),
         InstructionKind::Label { number: loop_end }.into(),
         InstructionKind::PopVar.into(),
         InstructionKind::Push { value: n() }.into(),
@@ -298,8 +373,11 @@ fn evaluate_assignment_properly() {
                 InstructionKind::Push { value: i(1) }.into(),
                 InstructionKind::Load { ident: "a".into() }.into(),
                 InstructionKind::SetIndex.into(),
-                InstructionKind::Pop.into(),
-                InstructionKind::Load { ident: "a".into() }.into(),
+                InstructionKind::Store {
+                    ident: "a".into(),
+                    declaration: false
+                }
+                .into(),
             ],
             Rc::clone(&scope)
         )
@@ -372,8 +450,8 @@ fn evaluate_fn_call_properly() {
                         scope: gen_scope(1, par!(scope)),
                     }
                     .into(),
-                    InstructionKind::Load { ident: "b".into() }.into(),
-                    InstructionKind::Load { ident: "a".into() }.into(),
+                    InstructionKind::LoadLocal { depth: 0, slot: 1 }.into(),
+                    InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
                     InstructionKind::BinaryAdd.into(),
                     InstructionKind::PopVar.into(),
                 ],
@@ -391,14 +469,8 @@ fn evaluate_fn_call_properly() {
                         scope: gen_scope(1, par!(scope)),
                     }
                     .into(),
-                    InstructionKind::Load {
-                        ident: "other".into(),
-                    }
-                    .into(),
-                    InstructionKind::Load {
-                        ident: "self".into(),
-                    }
-                    .into(),
+                    InstructionKind::LoadLocal { depth: 0, slot: 1 }.into(),
+                    InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
                     InstructionKind::BinaryAdd.into(),
                     InstructionKind::PopVar.into(),
                 ],
@@ -439,6 +511,114 @@ fn evaluate_fn_call_properly() {
     );
 }
 
+#[test]
+fn evaluate_call_depth_is_limited() {
+    // fn f() { f() }
+    let scope = gen_scope(0, None);
+    scope
+        .declare(
+            "f".into(),
+            make_fn(
+                vec![],
+                vec![
+                    InstructionKind::PushVar {
+                        scope: gen_scope(1, par!(scope)),
+                    }
+                    .into(),
+                    InstructionKind::Load { ident: "f".into() }.into(),
+                    InstructionKind::CallFunction { num_args: 0 }.into(),
+                    InstructionKind::PopVar.into(),
+                ],
+            ),
+        )
+        .unwrap();
+
+    let bytecode = finalize_bytecode(vec![
+        InstructionKind::PushVar { scope }.into(),
+        InstructionKind::Load { ident: "f".into() }.into(),
+        InstructionKind::CallFunction { num_args: 0 }.into(),
+        InstructionKind::PopVar.into(),
+    ]);
+    let src = SourceText::new("");
+    let diagnostics = Diagnostics::new(&src).no_print();
+    Evaluator::evaluate_with_max_depth(&bytecode, &diagnostics, 10);
+    assert!(diagnostics.any());
+}
+
+#[test]
+fn evaluate_tail_call_properly() {
+    let scope = gen_scope(0, None);
+    scope
+        .declare(
+            "one".into(),
+            make_fn(
+                vec![],
+                vec![
+                    InstructionKind::PushVar {
+                        scope: gen_scope(1, par!(scope)),
+                    }
+                    .into(),
+                    InstructionKind::Push { value: i(1) }.into(),
+                    InstructionKind::PopVar.into(),
+                ],
+            ),
+        )
+        .unwrap();
+    let call_one = |call| {
+        make_fn(
+            vec![],
+            vec![
+                InstructionKind::PushVar {
+                    scope: gen_scope(1, par!(scope)),
+                }
+                .into(),
+                InstructionKind::Load {
+                    ident: "one".into(),
+                }
+                .into(),
+                call,
+                InstructionKind::PopVar.into(),
+            ],
+        )
+    };
+    scope
+        .declare(
+            "call".into(),
+            call_one(InstructionKind::CallFunction { num_args: 0 }.into()),
+        )
+        .unwrap();
+    scope
+        .declare(
+            "tail_call".into(),
+            call_one(InstructionKind::TailCallFunction { num_args: 0 }.into()),
+        )
+        .unwrap();
+
+    // With only one frame allowed, calling `one` from another function only works if the frame
+    // is reused
+    let src = SourceText::new("");
+    let eval_depth_1 = |ident: &str| {
+        let diagnostics = Diagnostics::new(&src).no_print();
+        let bytecode = finalize_bytecode(vec![
+            InstructionKind::PushVar {
+                scope: Rc::clone(&scope),
+            }
+            .into(),
+            InstructionKind::Load {
+                ident: ident.into(),
+            }
+            .into(),
+            InstructionKind::CallFunction { num_args: 0 }.into(),
+            InstructionKind::PopVar.into(),
+        ]);
+        let value = Evaluator::evaluate_with_max_depth(&bytecode, &diagnostics, 1);
+        (value, diagnostics.any())
+    };
+
+    assert_eq!(eval_depth_1("tail_call"), (i(1), false));
+    assert!(eval_depth_1("call").1);
+}
+
 #[test]
 fn evaluate_range_properly() {
     assert_eq!(
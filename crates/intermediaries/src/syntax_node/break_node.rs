@@ -1,24 +1,49 @@
+use super::SyntaxNode;
 use crossterm::style;
 use source::TextSpan;
 use std::fmt;
+use std::rc::Rc;
 
+/// `break <label> <value>`, where both the label and value are optional
 #[derive(Debug, Clone)]
 pub struct BreakNode {
     pub span: TextSpan,
+    pub label: Option<Rc<str>>,
+    pub value: Option<Box<SyntaxNode>>,
 }
 
 impl BreakNode {
     pub fn new(span: TextSpan) -> Self {
-        Self { span }
+        Self {
+            span,
+            label: None,
+            value: None,
+        }
     }
 
-    pub(super) fn _prt(&self, indent: String, is_last: bool, stdout: &mut std::io::Stdout) {
+    pub fn with_value(span: TextSpan, label: Option<Rc<str>>, value: Option<SyntaxNode>) -> Self {
+        Self {
+            span,
+            label,
+            value: value.map(Box::new),
+        }
+    }
+
+    pub(super) fn _prt(&self, mut indent: String, is_last: bool, stdout: &mut std::io::Stdout) {
         let _ = super::print_node(style::Color::Green, &indent, self, is_last, stdout);
+
+        if let Some(ref value) = self.value {
+            indent += if is_last { "   " } else { "│  " };
+            value._prt(indent, true, stdout);
+        }
     }
 }
 
 impl fmt::Display for BreakNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "BreakToken")
+        match self.label {
+            Some(ref label) => write!(f, "BreakToken {}", label),
+            None => write!(f, "BreakToken"),
+        }
     }
 }
@@ -0,0 +1,76 @@
+use super::{print_node, SyntaxNode};
+use crate::tokens::Token;
+use crossterm::style;
+use source::TextSpan;
+use std::rc::Rc;
+
+/// A list or object comprehension
+///
+/// `[<value> for <vars>... in <iter> if <cond>]`
+/// `{<key>: <value> for <vars>... in <iter> if <cond>}`
+///
+/// If there is a key, then it is an object comprehension, otherwise it is a list comprehension
+#[derive(Debug, Clone)]
+pub struct ComprehensionNode {
+    pub span: TextSpan,
+    pub key: Option<Box<SyntaxNode>>,
+    pub value: Box<SyntaxNode>,
+    pub vars: Vec<Rc<str>>,
+    pub iter: Box<SyntaxNode>,
+    pub cond: Option<Box<SyntaxNode>>,
+}
+
+impl ComprehensionNode {
+    pub fn new(
+        open_token: &Token,
+        key: Option<SyntaxNode>,
+        value: SyntaxNode,
+        vars: Vec<Rc<str>>,
+        iter: SyntaxNode,
+        cond: Option<SyntaxNode>,
+        close_token: &Token,
+    ) -> Self {
+        Self {
+            span: TextSpan::from_spans(&open_token.text_span, &close_token.text_span),
+            key: key.map(Box::new),
+            value: Box::new(value),
+            vars,
+            iter: Box::new(iter),
+            cond: cond.map(Box::new),
+        }
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub(super) fn _prt(&self, mut indent: String, is_last: bool, stdout: &mut std::io::Stdout) {
+        let _ = print_node(style::Color::Blue, &indent, self, is_last, stdout);
+
+        indent += if is_last { "   " } else { "│  " };
+
+        if let Some(ref key) = self.key {
+            key._prt(indent.clone(), false, stdout);
+        }
+        self.value._prt(indent.clone(), false, stdout);
+        match self.cond {
+            Some(ref cond) => {
+                self.iter._prt(indent.clone(), false, stdout);
+                cond._prt(indent, true, stdout);
+            }
+            None => self.iter._prt(indent, true, stdout),
+        }
+    }
+}
+
+use std::fmt;
+impl fmt::Display for ComprehensionNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}Comprehension for {}",
+            if self.is_object() { "Object" } else { "List" },
+            self.vars.join(", ")
+        )
+    }
+}
@@ -1,14 +1,18 @@
 use super::{print_node, SyntaxNode};
 use crate::tokens::Token;
 use crossterm::style;
+use enumflags2::BitFlags;
 use source::TextSpan;
 use std::rc::Rc;
+use vm::types::{ToString, Type};
 
 #[derive(Debug, Clone)]
 pub struct DeclarationNode {
     pub span: TextSpan,
     pub ident: Rc<str>,
     pub value: Box<SyntaxNode>,
+    /// The type given with `let <ident>: <type> = ...`, if any
+    pub annotation: Option<BitFlags<Type>>,
 }
 
 impl DeclarationNode {
@@ -17,11 +21,22 @@ impl DeclarationNode {
             ident,
             span: TextSpan::from_spans(&declaration_token.text_span, value.span()),
             value: Box::new(value),
+            annotation: None,
         }
     }
 
     pub fn from_span(ident: Rc<str>, value: Box<SyntaxNode>, span: TextSpan) -> Self {
-        Self { ident, value, span }
+        Self {
+            ident,
+            value,
+            span,
+            annotation: None,
+        }
+    }
+
+    pub fn with_annotation(mut self, annotation: Option<BitFlags<Type>>) -> Self {
+        self.annotation = annotation;
+        self
     }
 
     pub(super) fn _prt(&self, mut indent: String, is_last: bool, stdout: &mut std::io::Stdout) {
@@ -36,6 +51,10 @@ impl DeclarationNode {
 use std::fmt;
 impl fmt::Display for DeclarationNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DeclarationOperator: {}", self.ident)
+        write!(f, "DeclarationOperator: {}", self.ident)?;
+        if let Some(annotation) = self.annotation {
+            write!(f, ": {}", annotation.to_string())?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,37 @@
+use super::{print_node, SyntaxNode};
+use crate::tokens::Token;
+use crossterm::style;
+use source::TextSpan;
+use std::fmt;
+
+/// A statement which is run when the enclosing block is exited
+///
+/// `defer <stmt>`
+#[derive(Debug, Clone)]
+pub struct DeferNode {
+    pub span: TextSpan,
+    pub statement: Box<SyntaxNode>,
+}
+
+impl DeferNode {
+    pub fn new(defer_token: &Token, statement: SyntaxNode) -> Self {
+        Self {
+            span: TextSpan::from_spans(&defer_token.text_span, statement.span()),
+            statement: Box::new(statement),
+        }
+    }
+
+    pub(super) fn _prt(&self, mut indent: String, is_last: bool, stdout: &mut std::io::Stdout) {
+        let _ = print_node(style::Color::Green, &indent, self, is_last, stdout);
+
+        indent += if is_last { "   " } else { "│  " };
+
+        self.statement._prt(indent, true, stdout);
+    }
+}
+
+impl fmt::Display for DeferNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DeferToken")
+    }
+}
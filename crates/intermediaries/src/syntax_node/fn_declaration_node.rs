@@ -1,8 +1,10 @@
 use super::{print_node, BlockNode};
 use crate::tokens::Token;
 use crossterm::style;
+use enumflags2::BitFlags;
 use source::TextSpan;
 use std::rc::Rc;
+use vm::types::{ToString, Type};
 
 #[derive(Debug, Clone)]
 pub struct FnDeclarationNode {
@@ -10,6 +12,10 @@ pub struct FnDeclarationNode {
     pub ident: Option<Rc<str>>,
     pub args: Vec<Rc<str>>,
     pub block: BlockNode,
+    /// The type annotations for each of the args, in the same order as `args`
+    pub arg_annotations: Vec<Option<BitFlags<Type>>>,
+    /// The type given with `-> <type>`, if any
+    pub return_annotation: Option<BitFlags<Type>>,
 }
 
 impl FnDeclarationNode {
@@ -21,6 +27,8 @@ impl FnDeclarationNode {
     ) -> Self {
         Self {
             span: TextSpan::from_spans(&fn_token.text_span, &block.span),
+            arg_annotations: vec![None; args.len()],
+            return_annotation: None,
             ident,
             args,
             block,
@@ -34,6 +42,8 @@ impl FnDeclarationNode {
         span: TextSpan,
     ) -> Self {
         Self {
+            arg_annotations: vec![None; args.len()],
+            return_annotation: None,
             ident,
             args,
             block,
@@ -41,14 +51,28 @@ impl FnDeclarationNode {
         }
     }
 
+    pub fn with_annotations(
+        mut self,
+        arg_annotations: Vec<Option<BitFlags<Type>>>,
+        return_annotation: Option<BitFlags<Type>>,
+    ) -> Self {
+        debug_assert_eq!(self.args.len(), arg_annotations.len());
+        self.arg_annotations = arg_annotations;
+        self.return_annotation = return_annotation;
+        self
+    }
+
     pub(super) fn _prt(&self, mut indent: String, is_last: bool, stdout: &mut std::io::Stdout) {
         let _ = print_node(style::Color::Blue, &indent, self, is_last, stdout);
 
         indent += if is_last { "   " } else { "│  " };
 
         println!("{}├── [", indent);
-        for arg in self.args.iter() {
-            println!("{}│  {}", indent, arg);
+        for (arg, annotation) in self.args.iter().zip(self.arg_annotations.iter()) {
+            match annotation {
+                Some(annotation) => println!("{}│  {}: {}", indent, arg, annotation.to_string()),
+                None => println!("{}│  {}", indent, arg),
+            }
         }
         println!("{}│ ]", indent);
         self.block._prt(indent, true, stdout);
@@ -58,6 +82,10 @@ impl FnDeclarationNode {
 use std::fmt;
 impl fmt::Display for FnDeclarationNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "FnKeyword -> {:?}", self.ident)
+        write!(f, "FnKeyword -> {:?}", self.ident)?;
+        if let Some(return_annotation) = self.return_annotation {
+            write!(f, " -> {}", return_annotation.to_string())?;
+        }
+        Ok(())
     }
 }
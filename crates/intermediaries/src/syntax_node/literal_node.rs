@@ -1,8 +1,6 @@
 use crossterm::style;
 use source::{SourceText, TextSpan};
-use std::cell::RefCell;
-use std::rc::Rc;
-use vm::value::Value;
+use vm::value::{Str, Value};
 
 type Result<T> = std::result::Result<T, ()>;
 
@@ -11,10 +9,14 @@ pub trait Parse {
 }
 
 impl Parse for i64 {
+    /// Literals which are too big for an `i64` are parsed as big ints
     fn parse(src: &str) -> Result<Value> {
         match src.parse() {
             Ok(v) => Ok(Value::Int(v)),
-            Err(_) => Err(()),
+            Err(_) => match src.parse() {
+                Ok(v) => Ok(Value::from_big_int(v)),
+                Err(_) => Err(()),
+            },
         }
     }
 }
@@ -62,7 +64,7 @@ impl Parse for String {
             string.push(chr);
         }
 
-        Ok(Value::String(Rc::new(RefCell::new(string))))
+        Ok(Value::String(Str::intern(&string)))
     }
 }
 
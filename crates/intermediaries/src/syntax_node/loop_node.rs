@@ -2,10 +2,12 @@ use super::{print_node, BlockNode, BreakNode, IfNode, SyntaxNode, UnaryNode};
 use crate::tokens::{Token, TokenKind};
 use crossterm::style;
 use source::TextSpan;
+use std::rc::Rc;
 
 #[derive(Default, Debug, Clone)]
 pub struct LoopNode {
     pub span: TextSpan,
+    pub label: Option<Rc<str>>,
     pub block: Vec<SyntaxNode>,
 }
 
@@ -14,10 +16,18 @@ impl LoopNode {
         let (block_span, block) = block.consume();
         Self {
             span: TextSpan::from_spans(&token.text_span, &block_span),
+            label: None,
             block,
         }
     }
 
+    /// Labels the loop so that it can be broken out of from nested loops with `break <label>`
+    pub fn with_label(mut self, label_token: &Token, label: Rc<str>) -> Self {
+        self.span = TextSpan::from_spans(&label_token.text_span, &self.span);
+        self.label = Some(label);
+        self
+    }
+
     pub fn construct_while(token: &Token, cond: SyntaxNode, block: BlockNode) -> Self {
         let (block_span, mut block) = block.consume();
         let span = cond.span().clone();
@@ -43,6 +53,7 @@ impl LoopNode {
 
         Self {
             span: TextSpan::from_spans(&token.text_span, &block_span),
+            label: None,
             block,
         }
     }
@@ -61,6 +72,9 @@ impl LoopNode {
 use std::fmt;
 impl fmt::Display for LoopNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "LoopStatement")
+        match self.label {
+            Some(ref label) => write!(f, "LoopStatement {}", label),
+            None => write!(f, "LoopStatement"),
+        }
     }
 }
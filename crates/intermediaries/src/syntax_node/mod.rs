@@ -2,7 +2,9 @@ mod assignment_node;
 mod binary_node;
 mod block_node;
 mod break_node;
+mod comprehension_node;
 mod declaration_node;
+mod defer_node;
 mod fn_call_node;
 mod fn_declaration_node;
 mod if_node;
@@ -27,7 +29,9 @@ pub mod node {
     pub use super::binary_node::BinaryNode;
     pub use super::block_node::BlockNode;
     pub use super::break_node::BreakNode;
+    pub use super::comprehension_node::ComprehensionNode;
     pub use super::declaration_node::DeclarationNode;
+    pub use super::defer_node::DeferNode;
     pub use super::fn_call_node::FnCallNode;
     pub use super::fn_declaration_node::FnDeclarationNode;
     pub use super::if_node::IfNode;
@@ -71,7 +75,9 @@ pub enum SyntaxNode {
     BinaryNode(BinaryNode),
     BlockNode(BlockNode),
     BreakNode(BreakNode),
+    ComprehensionNode(ComprehensionNode),
     DeclarationNode(DeclarationNode),
+    DeferNode(DeferNode),
     FnCallNode(FnCallNode),
     FnDeclarationNode(FnDeclarationNode),
     IfNode(IfNode),
@@ -95,7 +101,9 @@ impl fmt::Display for SyntaxNode {
             SyntaxNode::BinaryNode(ref n) => write!(f, "{}", n),
             SyntaxNode::BlockNode(ref n) => write!(f, "{}", n),
             SyntaxNode::BreakNode(ref n) => write!(f, "{}", n),
+            SyntaxNode::ComprehensionNode(ref n) => write!(f, "{}", n),
             SyntaxNode::DeclarationNode(ref n) => write!(f, "{}", n),
+            SyntaxNode::DeferNode(ref n) => write!(f, "{}", n),
             SyntaxNode::FnCallNode(ref n) => write!(f, "{}", n),
             SyntaxNode::FnDeclarationNode(ref n) => write!(f, "{}", n),
             SyntaxNode::IfNode(ref n) => write!(f, "{}", n),
@@ -120,7 +128,9 @@ impl SyntaxNode {
             SyntaxNode::BinaryNode(ref n) => &n.span,
             SyntaxNode::BlockNode(ref n) => &n.span,
             SyntaxNode::BreakNode(ref n) => &n.span,
+            SyntaxNode::ComprehensionNode(ref n) => &n.span,
             SyntaxNode::DeclarationNode(ref n) => &n.span,
+            SyntaxNode::DeferNode(ref n) => &n.span,
             SyntaxNode::FnCallNode(ref n) => &n.span,
             SyntaxNode::FnDeclarationNode(ref n) => &n.span,
             SyntaxNode::IfNode(ref n) => &n.span,
@@ -148,7 +158,9 @@ impl SyntaxNode {
             SyntaxNode::BinaryNode(ref n) => n._prt(indent, is_last, stdout),
             SyntaxNode::BlockNode(ref n) => n._prt(indent, is_last, stdout),
             SyntaxNode::BreakNode(ref n) => n._prt(indent, is_last, stdout),
+            SyntaxNode::ComprehensionNode(ref n) => n._prt(indent, is_last, stdout),
             SyntaxNode::DeclarationNode(ref n) => n._prt(indent, is_last, stdout),
+            SyntaxNode::DeferNode(ref n) => n._prt(indent, is_last, stdout),
             SyntaxNode::FnCallNode(ref n) => n._prt(indent, is_last, stdout),
             SyntaxNode::FnDeclarationNode(ref n) => n._prt(indent, is_last, stdout),
             SyntaxNode::IfNode(ref n) => n._prt(indent, is_last, stdout),
@@ -188,7 +200,9 @@ impl SyntaxNode {
 
             SyntaxNode::AssignmentNode(_) => false,
             SyntaxNode::BreakNode(_) => false,
+            SyntaxNode::ComprehensionNode(_) => false,
             SyntaxNode::DeclarationNode(_) => false,
+            SyntaxNode::DeferNode(_) => false,
             SyntaxNode::FnDeclarationNode(_) => false,
             SyntaxNode::FnCallNode(_) => false,
             SyntaxNode::InterfaceNode(_) => false,
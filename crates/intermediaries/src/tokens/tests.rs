@@ -8,15 +8,16 @@ use TokenKind::*;
 
 #[test]
 fn correct_unary_precedence() {
-    assert_eq!(t(NotOperator).unary_precedence(), 8);
-    assert_eq!(t(PlusOperator).unary_precedence(), 8);
-    assert_eq!(t(MinusOperator).unary_precedence(), 8);
+    assert_eq!(t(NotOperator).unary_precedence(), 9);
+    assert_eq!(t(PlusOperator).unary_precedence(), 9);
+    assert_eq!(t(MinusOperator).unary_precedence(), 9);
 
     assert_eq!(t(Whitespace).unary_precedence(), 0);
     assert_eq!(t(Number).unary_precedence(), 0);
     assert_eq!(t(Boolean).unary_precedence(), 0);
     assert_eq!(t(String).unary_precedence(), 0);
     assert_eq!(t(Ident).unary_precedence(), 0);
+    assert_eq!(t(Label).unary_precedence(), 0);
     assert_eq!(t(EOF).unary_precedence(), 0);
     assert_eq!(t(Comment).unary_precedence(), 0);
     assert_eq!(t(DotOperator).unary_precedence(), 0);
@@ -24,6 +25,9 @@ fn correct_unary_precedence() {
     assert_eq!(t(CommaOperator).unary_precedence(), 0);
     assert_eq!(t(ColonOperator).unary_precedence(), 0);
     assert_eq!(t(ColonColonOperator).unary_precedence(), 0);
+    assert_eq!(t(PipeOperator).unary_precedence(), 0);
+    assert_eq!(t(UnionOperator).unary_precedence(), 0);
+    assert_eq!(t(ArrowOperator).unary_precedence(), 0);
     assert_eq!(t(AssignmentOperator).unary_precedence(), 0);
     assert_eq!(t(StarOperator).unary_precedence(), 0);
     assert_eq!(t(SlashOperator).unary_precedence(), 0);
@@ -52,27 +56,34 @@ fn correct_unary_precedence() {
     assert_eq!(t(LetKeyword).unary_precedence(), 0);
     assert_eq!(t(FnKeyword).unary_precedence(), 0);
     assert_eq!(t(InterfaceKeyword).unary_precedence(), 0);
+    assert_eq!(t(ForKeyword).unary_precedence(), 0);
+    assert_eq!(t(InKeyword).unary_precedence(), 0);
+    assert_eq!(t(DeferKeyword).unary_precedence(), 0);
     assert_eq!(t(Bad).unary_precedence(), 0);
 }
 
 #[test]
 fn correct_binary_precedence() {
-    assert_eq!(t(CaretOperator).binary_precedence(), 7);
-    assert_eq!(t(ModOperator).binary_precedence(), 6);
-    assert_eq!(t(StarOperator).binary_precedence(), 5);
-    assert_eq!(t(SlashOperator).binary_precedence(), 5);
-    assert_eq!(t(PlusOperator).binary_precedence(), 4);
-    assert_eq!(t(MinusOperator).binary_precedence(), 4);
+    assert_eq!(t(CaretOperator).binary_precedence(), 8);
+    assert_eq!(t(ModOperator).binary_precedence(), 7);
+    assert_eq!(t(StarOperator).binary_precedence(), 6);
+    assert_eq!(t(SlashOperator).binary_precedence(), 6);
+    assert_eq!(t(PlusOperator).binary_precedence(), 5);
+    assert_eq!(t(MinusOperator).binary_precedence(), 5);
 
-    assert_eq!(t(NEOperator).binary_precedence(), 3);
-    assert_eq!(t(EqOperator).binary_precedence(), 3);
-    assert_eq!(t(LTOperator).binary_precedence(), 3);
-    assert_eq!(t(GTOperator).binary_precedence(), 3);
-    assert_eq!(t(LEOperator).binary_precedence(), 3);
-    assert_eq!(t(GEOperator).binary_precedence(), 3);
+    assert_eq!(t(NEOperator).binary_precedence(), 4);
+    assert_eq!(t(EqOperator).binary_precedence(), 4);
+    assert_eq!(t(LTOperator).binary_precedence(), 4);
+    assert_eq!(t(GTOperator).binary_precedence(), 4);
+    assert_eq!(t(LEOperator).binary_precedence(), 4);
+    assert_eq!(t(GEOperator).binary_precedence(), 4);
+    assert_eq!(t(InKeyword).binary_precedence(), 4);
 
-    assert_eq!(t(AndOperator).binary_precedence(), 2);
-    assert_eq!(t(OrOperator).binary_precedence(), 1);
+    assert_eq!(t(AndOperator).binary_precedence(), 3);
+    assert_eq!(t(OrOperator).binary_precedence(), 2);
+    assert_eq!(t(PipeOperator).binary_precedence(), 1);
+    assert_eq!(t(UnionOperator).binary_precedence(), 0);
+    assert_eq!(t(ArrowOperator).binary_precedence(), 0);
 
     assert_eq!(t(NotOperator).binary_precedence(), 0);
     assert_eq!(t(Whitespace).binary_precedence(), 0);
@@ -80,6 +91,7 @@ fn correct_binary_precedence() {
     assert_eq!(t(Boolean).binary_precedence(), 0);
     assert_eq!(t(String).binary_precedence(), 0);
     assert_eq!(t(Ident).binary_precedence(), 0);
+    assert_eq!(t(Label).binary_precedence(), 0);
     assert_eq!(t(EOF).binary_precedence(), 0);
     assert_eq!(t(Comment).binary_precedence(), 0);
     assert_eq!(t(DotOperator).binary_precedence(), 0);
@@ -103,6 +115,8 @@ fn correct_binary_precedence() {
     assert_eq!(t(LetKeyword).binary_precedence(), 0);
     assert_eq!(t(FnKeyword).binary_precedence(), 0);
     assert_eq!(t(InterfaceKeyword).binary_precedence(), 0);
+    assert_eq!(t(ForKeyword).binary_precedence(), 0);
+    assert_eq!(t(DeferKeyword).binary_precedence(), 0);
     assert_eq!(t(Bad).binary_precedence(), 0);
 }
 
@@ -129,6 +143,7 @@ fn is_calc_assign() {
     assert_eq!(t(Boolean).is_calc_assign(), false);
     assert_eq!(t(String).is_calc_assign(), false);
     assert_eq!(t(Ident).is_calc_assign(), false);
+    assert_eq!(t(Label).is_calc_assign(), false);
     assert_eq!(t(EOF).is_calc_assign(), false);
     assert_eq!(t(Comment).is_calc_assign(), false);
     assert_eq!(t(DotOperator).is_calc_assign(), false);
@@ -136,6 +151,9 @@ fn is_calc_assign() {
     assert_eq!(t(CommaOperator).is_calc_assign(), false);
     assert_eq!(t(ColonOperator).is_calc_assign(), false);
     assert_eq!(t(ColonColonOperator).is_calc_assign(), false);
+    assert_eq!(t(PipeOperator).is_calc_assign(), false);
+    assert_eq!(t(UnionOperator).is_calc_assign(), false);
+    assert_eq!(t(ArrowOperator).is_calc_assign(), false);
     assert_eq!(t(AssignmentOperator).is_calc_assign(), false);
     assert_eq!(t(OpenParan).is_calc_assign(), false);
     assert_eq!(t(CloseParan).is_calc_assign(), false);
@@ -152,5 +170,8 @@ fn is_calc_assign() {
     assert_eq!(t(LetKeyword).is_calc_assign(), false);
     assert_eq!(t(FnKeyword).is_calc_assign(), false);
     assert_eq!(t(InterfaceKeyword).is_calc_assign(), false);
+    assert_eq!(t(ForKeyword).is_calc_assign(), false);
+    assert_eq!(t(InKeyword).is_calc_assign(), false);
+    assert_eq!(t(DeferKeyword).is_calc_assign(), false);
     assert_eq!(t(Bad).is_calc_assign(), false);
 }
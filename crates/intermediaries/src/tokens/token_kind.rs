@@ -8,6 +8,7 @@ pub enum TokenKind {
     Boolean,    // 'true' | 'false'
     String,     // '"string"'
     Ident,      // A variable, function name etc.
+    Label,      // A loop label - `'outer`
     EOF,        // \0
     Comment,    // A comment - Either single-line(`// comment`) or multi-line(`/* comment */`)
 
@@ -17,6 +18,9 @@ pub enum TokenKind {
     AssignmentOperator, // '='
     ColonOperator,      // ':'
     ColonColonOperator, // '::'
+    PipeOperator,       // '|>'
+    UnionOperator,      // '|'
+    ArrowOperator,      // '->'
 
     // Arithmetic operators
     PlusOperator,  // '+'
@@ -55,6 +59,9 @@ pub enum TokenKind {
     LetKeyword,       // 'let'
     FnKeyword,        // 'fn'
     InterfaceKeyword, // `interface`
+    ForKeyword,       // 'for'
+    InKeyword,        // 'in'
+    DeferKeyword,     // 'defer'
 
     // Unrecognised
     Bad,
@@ -65,20 +72,22 @@ use TokenKind::*;
 impl TokenKind {
     pub fn unary_precedence(&self) -> u8 {
         match self {
-            NotOperator | PlusOperator | MinusOperator => 8,
+            NotOperator | PlusOperator | MinusOperator => 9,
             _ => 0,
         }
     }
 
     pub fn binary_precedence(&self) -> u8 {
         match self {
-            CaretOperator => 7,
-            ModOperator => 6,
-            StarOperator | SlashOperator => 5,
-            PlusOperator | MinusOperator => 4,
-            EqOperator | NEOperator | LTOperator | GTOperator | LEOperator | GEOperator => 3,
-            AndOperator => 2,
-            OrOperator => 1,
+            CaretOperator => 8,
+            ModOperator => 7,
+            StarOperator | SlashOperator => 6,
+            PlusOperator | MinusOperator => 5,
+            EqOperator | NEOperator | LTOperator | GTOperator | LEOperator | GEOperator
+            | InKeyword => 4,
+            AndOperator => 3,
+            OrOperator => 2,
+            PipeOperator => 1,
             _ => 0,
         }
     }
@@ -97,7 +97,14 @@ impl<'diagnostics, 'src, T: TextBase> Lexer<'diagnostics, 'src, T> {
                         }
                     }
 
-                    '-' => self.add(TokenKind::MinusOperator, i, 1),
+                    '-' => {
+                        if let Some((_, '>')) = self.chars.peek() {
+                            self.add(TokenKind::ArrowOperator, i, 2);
+                            self.chars.next();
+                        } else {
+                            self.add(TokenKind::MinusOperator, i, 1);
+                        }
+                    }
                     '+' => self.add(TokenKind::PlusOperator, i, 1),
                     '*' => self.add(TokenKind::StarOperator, i, 1),
                     '/' => match self.chars.peek() {
@@ -120,8 +127,11 @@ impl<'diagnostics, 'src, T: TextBase> Lexer<'diagnostics, 'src, T> {
                         if let Some((_, '|')) = self.chars.peek() {
                             self.add(TokenKind::OrOperator, i, 2);
                             self.chars.next();
+                        } else if let Some((_, '>')) = self.chars.peek() {
+                            self.add(TokenKind::PipeOperator, i, 2);
+                            self.chars.next();
                         } else {
-                            self.add(TokenKind::Bad, i, 1);
+                            self.add(TokenKind::UnionOperator, i, 1);
                         }
                     }
                     '&' => {
@@ -149,6 +159,7 @@ impl<'diagnostics, 'src, T: TextBase> Lexer<'diagnostics, 'src, T> {
                         }
                     }
 
+                    '\'' if self.is_label(i) => self.lex_label(i),
                     '\'' | '"' => self.lex_string(i, chr),
 
                     '[' => self.add(TokenKind::OpenBracket, i, 1),
@@ -225,6 +236,9 @@ impl<'diagnostics, 'src, T: TextBase> Lexer<'diagnostics, 'src, T> {
                 "let" => TokenKind::LetKeyword,
                 "fn" => TokenKind::FnKeyword,
                 "interface" => TokenKind::InterfaceKeyword,
+                "for" => TokenKind::ForKeyword,
+                "in" => TokenKind::InKeyword,
+                "defer" => TokenKind::DeferKeyword,
                 _ => TokenKind::Ident,
             },
             start,
@@ -232,6 +246,102 @@ impl<'diagnostics, 'src, T: TextBase> Lexer<'diagnostics, 'src, T> {
         );
     }
 
+    /// A `'` followed by an identifier starts a label instead of a string, if it is directly after a
+    /// `break`, or if it starts an expression and is followed by a `:` and a loop, for example
+    /// `let v = 'outer: loop`. An expression can't start right after a value, like an ident or a
+    /// `)` on the same line.
+    ///
+    /// A string which is closed on the same line, like `'hello world'` or `'note: while'`, isn't a
+    /// label, since its closing `'` would start a string which isn't closed instead.
+    ///
+    /// NOTE this is called after the `'` at `start` has been consumed
+    fn is_label(&self, start: usize) -> bool {
+        let mut chars = self.chars.clone();
+        if !matches!(chars.next(), Some((_, c)) if c.is_alphabetic()) {
+            return false;
+        }
+
+        while matches!(chars.peek(), Some((_, c)) if c.is_alphanumeric() || *c == '_') {
+            chars.next();
+        }
+        let after_ident = chars.clone();
+
+        let prev = self
+            .tokens
+            .iter()
+            .rev()
+            .find(|t| t.kind != TokenKind::Whitespace && t.kind != TokenKind::Comment);
+        let after_break = prev.is_some_and(|t| t.kind == TokenKind::BreakKeyword);
+        let starts_expression = match prev {
+            None => true,
+            Some(t) if self.src.lineno(t.text_span.end() - 1) != self.src.lineno(start) => true,
+            Some(t) => !matches!(
+                t.kind,
+                TokenKind::Number
+                    | TokenKind::Boolean
+                    | TokenKind::String
+                    | TokenKind::Ident
+                    | TokenKind::Label
+                    | TokenKind::CloseParan
+                    | TokenKind::CloseBrace
+                    | TokenKind::CloseBracket
+                    | TokenKind::DotOperator
+                    | TokenKind::ColonColonOperator
+            ),
+        };
+
+        let is_label = match chars.next() {
+            // 'label' is a string
+            Some((_, '\'')) => false,
+            _ if after_break => true,
+            Some((_, ':')) if starts_expression => {
+                let mut next = chars.next();
+                while let Some((_, c)) = next {
+                    if !c.is_whitespace() {
+                        break;
+                    }
+                    next = chars.next();
+                }
+
+                let mut keyword = String::new();
+                while let Some((_, c)) = next {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    keyword.push(c);
+                    next = chars.next();
+                }
+
+                keyword == "loop" || keyword == "while"
+            }
+            _ => false,
+        };
+
+        is_label
+            && (closes_on_line(after_ident, None)
+                || !closes_on_line(self.chars.clone(), Some('\'')))
+    }
+
+    fn lex_label(&mut self, start: usize) {
+        let mut e;
+        loop {
+            if let Some((i, chr)) = self.chars.peek() {
+                e = *i;
+
+                if !chr.is_alphanumeric() && *chr != '_' {
+                    break;
+                } else {
+                    self.chars.next();
+                }
+            } else {
+                e = self.src.len();
+                break;
+            }
+        }
+
+        self.add(TokenKind::Label, start, e - start);
+    }
+
     fn lex_number(&mut self, start: usize) {
         let mut e;
         loop {
@@ -322,3 +432,66 @@ impl<'diagnostics, 'src, T: TextBase> Lexer<'diagnostics, 'src, T> {
         self.add(TokenKind::Comment, start, e - start);
     }
 }
+
+/// Whether every string which is started in the rest of the line is also closed on it, where `open`
+/// is the delimiter of a string which has already been started. A `'` followed by an identifier is
+/// skipped as a label if it is after a `break` or followed by a `:`, which is only a rough version of
+/// `Lexer::is_label`, since its lookahead has to stop somewhere.
+fn closes_on_line(
+    chars: impl Iterator<Item = (usize, char)> + Clone,
+    mut open: Option<char>,
+) -> bool {
+    let mut chars = chars.peekable();
+    let mut is_escaped = false;
+    let mut word = String::new();
+    let mut prev_word = String::new();
+
+    while let Some((_, c)) = chars.next() {
+        if c == '\n' {
+            break;
+        }
+
+        if let Some(delim) = open {
+            if is_escaped {
+                is_escaped = false;
+            } else if c == '\\' {
+                is_escaped = true;
+            } else if c == delim {
+                open = None;
+            }
+            continue;
+        }
+
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            prev_word = std::mem::take(&mut word);
+        }
+
+        match c {
+            '\'' if matches!(chars.peek(), Some((_, c)) if c.is_alphabetic()) => {
+                let mut label = chars.clone();
+                while matches!(label.peek(), Some((_, c)) if c.is_alphanumeric() || *c == '_') {
+                    label.next();
+                }
+                if prev_word == "break" || matches!(label.peek(), Some((_, ':'))) {
+                    chars = label;
+                } else {
+                    open = Some(c);
+                }
+            }
+            '\'' | '"' => open = Some(c),
+            // Escapes are only valid in strings
+            '\\' => return false,
+            '/' if matches!(chars.peek(), Some((_, '/'))) => return true,
+            _ => {}
+        }
+        if !c.is_whitespace() {
+            prev_word.clear();
+        }
+    }
+
+    open.is_none()
+}
@@ -67,6 +67,9 @@ fn lexes_kind_properly() {
     assert_eq!(lex_one(",").kind, TokenKind::CommaOperator);
     assert_eq!(lex_one(":").kind, TokenKind::ColonOperator);
     assert_eq!(lex_one("::").kind, TokenKind::ColonColonOperator);
+    assert_eq!(lex_one("|>").kind, TokenKind::PipeOperator);
+    assert_eq!(lex_one("|").kind, TokenKind::UnionOperator);
+    assert_eq!(lex_one("->").kind, TokenKind::ArrowOperator);
     assert_eq!(lex_one("=").kind, TokenKind::AssignmentOperator);
     assert_eq!(lex_one("+").kind, TokenKind::PlusOperator);
     assert_eq!(lex_one("-").kind, TokenKind::MinusOperator);
@@ -100,10 +103,160 @@ fn lexes_kind_properly() {
     assert_eq!(lex_one("let").kind, TokenKind::LetKeyword);
     assert_eq!(lex_one("fn").kind, TokenKind::FnKeyword);
     assert_eq!(lex_one("interface").kind, TokenKind::InterfaceKeyword);
+    assert_eq!(lex_one("for").kind, TokenKind::ForKeyword);
+    assert_eq!(lex_one("in").kind, TokenKind::InKeyword);
+    assert_eq!(lex_one("defer").kind, TokenKind::DeferKeyword);
 
     assert_eq!(lex_one(";").kind, TokenKind::Bad);
 }
 
+#[test]
+fn lexes_labels() {
+    let kinds = |text| {
+        lex(text)
+            .into_iter()
+            .map(|t| t.kind)
+            .filter(|k| *k != TokenKind::Whitespace)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        kinds("'outer: loop"),
+        vec![
+            TokenKind::Label,
+            TokenKind::ColonOperator,
+            TokenKind::LoopKeyword,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("'outer:while"),
+        vec![
+            TokenKind::Label,
+            TokenKind::ColonOperator,
+            TokenKind::WhileKeyword,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("break 'outer"),
+        vec![TokenKind::BreakKeyword, TokenKind::Label, TokenKind::EOF]
+    );
+    assert_eq!(
+        kinds("break /* comment */ 'outer }"),
+        vec![
+            TokenKind::BreakKeyword,
+            TokenKind::Comment,
+            TokenKind::Label,
+            TokenKind::CloseBrace,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(lex("break 'outer")[2], Token::new(TokenKind::Label, 6, 6));
+
+    // Strings
+    assert_eq!(
+        kinds("break 'outer'"),
+        vec![TokenKind::BreakKeyword, TokenKind::String, TokenKind::EOF]
+    );
+    assert_eq!(
+        kinds("break '1 2'"),
+        vec![TokenKind::BreakKeyword, TokenKind::String, TokenKind::EOF]
+    );
+    assert_eq!(lex_one("'key: value'").kind, TokenKind::String);
+    assert_eq!(lex_one("'key: loops'").kind, TokenKind::String);
+    assert_eq!(lex_one("'outer loop'").kind, TokenKind::String);
+    // Strings which are closed on the same line aren't labels
+    assert_eq!(
+        kinds("break 'hello world'"),
+        vec![TokenKind::BreakKeyword, TokenKind::String, TokenKind::EOF]
+    );
+    assert_eq!(
+        kinds("break 'it\\'s'"),
+        vec![TokenKind::BreakKeyword, TokenKind::String, TokenKind::EOF]
+    );
+    assert_eq!(
+        kinds("let s = 'note: while'"),
+        vec![
+            TokenKind::LetKeyword,
+            TokenKind::Ident,
+            TokenKind::AssignmentOperator,
+            TokenKind::String,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("print('a: loop')"),
+        vec![
+            TokenKind::Ident,
+            TokenKind::OpenParan,
+            TokenKind::String,
+            TokenKind::CloseParan,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("{ 'a: loop"),
+        vec![
+            TokenKind::OpenBrace,
+            TokenKind::Label,
+            TokenKind::ColonOperator,
+            TokenKind::LoopKeyword,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("break 'outer 'x'"),
+        vec![
+            TokenKind::BreakKeyword,
+            TokenKind::Label,
+            TokenKind::String,
+            TokenKind::EOF
+        ]
+    );
+    // Labels can start any expression
+    assert_eq!(
+        kinds("let v = 'outer: loop { break 'outer 5 }"),
+        vec![
+            TokenKind::LetKeyword,
+            TokenKind::Ident,
+            TokenKind::AssignmentOperator,
+            TokenKind::Label,
+            TokenKind::ColonOperator,
+            TokenKind::LoopKeyword,
+            TokenKind::OpenBrace,
+            TokenKind::BreakKeyword,
+            TokenKind::Label,
+            TokenKind::Number,
+            TokenKind::CloseBrace,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("f(1, 'a: while"),
+        vec![
+            TokenKind::Ident,
+            TokenKind::OpenParan,
+            TokenKind::Number,
+            TokenKind::CommaOperator,
+            TokenKind::Label,
+            TokenKind::ColonOperator,
+            TokenKind::WhileKeyword,
+            TokenKind::EOF
+        ]
+    );
+    assert_eq!(
+        kinds("x\n'a: loop"),
+        vec![
+            TokenKind::Ident,
+            TokenKind::Label,
+            TokenKind::ColonOperator,
+            TokenKind::LoopKeyword,
+            TokenKind::EOF
+        ]
+    );
+}
+
 #[test]
 fn ignores_singleline_comment() {
     let mut tokens = lex("1 + 2// + 3").into_iter();
@@ -1,7 +1,6 @@
 use diagnostics::Diagnostics;
 use intermediaries::{node, SyntaxNode, TokenKind};
-use std::collections::HashMap;
-use vm::value::ErrorKind;
+use vm::value::{ErrorKind, Object};
 use vm::{Type, Value};
 
 /// An evaluator used to optimize constant expressions to a single value.
@@ -60,6 +59,8 @@ impl<'diagnostics, 'src> ConstEvaluator<'diagnostics, 'src> {
             TokenKind::GTOperator => left.gt(right),
             TokenKind::LEOperator => left.le(right),
             TokenKind::GEOperator => left.ge(right),
+
+            TokenKind::InKeyword => right.contains(left),
             _ => unreachable!(),
         };
 
@@ -115,16 +116,16 @@ impl<'diagnostics, 'src> ConstEvaluator<'diagnostics, 'src> {
         for e in node.elements {
             list.push(self.evaluate_node(e));
         }
-        Value::List(std::rc::Rc::new(std::cell::RefCell::new(list)))
+        Value::new_list(list)
     }
 
-    fn evaluate_object(&self, mut node: node::ObjectNode) -> Value {
-        let len = node.elements.len() / 2;
-        let mut map = HashMap::with_capacity(len);
+    fn evaluate_object(&self, node: node::ObjectNode) -> Value {
+        let mut object = Object::new();
 
-        for _ in 0..len {
-            let v = self.evaluate_node(node.elements.pop().unwrap());
-            let k_node = node.elements.pop().unwrap();
+        // The keys are added in the order they are written in, the same as when evaluating
+        let mut elements = node.elements.into_iter();
+        while let (Some(k_node), Some(v_node)) = (elements.next(), elements.next()) {
+            let v = self.evaluate_node(v_node);
             let k_span = k_node.span().clone();
             let k = self.evaluate_node(k_node);
             if k.type_() != Type::String {
@@ -136,11 +137,11 @@ impl<'diagnostics, 'src> ConstEvaluator<'diagnostics, 'src> {
                     k_span,
                 );
             } else {
-                map.insert(k.into_str(), v);
+                object.insert(k.to_ref_str(), v);
             }
         }
 
-        Value::Object(std::rc::Rc::new(std::cell::RefCell::new(map)))
+        Value::new_object(object)
     }
 
     fn evaluate_unary(&self, node: node::UnaryNode) -> Value {
@@ -599,6 +600,23 @@ mod tests {
             })),
             b(false),
         );
+
+        // Ints are promoted to big ints instead of overflowing
+        assert_eq!(
+            eval(SyntaxNode::BinaryNode(node::BinaryNode {
+                operator: TokenKind::StarOperator,
+                span: span(),
+                left: Box::new(SyntaxNode::LiteralNode(node::LiteralNode {
+                    value: i(i64::MAX),
+                    span: span(),
+                })),
+                right: Box::new(SyntaxNode::LiteralNode(node::LiteralNode {
+                    value: i(4),
+                    span: span(),
+                })),
+            })),
+            big("36893488147419103228"),
+        );
     }
 
     #[test]
@@ -0,0 +1,131 @@
+use super::{ConstEvaluator, Lowerer};
+use intermediaries::{node, SyntaxNode};
+use std::rc::Rc;
+use vm::Value;
+
+impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
+    /// Like [`SyntaxNode::can_const_eval`], except that variables whose value is known while
+    /// lowering are treated as constants as well.
+    pub(super) fn can_const_eval(&self, node: &SyntaxNode) -> bool {
+        match node {
+            SyntaxNode::VariableNode(variable) => self.constant(&variable.ident).is_some(),
+            SyntaxNode::BinaryNode(node) => {
+                self.can_const_eval(&node.left) && self.can_const_eval(&node.right)
+            }
+            SyntaxNode::UnaryNode(node) => self.can_const_eval(&node.child),
+            SyntaxNode::IndexNode(node) => {
+                self.can_const_eval(&node.child) && self.can_const_eval(&node.index)
+            }
+            node => node.can_const_eval(),
+        }
+    }
+
+    /// Evaluates a node which [`can_const_eval`](Self::can_const_eval).
+    pub(super) fn const_eval(&mut self, node: SyntaxNode) -> Value {
+        let node = self.substitute_constants(node);
+        ConstEvaluator::evaluate(node, self.diagnostics)
+    }
+
+    /// The value of a node which [`can_const_eval`](Self::can_const_eval), if it can be pushed as
+    /// a constant. A list or object is created every time the node is run, and can be changed
+    /// afterwards, so it can't be shared by pushing the same value every time.
+    pub(super) fn const_value(&mut self, node: &SyntaxNode) -> Option<Value> {
+        let value = self.const_eval(node.clone());
+        if is_immutable(&value) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Remembers the value of a variable which has just been declared, if it can never change.
+    /// Lists and objects aren't remembered, since they can be changed through another variable
+    /// which refers to the same value, unlike strings which can't be changed at all.
+    pub(super) fn record_constant(&mut self, ident: &Rc<str>, value: Value) {
+        if !is_immutable(&value) {
+            return;
+        }
+
+        let local = match self.locate(ident) {
+            Some(local) if local.depth == 0 => local,
+            _ => return,
+        };
+        let is_assigned = self
+            .assigned
+            .get(&local.scope_id)
+            .is_none_or(|assigned| assigned.contains(ident));
+        if !is_assigned {
+            self.constants.insert((local.scope_id, local.slot), value);
+        }
+    }
+
+    fn constant(&self, ident: &str) -> Option<&Value> {
+        let local = self.locate(ident)?;
+        self.constants.get(&(local.scope_id, local.slot))
+    }
+
+    /// Replaces the variables with constant values in the parts of the node which are evaluated
+    /// while lowering.
+    fn substitute_constants(&mut self, node: SyntaxNode) -> SyntaxNode {
+        match node {
+            SyntaxNode::VariableNode(variable) => {
+                let local = self.locate(&variable.ident);
+                let value =
+                    local.and_then(|local| self.constants.get(&(local.scope_id, local.slot)));
+                match value {
+                    Some(value) => {
+                        let value = value.clone();
+                        // The value is only the same at a call site the function is inlined into,
+                        // if the variable is too
+                        self.note_free_var(&variable.ident, local.map(|local| local.scope_id));
+                        SyntaxNode::LiteralNode(node::LiteralNode::from_val(value, variable.span))
+                    }
+                    None => SyntaxNode::VariableNode(variable),
+                }
+            }
+            SyntaxNode::BinaryNode(mut node) => {
+                node.left = Box::new(self.substitute_constants(*node.left));
+                node.right = Box::new(self.substitute_constants(*node.right));
+                SyntaxNode::BinaryNode(node)
+            }
+            SyntaxNode::UnaryNode(mut node) => {
+                node.child = Box::new(self.substitute_constants(*node.child));
+                SyntaxNode::UnaryNode(node)
+            }
+            SyntaxNode::IndexNode(mut node) => {
+                node.child = Box::new(self.substitute_constants(*node.child));
+                node.index = Box::new(self.substitute_constants(*node.index));
+                SyntaxNode::IndexNode(node)
+            }
+            SyntaxNode::ListNode(mut node) => {
+                node.elements = self.substitute_all(node.elements);
+                SyntaxNode::ListNode(node)
+            }
+            SyntaxNode::ObjectNode(mut node) => {
+                node.elements = self.substitute_all(node.elements);
+                SyntaxNode::ObjectNode(node)
+            }
+            node => node,
+        }
+    }
+
+    fn substitute_all(&mut self, nodes: Vec<SyntaxNode>) -> Vec<SyntaxNode> {
+        nodes
+            .into_iter()
+            .map(|node| self.substitute_constants(node))
+            .collect()
+    }
+}
+
+fn is_immutable(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Int(_)
+            | Value::BigInt(_)
+            | Value::Float(_)
+            | Value::Bool(_)
+            | Value::Null
+            | Value::Range(..)
+            | Value::String(_)
+    )
+}
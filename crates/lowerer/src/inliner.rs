@@ -0,0 +1,205 @@
+use super::{Lowerer, OptLevel};
+use intermediaries::{node, SyntaxNode};
+use source::TextSpan;
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+use vm::{Instruction, InstructionKind};
+
+/// Functions whose finalized body is longer than this are not inlined, since every call would grow
+/// the bytecode by more than the call itself costs.
+const MAX_INLINE_LEN: usize = 24;
+
+/// A function which calls can be replaced with the body of.
+pub(super) struct InlineFn {
+    node: node::FnDeclarationNode,
+    /// The variables the body uses from outside of the function, see `Lowerer::free_vars`
+    free_vars: Vec<(Rc<str>, Option<usize>)>,
+}
+
+impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
+    /// Remembers a named function which has just been lowered and stored, if it can be inlined.
+    /// Recursive functions, and functions stored in variables which are assigned to, are never
+    /// inlined. Functions in the global scope aren't either, since code lowered later, like the
+    /// next line in the REPL, could replace them.
+    pub(super) fn register_inlinable(
+        &mut self,
+        node: node::FnDeclarationNode,
+        body_len: usize,
+        free_vars: Vec<(Rc<str>, Option<usize>)>,
+    ) {
+        let ident = node
+            .ident
+            .as_ref()
+            .expect("Only named functions can be inlined");
+        if node.block.block.is_empty()
+            || body_len > MAX_INLINE_LEN
+            || free_vars.iter().any(|(var, _)| var == ident)
+        {
+            return;
+        }
+
+        let local = match self.locate(ident) {
+            Some(local) if local.depth == 0 => local,
+            _ => return,
+        };
+        let is_assigned = self
+            .assigned
+            .get(&local.scope_id)
+            .is_none_or(|assigned| assigned.contains(ident));
+        if !is_assigned {
+            let function = InlineFn { node, free_vars };
+            self.inlinable
+                .insert((local.scope_id, local.slot), Rc::new(function));
+        }
+    }
+
+    /// Finds the function being called, if the call can be replaced with its body. The variables
+    /// the body uses from outside of the function must be the same ones at the call site, so that
+    /// the body does the same thing when it is inlined.
+    pub(super) fn inline_target(
+        &self,
+        child: &SyntaxNode,
+        num_args: usize,
+    ) -> Option<((usize, usize), Rc<InlineFn>)> {
+        if self.opt_level < OptLevel::O2 {
+            return None;
+        }
+
+        let ident = match child {
+            SyntaxNode::VariableNode(variable) => &variable.ident,
+            _ => return None,
+        };
+        // The function has to have been declared by the time it is called
+        let local = self.locate(ident)?;
+        if !self
+            .declared
+            .get(&local.scope_id)
+            .is_some_and(|declared| declared.contains(ident))
+        {
+            return None;
+        }
+
+        let key = (local.scope_id, local.slot);
+        let function = self.inlinable.get(&key)?;
+        // A call with the wrong number of arguments is left to fail when it is run
+        if function.node.args.len() != num_args || self.inlining.contains(&key) {
+            return None;
+        }
+
+        let scope = self.current_scope.as_ref()?;
+        let same_vars = function.free_vars.iter().all(|(ident, scope_id)| {
+            let local = self.locate(ident);
+            match scope_id {
+                Some(_) => local.map(|local| local.scope_id) == *scope_id,
+                None => local.is_none() && self.is_declared(scope, ident),
+            }
+        });
+
+        if same_vars {
+            Some((key, Rc::clone(function)))
+        } else {
+            None
+        }
+    }
+
+    // inlined calls
+    //
+    // <args>
+    // <push-var>
+    //   <store args>
+    //   <fn-block>
+    // <return-label>
+    //
+    // The body is lowered in a new scope, like it is when the function is declared, except that the
+    // arguments are taken off the stack instead of being stored by the call
+    pub(super) fn lower_inlined_call(
+        &mut self,
+        key: (usize, usize),
+        function: &InlineFn,
+        args: Vec<SyntaxNode>,
+        span: TextSpan,
+    ) {
+        for arg in args.into_iter().rev() {
+            self.lower_node(arg);
+        }
+
+        let return_label = self.next_label();
+        let prev_loops = mem::take(&mut self.loops);
+        let prev_return_label = self.return_label.replace(return_label);
+        let prev_scopes_since_fn = mem::replace(&mut self.scopes_since_fn, 0);
+        self.inlining.push(key);
+
+        self.lower_block_declaring(function.node.block.clone(), &function.node.args, true);
+        self.bytecode.push(Instruction::new(
+            InstructionKind::Label {
+                number: return_label,
+            },
+            span,
+        ));
+
+        self.inlining.pop();
+        self.loops = prev_loops;
+        self.return_label = prev_return_label;
+        self.scopes_since_fn = prev_scopes_since_fn;
+    }
+}
+
+/// Finds the variables which are assigned to anywhere in a statement, including in nested scopes
+/// and functions.
+pub(super) fn collect_assignments(node: &SyntaxNode, assigned: &mut HashSet<Rc<str>>) {
+    let mut collect = |node: &SyntaxNode| collect_assignments(node, assigned);
+    match node {
+        SyntaxNode::AssignmentNode(node) => {
+            collect(&node.value);
+            node.indices.iter().flatten().for_each(collect);
+            assigned.insert(Rc::clone(&node.ident));
+        }
+        SyntaxNode::BlockNode(node::BlockNode { block, .. })
+        | SyntaxNode::LoopNode(node::LoopNode { block, .. })
+        | SyntaxNode::FnDeclarationNode(node::FnDeclarationNode {
+            block: node::BlockNode { block, .. },
+            ..
+        })
+        | SyntaxNode::ListNode(node::ListNode {
+            elements: block, ..
+        })
+        | SyntaxNode::ObjectNode(node::ObjectNode {
+            elements: block, ..
+        }) => block.iter().for_each(collect),
+        SyntaxNode::InterfaceNode(node) => node.values.iter().for_each(|(_, v)| collect(v)),
+        SyntaxNode::IfNode(node) => {
+            collect(&node.cond);
+            node.if_block.block.iter().for_each(&mut collect);
+            for block in node.else_block.iter() {
+                block.block.iter().for_each(&mut collect);
+            }
+        }
+        SyntaxNode::ComprehensionNode(node) => {
+            collect(&node.iter);
+            node.cond.iter().for_each(|cond| collect(cond));
+            node.key.iter().for_each(|key| collect(key));
+            collect(&node.value);
+        }
+        SyntaxNode::FnCallNode(node) => {
+            node.args.iter().for_each(&mut collect);
+            collect(&node.child);
+        }
+        SyntaxNode::IndexNode(node) => {
+            collect(&node.index);
+            collect(&node.child);
+        }
+        SyntaxNode::BinaryNode(node) => {
+            collect(&node.left);
+            collect(&node.right);
+        }
+        SyntaxNode::DeclarationNode(node) => collect(&node.value),
+        SyntaxNode::DeferNode(node) => collect(&node.statement),
+        SyntaxNode::UnaryNode(node) => collect(&node.child),
+        SyntaxNode::BreakNode(node::BreakNode { value, .. })
+        | SyntaxNode::ReturnNode(node::ReturnNode { value, .. }) => {
+            value.iter().for_each(|value| collect(value))
+        }
+        SyntaxNode::LiteralNode(_) | SyntaxNode::VariableNode(_) | SyntaxNode::BadNode(_) => {}
+    }
+}
@@ -1,23 +1,47 @@
 use diagnostics::Diagnostics;
 use intermediaries::{node, SyntaxNode, TokenKind};
-use std::cell::RefCell;
+use source::TextSpan;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ops::RangeFrom;
 use std::rc::Rc;
-use vm::function::Function;
-use vm::{Bytecode, Instruction, InstructionKind, LabelNumber, Scope, Value};
+use vm::function::{AnilangFn, Function};
+use vm::value::Str;
+use vm::{
+    finalize_bytecode, Bytecode, Chunk, Instruction, InstructionKind, LabelNumber, Op, Scope, Value,
+};
 
 mod const_evaluator;
+mod const_propagation;
 use const_evaluator::ConstEvaluator;
 
+mod inliner;
+mod opt_level;
+mod optimizer;
+use inliner::{collect_assignments, InlineFn};
+pub use opt_level::OptLevel;
+
+/// A loop which can be broken out of
+struct BreakTarget {
+    label: Option<Rc<str>>,
+    end_label: LabelNumber,
+    /// The `scopes_since_loop` of the enclosing loop, at the point this loop was entered
+    outer_scopes_since_loop: usize,
+}
+
 /// Lowers the AST into Bytecode.
 ///
-/// The last argument is whether to perform optimizations based on
-/// constant expressions. For example the expression `a = 1 + 2` can be optimized to `a = 3` since
-/// the result of `1 + 2` is independent of all variables. These optimizations however may require
+/// The last argument is the [`OptLevel`] to lower with. From `O1`, optimizations are performed based
+/// on constant expressions. For example the expression `a = 1 + 2` can be optimized to `a = 3` since
+/// the result of `1 + 2` is independent of all variables. Variables which are declared with a
+/// constant number, boolean or range and never assigned to are treated as constants too. The
+/// lowered bytecode is then cleaned up, by removing unreachable code, values which are pushed only
+/// to be popped, empty scopes and unnecessary jumps. These optimizations however may require
 /// traversing a subtree multiple times and hence is slower than just lowering everything and
 /// executing the lowered code. Therefore the optimization should only be enabled when the code is
-/// being 'compiled' and written to a file, instead of being 'interpreted'.
+/// being 'compiled' and written to a file, instead of being 'interpreted'. At `O2`, calls to small
+/// functions are also replaced with the body of the function.
 ///
 /// # Examples
 /// Evaluate from a node
@@ -34,14 +58,15 @@ use const_evaluator::ConstEvaluator;
 /// #         BlockNode::new(vec![], Default::default())
 /// #     }
 /// # }
-/// use lowerer::Lowerer;
+/// use lowerer::{Lowerer, OptLevel};
 ///
 /// let src = SourceText::new("1 + 2 + 3");
 /// let diagnostics = Diagnostics::new(&src);
 ///
 /// let tokens = Lexer::lex(&src, &diagnostics);
 /// let root_node = Parser::parse(tokens, &src, &diagnostics);
-/// let bytecode_kind: Vec<_> = Lowerer::lower(root_node, &diagnostics, false)
+/// let bytecode_kind: Vec<_> = Lowerer::lower(root_node, &diagnostics, OptLevel::O0)
+///     .to_bytecode()
 ///     .into_iter()
 ///     .map(|instr| instr.kind)
 ///     .collect();
@@ -79,14 +104,15 @@ use const_evaluator::ConstEvaluator;
 /// #         BlockNode::new(vec![], Default::default())
 /// #     }
 /// # }
-/// use lowerer::Lowerer;
+/// use lowerer::{Lowerer, OptLevel};
 ///
 /// let src = SourceText::new("1 + 2 + 3");
 /// let diagnostics = Diagnostics::new(&src);
 ///
 /// let tokens = Lexer::lex(&src, &diagnostics);
 /// let root_node = Parser::parse(tokens, &src, &diagnostics);
-/// let bytecode_kind: Vec<_> = Lowerer::lower(root_node, &diagnostics, true)
+/// let bytecode_kind: Vec<_> = Lowerer::lower(root_node, &diagnostics, OptLevel::O1)
+///     .to_bytecode()
 ///     .into_iter()
 ///     .map(|instr| instr.kind)
 ///     .collect();
@@ -109,33 +135,115 @@ pub struct Lowerer<'diagnostics, 'src> {
     scopes_since_loop: usize,
     scopes_since_fn: usize,
     current_scope: Option<Rc<Scope>>,
-    break_label: Option<LabelNumber>,
+    /// The loops which are currently being lowered, with the innermost loop at the end
+    loops: Vec<BreakTarget>,
     return_label: Option<LabelNumber>,
-    should_optimize: bool,
+    /// The statements deferred in each scope which is currently being lowered, along with the
+    /// scope itself
+    deferred: Vec<(Rc<Scope>, Vec<SyntaxNode>)>,
+    /// The variables declared so far in each scope, by the id of the scope
+    declared: HashMap<usize, HashSet<Rc<str>>>,
+    /// The variables stored in slots of each scope, by the id of the scope. The index of a variable
+    /// is its slot. These are found before the scope is lowered, so functions can refer to the
+    /// slots of variables declared after them.
+    slots: HashMap<usize, Vec<Rc<str>>>,
+    /// A scope holding variables which are declared before the bytecode is run, like the std or
+    /// the variables from previous lines in the REPL
+    globals: Option<Rc<Scope>>,
+    /// The number of function declarations the current node is nested in
+    fn_depth: usize,
+    /// The id of the scope of the innermost function being lowered. Enclosing scopes have smaller
+    /// ids, since they are created first.
+    fn_scope_id: usize,
+    /// Variables used inside functions which weren't declared when the function was lowered,
+    /// along with the scope they are used in. Since functions look up variables when they are
+    /// called, these only need to be declared by the end of lowering.
+    unresolved: Vec<(Rc<Scope>, Rc<str>, TextSpan)>,
+    /// The variables used by the innermost function being lowered which are stored outside of it,
+    /// along with the id of the scope they are stored in, or `None` if they are looked up by name
+    free_vars: Vec<(Rc<str>, Option<usize>)>,
+    /// The named functions which can be inlined, by the scope id and slot of their variable
+    inlinable: HashMap<(usize, usize), Rc<InlineFn>>,
+    /// The functions whose bodies are currently being inlined, so that mutually recursive
+    /// functions aren't inlined into each other forever
+    inlining: Vec<(usize, usize)>,
+    /// The variables assigned to anywhere in each scope, by the id of the scope. These are only
+    /// collected when optimizing, since the value of a variable in one of them may change.
+    assigned: HashMap<usize, HashSet<Rc<str>>>,
+    /// The values of variables which are declared with a constant value and never assigned to, by
+    /// the scope id and slot of their variable
+    constants: HashMap<(usize, usize), Value>,
+    opt_level: OptLevel,
+}
+
+/// Where a variable with a slot is stored, relative to the current scope
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    scope_id: usize,
+    depth: usize,
+    slot: usize,
 }
 
 impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
-    pub fn lower(
-        root: node::BlockNode,
+    fn new(
         diagnostics: &'diagnostics Diagnostics<'src>,
-        should_optimize: bool,
-    ) -> Bytecode {
-        let mut lowerer = Self {
+        globals: Option<Rc<Scope>>,
+        opt_level: OptLevel,
+    ) -> Self {
+        Self {
             diagnostics,
             bytecode: Default::default(),
             labels: 0..,
             scope_ids: 0..,
             scopes_since_loop: 0,
             current_scope: None,
-            break_label: None,
+            loops: Vec::new(),
             scopes_since_fn: 0,
             return_label: None,
-            should_optimize,
-        };
+            deferred: Vec::new(),
+            declared: HashMap::new(),
+            slots: HashMap::new(),
+            globals,
+            fn_depth: 0,
+            fn_scope_id: 0,
+            unresolved: Vec::new(),
+            free_vars: Vec::new(),
+            inlinable: HashMap::new(),
+            inlining: Vec::new(),
+            assigned: HashMap::new(),
+            constants: HashMap::new(),
+            opt_level,
+        }
+    }
+
+    pub fn lower(
+        root: node::BlockNode,
+        diagnostics: &'diagnostics Diagnostics<'src>,
+        opt_level: OptLevel,
+    ) -> Chunk {
+        let mut lowerer = Self::new(diagnostics, None, opt_level);
+
+        lowerer.lower_block(root);
+        lowerer.report_unresolved();
+
+        finalize(lowerer.bytecode, opt_level)
+    }
+
+    /// Like the lower function except that variables declared in `std` are also allowed to be
+    /// used. The std scope is not part of the bytecode, and must be given as the parent of the root
+    /// scope when it is run.
+    pub fn lower_with_std(
+        root: node::BlockNode,
+        diagnostics: &'diagnostics Diagnostics<'src>,
+        std: Rc<Scope>,
+        opt_level: OptLevel,
+    ) -> Chunk {
+        let mut lowerer = Self::new(diagnostics, Some(std), opt_level);
 
         lowerer.lower_block(root);
+        lowerer.report_unresolved();
 
-        lowerer.bytecode
+        finalize(lowerer.bytecode, opt_level)
     }
 
     /// Like the lower function except that it doesn't add a scope for root block
@@ -143,37 +251,32 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
         root: node::BlockNode,
         diagnostics: &'diagnostics Diagnostics<'src>,
         scope: Rc<Scope>,
-        should_optimize: bool,
-    ) -> Bytecode {
-        let mut lowerer = Self {
-            diagnostics,
-            bytecode: Default::default(),
-            labels: 0..,
-            scope_ids: (scope.id + 1)..,
-            scopes_since_loop: 1,
-            current_scope: Some(Rc::clone(&scope)),
-            break_label: None,
-            scopes_since_fn: 0,
-            return_label: None,
-            should_optimize,
-        };
+        opt_level: OptLevel,
+    ) -> Chunk {
+        let mut lowerer = Self::new(diagnostics, Some(Rc::clone(&scope)), opt_level);
+        lowerer.scope_ids = (scope.id + 1)..;
+        lowerer.scopes_since_loop = 1;
+        lowerer.current_scope = Some(Rc::clone(&scope));
 
         if root.block.is_empty() {
-            return vec![];
+            return Chunk::default();
         }
 
+        lowerer.deferred.push((Rc::clone(&scope), Vec::new()));
         lowerer.bytecode.push(Instruction::new(
             InstructionKind::PushVar { scope },
             root.span.clone(),
         ));
 
         lowerer.lower_block_statements(root.block);
+        lowerer.lower_deferred(0);
 
         lowerer
             .bytecode
             .push(Instruction::new(InstructionKind::PopVar, root.span));
+        lowerer.report_unresolved();
 
-        lowerer.bytecode
+        finalize(lowerer.bytecode, opt_level)
     }
 
     fn next_label(&mut self) -> LabelNumber {
@@ -186,17 +289,198 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
         self.scope_ids.next().unwrap()
     }
 
+    fn declare(&mut self, ident: &Rc<str>) {
+        let scope = self
+            .current_scope
+            .as_ref()
+            .expect("Variables are always declared inside a scope");
+        self.declared
+            .entry(scope.id)
+            .or_default()
+            .insert(Rc::clone(ident));
+    }
+
+    fn is_declared(&self, scope: &Rc<Scope>, ident: &str) -> bool {
+        let mut scope = Some(scope);
+        while let Some(s) = scope {
+            if self.declared.get(&s.id).is_some_and(|d| d.contains(ident)) {
+                return true;
+            }
+            scope = s.parent();
+        }
+
+        self.globals
+            .as_ref()
+            .is_some_and(|globals| globals.try_get_value(ident).is_some())
+    }
+
+    /// Reports the variable if it can't be found in any of the enclosing scopes
+    fn resolve(&mut self, ident: &Rc<str>, span: &TextSpan) {
+        let scope = self
+            .current_scope
+            .as_ref()
+            .expect("Variables are always used inside a scope");
+        if self.is_declared(scope, ident) {
+            return;
+        }
+
+        if self.fn_depth > 0 {
+            self.unresolved
+                .push((Rc::clone(scope), Rc::clone(ident), span.clone()));
+        } else {
+            self.diagnostics.unknown_reference(ident, span.clone());
+        }
+    }
+
+    /// The global scope is looked up by name, since its variables outlive the bytecode
+    fn is_global(&self, scope: &Rc<Scope>) -> bool {
+        self.globals
+            .as_ref()
+            .is_some_and(|globals| Rc::ptr_eq(globals, scope))
+    }
+
+    /// Gives a slot to every variable which is declared in the scope, with `vars` in the first
+    /// slots
+    fn assign_slots<'a>(
+        &mut self,
+        scope: &Rc<Scope>,
+        vars: &[Rc<str>],
+        statements: impl IntoIterator<Item = &'a SyntaxNode>,
+    ) {
+        let mut declarations = Vec::new();
+        let mut assigned = HashSet::new();
+        for statement in statements {
+            collect_declarations(statement, &mut declarations);
+            if self.opt_level >= OptLevel::O1 {
+                collect_assignments(statement, &mut assigned);
+            }
+        }
+
+        let mut slots = vars.to_vec();
+        for (ident, span) in declarations {
+            if slots.contains(&ident) {
+                self.diagnostics.already_declared(&ident, span);
+            } else {
+                slots.push(ident);
+            }
+        }
+
+        self.slots.insert(scope.id, slots);
+        if self.opt_level >= OptLevel::O1 {
+            self.assigned.insert(scope.id, assigned);
+        }
+    }
+
+    /// Finds the slot of a variable, along with the number of scopes above the current one it is
+    /// stored in. Variables which are declared later in a scope are skipped, since an outer
+    /// variable with the same name is the one used, unless the scope is outside the current
+    /// function.
+    fn locate(&self, ident: &str) -> Option<Local> {
+        let mut scope = self.current_scope.as_ref();
+        let mut depth = 0;
+        while let Some(s) = scope {
+            if self.is_global(s) {
+                return None;
+            }
+
+            let slot = self
+                .slots
+                .get(&s.id)
+                .and_then(|slots| slots.iter().position(|var| **var == *ident));
+            if let Some(slot) = slot {
+                let is_declared = self.declared.get(&s.id).is_some_and(|d| d.contains(ident));
+                if is_declared || (self.fn_depth > 0 && s.id < self.fn_scope_id) {
+                    return Some(Local {
+                        scope_id: s.id,
+                        depth,
+                        slot,
+                    });
+                }
+            }
+
+            scope = s.parent();
+            depth += 1;
+        }
+
+        None
+    }
+
+    /// Remembers a variable used by the function being lowered, if it is stored outside of it
+    fn note_free_var(&mut self, ident: &Rc<str>, scope_id: Option<usize>) {
+        if self.fn_depth > 0 && scope_id.is_none_or(|id| id < self.fn_scope_id) {
+            self.free_vars.push((Rc::clone(ident), scope_id));
+        }
+    }
+
+    fn lower_load(&mut self, ident: Rc<str>, span: TextSpan) {
+        let local = self.locate(&ident);
+        self.note_free_var(&ident, local.map(|local| local.scope_id));
+        let kind = match local {
+            Some(Local { depth, slot, .. }) => InstructionKind::LoadLocal { depth, slot },
+            None => InstructionKind::Load { ident },
+        };
+        self.bytecode.push(Instruction::new(kind, span));
+    }
+
+    fn lower_store(&mut self, ident: Rc<str>, declaration: bool, span: TextSpan) {
+        let scope = self
+            .current_scope
+            .as_ref()
+            .expect("Variables are always stored inside a scope");
+        let kind = if declaration && !self.is_global(scope) {
+            let slots = self.slots.entry(scope.id).or_default();
+            let slot = match slots.iter().position(|var| *var == ident) {
+                Some(slot) => slot,
+                None => {
+                    slots.push(Rc::clone(&ident));
+                    slots.len() - 1
+                }
+            };
+            self.declare(&ident);
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot,
+                declaration,
+            }
+        } else if declaration {
+            self.declare(&ident);
+            InstructionKind::Store { ident, declaration }
+        } else {
+            let local = self.locate(&ident);
+            self.note_free_var(&ident, local.map(|local| local.scope_id));
+            match local {
+                Some(Local { depth, slot, .. }) => InstructionKind::StoreLocal {
+                    depth,
+                    slot,
+                    declaration,
+                },
+                None => InstructionKind::Store { ident, declaration },
+            }
+        };
+        self.bytecode.push(Instruction::new(kind, span));
+    }
+
+    fn report_unresolved(&mut self) {
+        for (scope, ident, span) in mem::take(&mut self.unresolved) {
+            if !self.is_declared(&scope, &ident) {
+                self.diagnostics.unknown_reference(&ident, span);
+            }
+        }
+    }
+
     fn lower_node(&mut self, node: SyntaxNode) {
-        if self.should_optimize && node.can_const_eval() {
-            // The code represented by this tree is independent of all variables, so it can directly
-            // be evaluated and added as a push Instruction
+        let value = if self.opt_level >= OptLevel::O1 && self.can_const_eval(&node) {
+            self.const_value(&node)
+        } else {
+            None
+        };
+
+        if let Some(value) = value {
+            // The code represented by this tree is independent of all variables which can change,
+            // so it can directly be evaluated and added as a push Instruction
             let span = node.span().clone();
-            self.bytecode.push(Instruction::new(
-                InstructionKind::Push {
-                    value: ConstEvaluator::evaluate(node, self.diagnostics),
-                },
-                span,
-            ))
+            self.bytecode
+                .push(Instruction::new(InstructionKind::Push { value }, span))
         } else {
             match node {
                 SyntaxNode::BlockNode(block) => self.lower_block(block),
@@ -208,8 +492,10 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
                 SyntaxNode::IndexNode(node) => self.lower_index(node),
                 SyntaxNode::IfNode(node) => self.lower_if(node),
                 SyntaxNode::LoopNode(node) => self.lower_loop(node),
+                SyntaxNode::ComprehensionNode(node) => self.lower_comprehension(node),
                 SyntaxNode::AssignmentNode(node) => self.lower_assignment(node),
                 SyntaxNode::DeclarationNode(node) => self.lower_declaration(node),
+                SyntaxNode::DeferNode(node) => self.lower_defer(node),
                 SyntaxNode::FnDeclarationNode(node) => self.lower_fn_declaration(node),
                 SyntaxNode::FnCallNode(node) => self.lower_fn_call(node),
                 SyntaxNode::BinaryNode(node) => self.lower_binary(node),
@@ -230,7 +516,7 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
         for (i, node) in statements.into_iter().enumerate() {
             let node_span = node.span().clone();
             if i < last_index {
-                if self.should_optimize && node.can_const_eval() {
+                if self.opt_level >= OptLevel::O1 && self.can_const_eval(&node) {
                     self.diagnostics.unused_statement(node_span);
                     continue;
                 }
@@ -244,22 +530,55 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
     }
 
     fn lower_block(&mut self, block: node::BlockNode) {
+        self.lower_block_declaring(block, &[], false);
+    }
+
+    /// Lowers the block, with `vars` already declared in its scope when it is run, like the
+    /// arguments of a function. If `vars_on_stack` is set, the values of `vars` are instead popped
+    /// off the stack once the scope is entered, with the first one on the top of the stack.
+    fn lower_block_declaring(
+        &mut self,
+        block: node::BlockNode,
+        vars: &[Rc<str>],
+        vars_on_stack: bool,
+    ) {
         if block.block.is_empty() {
             return;
         }
 
         let prev_scope = self.current_scope.take();
         let scope = Rc::new(Scope::new(self.next_scope_id(), prev_scope.clone()));
+        self.assign_slots(&scope, vars, &block.block);
         self.current_scope = Some(Rc::clone(&scope));
+        for var in vars {
+            self.declare(var);
+        }
         self.scopes_since_loop += 1;
         self.scopes_since_fn += 1;
+        self.deferred.push((Rc::clone(&scope), Vec::new()));
 
         self.bytecode.push(Instruction::new(
             InstructionKind::PushVar { scope },
             block.span.clone(),
         ));
+        if vars_on_stack {
+            for slot in 0..vars.len() {
+                self.bytecode.push(Instruction::new(
+                    InstructionKind::StoreLocal {
+                        depth: 0,
+                        slot,
+                        declaration: true,
+                    },
+                    block.span.clone(),
+                ));
+                self.bytecode
+                    .push(Instruction::new(InstructionKind::Pop, block.span.clone()));
+            }
+        }
 
         self.lower_block_statements(block.block);
+        self.lower_deferred(0);
+        self.deferred.pop();
 
         self.bytecode
             .push(Instruction::new(InstructionKind::PopVar, block.span));
@@ -318,14 +637,14 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
                 // Got a function, if first arg is 'self', we want to include it in the object
                 if let Some("self") = node.args.first().map(Rc::as_ref) {
                     object_elements.push(SyntaxNode::LiteralNode(node::LiteralNode::from_val(
-                        Value::String(Rc::new(RefCell::new(k.clone()))),
+                        Value::String(Str::intern(k)),
                         v.span().clone(),
                     )));
                     object_elements.push(v.clone());
                 }
             } else {
                 object_elements.push(SyntaxNode::LiteralNode(node::LiteralNode::from_val(
-                    Value::String(Rc::new(RefCell::new(k.clone()))),
+                    Value::String(Str::intern(k)),
                     v.span().clone(),
                 )));
                 object_elements.push(v.clone());
@@ -371,24 +690,14 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
                 k.insert_str(0, &*interface.ident);
 
                 self.lower_node(v);
-                self.bytecode.push(Instruction::new(
-                    InstructionKind::Store {
-                        ident: k.into(),
-                        declaration: true,
-                    },
-                    span,
-                ));
+                self.lower_store(k.into(), true, span);
             }
         }
     }
 
     fn lower_variable(&mut self, variable: node::VariableNode) {
-        self.bytecode.push(Instruction::new(
-            InstructionKind::Load {
-                ident: variable.ident,
-            },
-            variable.span,
-        ));
+        self.resolve(&variable.ident, &variable.span);
+        self.lower_load(variable.ident, variable.span);
     }
 
     fn lower_index(&mut self, index: node::IndexNode) {
@@ -410,8 +719,8 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
         // For the full if condition to be constant, not only the condition, but the if and else
         // blocks must also be constant. If the condition is constant (but one of the blocks is not)
         // it can be optimized out into just the block.
-        if self.should_optimize && if_node.cond.can_const_eval() {
-            if bool::from(ConstEvaluator::evaluate(*if_node.cond, self.diagnostics)) {
+        if self.opt_level >= OptLevel::O1 && self.can_const_eval(&if_node.cond) {
+            if bool::from(self.const_eval(*if_node.cond)) {
                 self.lower_block(if_node.if_block);
             } else if let Some(block) = if_node.else_block {
                 self.lower_block(block);
@@ -432,7 +741,7 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
 
         self.lower_node(*if_node.cond);
         self.bytecode.push(Instruction::new(
-            InstructionKind::PopJumpIfTrue { label: then_label },
+            InstructionKind::PopJumpIfTrue { target: then_label },
             if_cond_span,
         ));
 
@@ -448,7 +757,7 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
             ));
         }
         self.bytecode.push(Instruction::new(
-            InstructionKind::JumpTo { label: end_label },
+            InstructionKind::JumpTo { target: end_label },
             if_node.span.clone(),
         ));
 
@@ -469,19 +778,26 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
     //   <loop-block>
     //   <goto start-label>
     // <end-label>
+    //
+    // The only way out of a loop is a break, which pushes the value of the loop before jumping to
+    // the end label
     fn lower_loop(&mut self, loop_node: node::LoopNode) {
         let start_label = self.next_label();
         let end_label = self.next_label();
 
-        let mut previous_break_label = Some(end_label);
-        mem::swap(&mut self.break_label, &mut previous_break_label);
-        let previos_scopes_since_loop = self.scopes_since_loop;
+        self.loops.push(BreakTarget {
+            label: loop_node.label,
+            end_label,
+            outer_scopes_since_loop: self.scopes_since_loop,
+        });
         self.scopes_since_loop = 0;
         self.scopes_since_fn += 1;
 
         let prev_scope = self.current_scope.take();
         let scope = Rc::new(Scope::new(self.next_scope_id(), prev_scope.clone()));
+        self.assign_slots(&scope, &[], &loop_node.block);
         self.current_scope = Some(Rc::clone(&scope));
+        self.deferred.push((Rc::clone(&scope), Vec::new()));
 
         self.bytecode.push(Instruction::new(
             InstructionKind::PushVar { scope },
@@ -496,7 +812,7 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
 
         for node in loop_node.block {
             let node_span = node.span().clone();
-            if self.should_optimize && node.can_const_eval() {
+            if self.opt_level >= OptLevel::O1 && self.can_const_eval(&node) {
                 self.diagnostics.unused_statement(node_span);
             } else {
                 self.lower_node(node);
@@ -506,105 +822,313 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
             }
         }
 
+        // The loop body is exited at the end of every iteration
+        self.lower_deferred(0);
+        self.deferred.pop();
+
         self.bytecode.push(Instruction::new(
-            InstructionKind::JumpTo { label: start_label },
+            InstructionKind::JumpTo {
+                target: start_label,
+            },
             loop_node.span.clone(),
         ));
         self.bytecode.push(Instruction::new(
             InstructionKind::Label { number: end_label },
             loop_node.span.clone(),
         ));
+        self.bytecode
+            .push(Instruction::new(InstructionKind::PopVar, loop_node.span));
+
+        let target = self.loops.pop().expect("Loop was pushed");
+        self.scopes_since_loop = target.outer_scopes_since_loop;
+        self.scopes_since_fn -= 1;
+        self.current_scope = prev_scope;
+    }
+
+    // comprehensions
+    //
+    // <empty-collection>
+    // <iter> => <make-iter>
+    // <declare vars>
+    // <start-label>
+    //   <for-iter end-label>
+    //   <store vars>
+    //   <cond> => <not> => <goto start-label>
+    //   [<value>] <key> => <insert into collection>
+    //   <goto start-label>
+    // <end-label>
+    //
+    // While iterating, the stack is in the form `[index, iter, collection, ...]`, so the collection
+    // is 2 values below the value or key being inserted
+    fn lower_comprehension(&mut self, comprehension_node: node::ComprehensionNode) {
+        let start_label = self.next_label();
+        let end_label = self.next_label();
+        let span = comprehension_node.span.clone();
+
+        // break wouldn't clean up the iterator on the stack, so it is treated like a function
+        // boundary
+        let previous_loops = mem::take(&mut self.loops);
+
         self.bytecode.push(Instruction::new(
-            InstructionKind::PopVar,
-            loop_node.span.clone(),
+            if comprehension_node.is_object() {
+                InstructionKind::MakeObject { len: 0 }
+            } else {
+                InstructionKind::MakeList { len: 0 }
+            },
+            span.clone(),
         ));
-        // Every high level statement must produce a value on the stack so we push a null
+
+        // The iterable is lowered outside the comprehension's scope, so `[x for x in x]` iterates
+        // over the outer `x`
+        let iter_span = comprehension_node.iter.span().clone();
+        self.lower_node(*comprehension_node.iter);
+        self.bytecode
+            .push(Instruction::new(InstructionKind::MakeIter, iter_span));
+
+        self.scopes_since_fn += 1;
+        let prev_scope = self.current_scope.take();
+        let scope = Rc::new(Scope::new(self.next_scope_id(), prev_scope.clone()));
+        let nodes = comprehension_node
+            .cond
+            .iter()
+            .chain(&comprehension_node.key);
+        let nodes = nodes
+            .map(AsRef::as_ref)
+            .chain(Some(&*comprehension_node.value));
+        self.assign_slots(&scope, &comprehension_node.vars, nodes);
+        self.current_scope = Some(Rc::clone(&scope));
+        self.deferred.push((Rc::clone(&scope), Vec::new()));
+        for var in comprehension_node.vars.iter() {
+            self.declare(var);
+        }
+
         self.bytecode.push(Instruction::new(
-            InstructionKind::Push { value: Value::Null },
-            loop_node.span,
+            InstructionKind::PushVar { scope },
+            span.clone(),
         ));
 
-        mem::swap(&mut self.break_label, &mut previous_break_label);
-        self.scopes_since_loop = previos_scopes_since_loop;
+        self.bytecode.push(Instruction::new(
+            InstructionKind::Label {
+                number: start_label,
+            },
+            span.clone(),
+        ));
+        self.bytecode.push(Instruction::new(
+            InstructionKind::ForIter { target: end_label },
+            span.clone(),
+        ));
+        self.lower_comprehension_vars(comprehension_node.vars, &span);
+
+        if let Some(cond) = comprehension_node.cond {
+            let cond_span = cond.span().clone();
+            self.lower_node(*cond);
+            self.bytecode.push(Instruction::new(
+                InstructionKind::UnaryNot,
+                cond_span.clone(),
+            ));
+            self.bytecode.push(Instruction::new(
+                InstructionKind::PopJumpIfTrue {
+                    target: start_label,
+                },
+                cond_span,
+            ));
+        }
+
+        let value_span = comprehension_node.value.span().clone();
+        self.lower_node(*comprehension_node.value);
+        if let Some(key) = comprehension_node.key {
+            self.lower_node(*key);
+            self.bytecode.push(Instruction::new(
+                InstructionKind::ObjectInsert { depth: 2 },
+                value_span,
+            ));
+        } else {
+            self.bytecode.push(Instruction::new(
+                InstructionKind::ListAppend { depth: 2 },
+                value_span,
+            ));
+        }
+
+        self.lower_deferred(0);
+        self.deferred.pop();
+
+        self.bytecode.push(Instruction::new(
+            InstructionKind::JumpTo {
+                target: start_label,
+            },
+            span.clone(),
+        ));
+        self.bytecode.push(Instruction::new(
+            InstructionKind::Label { number: end_label },
+            span.clone(),
+        ));
+        self.bytecode
+            .push(Instruction::new(InstructionKind::PopVar, span));
+
+        self.loops = previous_loops;
         self.scopes_since_fn -= 1;
         self.current_scope = prev_scope;
     }
 
+    /// Stores the element on the top of the stack into the comprehension variables. If there are
+    /// 2 variables, the element is destructured into them as `[first, second]`
+    fn lower_comprehension_vars(&mut self, vars: Vec<Rc<str>>, span: &TextSpan) {
+        let mut vars = vars.into_iter();
+        let first = vars.next().expect("Comprehension must have a variable");
+        self.lower_store(Rc::clone(&first), true, span.clone());
+        self.bytecode
+            .push(Instruction::new(InstructionKind::Pop, span.clone()));
+
+        let second = match vars.next() {
+            Some(second) => second,
+            None => return,
+        };
+
+        // The second value has to be indexed out before the first variable is overwritten with
+        // its own first value
+        for (ident, index) in [(second, 1), (Rc::clone(&first), 0)] {
+            self.bytecode.push(Instruction::new(
+                InstructionKind::Push {
+                    value: Value::Int(index),
+                },
+                span.clone(),
+            ));
+            self.lower_load(Rc::clone(&first), span.clone());
+            self.bytecode
+                .push(Instruction::new(InstructionKind::GetIndex, span.clone()));
+            self.lower_store(ident, true, span.clone());
+            self.bytecode
+                .push(Instruction::new(InstructionKind::Pop, span.clone()));
+        }
+    }
+
     fn lower_assignment(&mut self, assignment_node: node::AssignmentNode) {
         self.lower_node(*assignment_node.value);
+        self.resolve(&assignment_node.ident, &assignment_node.span);
         if let Some(indices) = assignment_node.indices {
             let len = indices.len();
-            let mut indices_spans: Vec<_> =
-                indices.iter().rev().map(|i| i.span().clone()).collect();
+            let span = indices.last().unwrap().span().clone();
             for node in indices.into_iter().rev() {
                 self.lower_node(node);
             }
-            self.bytecode.push(Instruction::new(
-                InstructionKind::Load {
-                    ident: assignment_node.ident.clone(),
-                },
-                assignment_node.span.clone(),
-            ));
-            self.bytecode.extend((0..(len - 1)).map(|_| {
-                Instruction::new(InstructionKind::GetIndex, indices_spans.pop().unwrap())
-            }));
-            self.bytecode.push(Instruction::new(
-                InstructionKind::SetIndex,
-                indices_spans.pop().unwrap(),
-            ));
+            self.lower_load(assignment_node.ident.clone(), assignment_node.span.clone());
 
-            if len > 1 {
-                // Nested index access, we want to remove the last value, and put the root value
-                self.bytecode.push(Instruction::new(
-                    InstructionKind::Pop,
-                    assignment_node.span.clone(),
-                ));
-                self.bytecode.push(Instruction::new(
-                    InstructionKind::Load {
-                        ident: assignment_node.ident,
-                    },
-                    assignment_node.span,
-                ));
-            }
-        } else {
+            // Strings can't be changed, so setting an index of one creates a new string, and the
+            // updated value is stored back into the variable
+            let kind = if len > 1 {
+                InstructionKind::SetNestedIndex { depth: len }
+            } else {
+                InstructionKind::SetIndex
+            };
+            self.bytecode.push(Instruction::new(kind, span));
+        }
+        self.lower_store(assignment_node.ident, false, assignment_node.span);
+    }
+
+    fn lower_declaration(&mut self, declaration_node: node::DeclarationNode) {
+        let value =
+            if self.opt_level >= OptLevel::O1 && self.can_const_eval(&declaration_node.value) {
+                self.const_value(&declaration_node.value)
+            } else {
+                None
+            };
+
+        if let Some(value) = value {
+            let span = declaration_node.value.span().clone();
             self.bytecode.push(Instruction::new(
-                InstructionKind::Store {
-                    ident: assignment_node.ident,
-                    declaration: false,
+                InstructionKind::Push {
+                    value: value.clone(),
                 },
-                assignment_node.span,
+                span,
             ));
+            self.lower_store(
+                Rc::clone(&declaration_node.ident),
+                true,
+                declaration_node.span,
+            );
+            self.record_constant(&declaration_node.ident, value);
+        } else {
+            self.lower_node(*declaration_node.value);
+            self.lower_store(declaration_node.ident, true, declaration_node.span);
         }
     }
 
-    fn lower_declaration(&mut self, declaration_node: node::DeclarationNode) {
-        self.lower_node(*declaration_node.value);
+    fn lower_defer(&mut self, defer_node: node::DeferNode) {
+        let (_, statements) = self
+            .deferred
+            .last_mut()
+            .expect("defer is always inside a scope");
+        statements.push(*defer_node.statement);
+
+        // Every high level statement must produce a value on the stack so we push a null
         self.bytecode.push(Instruction::new(
-            InstructionKind::Store {
-                ident: declaration_node.ident,
-                declaration: true,
-            },
-            declaration_node.span,
+            InstructionKind::Push { value: Value::Null },
+            defer_node.span,
         ));
     }
 
+    /// Lowers the statements deferred in the scope `depth` scopes out from the current one, in
+    /// reverse order of declaration. The deferred statements are lowered every time the scope can
+    /// be exited, that is at the end of the scope, and at any `break` or `return` that leaves it.
+    fn lower_deferred(&mut self, depth: usize) {
+        let index = self.deferred.len() - 1 - depth;
+        if self.deferred[index].1.is_empty() {
+            return;
+        }
+
+        // Taken out so a deferred statement can't defer more statements into the scope which is
+        // being exited
+        let statements = mem::take(&mut self.deferred[index].1);
+
+        // Deferred statements are run in the scope they were declared in, and are not allowed to
+        // jump out of it
+        let mut prev_scope = Some(Rc::clone(&self.deferred[index].0));
+        mem::swap(&mut self.current_scope, &mut prev_scope);
+        let prev_loops = mem::take(&mut self.loops);
+        let prev_return_label = self.return_label.take();
+
+        for statement in statements.iter().rev() {
+            let span = statement.span().clone();
+            self.lower_node(statement.clone());
+            self.bytecode
+                .push(Instruction::new(InstructionKind::Pop, span));
+        }
+
+        self.deferred[index].1 = statements;
+        self.current_scope = prev_scope;
+        self.loops = prev_loops;
+        self.return_label = prev_return_label;
+    }
+
     fn lower_fn_declaration(&mut self, fn_declaration_node: node::FnDeclarationNode) {
         let mut fn_body = Vec::new();
+        let outer_free_vars = mem::take(&mut self.free_vars);
+        // A function whose body reports diagnostics is not inlined, since they would be reported
+        // again for every call it is inlined into
+        let num_diagnostics = self.diagnostics.num_errors() + self.diagnostics.num_warnings();
+        let inline_candidate = match fn_declaration_node.ident {
+            Some(_) if self.opt_level >= OptLevel::O2 => Some(fn_declaration_node.clone()),
+            _ => None,
+        };
 
         if !fn_declaration_node.block.block.is_empty() {
             let return_label = self.next_label();
-            let mut reset_break_label = None;
+            let mut reset_loops = Vec::new();
             let mut reset_return_label = Some(return_label);
             let prev_scopes_since_fn = self.scopes_since_fn;
             self.scopes_since_fn = 0;
             let block_span = fn_declaration_node.block.span.clone();
 
-            // Swap out the current bytecode and break label, for empty ones to lower function body
+            // Swap out the current bytecode and enclosing loops, for empty ones to lower the function
             mem::swap(&mut self.bytecode, &mut fn_body);
-            mem::swap(&mut self.break_label, &mut reset_break_label);
+            mem::swap(&mut self.loops, &mut reset_loops);
             mem::swap(&mut self.return_label, &mut reset_return_label);
 
-            self.lower_block(fn_declaration_node.block);
+            self.fn_depth += 1;
+            let prev_fn_scope_id = mem::replace(&mut self.fn_scope_id, self.scope_ids.start);
+            self.lower_block_declaring(fn_declaration_node.block, &fn_declaration_node.args, false);
+            self.fn_scope_id = prev_fn_scope_id;
+            self.fn_depth -= 1;
             self.bytecode.push(Instruction::new(
                 InstructionKind::Label {
                     number: return_label,
@@ -612,13 +1136,24 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
                 block_span,
             ));
 
-            // Swap back the current bytecode and break label to continue regular processing
+            // Swap back the current bytecode and enclosing loops to continue regular processing
             mem::swap(&mut self.bytecode, &mut fn_body);
-            mem::swap(&mut self.break_label, &mut reset_break_label);
+            mem::swap(&mut self.loops, &mut reset_loops);
             mem::swap(&mut self.return_label, &mut reset_return_label);
             self.scopes_since_fn = prev_scopes_since_fn;
         }
-        let function = Function::anilang_fn(fn_declaration_node.args, fn_body);
+
+        // The variables this function uses from outside of an enclosing function are also used by
+        // the enclosing function
+        let free_vars = mem::replace(&mut self.free_vars, outer_free_vars);
+        for (ident, scope_id) in free_vars.iter() {
+            self.note_free_var(ident, *scope_id);
+        }
+
+        let mut fn_body = finalize(fn_body, self.opt_level);
+        mark_tail_calls(&mut fn_body);
+        let body_len = fn_body.len();
+        let function = Function::new(AnilangFn::new(fn_declaration_node.args, fn_body).into());
 
         self.bytecode.push(Instruction::new(
             InstructionKind::Push {
@@ -628,18 +1163,23 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
         ));
 
         if let Some(ident) = fn_declaration_node.ident {
-            self.bytecode.push(Instruction::new(
-                InstructionKind::Store {
-                    ident,
-                    declaration: true,
-                },
-                fn_declaration_node.span,
-            ));
+            self.lower_store(ident, true, fn_declaration_node.span);
+        }
+
+        if let Some(node) = inline_candidate {
+            if num_diagnostics == self.diagnostics.num_errors() + self.diagnostics.num_warnings() {
+                self.register_inlinable(node, body_len, free_vars);
+            }
         }
     }
 
     fn lower_fn_call(&mut self, fn_call_node: node::FnCallNode) {
         let num_args = fn_call_node.args.len();
+        if let Some((key, function)) = self.inline_target(&fn_call_node.child, num_args) {
+            self.lower_inlined_call(key, &function, fn_call_node.args, fn_call_node.span);
+            return;
+        }
+
         for arg in fn_call_node.args.into_iter().rev() {
             self.lower_node(arg);
         }
@@ -675,6 +1215,8 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
             TokenKind::LEOperator => InstructionKind::CompareLE,
             TokenKind::GEOperator => InstructionKind::CompareGE,
 
+            TokenKind::InKeyword => InstructionKind::BinaryIn,
+
             _ => unreachable!(),
         };
 
@@ -695,18 +1237,68 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
     }
 
     fn lower_break(&mut self, break_node: node::BreakNode) {
-        if let Some(break_label) = self.break_label {
-            self.bytecode.extend(
-                (0..self.scopes_since_loop)
-                    .map(|_| Instruction::new(InstructionKind::PopVar, break_node.span.clone())),
-            );
+        // The number of loops between the break and the loop being broken out of
+        let depth = match break_node.label {
+            Some(ref label) => {
+                let depth = self
+                    .loops
+                    .iter()
+                    .rev()
+                    .position(|target| target.label.as_ref() == Some(label));
+                if depth.is_none() {
+                    self.diagnostics.unknown_label(label, break_node.span);
+                    return;
+                }
+                depth
+            }
+            None if self.loops.is_empty() => None,
+            None => Some(0),
+        };
+
+        let depth = match depth {
+            Some(depth) => depth,
+            None => {
+                self.diagnostics.break_outside_loop(break_node.span);
+                return;
+            }
+        };
+
+        if let Some(value) = break_node.value {
+            self.lower_node(*value);
+        } else {
             self.bytecode.push(Instruction::new(
-                InstructionKind::JumpTo { label: break_label },
-                break_node.span,
+                InstructionKind::Push { value: Value::Null },
+                break_node.span.clone(),
+            ));
+        }
+
+        // All the scopes inside the loop being broken out of are exited, including those of the
+        // nested loops
+        let scopes = self.scopes_since_loop
+            + self
+                .loops
+                .iter()
+                .rev()
+                .take(depth)
+                .map(|target| target.outer_scopes_since_loop + 1)
+                .sum::<usize>();
+
+        for depth in 0..scopes {
+            self.lower_deferred(depth);
+            self.bytecode.push(Instruction::new(
+                InstructionKind::PopVar,
+                break_node.span.clone(),
             ));
-        } else {
-            self.diagnostics.break_outside_loop(break_node.span);
         }
+        // The loop's scope is popped after the end label, but its deferred statements are run
+        // at the end of every iteration, so they have to be run here
+        self.lower_deferred(scopes);
+
+        let end_label = self.loops[self.loops.len() - 1 - depth].end_label;
+        self.bytecode.push(Instruction::new(
+            InstructionKind::JumpTo { target: end_label },
+            break_node.span,
+        ));
     }
 
     fn lower_return(&mut self, return_node: node::ReturnNode) {
@@ -721,13 +1313,14 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
 
         if let Some(return_label) = self.return_label {
             let span = return_node.span;
-            self.bytecode.extend(
-                (0..self.scopes_since_fn)
-                    .map(|_| Instruction::new(InstructionKind::PopVar, span.clone())),
-            );
+            for depth in 0..self.scopes_since_fn {
+                self.lower_deferred(depth);
+                self.bytecode
+                    .push(Instruction::new(InstructionKind::PopVar, span.clone()));
+            }
             self.bytecode.push(Instruction::new(
                 InstructionKind::JumpTo {
-                    label: return_label,
+                    target: return_label,
                 },
                 span,
             ));
@@ -736,3 +1329,107 @@ impl<'diagnostics, 'src> Lowerer<'diagnostics, 'src> {
         }
     }
 }
+
+/// Resolves the labels of lowered bytecode, after running the optimizer over it if optimizations
+/// are enabled.
+fn finalize(mut bytecode: Bytecode, opt_level: OptLevel) -> Chunk {
+    if opt_level >= OptLevel::O1 {
+        optimizer::optimize(&mut bytecode);
+    }
+    finalize_bytecode(bytecode)
+}
+
+/// Turns the calls in a finalized function body which are in tail position into tail calls. A call
+/// is in tail position if nothing but exiting scopes and jumping happens between it and the end of
+/// the body. This covers the last statement of the body, `return f(...)`, and the branches of an
+/// `if` which is itself in tail position, but not calls followed by deferred statements.
+fn mark_tail_calls(body: &mut Chunk) {
+    for i in 0..body.ops.len() {
+        if let Op::CallFunction { num_args } = body.ops[i].get() {
+            if is_tail_position(&body.ops, i + 1) {
+                body.ops[i].set(Op::TailCallFunction { num_args });
+            }
+        }
+    }
+}
+
+fn is_tail_position(ops: &[Cell<Op>], mut i: usize) -> bool {
+    // Following more ops than there are in the body means the jumps loop forever
+    for _ in 0..=ops.len() {
+        match ops.get(i).map(Cell::get) {
+            None => return true,
+            Some(Op::PopVar) => i += 1,
+            Some(Op::JumpTo { target }) => i = target as usize,
+            Some(_) => return false,
+        }
+    }
+    false
+}
+
+/// Finds the variables a statement declares in the scope it is run in. Blocks, loops, comprehensions
+/// and functions have scopes of their own, so they are not searched.
+fn collect_declarations(node: &SyntaxNode, declarations: &mut Vec<(Rc<str>, TextSpan)>) {
+    match node {
+        SyntaxNode::DeclarationNode(node) => {
+            collect_declarations(&node.value, declarations);
+            declarations.push((Rc::clone(&node.ident), node.span.clone()));
+        }
+        SyntaxNode::FnDeclarationNode(node) => {
+            if let Some(ref ident) = node.ident {
+                declarations.push((Rc::clone(ident), node.span.clone()));
+            }
+        }
+        SyntaxNode::InterfaceNode(node) => {
+            for (k, v) in node.values.iter() {
+                if **k == *node.ident {
+                    declarations.push((Rc::clone(&node.ident), v.span().clone()));
+                } else {
+                    collect_declarations(v, declarations);
+                    let ident = format!("{}::{}", node.ident, k);
+                    declarations.push((ident.into(), v.span().clone()));
+                }
+            }
+        }
+        SyntaxNode::AssignmentNode(node) => {
+            collect_declarations(&node.value, declarations);
+            for index in node.indices.iter().flatten() {
+                collect_declarations(index, declarations);
+            }
+        }
+        SyntaxNode::ListNode(node::ListNode { elements, .. })
+        | SyntaxNode::ObjectNode(node::ObjectNode { elements, .. }) => {
+            for element in elements {
+                collect_declarations(element, declarations);
+            }
+        }
+        SyntaxNode::IndexNode(node) => {
+            collect_declarations(&node.index, declarations);
+            collect_declarations(&node.child, declarations);
+        }
+        SyntaxNode::FnCallNode(node) => {
+            for arg in node.args.iter() {
+                collect_declarations(arg, declarations);
+            }
+            collect_declarations(&node.child, declarations);
+        }
+        SyntaxNode::BinaryNode(node) => {
+            collect_declarations(&node.right, declarations);
+            collect_declarations(&node.left, declarations);
+        }
+        SyntaxNode::UnaryNode(node) => collect_declarations(&node.child, declarations),
+        SyntaxNode::IfNode(node) => collect_declarations(&node.cond, declarations),
+        SyntaxNode::ComprehensionNode(node) => collect_declarations(&node.iter, declarations),
+        SyntaxNode::DeferNode(node) => collect_declarations(&node.statement, declarations),
+        SyntaxNode::BreakNode(node::BreakNode { value, .. })
+        | SyntaxNode::ReturnNode(node::ReturnNode { value, .. }) => {
+            if let Some(value) = value {
+                collect_declarations(value, declarations);
+            }
+        }
+        SyntaxNode::BlockNode(_)
+        | SyntaxNode::LoopNode(_)
+        | SyntaxNode::LiteralNode(_)
+        | SyntaxNode::VariableNode(_)
+        | SyntaxNode::BadNode(_) => {}
+    }
+}
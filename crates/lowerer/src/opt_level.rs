@@ -0,0 +1,38 @@
+use std::str::FromStr;
+
+/// How much the lowerer optimizes the bytecode it produces. Each level does everything the levels
+/// below it do. Higher levels produce faster bytecode, but take longer to lower, so they are worth
+/// it when code is compiled once and run many times.
+///
+/// # Examples
+/// ```
+/// use lowerer::OptLevel;
+///
+/// assert_eq!("2".parse(), Ok(OptLevel::O2));
+/// assert!(OptLevel::O1 > OptLevel::O0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// No optimizations, the bytecode follows the source code as closely as possible
+    O0,
+    /// Constant expressions are evaluated while lowering, and the lowered bytecode is cleaned up
+    O1,
+    /// Aggressive optimizations, which may make the bytecode larger, like inlining small functions
+    O2,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            _ => Err(format!(
+                "'{}' is not an optimization level, expected 0, 1 or 2",
+                s
+            )),
+        }
+    }
+}
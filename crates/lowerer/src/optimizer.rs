@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::Rc;
+use vm::{Bytecode, Instruction, InstructionKind, LabelNumber, Value};
+
+/// A pass over lowered bytecode, which returns whether it changed anything.
+type Pass = fn(&mut Bytecode) -> bool;
+
+/// Removing an instruction in one pass can let another pass remove more, so the passes are run
+/// until none of them change anything.
+const PASSES: [Pass; 4] = [
+    remove_unreachable,
+    thread_jumps,
+    remove_empty_scopes,
+    remove_push_pop,
+];
+
+/// Optimizes lowered bytecode before its labels are resolved. Unlike the [`ConstEvaluator`], which
+/// works on whole subtrees, these passes only look at the instructions, so they also clean up what
+/// the lowerer emits around expressions which aren't constant.
+///
+/// [`ConstEvaluator`]: super::ConstEvaluator
+pub(super) fn optimize(bytecode: &mut Bytecode) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for pass in PASSES.iter() {
+            changed |= pass(bytecode);
+        }
+    }
+
+    // The other passes look for the instructions which are fused, so fusing is done last
+    fuse_instructions(bytecode);
+}
+
+fn jump_target(kind: &InstructionKind) -> Option<LabelNumber> {
+    match *kind {
+        InstructionKind::JumpTo { target }
+        | InstructionKind::PopJumpIfTrue { target }
+        | InstructionKind::PopJumpIfFalse { target }
+        | InstructionKind::ForIter { target } => Some(target),
+        _ => None,
+    }
+}
+
+fn jump_target_mut(kind: &mut InstructionKind) -> Option<&mut LabelNumber> {
+    match kind {
+        InstructionKind::JumpTo { target }
+        | InstructionKind::PopJumpIfTrue { target }
+        | InstructionKind::PopJumpIfFalse { target }
+        | InstructionKind::ForIter { target } => Some(target),
+        _ => None,
+    }
+}
+
+/// Finds the index of the instruction each label is in front of.
+fn label_destinations(bytecode: &[Instruction]) -> HashMap<LabelNumber, usize> {
+    let mut destinations = HashMap::new();
+    let mut labels = Vec::new();
+    for (i, instr) in bytecode.iter().enumerate() {
+        if let InstructionKind::Label { number } = instr.kind {
+            labels.push(number);
+        } else {
+            destinations.extend(labels.drain(..).map(|label| (label, i)));
+        }
+    }
+    destinations.extend(labels.drain(..).map(|label| (label, bytecode.len())));
+    destinations
+}
+
+/// Removes the instructions which can never be run. Everything after a `JumpTo` is unreachable up
+/// to a label which a reachable instruction jumps to, which covers the code after a `break` or a
+/// `return`, as well as loops which are only entered from such code. Labels which nothing jumps to
+/// are removed as well.
+fn remove_unreachable(bytecode: &mut Bytecode) -> bool {
+    let mut reachable_labels = HashSet::new();
+    let mut reachable = vec![false; bytecode.len()];
+
+    // A label may only be found to be reachable after the code following it has been visited, so
+    // the bytecode is visited until no more labels are found
+    let mut found_label = true;
+    while found_label {
+        found_label = false;
+        let mut is_reachable = true;
+        for (i, instr) in bytecode.iter().enumerate() {
+            if let InstructionKind::Label { number } = instr.kind {
+                is_reachable |= reachable_labels.contains(&number);
+            }
+            reachable[i] = is_reachable;
+            if !is_reachable {
+                continue;
+            }
+
+            if let Some(target) = jump_target(&instr.kind) {
+                found_label |= reachable_labels.insert(target);
+            }
+            if let InstructionKind::JumpTo { .. } = instr.kind {
+                is_reachable = false;
+            }
+        }
+    }
+
+    let len = bytecode.len();
+    let mut reachable = reachable.into_iter();
+    bytecode.retain(|instr| {
+        let is_reachable = reachable.next().unwrap();
+        match instr.kind {
+            InstructionKind::Label { number } => reachable_labels.contains(&number),
+            _ => is_reachable,
+        }
+    });
+    bytecode.len() != len
+}
+
+/// Makes jumps which land on a `JumpTo` go straight to where it goes, and removes `JumpTo`s to the
+/// instruction right after them.
+fn thread_jumps(bytecode: &mut Bytecode) -> bool {
+    let destinations = label_destinations(bytecode);
+
+    let mut changed = false;
+    for i in 0..bytecode.len() {
+        let mut target = match jump_target(&bytecode[i].kind) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        // Following more jumps than there are instructions means the jumps loop forever
+        for _ in 0..bytecode.len() {
+            match bytecode.get(destinations[&target]).map(|instr| &instr.kind) {
+                Some(InstructionKind::JumpTo { target: next }) if *next != target => target = *next,
+                _ => break,
+            }
+        }
+
+        let current = jump_target_mut(&mut bytecode[i].kind).unwrap();
+        if *current != target {
+            *current = target;
+            changed = true;
+        }
+    }
+
+    // The index of the first instruction from `i` which isn't a label
+    let next = |bytecode: &[Instruction], i: usize| {
+        bytecode[i..]
+            .iter()
+            .position(|instr| !matches!(instr.kind, InstructionKind::Label { .. }))
+            .map_or(bytecode.len(), |offset| i + offset)
+    };
+
+    let mut keep = vec![true; bytecode.len()];
+    for i in 0..bytecode.len() {
+        if !keep[i] {
+            continue;
+        }
+
+        match (
+            &bytecode[i].kind,
+            bytecode.get(i + 1).map(|instr| &instr.kind),
+        ) {
+            (InstructionKind::JumpTo { target }, _) => {
+                keep[i] = destinations[target] != next(bytecode, i + 1);
+            }
+            // A conditional jump over a `JumpTo` is the same as the opposite conditional jump to
+            // where the `JumpTo` goes
+            (
+                InstructionKind::PopJumpIfTrue { target }
+                | InstructionKind::PopJumpIfFalse { target },
+                Some(InstructionKind::JumpTo { target: over }),
+            ) if destinations[target] == next(bytecode, i + 2) => {
+                let target = *over;
+                bytecode[i].kind = match bytecode[i].kind {
+                    InstructionKind::PopJumpIfTrue { .. } => {
+                        InstructionKind::PopJumpIfFalse { target }
+                    }
+                    _ => InstructionKind::PopJumpIfTrue { target },
+                };
+                keep[i + 1] = false;
+                changed = true;
+            }
+            _ => {}
+        }
+    }
+
+    let len = bytecode.len();
+    let mut keep = keep.into_iter();
+    bytecode.retain(|_| keep.next().unwrap());
+    changed || bytecode.len() != len
+}
+
+/// Removes scopes which nothing is declared in. Only scopes which can't be left except through the
+/// `PopVar` at their end are removed, so there is no other `PopVar` which would have to be removed
+/// along with it.
+///
+/// The first instruction is never removed, since it is either the root scope or the scope the
+/// arguments of a function are stored in.
+fn remove_empty_scopes(bytecode: &mut Bytecode) -> bool {
+    let mut changed = false;
+    let mut i = 1;
+    while i < bytecode.len() {
+        if let InstructionKind::PushVar { .. } = bytecode[i].kind {
+            if let Some(len) = empty_scope_len(&bytecode[i + 1..]) {
+                let end = i + 1 + len;
+                // The variables of enclosing scopes are now one scope closer
+                for instr in bytecode[i + 1..end].iter_mut() {
+                    match &mut instr.kind {
+                        InstructionKind::LoadLocal { depth, .. }
+                        | InstructionKind::StoreLocal { depth, .. } => *depth -= 1,
+                        _ => {}
+                    }
+                }
+
+                bytecode.remove(end);
+                bytecode.remove(i);
+                changed = true;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    changed
+}
+
+/// Finds the number of instructions between a `PushVar` and the `PopVar` which ends its scope, if
+/// the scope can be removed. `body` is the bytecode after the `PushVar`.
+fn empty_scope_len(body: &[Instruction]) -> Option<usize> {
+    for (i, instr) in body.iter().enumerate() {
+        match &instr.kind {
+            InstructionKind::PopVar => return Some(i),
+            // Uses a variable in the scope
+            InstructionKind::LoadLocal { depth: 0, .. }
+            | InstructionKind::StoreLocal { depth: 0, .. }
+            | InstructionKind::Store {
+                declaration: true, ..
+            } => return None,
+            // Functions look up variables through the scope they are declared in
+            InstructionKind::Push {
+                value: Value::Function(_),
+            } => return None,
+            InstructionKind::PushVar { .. } | InstructionKind::Label { .. } => return None,
+            kind if jump_target(kind).is_some() => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Removes values which are popped right after being pushed. This includes values pushed right
+/// before jumping to a `Pop`, like the `null` of an `if` without an `else`, in which case the jump
+/// goes past the `Pop` instead.
+fn remove_push_pop(bytecode: &mut Bytecode) -> bool {
+    let len = bytecode.len();
+    let mut i = 0;
+    while i + 1 < bytecode.len() {
+        match (&bytecode[i].kind, &bytecode[i + 1].kind) {
+            (InstructionKind::Push { .. }, InstructionKind::Pop) => {
+                bytecode.drain(i..i + 2);
+                // The instruction before may now be followed by a `Pop`
+                i = i.saturating_sub(1);
+            }
+            _ => i += 1,
+        }
+    }
+
+    let destinations = label_destinations(bytecode);
+    let mut next_label = bytecode
+        .iter()
+        .filter_map(|instr| match instr.kind {
+            InstructionKind::Label { number } => Some(number + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    // The labels to add after each `Pop` which is jumped over, by the index of the `Pop`
+    let mut after_pop = HashMap::new();
+    // The instructions to remove, or labels to insert, at each index
+    let mut edits = Vec::new();
+    for i in 1..bytecode.len() {
+        if let (InstructionKind::Push { .. }, InstructionKind::JumpTo { target }) =
+            (&bytecode[i - 1].kind, &bytecode[i].kind)
+        {
+            let destination = destinations[target];
+            if let Some(InstructionKind::Pop) = bytecode.get(destination).map(|instr| &instr.kind) {
+                let label = *after_pop.entry(destination).or_insert_with(|| {
+                    next_label += 1;
+                    edits.push((destination + 1, Some(next_label - 1)));
+                    next_label - 1
+                });
+                bytecode[i].kind = InstructionKind::JumpTo { target: label };
+                edits.push((i - 1, None));
+            }
+        }
+    }
+
+    // Edits are made from the back so the indices of the rest stay the same. If a `Push` right
+    // after a `Pop` is removed, it has to be removed before the label is inserted in its place
+    edits.sort_by(|(a, a_label), (b, b_label)| {
+        b.cmp(a).then(a_label.is_some().cmp(&b_label.is_some()))
+    });
+    for (i, label) in edits.iter() {
+        match label {
+            Some(number) => {
+                let span = bytecode[i - 1].span.clone();
+                bytecode.insert(
+                    *i,
+                    Instruction::new(InstructionKind::Label { number: *number }, span),
+                );
+            }
+            None => {
+                bytecode.remove(*i);
+            }
+        }
+    }
+
+    bytecode.len() != len || !edits.is_empty()
+}
+
+/// Replaces common pairs of instructions with a single instruction which does the same thing, so
+/// fewer instructions are dispatched when the bytecode is run.
+fn fuse_instructions(bytecode: &mut Bytecode) {
+    let mut instrs = mem::take(bytecode).into_iter().peekable();
+    while let Some(instr) = instrs.next() {
+        let fused = match (&instr.kind, instrs.peek().map(|next| &next.kind)) {
+            (InstructionKind::UnaryNot, Some(InstructionKind::PopJumpIfTrue { target })) => {
+                InstructionKind::PopJumpIfFalse { target: *target }
+            }
+            (InstructionKind::UnaryNot, Some(InstructionKind::PopJumpIfFalse { target })) => {
+                InstructionKind::PopJumpIfTrue { target: *target }
+            }
+            (InstructionKind::Store { ident, declaration }, Some(InstructionKind::Pop)) => {
+                InstructionKind::PopStore {
+                    ident: Rc::clone(ident),
+                    declaration: *declaration,
+                }
+            }
+            (
+                InstructionKind::StoreLocal {
+                    depth,
+                    slot,
+                    declaration,
+                },
+                Some(InstructionKind::Pop),
+            ) => InstructionKind::PopStoreLocal {
+                depth: *depth,
+                slot: *slot,
+                declaration: *declaration,
+            },
+            _ => {
+                bytecode.push(instr);
+                continue;
+            }
+        };
+
+        instrs.next();
+        bytecode.push(Instruction::new(fused, instr.span));
+    }
+}
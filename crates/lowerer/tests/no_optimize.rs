@@ -1,10 +1,10 @@
 use diagnostics::Diagnostics;
 use intermediaries::{node, SyntaxNode, TokenKind};
-use lowerer::Lowerer;
+use lowerer::{Lowerer, OptLevel};
 use source::{SourceText, TextSpan};
 use std::rc::Rc;
 use vm::test_helpers::*;
-use vm::{Bytecode, Instruction, InstructionKind, Scope, Type, Value};
+use vm::{finalize_bytecode, Bytecode, Instruction, InstructionKind, Scope, Type, Value};
 
 // These tests are leveraging the fact that scopes are compared through id only, in a real world
 // environment, this would be erroneous
@@ -20,7 +20,7 @@ fn lower_b(node: node::BlockNode) -> Bytecode {
     // The source text is only needed in diagnostics, so can be ignored
     let src = SourceText::new("");
     let diagnostics = Diagnostics::new(&src).no_print();
-    Lowerer::lower(node, &diagnostics, false)
+    Lowerer::lower(node, &diagnostics, OptLevel::O0).to_bytecode()
 }
 
 fn lower(node: SyntaxNode) -> Bytecode {
@@ -28,11 +28,27 @@ fn lower(node: SyntaxNode) -> Bytecode {
     // Remove first and last elements since they will be specific to the `BlockNode`
     bytecode.pop();
     bytecode.remove(0);
+    // The jumps are to instructions in the full bytecode, which are now one place earlier
+    for instr in bytecode.iter_mut() {
+        match &mut instr.kind {
+            InstructionKind::JumpTo { target }
+            | InstructionKind::PopJumpIfTrue { target }
+            | InstructionKind::ForIter { target } => *target -= 1,
+            _ => {}
+        }
+    }
     bytecode
 }
 
+/// The expected bytecode is written with labels, which are resolved the same way as the lowered
+/// bytecode
 fn test(a: Bytecode, b: Vec<InstructionKind>) {
     let a: Vec<_> = a.into_iter().map(|i| i.kind).collect();
+    let b: Vec<_> = finalize_bytecode(b.into_iter().map(Instruction::from).collect())
+        .to_bytecode()
+        .into_iter()
+        .map(|i| i.kind)
+        .collect();
     assert_eq!(
         a.len(),
         b.len(),
@@ -70,8 +86,29 @@ fn block_from_vec(block: Vec<SyntaxNode>) -> node::BlockNode {
     node::BlockNode::new(block, span())
 }
 
-fn make_break() -> SyntaxNode {
-    SyntaxNode::BreakNode(node::BreakNode::new(span()))
+fn make_break(label: Option<&str>, value: Option<SyntaxNode>) -> SyntaxNode {
+    SyntaxNode::BreakNode(node::BreakNode::with_value(
+        span(),
+        label.map(Rc::from),
+        value,
+    ))
+}
+
+fn make_comprehension(
+    key: Option<SyntaxNode>,
+    value: SyntaxNode,
+    vars: Vec<&str>,
+    iter: SyntaxNode,
+    cond: Option<SyntaxNode>,
+) -> SyntaxNode {
+    SyntaxNode::ComprehensionNode(node::ComprehensionNode {
+        key: key.map(Box::new),
+        value: Box::new(value),
+        vars: vars.into_iter().map(|v| v.into()).collect(),
+        iter: Box::new(iter),
+        cond: cond.map(Box::new),
+        span: span(),
+    })
 }
 
 fn make_declaration(ident: &str, value: SyntaxNode) -> SyntaxNode {
@@ -79,6 +116,14 @@ fn make_declaration(ident: &str, value: SyntaxNode) -> SyntaxNode {
         ident: ident.into(),
         value: Box::new(value),
         span: span(),
+        annotation: None,
+    })
+}
+
+fn make_defer(statement: SyntaxNode) -> SyntaxNode {
+    SyntaxNode::DeferNode(node::DeferNode {
+        statement: Box::new(statement),
+        span: span(),
     })
 }
 
@@ -93,6 +138,8 @@ fn make_fn_call(child: SyntaxNode, args: Vec<SyntaxNode>) -> SyntaxNode {
 fn make_fn_declaration(ident: Option<&str>, args: Vec<&str>, block: Vec<SyntaxNode>) -> SyntaxNode {
     SyntaxNode::FnDeclarationNode(node::FnDeclarationNode {
         ident: ident.map(Rc::from),
+        arg_annotations: vec![None; args.len()],
+        return_annotation: None,
         args: args.into_iter().map(Rc::from).collect(),
         block: block_from_vec(block),
         span: span(),
@@ -142,8 +189,9 @@ fn make_literal(value: Value) -> SyntaxNode {
     })
 }
 
-fn make_loop(block: Vec<SyntaxNode>) -> SyntaxNode {
+fn make_loop(label: Option<&str>, block: Vec<SyntaxNode>) -> SyntaxNode {
     SyntaxNode::LoopNode(node::LoopNode {
+        label: label.map(Rc::from),
         block,
         span: span(),
     })
@@ -205,29 +253,80 @@ fn lower_block_properly() {
                 scope: gen_scope(0),
             },
             InstructionKind::Push { value: i(3) },
-            InstructionKind::Store {
-                ident: "a".into(),
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot: 0,
                 declaration: true,
             },
             InstructionKind::Pop,
             InstructionKind::Load {
                 ident: "global".into(),
             },
-            InstructionKind::Load { ident: "a".into() },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
             InstructionKind::BinarySubtract,
-            InstructionKind::Store {
-                ident: "a".into(),
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot: 0,
                 declaration: false,
             },
             InstructionKind::Pop,
             InstructionKind::Push { value: i(1) },
-            InstructionKind::Load { ident: "a".into() },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
             InstructionKind::BinaryAdd,
             InstructionKind::PopVar,
         ],
     );
 }
 
+#[test]
+fn lower_unknown_references() {
+    let src = SourceText::new("");
+    let count_errors = |block: Vec<SyntaxNode>, globals: Option<Rc<Scope>>| {
+        let diagnostics = Diagnostics::new(&src).no_print();
+        match globals {
+            Some(globals) => {
+                Lowerer::lower_with_std(block_from_vec(block), &diagnostics, globals, OptLevel::O0)
+            }
+            None => Lowerer::lower(block_from_vec(block), &diagnostics, OptLevel::O0),
+        };
+        diagnostics.num_errors()
+    };
+
+    assert_eq!(count_errors(vec![make_variable("a")], None), 1);
+    assert_eq!(
+        count_errors(vec![make_assignment("a", make_literal(i(0)), None)], None),
+        1
+    );
+    assert_eq!(
+        count_errors(
+            vec![
+                make_declaration("a", make_literal(i(0))),
+                make_variable("a"),
+            ],
+            None
+        ),
+        0
+    );
+    assert_eq!(
+        count_errors(
+            vec![
+                make_fn_declaration(
+                    Some("f"),
+                    vec!["b"],
+                    vec![make_variable("a"), make_variable("b")]
+                ),
+                make_declaration("a", make_literal(i(0))),
+            ],
+            None
+        ),
+        0
+    );
+
+    let globals = gen_scope(0);
+    globals.declare("a".into(), i(0)).unwrap();
+    assert_eq!(count_errors(vec![make_variable("a")], Some(globals)), 0);
+}
+
 #[test]
 fn lower_variable_properly() {
     test(
@@ -289,13 +388,13 @@ fn lower_if_properly() {
         bytecode,
         vec![
             InstructionKind::Push { value: b(true) },
-            InstructionKind::PopJumpIfTrue { label: 0 },
+            InstructionKind::PopJumpIfTrue { target: 0 },
             InstructionKind::PushVar {
                 scope: gen_scope(1),
             },
             InstructionKind::Push { value: i(1) },
             InstructionKind::PopVar,
-            InstructionKind::JumpTo { label: 1 },
+            InstructionKind::JumpTo { target: 1 },
             InstructionKind::Label { number: 0 },
             InstructionKind::PushVar {
                 scope: gen_scope(2),
@@ -309,26 +408,29 @@ fn lower_if_properly() {
 
 #[test]
 fn lower_loop_properly() {
-    let bytecode = lower(make_loop(vec![
-        make_if(
-            make_binary(
-                TokenKind::GEOperator,
-                make_variable("a"),
-                make_literal(i(100)),
+    let bytecode = lower(make_loop(
+        None,
+        vec![
+            make_if(
+                make_binary(
+                    TokenKind::GEOperator,
+                    make_variable("a"),
+                    make_literal(i(100)),
+                ),
+                vec![make_break(None, None)],
+                None,
             ),
-            vec![make_break()],
-            None,
-        ),
-        make_assignment(
-            "a",
-            make_binary(
-                TokenKind::PlusOperator,
-                make_variable("a"),
-                make_literal(i(5)),
+            make_assignment(
+                "a",
+                make_binary(
+                    TokenKind::PlusOperator,
+                    make_variable("a"),
+                    make_literal(i(5)),
+                ),
+                None,
             ),
-            None,
-        ),
-    ]));
+        ],
+    ));
 
     let loop_start = 0;
     let loop_end = 1;
@@ -345,15 +447,16 @@ fn lower_loop_properly() {
             InstructionKind::Push { value: i(100) },
             InstructionKind::Load { ident: "a".into() },
             InstructionKind::CompareGE,
-            InstructionKind::PopJumpIfTrue { label: if_then },
+            InstructionKind::PopJumpIfTrue { target: if_then },
             InstructionKind::Push { value: n() },
-            InstructionKind::JumpTo { label: if_end },
+            InstructionKind::JumpTo { target: if_end },
             InstructionKind::Label { number: if_then },
             InstructionKind::PushVar {
                 scope: gen_scope(2),
             },
+            InstructionKind::Push { value: n() },
             InstructionKind::PopVar,
-            InstructionKind::JumpTo { label: loop_end },
+            InstructionKind::JumpTo { target: loop_end },
             InstructionKind::PopVar,
             InstructionKind::Label { number: if_end },
             InstructionKind::Pop,
@@ -365,10 +468,176 @@ fn lower_loop_properly() {
                 declaration: false,
             },
             InstructionKind::Pop,
-            InstructionKind::JumpTo { label: loop_start },
+            InstructionKind::JumpTo { target: loop_start },
             InstructionKind::Label { number: loop_end },
             InstructionKind::PopVar,
+        ],
+    );
+}
+
+#[test]
+fn lower_labeled_break_properly() {
+    let bytecode = lower(make_loop(
+        Some("'outer"),
+        vec![make_loop(
+            None,
+            vec![make_if(
+                make_variable("a"),
+                vec![make_break(Some("'outer"), Some(make_literal(i(1))))],
+                None,
+            )],
+        )],
+    ));
+
+    let outer_start = 0;
+    let outer_end = 1;
+    let inner_start = 2;
+    let inner_end = 3;
+    let if_then = 4;
+    let if_end = 5;
+
+    test(
+        bytecode,
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::Label {
+                number: outer_start,
+            },
+            InstructionKind::PushVar {
+                scope: gen_scope(2),
+            },
+            InstructionKind::Label {
+                number: inner_start,
+            },
+            InstructionKind::Load { ident: "a".into() },
+            InstructionKind::PopJumpIfTrue { target: if_then },
             InstructionKind::Push { value: n() },
+            InstructionKind::JumpTo { target: if_end },
+            InstructionKind::Label { number: if_then },
+            InstructionKind::PushVar {
+                scope: gen_scope(3),
+            },
+            InstructionKind::Push { value: i(1) },
+            // Pop the if block's and inner loop's scopes
+            InstructionKind::PopVar,
+            InstructionKind::PopVar,
+            InstructionKind::JumpTo { target: outer_end },
+            InstructionKind::PopVar,
+            InstructionKind::Label { number: if_end },
+            InstructionKind::Pop,
+            InstructionKind::JumpTo {
+                target: inner_start,
+            },
+            InstructionKind::Label { number: inner_end },
+            InstructionKind::PopVar,
+            InstructionKind::Pop,
+            InstructionKind::JumpTo {
+                target: outer_start,
+            },
+            InstructionKind::Label { number: outer_end },
+            InstructionKind::PopVar,
+        ],
+    );
+}
+
+#[test]
+fn lower_comprehension_properly() {
+    let bytecode = lower(make_comprehension(
+        None,
+        make_binary(
+            TokenKind::StarOperator,
+            make_variable("x"),
+            make_literal(i(2)),
+        ),
+        vec!["x"],
+        make_variable("xs"),
+        Some(make_variable("x")),
+    ));
+
+    let start = 0;
+    let end = 1;
+
+    test(
+        bytecode,
+        vec![
+            InstructionKind::MakeList { len: 0 },
+            InstructionKind::Load { ident: "xs".into() },
+            InstructionKind::MakeIter,
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::Label { number: start },
+            InstructionKind::ForIter { target: end },
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot: 0,
+                declaration: true,
+            },
+            InstructionKind::Pop,
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::UnaryNot,
+            InstructionKind::PopJumpIfTrue { target: start },
+            InstructionKind::Push { value: i(2) },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::BinaryMultiply,
+            InstructionKind::ListAppend { depth: 2 },
+            InstructionKind::JumpTo { target: start },
+            InstructionKind::Label { number: end },
+            InstructionKind::PopVar,
+        ],
+    );
+
+    let bytecode = lower(make_comprehension(
+        Some(make_variable("k")),
+        make_variable("v"),
+        vec!["k", "v"],
+        make_variable("o"),
+        None,
+    ));
+
+    let k_store = InstructionKind::StoreLocal {
+        depth: 0,
+        slot: 0,
+        declaration: true,
+    };
+    let v_store = InstructionKind::StoreLocal {
+        depth: 0,
+        slot: 1,
+        declaration: true,
+    };
+    let k_load = InstructionKind::LoadLocal { depth: 0, slot: 0 };
+
+    test(
+        bytecode,
+        vec![
+            InstructionKind::MakeObject { len: 0 },
+            InstructionKind::Load { ident: "o".into() },
+            InstructionKind::MakeIter,
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::Label { number: start },
+            InstructionKind::ForIter { target: end },
+            k_store.clone(),
+            InstructionKind::Pop,
+            InstructionKind::Push { value: i(1) },
+            k_load.clone(),
+            InstructionKind::GetIndex,
+            v_store,
+            InstructionKind::Pop,
+            InstructionKind::Push { value: i(0) },
+            k_load.clone(),
+            InstructionKind::GetIndex,
+            k_store,
+            InstructionKind::Pop,
+            InstructionKind::LoadLocal { depth: 0, slot: 1 },
+            k_load,
+            InstructionKind::ObjectInsert { depth: 2 },
+            InstructionKind::JumpTo { target: start },
+            InstructionKind::Label { number: end },
+            InstructionKind::PopVar,
         ],
     );
 }
@@ -436,20 +705,19 @@ fn lower_interface_properly() {
             let f = f.as_anilang_fn().unwrap();
             assert!(f.args.is_empty());
             test(
-                f.body.clone(),
+                f.body.to_bytecode(),
                 vec![
                     InstructionKind::PushVar {
                         scope: gen_scope(1),
                     },
                     InstructionKind::MakeObject { len: 0 },
-                    InstructionKind::Store {
-                        ident: "self".into(),
+                    InstructionKind::StoreLocal {
+                        depth: 0,
+                        slot: 0,
                         declaration: true,
                     },
                     InstructionKind::Pop,
-                    InstructionKind::Load {
-                        ident: "self".into(),
-                    },
+                    InstructionKind::LoadLocal { depth: 0, slot: 0 },
                     InstructionKind::PopVar,
                     InstructionKind::Label { number: 0 },
                 ],
@@ -460,8 +728,9 @@ fn lower_interface_properly() {
 
     assert_eq!(
         bytecode.next().unwrap().kind,
-        InstructionKind::Store {
-            ident: "I".into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 0,
             declaration: true
         }
     );
@@ -515,7 +784,7 @@ fn lower_interface_properly() {
             let f = value.into_rc_fn();
             let f = f.as_anilang_fn().unwrap();
             assert_eq!(f.args, vec!["val".into()]);
-            f.body.clone()
+            f.body.to_bytecode()
         }
         i => panic!("expected InstructionKind::Push function, got {:?}", i),
     }
@@ -534,16 +803,14 @@ fn lower_interface_properly() {
             let f = f.as_anilang_fn().unwrap();
             assert_eq!(f.args, vec!["self".into()]);
             test(
-                f.body.clone(),
+                f.body.to_bytecode(),
                 vec![
                     InstructionKind::PushVar {
                         scope: gen_scope(2 + delta),
                     },
                     InstructionKind::Push { value: i(10) },
                     InstructionKind::Push { value: s("val") },
-                    InstructionKind::Load {
-                        ident: "self".into(),
-                    },
+                    InstructionKind::LoadLocal { depth: 0, slot: 0 },
                     InstructionKind::GetIndex,
                     InstructionKind::BinaryAdd,
                     InstructionKind::PopVar,
@@ -578,8 +845,9 @@ fn lower_interface_properly() {
 
     assert_eq!(
         body.next().unwrap().kind,
-        InstructionKind::Store {
-            ident: "self".into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 1,
             declaration: true
         }
     );
@@ -587,9 +855,7 @@ fn lower_interface_properly() {
 
     assert_eq!(
         body.next().unwrap().kind,
-        InstructionKind::Load {
-            ident: "val".into()
-        }
+        InstructionKind::LoadLocal { depth: 0, slot: 0 }
     );
     assert_eq!(
         body.next().unwrap().kind,
@@ -597,30 +863,31 @@ fn lower_interface_properly() {
     );
     assert_eq!(
         body.next().unwrap().kind,
-        InstructionKind::Load {
-            ident: "self".into()
-        }
+        InstructionKind::LoadLocal { depth: 0, slot: 1 }
     );
     assert_eq!(body.next().unwrap().kind, InstructionKind::SetIndex);
-    assert_eq!(body.next().unwrap().kind, InstructionKind::Pop);
-
     assert_eq!(
         body.next().unwrap().kind,
-        InstructionKind::Load {
-            ident: "self".into()
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 1,
+            declaration: false
         }
     );
-    assert_eq!(body.next().unwrap().kind, InstructionKind::PopVar);
+    assert_eq!(body.next().unwrap().kind, InstructionKind::Pop);
+
     assert_eq!(
         body.next().unwrap().kind,
-        InstructionKind::Label { number: 0 }
+        InstructionKind::LoadLocal { depth: 0, slot: 1 }
     );
+    assert_eq!(body.next().unwrap().kind, InstructionKind::PopVar);
     assert_eq!(body.next(), None);
 
     assert_eq!(
         bytecode.next().unwrap().kind,
-        InstructionKind::Store {
-            ident: "I".into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 0,
             declaration: true
         }
     );
@@ -630,8 +897,9 @@ fn lower_interface_properly() {
     );
     assert_eq!(
         bytecode.next().unwrap().kind,
-        InstructionKind::Store {
-            ident: "I::val".into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 1,
             declaration: true
         }
     );
@@ -639,8 +907,9 @@ fn lower_interface_properly() {
     match_val_10(bytecode.next().unwrap(), 1);
     assert_eq!(
         bytecode.next().unwrap().kind,
-        InstructionKind::Store {
-            ident: "I::val_10".into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 2,
             declaration: true
         }
     );
@@ -671,10 +940,11 @@ fn lower_assignment_properly() {
             InstructionKind::Push { value: i(1) },
             InstructionKind::Push { value: i(0) },
             InstructionKind::Load { ident: "a".into() },
-            InstructionKind::GetIndex,
-            InstructionKind::SetIndex,
-            InstructionKind::Pop,
-            InstructionKind::Load { ident: "a".into() },
+            InstructionKind::SetNestedIndex { depth: 2 },
+            InstructionKind::Store {
+                ident: "a".into(),
+                declaration: false,
+            },
         ],
     );
 }
@@ -685,14 +955,75 @@ fn lowerer_declaration_properly() {
         lower(make_declaration("a", make_literal(i(0)))),
         vec![
             InstructionKind::Push { value: i(0) },
-            InstructionKind::Store {
-                ident: "a".into(),
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot: 0,
                 declaration: true,
             },
         ],
     );
 }
 
+#[test]
+fn lower_defer_properly() {
+    let bytecode = lower_b(block_from_vec(vec![
+        make_defer(make_fn_call(make_variable("f"), vec![])),
+        make_literal(i(1)),
+    ]));
+
+    test(
+        bytecode,
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(0),
+            },
+            InstructionKind::Push { value: n() },
+            InstructionKind::Pop,
+            InstructionKind::Push { value: i(1) },
+            InstructionKind::Load { ident: "f".into() },
+            InstructionKind::CallFunction { num_args: 0 },
+            InstructionKind::Pop,
+            InstructionKind::PopVar,
+        ],
+    );
+
+    // Deferred statements in a loop are run at the end of every iteration, and before breaking
+    let bytecode = lower(make_loop(
+        None,
+        vec![
+            make_defer(make_fn_call(make_variable("f"), vec![])),
+            make_break(None, None),
+        ],
+    ));
+
+    let loop_start = 0;
+    let loop_end = 1;
+
+    test(
+        bytecode,
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::Label { number: loop_start },
+            InstructionKind::Push { value: n() },
+            InstructionKind::Pop,
+            InstructionKind::Push { value: n() },
+            InstructionKind::Load { ident: "f".into() },
+            InstructionKind::CallFunction { num_args: 0 },
+            InstructionKind::Pop,
+            InstructionKind::JumpTo { target: loop_end },
+            InstructionKind::Pop,
+            InstructionKind::Load { ident: "f".into() },
+            InstructionKind::CallFunction { num_args: 0 },
+            InstructionKind::Pop,
+            InstructionKind::JumpTo { target: loop_start },
+            InstructionKind::Label { number: loop_end },
+            InstructionKind::PopVar,
+        ],
+    );
+}
+
 #[test]
 fn lower_fn_declaration_properly() {
     let bytecode = lower(make_fn_declaration(Some("a"), vec!["arg1"], vec![]));
@@ -712,8 +1043,9 @@ fn lower_fn_declaration_properly() {
 
     assert_eq!(
         bytecode[1].kind,
-        InstructionKind::Store {
-            ident: "a".into(),
+        InstructionKind::StoreLocal {
+            depth: 0,
+            slot: 0,
             declaration: true
         }
     );
@@ -733,7 +1065,7 @@ fn lower_fn_declaration_properly() {
         } => {
             let f = f.as_anilang_fn().unwrap();
             assert_eq!(f.args, vec!["arg1".into()]);
-            f.body.clone()
+            f.body.to_bytecode()
         }
         i => panic!("Expected Push Value::Function, got {:?}", i),
     };
@@ -744,7 +1076,7 @@ fn lower_fn_declaration_properly() {
         },
         InstructionKind::Push { value: i(123) },
         InstructionKind::PopVar,
-        InstructionKind::JumpTo { label: 0 },
+        InstructionKind::JumpTo { target: 0 },
         InstructionKind::PopVar,
         InstructionKind::Label { number: 0 },
     ];
@@ -786,6 +1118,81 @@ fn lower_fn_call_properly() {
     );
 }
 
+#[test]
+fn lower_tail_call_properly() {
+    let fn_body = |block| {
+        let bytecode = lower(make_fn_declaration(None, vec!["a"], block));
+        match &bytecode[0].kind {
+            InstructionKind::Push {
+                value: Value::Function(f),
+            } => f.as_anilang_fn().unwrap().body.to_bytecode(),
+            i => panic!("Expected Push Value::Function, got {:?}", i),
+        }
+    };
+    let call = || make_fn_call(make_variable("a"), vec![]);
+
+    test(
+        fn_body(vec![call()]),
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::TailCallFunction { num_args: 0 },
+            InstructionKind::PopVar,
+        ],
+    );
+
+    test(
+        fn_body(vec![make_return(Some(call())), make_literal(i(1))]),
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::TailCallFunction { num_args: 0 },
+            InstructionKind::PopVar,
+            InstructionKind::JumpTo { target: 0 },
+            InstructionKind::Pop,
+            InstructionKind::Push { value: i(1) },
+            InstructionKind::PopVar,
+            InstructionKind::Label { number: 0 },
+        ],
+    );
+
+    // The deferred statement is run after the call returns
+    test(
+        fn_body(vec![make_defer(make_literal(i(1))), call()]),
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::Push { value: n() },
+            InstructionKind::Pop,
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::CallFunction { num_args: 0 },
+            InstructionKind::Push { value: i(1) },
+            InstructionKind::Pop,
+            InstructionKind::PopVar,
+        ],
+    );
+
+    // Only the outer call is in tail position
+    test(
+        fn_body(vec![make_fn_call(make_variable("a"), vec![call()])]),
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(1),
+            },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::CallFunction { num_args: 0 },
+            InstructionKind::LoadLocal { depth: 0, slot: 0 },
+            InstructionKind::TailCallFunction { num_args: 1 },
+            InstructionKind::PopVar,
+        ],
+    );
+}
+
 #[test]
 fn lower_range_properly() {
     test(
@@ -985,6 +1392,19 @@ fn lower_binary_properly() {
             InstructionKind::CompareGE,
         ],
     );
+
+    test(
+        lower(make_binary(
+            TokenKind::InKeyword,
+            make_literal(i(1)),
+            make_literal(r(0, 2)),
+        )),
+        vec![
+            InstructionKind::Push { value: r(0, 2) },
+            InstructionKind::Push { value: i(1) },
+            InstructionKind::BinaryIn,
+        ],
+    );
 }
 
 #[test]
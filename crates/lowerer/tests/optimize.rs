@@ -1,6 +1,6 @@
 use diagnostics::Diagnostics;
 use intermediaries::{node, SyntaxNode, TokenKind};
-use lowerer::Lowerer;
+use lowerer::{Lowerer, OptLevel};
 use source::{SourceText, TextSpan};
 use std::rc::Rc;
 use vm::test_helpers::*;
@@ -16,11 +16,15 @@ fn span() -> TextSpan {
     Default::default()
 }
 
-fn lower_b(node: node::BlockNode) -> Bytecode {
+fn lower_at(node: node::BlockNode, opt_level: OptLevel) -> Bytecode {
     // The source text is only needed in diagnostics, so can be ignored
     let src = crate::SourceText::new("");
     let diagnostics = Diagnostics::new(&src).no_print();
-    Lowerer::lower(node, &diagnostics, true)
+    Lowerer::lower(node, &diagnostics, opt_level).to_bytecode()
+}
+
+fn lower_b(node: node::BlockNode) -> Bytecode {
+    lower_at(node, OptLevel::O1)
 }
 
 fn lower(node: SyntaxNode) -> Bytecode {
@@ -36,6 +40,7 @@ fn optimize_arithmetic_expr() {
     let bytecode = lower(SyntaxNode::BlockNode(node::BlockNode {
         block: vec![SyntaxNode::DeclarationNode(node::DeclarationNode {
             ident: "a".into(),
+            annotation: None,
             value: Box::new(SyntaxNode::BinaryNode(node::BinaryNode {
                 operator: TokenKind::StarOperator,
                 left: Box::new(SyntaxNode::LiteralNode(node::LiteralNode {
@@ -61,8 +66,9 @@ fn optimize_arithmetic_expr() {
             }
             .into(),
             InstructionKind::Push { value: i(12) }.into(),
-            InstructionKind::Store {
-                ident: "a".into(),
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot: 0,
                 declaration: true
             }
             .into(),
@@ -76,6 +82,7 @@ fn optimize_index() {
     let bytecode = lower(SyntaxNode::BlockNode(node::BlockNode {
         block: vec![SyntaxNode::DeclarationNode(node::DeclarationNode {
             ident: "a".into(),
+            annotation: None,
             value: Box::new(SyntaxNode::BinaryNode(node::BinaryNode {
                 operator: TokenKind::StarOperator,
                 left: Box::new(SyntaxNode::IndexNode(node::IndexNode {
@@ -121,8 +128,9 @@ fn optimize_index() {
             }
             .into(),
             InstructionKind::Push { value: i(12) }.into(),
-            InstructionKind::Store {
-                ident: "a".into(),
+            InstructionKind::StoreLocal {
+                depth: 0,
+                slot: 0,
                 declaration: true
             }
             .into(),
@@ -242,14 +250,6 @@ fn optimize_const_if_condition() {
     assert_eq!(
         generate_bytecode(true),
         vec![
-            InstructionKind::PushVar {
-                scope: gen_scope(1)
-            }
-            .into(),
-            InstructionKind::PushVar {
-                scope: gen_scope(2)
-            }
-            .into(),
             InstructionKind::Push { value: i(2) }.into(),
             InstructionKind::Load { ident: "a".into() }.into(),
             InstructionKind::BinaryAdd.into(),
@@ -258,31 +258,366 @@ fn optimize_const_if_condition() {
                 declaration: false
             }
             .into(),
+        ]
+    );
+
+    assert_eq!(
+        generate_bytecode(false),
+        vec![
+            InstructionKind::Push { value: i(4) }.into(),
+            InstructionKind::Load { ident: "b".into() }.into(),
+            InstructionKind::BinaryMultiply.into(),
+            InstructionKind::Store {
+                ident: "b".into(),
+                declaration: false
+            }
+            .into(),
+        ]
+    );
+}
+
+#[test]
+fn optimize_in() {
+    let bytecode = lower(SyntaxNode::BinaryNode(node::BinaryNode {
+        operator: TokenKind::InKeyword,
+        left: Box::new(SyntaxNode::LiteralNode(node::LiteralNode {
+            value: s("ell"),
+            span: span(),
+        })),
+        right: Box::new(SyntaxNode::LiteralNode(node::LiteralNode {
+            value: s("hello"),
+            span: span(),
+        })),
+        span: span(),
+    }));
+
+    assert_eq!(
+        bytecode,
+        vec![InstructionKind::Push { value: b(true) }.into()]
+    );
+}
+
+fn literal(value: vm::Value) -> SyntaxNode {
+    SyntaxNode::LiteralNode(node::LiteralNode {
+        value,
+        span: span(),
+    })
+}
+
+#[test]
+fn optimize_push_pop() {
+    let bytecode = lower_b(node::BlockNode::new(
+        vec![literal(i(1)), literal(i(2))],
+        span(),
+    ));
+
+    assert_eq!(
+        bytecode,
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(0)
+            }
+            .into(),
+            InstructionKind::Push { value: i(2) }.into(),
             InstructionKind::PopVar.into(),
+        ]
+    );
+}
+
+#[test]
+fn optimize_empty_scope() {
+    // let a = 1
+    // { a + 1 }
+    let bytecode = lower_b(node::BlockNode::new(
+        vec![
+            SyntaxNode::DeclarationNode(node::DeclarationNode {
+                ident: "a".into(),
+                annotation: None,
+                value: Box::new(literal(i(1))),
+                span: span(),
+            }),
+            SyntaxNode::BlockNode(node::BlockNode::new(
+                vec![SyntaxNode::BinaryNode(node::BinaryNode {
+                    operator: TokenKind::PlusOperator,
+                    left: Box::new(SyntaxNode::VariableNode(node::VariableNode {
+                        ident: "a".into(),
+                        span: span(),
+                    })),
+                    right: Box::new(literal(i(1))),
+                    span: span(),
+                })],
+                span(),
+            )),
+        ],
+        span(),
+    ));
+
+    assert_eq!(
+        bytecode,
+        vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(0)
+            }
+            .into(),
+            InstructionKind::Push { value: i(1) }.into(),
+            InstructionKind::PopStoreLocal {
+                depth: 0,
+                slot: 0,
+                declaration: true
+            }
+            .into(),
+            InstructionKind::Push { value: i(2) }.into(),
             InstructionKind::PopVar.into(),
         ]
     );
+}
+
+#[test]
+fn optimize_unreachable() {
+    // loop { break 1; 2 }
+    let bytecode = lower(SyntaxNode::LoopNode(node::LoopNode {
+        label: None,
+        block: vec![
+            SyntaxNode::BreakNode(node::BreakNode {
+                label: None,
+                value: Some(Box::new(literal(i(1)))),
+                span: span(),
+            }),
+            literal(i(2)),
+        ],
+        span: span(),
+    }));
+
+    assert_eq!(bytecode, vec![InstructionKind::Push { value: i(1) }.into()]);
+}
+
+#[test]
+fn optimize_while_loop() {
+    // let a = true
+    // while a { a = false }
+    let bytecode = lower_b(node::BlockNode::new(
+        vec![
+            SyntaxNode::DeclarationNode(node::DeclarationNode {
+                ident: "a".into(),
+                annotation: None,
+                value: Box::new(literal(b(true))),
+                span: span(),
+            }),
+            SyntaxNode::LoopNode(node::LoopNode {
+                label: None,
+                block: vec![
+                    SyntaxNode::IfNode(node::IfNode {
+                        cond: Box::new(SyntaxNode::UnaryNode(node::UnaryNode {
+                            operator: TokenKind::NotOperator,
+                            child: Box::new(SyntaxNode::VariableNode(node::VariableNode {
+                                ident: "a".into(),
+                                span: span(),
+                            })),
+                            span: span(),
+                        })),
+                        if_block: node::BlockNode::new(
+                            vec![SyntaxNode::BreakNode(node::BreakNode {
+                                label: None,
+                                value: None,
+                                span: span(),
+                            })],
+                            span(),
+                        ),
+                        else_block: None,
+                        span: span(),
+                    }),
+                    SyntaxNode::AssignmentNode(node::AssignmentNode {
+                        ident: "a".into(),
+                        indices: None,
+                        value: Box::new(literal(b(false))),
+                        span: span(),
+                    }),
+                ],
+                span: span(),
+            }),
+        ],
+        span(),
+    ));
 
     assert_eq!(
-        generate_bytecode(false),
+        bytecode,
         vec![
+            InstructionKind::PushVar {
+                scope: gen_scope(0)
+            }
+            .into(),
+            InstructionKind::Push { value: b(true) }.into(),
+            InstructionKind::PopStoreLocal {
+                depth: 0,
+                slot: 0,
+                declaration: true
+            }
+            .into(),
             InstructionKind::PushVar {
                 scope: gen_scope(1)
             }
             .into(),
+            InstructionKind::LoadLocal { depth: 1, slot: 0 }.into(),
+            InstructionKind::PopJumpIfTrue { target: 8 }.into(),
+            InstructionKind::Push { value: n() }.into(),
+            InstructionKind::JumpTo { target: 11 }.into(),
+            InstructionKind::Push { value: b(false) }.into(),
+            InstructionKind::PopStoreLocal {
+                depth: 1,
+                slot: 0,
+                declaration: false
+            }
+            .into(),
+            InstructionKind::JumpTo { target: 4 }.into(),
+            InstructionKind::PopVar.into(),
+            InstructionKind::PopVar.into(),
+        ]
+    );
+}
+
+#[test]
+fn optimize_inline_call() {
+    // fn sq(x) { x * x }
+    // sq(3)
+    let x = || {
+        Box::new(SyntaxNode::VariableNode(node::VariableNode {
+            ident: "x".into(),
+            span: span(),
+        }))
+    };
+    let block = node::BlockNode::new(
+        vec![
+            SyntaxNode::FnDeclarationNode(node::FnDeclarationNode::with_span(
+                Some("sq".into()),
+                vec!["x".into()],
+                node::BlockNode::new(
+                    vec![SyntaxNode::BinaryNode(node::BinaryNode {
+                        operator: TokenKind::StarOperator,
+                        left: x(),
+                        right: x(),
+                        span: span(),
+                    })],
+                    span(),
+                ),
+                span(),
+            )),
+            SyntaxNode::FnCallNode(node::FnCallNode {
+                child: Box::new(SyntaxNode::VariableNode(node::VariableNode {
+                    ident: "sq".into(),
+                    span: span(),
+                })),
+                args: vec![literal(i(3))],
+                span: span(),
+            }),
+        ],
+        span(),
+    );
+
+    let bytecode = lower_at(block.clone(), OptLevel::O1);
+    assert_eq!(
+        bytecode[3..],
+        [
+            InstructionKind::Push { value: i(3) }.into(),
+            InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
+            InstructionKind::CallFunction { num_args: 1 }.into(),
+            InstructionKind::PopVar.into(),
+        ]
+    );
+
+    // The function is still declared, but the call is replaced by its body
+    let bytecode = lower_at(block, OptLevel::O2);
+    assert_eq!(
+        bytecode[3..],
+        [
+            InstructionKind::Push { value: i(3) }.into(),
             InstructionKind::PushVar {
                 scope: gen_scope(2)
             }
             .into(),
-            InstructionKind::Push { value: i(4) }.into(),
-            InstructionKind::Load { ident: "b".into() }.into(),
+            InstructionKind::PopStoreLocal {
+                depth: 0,
+                slot: 0,
+                declaration: true
+            }
+            .into(),
+            InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
+            InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
             InstructionKind::BinaryMultiply.into(),
-            InstructionKind::Store {
-                ident: "b".into(),
-                declaration: false
+            InstructionKind::PopVar.into(),
+            InstructionKind::PopVar.into(),
+        ]
+    );
+}
+
+#[test]
+fn optimize_constant_variable() {
+    let n = || {
+        Box::new(SyntaxNode::VariableNode(node::VariableNode {
+            ident: "n".into(),
+            span: span(),
+        }))
+    };
+    let declare_n = SyntaxNode::DeclarationNode(node::DeclarationNode {
+        ident: "n".into(),
+        annotation: None,
+        value: Box::new(literal(i(10))),
+        span: span(),
+    });
+    let double_n = SyntaxNode::BinaryNode(node::BinaryNode {
+        operator: TokenKind::StarOperator,
+        left: n(),
+        right: Box::new(literal(i(2))),
+        span: span(),
+    });
+
+    // let n = 10
+    // n * 2
+    let bytecode = lower_b(node::BlockNode::new(
+        vec![declare_n.clone(), double_n.clone()],
+        span(),
+    ));
+    assert_eq!(
+        bytecode[1..],
+        [
+            InstructionKind::Push { value: i(10) }.into(),
+            InstructionKind::PopStoreLocal {
+                depth: 0,
+                slot: 0,
+                declaration: true
             }
             .into(),
+            InstructionKind::Push { value: i(20) }.into(),
             InstructionKind::PopVar.into(),
+        ]
+    );
+
+    // let n = 10
+    // n = n + 1
+    // n * 2
+    let bytecode = lower_b(node::BlockNode::new(
+        vec![
+            declare_n,
+            SyntaxNode::AssignmentNode(node::AssignmentNode {
+                ident: "n".into(),
+                value: Box::new(SyntaxNode::BinaryNode(node::BinaryNode {
+                    operator: TokenKind::PlusOperator,
+                    left: n(),
+                    right: Box::new(literal(i(1))),
+                    span: span(),
+                })),
+                indices: None,
+                span: span(),
+            }),
+            double_n,
+        ],
+        span(),
+    ));
+    assert_eq!(
+        bytecode[bytecode.len() - 4..],
+        [
+            InstructionKind::Push { value: i(2) }.into(),
+            InstructionKind::LoadLocal { depth: 0, slot: 0 }.into(),
+            InstructionKind::BinaryMultiply.into(),
             InstructionKind::PopVar.into(),
         ]
     );
@@ -1,10 +1,12 @@
 use diagnostics::Diagnostics;
+use enumflags2::BitFlags;
 use intermediaries::{node, SyntaxNode, Token, TokenKind};
 use source::{SourceText, TextSpan};
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
-use vm::Value;
+use vm::value::Str;
+use vm::{Type, Value};
 
 /// Converts given a stream of tokens into a parsed AST. The root node returned is a `BlockNode`
 /// defined in `intermediaries/src/syntax_node/block_node.rs`
@@ -103,7 +105,7 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
     // ----- Other helper Methods -----
 
     fn literal_from_ident(&self, ident: &Token) -> SyntaxNode {
-        let value = Value::String(Rc::new(RefCell::new(self.src[&ident.text_span].to_owned())));
+        let value = Value::String(Str::intern(&self.src[&ident.text_span]));
         SyntaxNode::LiteralNode(node::LiteralNode::from_val(value, ident.text_span.clone()))
     }
 
@@ -190,7 +192,11 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
                     i += 1;
 
                     if parans == 0 {
-                        return self.tokens[i].kind == TokenKind::OpenBrace;
+                        // The return type annotation also comes before the brace
+                        return matches!(
+                            self.tokens[i].kind,
+                            TokenKind::OpenBrace | TokenKind::ArrowOperator
+                        );
                     }
                 }
             }
@@ -214,9 +220,15 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
         //     a: 123,     |      { 2 }: 3,
         // }               |  }
         // This means that (a) is an invalid object declaration, but (b) is valid
+        //
+        // A key can't be a declaration either, so a `let` or `fn` means the colon belongs to a type
+        // annotation and this is a block.
         while i < self.tokens.len() {
             match self.tokens[i].kind {
-                TokenKind::OpenBrace | TokenKind::CloseBrace => break,
+                TokenKind::OpenBrace
+                | TokenKind::CloseBrace
+                | TokenKind::LetKeyword
+                | TokenKind::FnKeyword => break,
                 TokenKind::ColonOperator => return true,
                 _ => {
                     i += 1;
@@ -227,6 +239,32 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
         false
     }
 
+    fn is_comprehension(&self) -> bool {
+        // Already matched '[' or '{'
+        let mut i = self.index();
+        let mut depth = 0;
+
+        // [<stmt> for ...
+        //         ^^^-- A `for` which isn't nested in some other delimiter differentiates this
+        //               from a regular list or object
+        while i < self.tokens.len() {
+            match self.tokens[i].kind {
+                TokenKind::OpenParan | TokenKind::OpenBrace | TokenKind::OpenBracket => depth += 1,
+                TokenKind::CloseParan | TokenKind::CloseBrace | TokenKind::CloseBracket => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                TokenKind::ForKeyword if depth == 0 => return true,
+                _ => {}
+            }
+            i += 1;
+        }
+
+        false
+    }
+
     // ----- Parse Methods -----
 
     fn parse_block(&self, delim: TokenKind) -> node::BlockNode {
@@ -234,6 +272,14 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
         let mut block = Vec::new();
 
         while self.cur().kind != delim {
+            if self.cur().kind == TokenKind::EOF {
+                // The block is never closed
+                let span = self.tokens[self.index().saturating_sub(1)]
+                    .text_span
+                    .clone();
+                self.diagnostics.unexpected_eof(span);
+                break;
+            }
             block.push(self.parse_statement());
         }
         let e = self.next().text_span.end();
@@ -269,11 +315,11 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
             TokenKind::FnKeyword => self.parse_fn_declaration_statement(self.next()),
             TokenKind::InterfaceKeyword => self.parse_interface_statement(),
             TokenKind::IfKeyword => self.parse_if_statement(),
-            TokenKind::BreakKeyword => {
-                SyntaxNode::BreakNode(node::BreakNode::new(self.next().text_span.clone()))
-            }
+            TokenKind::BreakKeyword => self.parse_break_statement(),
             TokenKind::ReturnKeyword => self.parse_return_statement(),
+            TokenKind::DeferKeyword => self.parse_defer_statement(),
             TokenKind::LoopKeyword => self.parse_loop_statement(),
+            TokenKind::Label => self.parse_labeled_loop_statement(),
             TokenKind::WhileKeyword => self.parse_while_statement(),
             _ => self.parse_binary_expression(0),
         };
@@ -290,13 +336,52 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
     fn parse_declaration_expression(&self) -> SyntaxNode {
         let declaration_token = self.next();
         let ident = self.match_token(TokenKind::Ident);
+        let annotation = self.try_parse_type_annotation(TokenKind::ColonOperator);
         self.match_token(TokenKind::AssignmentOperator);
         let value = self.parse_statement();
-        SyntaxNode::DeclarationNode(node::DeclarationNode::new(
-            declaration_token,
-            self.new_ident(&ident.text_span),
-            value,
-        ))
+        SyntaxNode::DeclarationNode(
+            node::DeclarationNode::new(declaration_token, self.new_ident(&ident.text_span), value)
+                .with_annotation(annotation),
+        )
+    }
+
+    /// Parses `<delim> <type>` if the current token is the delimiter
+    fn try_parse_type_annotation(&self, delim: TokenKind) -> Option<BitFlags<Type>> {
+        if self.cur().kind == delim {
+            self.next();
+            Some(self.parse_type())
+        } else {
+            None
+        }
+    }
+
+    // <type> | <type> | ...
+    fn parse_type(&self) -> BitFlags<Type> {
+        let mut type_ = BitFlags::empty();
+        loop {
+            let ident = self.next();
+            if ident.kind != TokenKind::Ident {
+                self.diagnostics
+                    .unexpected_token(ident, Some(&TokenKind::Ident));
+                return BitFlags::all();
+            }
+
+            match &self.src[&ident.text_span] {
+                "any" => type_ = BitFlags::all(),
+                name => match Type::from_name(name) {
+                    Some(t) => type_ |= t,
+                    None => {
+                        self.diagnostics.unknown_type(ident.text_span.clone());
+                        type_ = BitFlags::all();
+                    }
+                },
+            }
+
+            if self.cur().kind != TokenKind::UnionOperator {
+                return type_;
+            }
+            self.next();
+        }
     }
 
     fn try_parse_indices(&self) -> Option<Vec<SyntaxNode>> {
@@ -389,12 +474,14 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
         self.match_token(TokenKind::OpenParan);
 
         let mut args = Vec::new();
+        let mut arg_annotations = Vec::new();
         if self.cur().kind != TokenKind::CloseParan {
             loop {
                 // `match_token()` not used because if the token is not an ident, loop should stop
                 let next = self.next();
                 if next.kind == TokenKind::Ident {
                     args.push(self.new_ident(&next.text_span));
+                    arg_annotations.push(self.try_parse_type_annotation(TokenKind::ColonOperator));
 
                     let next = self.next();
                     match next.kind {
@@ -416,15 +503,15 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
             self.next();
         }
 
+        let return_annotation = self.try_parse_type_annotation(TokenKind::ArrowOperator);
+
         self.match_token(TokenKind::OpenBrace);
         let block = self.parse_block(TokenKind::CloseBrace);
 
-        SyntaxNode::FnDeclarationNode(node::FnDeclarationNode::new(
-            start_token,
-            ident,
-            args,
-            block,
-        ))
+        SyntaxNode::FnDeclarationNode(
+            node::FnDeclarationNode::new(start_token, ident, args, block)
+                .with_annotations(arg_annotations, return_annotation),
+        )
     }
 
     fn parse_interface_statement(&self) -> SyntaxNode {
@@ -562,6 +649,64 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
         SyntaxNode::ReturnNode(node::ReturnNode::new(value, return_token))
     }
 
+    fn parse_defer_statement(&self) -> SyntaxNode {
+        let defer_token = self.match_token(TokenKind::DeferKeyword);
+        let statement = self.parse_statement();
+
+        SyntaxNode::DeferNode(node::DeferNode::new(defer_token, statement))
+    }
+
+    fn parse_break_statement(&self) -> SyntaxNode {
+        let break_token = self.match_token(TokenKind::BreakKeyword);
+        let mut span = break_token.text_span.clone();
+
+        let label = if self.cur().kind == TokenKind::Label {
+            let label_token = self.next();
+            span = TextSpan::from_spans(&span, &label_token.text_span);
+            Some(self.new_ident(&label_token.text_span))
+        } else {
+            None
+        };
+
+        // The value has to start on the same line, otherwise the next statement would be taken as
+        // the value of a bare `break`
+        let same_line =
+            self.src.lineno(span.end() - 1) == self.src.lineno(self.cur().text_span.start());
+        let value = match self.cur().kind {
+            TokenKind::CloseBrace
+            | TokenKind::CloseParan
+            | TokenKind::CloseBracket
+            | TokenKind::CommaOperator
+            | TokenKind::EOF => None,
+            _ if !same_line => None,
+            _ => {
+                let value = self.parse_statement();
+                span = TextSpan::from_spans(&span, value.span());
+                Some(value)
+            }
+        };
+
+        SyntaxNode::BreakNode(node::BreakNode::with_value(span, label, value))
+    }
+
+    fn parse_labeled_loop_statement(&self) -> SyntaxNode {
+        let label_token = self.match_token(TokenKind::Label);
+        let label = self.new_ident(&label_token.text_span);
+        self.match_token(TokenKind::ColonOperator);
+
+        let loop_node = match self.cur().kind {
+            TokenKind::WhileKeyword => self.parse_while_statement(),
+            _ => self.parse_loop_statement(),
+        };
+
+        match loop_node {
+            SyntaxNode::LoopNode(loop_node) => {
+                SyntaxNode::LoopNode(loop_node.with_label(label_token, label))
+            }
+            _ => unreachable!(),
+        }
+    }
+
     fn parse_loop_statement(&self) -> SyntaxNode {
         let loop_token = self.match_token(TokenKind::LoopKeyword);
 
@@ -601,12 +746,38 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
 
             let op = self.next();
             let right = self.parse_binary_expression(precedence);
-            left = SyntaxNode::BinaryNode(node::BinaryNode::new(op, left, right));
+            left = if op.kind == TokenKind::PipeOperator {
+                self.desugar_pipe(left, right)
+            } else {
+                SyntaxNode::BinaryNode(node::BinaryNode::new(op, left, right))
+            };
         }
 
         left
     }
 
+    /// `<value> |> <fn>(<args>...)` is sugar for `<fn>(<value>, <args>...)`
+    ///
+    /// If the right side isn't a function call, it is called with the value as its only argument,
+    /// so `<value> |> <fn>` becomes `<fn>(<value>)`
+    fn desugar_pipe(&self, value: SyntaxNode, call: SyntaxNode) -> SyntaxNode {
+        let span = TextSpan::from_spans(value.span(), call.span());
+        match call {
+            SyntaxNode::FnCallNode(node::FnCallNode {
+                child, mut args, ..
+            }) => {
+                args.insert(0, value);
+                SyntaxNode::FnCallNode(node::FnCallNode::with_span(child, args, span))
+            }
+            SyntaxNode::BadNode(_) => call,
+            callee => SyntaxNode::FnCallNode(node::FnCallNode::with_span(
+                Box::new(callee),
+                vec![value],
+                span,
+            )),
+        }
+    }
+
     fn parse_general_expression(&self) -> SyntaxNode {
         let mut node = match self.cur().kind {
             // Float in the form of '.123'
@@ -638,6 +809,7 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
             }
             TokenKind::OpenParan => self.parse_paran_expression(),
             TokenKind::OpenBracket => self.parse_list_expression(),
+            TokenKind::Label => self.parse_labeled_loop_statement(),
             TokenKind::EOF => {
                 let span = self.tokens[self.index() - 1].text_span.clone();
                 self.diagnostics.unexpected_eof(span.clone());
@@ -706,6 +878,11 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
 
     fn parse_list_expression(&self) -> SyntaxNode {
         let open_bracket = self.match_token(TokenKind::OpenBracket);
+        if self.is_comprehension() {
+            let value = self.parse_statement();
+            return self.parse_comprehension(open_bracket, None, value, TokenKind::CloseBracket);
+        }
+
         let (list, close_bracket) = self.parse_comma_seperated_values(TokenKind::CloseBracket);
 
         SyntaxNode::ListNode(node::ListNode::new(open_bracket, list, close_bracket))
@@ -713,6 +890,15 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
 
     fn parse_object_expression(&self) -> SyntaxNode {
         let open_brace = self.match_token(TokenKind::OpenBrace);
+        if self.is_comprehension() {
+            // Unlike regular objects, the key is always an expression, so `{ k: v for k, v in o }`
+            // uses the value of `k` rather than the string "k"
+            let key = self.parse_statement();
+            self.match_token(TokenKind::ColonOperator);
+            let value = self.parse_statement();
+            return self.parse_comprehension(open_brace, Some(key), value, TokenKind::CloseBrace);
+        }
+
         let mut elements = Vec::new();
 
         loop {
@@ -777,6 +963,47 @@ impl<'diagnostics, 'src> Parser<'diagnostics, 'src> {
         SyntaxNode::ObjectNode(node::ObjectNode::new(open_brace, elements, close_brace))
     }
 
+    /// Parses the rest of a comprehension after the key and value have been parsed
+    ///
+    /// for <ident>[, <ident>] in <stmt> [if <stmt>] <delim>
+    fn parse_comprehension(
+        &self,
+        open_token: &Token,
+        key: Option<SyntaxNode>,
+        value: SyntaxNode,
+        delim: TokenKind,
+    ) -> SyntaxNode {
+        self.match_token(TokenKind::ForKeyword);
+
+        let mut vars = vec![self.new_ident(&self.match_token(TokenKind::Ident).text_span)];
+        if self.cur().kind == TokenKind::CommaOperator {
+            self.next();
+            vars.push(self.new_ident(&self.match_token(TokenKind::Ident).text_span));
+        }
+
+        self.match_token(TokenKind::InKeyword);
+        let iter = self.parse_statement();
+
+        let cond = if self.cur().kind == TokenKind::IfKeyword {
+            self.next();
+            Some(self.parse_statement())
+        } else {
+            None
+        };
+
+        let close_token = self.match_token(delim);
+
+        SyntaxNode::ComprehensionNode(node::ComprehensionNode::new(
+            open_token,
+            key,
+            value,
+            vars,
+            iter,
+            cond,
+            close_token,
+        ))
+    }
+
     fn parse_paran_expression(&self) -> SyntaxNode {
         self.match_token(TokenKind::OpenParan);
         let expression = self.parse_statement();
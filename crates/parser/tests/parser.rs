@@ -1,10 +1,11 @@
 use diagnostics::Diagnostics;
+use enumflags2::BitFlags;
 use intermediaries::{node, SyntaxNode, Token, TokenKind};
 use parser::Parser;
 use source::SourceText;
 use std::rc::Rc;
 use vm::test_helpers::*;
-use vm::Value;
+use vm::{Type, Value};
 
 static NA: usize = 0;
 
@@ -74,8 +75,41 @@ fn match_block(node: SyntaxNode, expected_len: usize) -> Vec<SyntaxNode> {
     }
 }
 
-fn match_break(node: SyntaxNode) {
-    assert!(matches!(node, SyntaxNode::BreakNode(_)));
+/// (label, value)
+fn match_break(node: SyntaxNode) -> (Option<Rc<str>>, Option<SyntaxNode>) {
+    match node {
+        SyntaxNode::BreakNode(node::BreakNode { label, value, .. }) => (label, value.map(|v| *v)),
+        n => panic!("expected break, got {:?}", n),
+    }
+}
+
+/// (key, value, iter, cond)
+fn match_comprehension(
+    node: SyntaxNode,
+    expected_vars: Vec<&str>,
+) -> (
+    Option<SyntaxNode>,
+    SyntaxNode,
+    SyntaxNode,
+    Option<SyntaxNode>,
+) {
+    match node {
+        SyntaxNode::ComprehensionNode(node::ComprehensionNode {
+            key,
+            value,
+            vars,
+            iter,
+            cond,
+            ..
+        }) => {
+            assert_eq!(vars.len(), expected_vars.len());
+            for (var, expected_var) in vars.iter().zip(expected_vars) {
+                assert_eq!(&**var, expected_var);
+            }
+            (key.map(|k| *k), *value, *iter, cond.map(|c| *c))
+        }
+        n => panic!("expected comprehension, got {:?}", n),
+    }
 }
 
 /// value
@@ -209,6 +243,13 @@ fn match_object(node: SyntaxNode, len: usize) -> Vec<SyntaxNode> {
 }
 
 /// value
+fn match_defer(node: SyntaxNode) -> SyntaxNode {
+    match node {
+        SyntaxNode::DeferNode(node::DeferNode { statement, .. }) => *statement,
+        n => panic!("expected defer, got {:?}", n),
+    }
+}
+
 fn match_return(node: SyntaxNode) -> Option<SyntaxNode> {
     match node {
         SyntaxNode::ReturnNode(node::ReturnNode { value, .. }) => value.map(|v| *v),
@@ -253,6 +294,83 @@ fn parse_declaration_properly() {
     match_literal(value, i(123));
 }
 
+#[test]
+fn parse_type_annotations_properly() {
+    let tokens = vec![
+        Token::new(TokenKind::LetKeyword, 0, 3),
+        Token::new(TokenKind::Ident, 4, 1),
+        Token::new(TokenKind::ColonOperator, 5, 1),
+        Token::new(TokenKind::Ident, 7, 3),
+        Token::new(TokenKind::UnionOperator, 11, 1),
+        Token::new(TokenKind::Ident, 13, 5),
+        Token::new(TokenKind::AssignmentOperator, 19, 1),
+        Token::new(TokenKind::Number, 21, 1),
+    ];
+    let root = parse("let a: int | float = 1", tokens);
+
+    match root {
+        SyntaxNode::DeclarationNode(node::DeclarationNode {
+            ident,
+            value,
+            annotation,
+            ..
+        }) => {
+            assert_eq!(&*ident, "a");
+            assert_eq!(annotation, Some(Type::Int | Type::Float));
+            match_literal(*value, i(1));
+        }
+        n => panic!("expected declaration, got {:?}", n),
+    }
+
+    let tokens = vec![
+        Token::new(TokenKind::FnKeyword, 0, 2),
+        Token::new(TokenKind::Ident, 3, 1),
+        Token::new(TokenKind::OpenParan, 4, 1),
+        Token::new(TokenKind::Ident, 5, 1),
+        Token::new(TokenKind::ColonOperator, 6, 1),
+        Token::new(TokenKind::Ident, 8, 4),
+        Token::new(TokenKind::CommaOperator, 12, 1),
+        Token::new(TokenKind::Ident, 14, 1),
+        Token::new(TokenKind::CloseParan, 15, 1),
+        Token::new(TokenKind::ArrowOperator, 17, 2),
+        Token::new(TokenKind::Ident, 20, 3),
+        Token::new(TokenKind::OpenBrace, 24, 1),
+        Token::new(TokenKind::Ident, 26, 1),
+        Token::new(TokenKind::CloseBrace, 28, 1),
+    ];
+    let root = parse("fn f(a: list, b) -> any { a }", tokens);
+
+    match root {
+        SyntaxNode::FnDeclarationNode(node::FnDeclarationNode {
+            arg_annotations,
+            return_annotation,
+            ..
+        }) => {
+            assert_eq!(arg_annotations, vec![Some(Type::List.into()), None]);
+            assert_eq!(return_annotation, Some(BitFlags::all()));
+        }
+        n => panic!("expected fn declaration, got {:?}", n),
+    }
+}
+
+#[test]
+fn parse_unknown_type() {
+    let src = SourceText::new("let a: integer = 1");
+    let diagnostics = Diagnostics::new(&src).no_print();
+    let tokens = vec![
+        Token::new(TokenKind::LetKeyword, 0, 3),
+        Token::new(TokenKind::Ident, 4, 1),
+        Token::new(TokenKind::ColonOperator, 5, 1),
+        Token::new(TokenKind::Ident, 7, 7),
+        Token::new(TokenKind::AssignmentOperator, 15, 1),
+        Token::new(TokenKind::Number, 17, 1),
+        Token::new(TokenKind::EOF, 18, 0),
+    ];
+    Parser::parse(tokens, &src, &diagnostics);
+
+    assert_eq!(diagnostics.num_errors(), 1);
+}
+
 #[test]
 fn parse_assignment_properly() {
     let tokens = vec![
@@ -433,6 +551,89 @@ fn parse_fn_call_properly() {
     match_literal(args.pop().unwrap(), i(1));
 }
 
+#[test]
+fn parse_pipe_properly() {
+    let tokens = vec![
+        Token::new(TokenKind::Ident, 0, 1),
+        Token::new(TokenKind::PipeOperator, 2, 2),
+        Token::new(TokenKind::Ident, 5, 1),
+        Token::new(TokenKind::OpenParan, 6, 1),
+        Token::new(TokenKind::Number, 7, 1),
+        Token::new(TokenKind::CloseParan, 8, 1),
+        Token::new(TokenKind::PipeOperator, 10, 2),
+        Token::new(TokenKind::Ident, 13, 1),
+    ];
+    let root = parse("a |> f(1) |> g", tokens);
+
+    let (child, mut args) = match_fn_call(root, 1);
+    match_variable(child, "g");
+
+    let (child, mut args) = match_fn_call(args.pop().unwrap(), 2);
+    match_variable(child, "f");
+    match_literal(args.pop().unwrap(), i(1));
+    match_variable(args.pop().unwrap(), "a");
+
+    let tokens = vec![
+        Token::new(TokenKind::Number, 0, 1),
+        Token::new(TokenKind::PlusOperator, 2, 1),
+        Token::new(TokenKind::Number, 4, 1),
+        Token::new(TokenKind::PipeOperator, 6, 2),
+        Token::new(TokenKind::Ident, 9, 1),
+        Token::new(TokenKind::OpenParan, 10, 1),
+        Token::new(TokenKind::CloseParan, 11, 1),
+    ];
+    let root = parse("1 + 2 |> f()", tokens);
+
+    let (child, mut args) = match_fn_call(root, 1);
+    match_variable(child, "f");
+    let (left, right) = match_binary(args.pop().unwrap(), TokenKind::PlusOperator);
+    match_literal(left, i(1));
+    match_literal(right, i(2));
+}
+
+#[test]
+fn parse_comprehension_properly() {
+    let tokens = vec![
+        Token::new(TokenKind::OpenBracket, 0, 1),
+        Token::new(TokenKind::Ident, 1, 1),
+        Token::new(TokenKind::ForKeyword, 3, 3),
+        Token::new(TokenKind::Ident, 7, 1),
+        Token::new(TokenKind::InKeyword, 9, 2),
+        Token::new(TokenKind::Ident, 12, 2),
+        Token::new(TokenKind::IfKeyword, 15, 2),
+        Token::new(TokenKind::Ident, 18, 1),
+        Token::new(TokenKind::CloseBracket, 19, 1),
+    ];
+    let root = parse("[x for x in xs if x]", tokens);
+
+    let (key, value, iter, cond) = match_comprehension(root, vec!["x"]);
+    assert!(key.is_none());
+    match_variable(value, "x");
+    match_variable(iter, "xs");
+    match_variable(cond.unwrap(), "x");
+
+    let tokens = vec![
+        Token::new(TokenKind::OpenBrace, 0, 1),
+        Token::new(TokenKind::Ident, 1, 1),
+        Token::new(TokenKind::ColonOperator, 2, 1),
+        Token::new(TokenKind::Ident, 4, 1),
+        Token::new(TokenKind::ForKeyword, 6, 3),
+        Token::new(TokenKind::Ident, 10, 1),
+        Token::new(TokenKind::CommaOperator, 11, 1),
+        Token::new(TokenKind::Ident, 13, 1),
+        Token::new(TokenKind::InKeyword, 15, 2),
+        Token::new(TokenKind::Ident, 18, 1),
+        Token::new(TokenKind::CloseBrace, 19, 1),
+    ];
+    let root = parse("{k: v for k, v in o}", tokens);
+
+    let (key, value, iter, cond) = match_comprehension(root, vec!["k", "v"]);
+    match_variable(key.unwrap(), "k");
+    match_variable(value, "v");
+    match_variable(iter, "o");
+    assert!(cond.is_none());
+}
+
 #[test]
 fn parse_if_properly() {
     let tokens = vec![
@@ -512,6 +713,94 @@ fn parse_loop_properly() {
     match_literal(block.pop().unwrap(), i(123));
 }
 
+#[test]
+fn parse_labeled_loop_properly() {
+    let tokens = vec![
+        Token::new(TokenKind::Label, 0, 6),
+        Token::new(TokenKind::ColonOperator, 6, 1),
+        Token::new(TokenKind::LoopKeyword, 8, 4),
+        Token::new(TokenKind::OpenBrace, 13, 1),
+        Token::new(TokenKind::BreakKeyword, 15, 5),
+        Token::new(TokenKind::Label, 21, 6),
+        Token::new(TokenKind::Number, 28, 1),
+        Token::new(TokenKind::CloseBrace, 30, 1),
+    ];
+    let root = parse("'outer: loop { break 'outer 1 }", tokens);
+    match root {
+        SyntaxNode::LoopNode(node::LoopNode { ref label, .. }) => {
+            assert_eq!(label.as_deref(), Some("'outer"))
+        }
+        ref n => panic!("expected loop, got {:?}", n),
+    }
+
+    let mut block = match_loop(root, 1);
+    let (label, value) = match_break(block.pop().unwrap());
+    assert_eq!(label.as_deref(), Some("'outer"));
+    match_literal(value.unwrap(), i(1));
+
+    let tokens = vec![
+        Token::new(TokenKind::LoopKeyword, 0, 4),
+        Token::new(TokenKind::OpenBrace, 5, 1),
+        Token::new(TokenKind::BreakKeyword, 7, 5),
+        Token::new(TokenKind::CloseBrace, 13, 1),
+    ];
+    let root = parse("loop { break }", tokens);
+    let mut block = match_loop(root, 1);
+    let (label, value) = match_break(block.pop().unwrap());
+    assert!(label.is_none());
+    assert!(value.is_none());
+
+    // A value on the next line is another statement
+    let tokens = vec![
+        Token::new(TokenKind::LoopKeyword, 0, 4),
+        Token::new(TokenKind::OpenBrace, 5, 1),
+        Token::new(TokenKind::BreakKeyword, 7, 5),
+        Token::new(TokenKind::Number, 13, 1),
+        Token::new(TokenKind::CloseBrace, 15, 1),
+    ];
+    let root = parse("loop { break\n1 }", tokens);
+    let mut block = match_loop(root, 2);
+    match_literal(block.pop().unwrap(), i(1));
+    let (label, value) = match_break(block.pop().unwrap());
+    assert!(label.is_none());
+    assert!(value.is_none());
+
+    // A labeled loop can be a value
+    let tokens = vec![
+        Token::new(TokenKind::LetKeyword, 0, 3),
+        Token::new(TokenKind::Ident, 4, 1),
+        Token::new(TokenKind::AssignmentOperator, 6, 1),
+        Token::new(TokenKind::Label, 8, 2),
+        Token::new(TokenKind::ColonOperator, 10, 1),
+        Token::new(TokenKind::LoopKeyword, 12, 4),
+        Token::new(TokenKind::OpenBrace, 17, 1),
+        Token::new(TokenKind::BreakKeyword, 19, 5),
+        Token::new(TokenKind::Label, 25, 2),
+        Token::new(TokenKind::Number, 28, 1),
+        Token::new(TokenKind::CloseBrace, 30, 1),
+    ];
+    let root = parse("let v = 'a: loop { break 'a 1 }", tokens);
+    let mut block = match_loop(match_declaration(root, "v"), 1);
+    let (label, value) = match_break(block.pop().unwrap());
+    assert_eq!(label.as_deref(), Some("'a"));
+    match_literal(value.unwrap(), i(1));
+}
+
+#[test]
+fn parse_unclosed_block() {
+    let src = SourceText::new("loop { 1");
+    let diagnostics = Diagnostics::new(&src).no_print();
+    let tokens = vec![
+        Token::new(TokenKind::LoopKeyword, 0, 4),
+        Token::new(TokenKind::OpenBrace, 5, 1),
+        Token::new(TokenKind::Number, 7, 1),
+        Token::new(TokenKind::EOF, 8, 0),
+    ];
+    Parser::parse(tokens, &src, &diagnostics);
+
+    assert_eq!(diagnostics.num_errors(), 1);
+}
+
 #[test]
 fn parse_while_properly() {
     let tokens = vec![
@@ -534,6 +823,20 @@ fn parse_while_properly() {
     assert!(else_block.is_none());
 }
 
+#[test]
+fn parse_defer_properly() {
+    let tokens = vec![
+        Token::new(TokenKind::DeferKeyword, 0, 5),
+        Token::new(TokenKind::Ident, 6, 1),
+        Token::new(TokenKind::OpenParan, 7, 1),
+        Token::new(TokenKind::CloseParan, 8, 1),
+    ];
+    let root = parse("defer f()", tokens);
+
+    let (child, _) = match_fn_call(match_defer(root), 0);
+    match_variable(child, "f");
+}
+
 #[test]
 fn parse_return_properly() {
     // Return a value
@@ -893,4 +1196,19 @@ fn parse_binary_properly() {
     let (left, right) = match_binary(left, TokenKind::StarOperator);
     match_literal(left, i(1));
     match_literal(right, i(2));
+
+    let tokens = vec![
+        Token::new(TokenKind::Ident, 0, 1),
+        Token::new(TokenKind::InKeyword, 2, 2),
+        Token::new(TokenKind::Ident, 5, 1),
+        Token::new(TokenKind::AndOperator, 7, 2),
+        Token::new(TokenKind::Boolean, 10, 4),
+    ];
+    let root = parse("a in b && true", tokens);
+    let (left, right) = match_binary(root, TokenKind::AndOperator);
+    match_literal(right, b(true));
+
+    let (left, right) = match_binary(left, TokenKind::InKeyword);
+    match_variable(left, "a");
+    match_variable(right, "b");
 }
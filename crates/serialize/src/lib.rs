@@ -52,7 +52,9 @@ impl Deserialize for bool {
     }
 }
 
+impl_serialize!(u8 1);
 impl_serialize!(u16 2);
+impl_serialize!(u32 4);
 impl_serialize!(i64 8);
 impl_serialize!(f64 8);
 
@@ -100,7 +102,7 @@ impl Deserialize for String {
     }
 }
 
-impl<T: Serialize> Serialize for Vec<T> {
+impl<T: Serialize> Serialize for [T] {
     fn serialize<W: Write>(&self, buf: &mut W) -> Result<usize> {
         let mut written = self.len().serialize(buf)?;
         for e in self {
@@ -110,6 +112,12 @@ impl<T: Serialize> Serialize for Vec<T> {
     }
 }
 
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize<W: Write>(&self, buf: &mut W) -> Result<usize> {
+        self[..].serialize(buf)
+    }
+}
+
 impl<T: Deserialize> Deserialize for Vec<T> {
     fn deserialize<R: BufRead>(data: &mut R) -> Result<Self> {
         let len = usize::deserialize(data)?;
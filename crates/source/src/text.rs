@@ -66,8 +66,8 @@ impl<T: TextBase> Index<Range<usize>> for Text<T> {
 /// This trait allows us to generalize over &[String] and &str
 pub trait TextBase {
     /// The actual iterator type, it must return (usize, char) similar to CharIndices which is used
-    /// in the lexer
-    type Iter: Iterator<Item = (usize, char)>;
+    /// in the lexer. It is cloned by the lexer to look ahead
+    type Iter: Iterator<Item = (usize, char)> + Clone;
 
     /// Generates the lines array which is used for efficient O(log(lines.len())) searches for line
     /// number
@@ -154,6 +154,7 @@ impl<'s> TextBase for &'s [String] {
 
 mod lines_iterator {
     /// An equivalent to CharIndices, but on &[String]. At the end of each String, it yields a '\n'
+    #[derive(Clone)]
     pub struct LinesIterator<'a> {
         lines: &'a [String],
         line_char_offset: usize,
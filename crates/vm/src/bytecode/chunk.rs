@@ -0,0 +1,748 @@
+use super::{Bytecode, Instruction, InstructionKind};
+use crate::scope::Scope;
+use crate::value::{InlineCache, Value};
+use crate::DeserializationContext;
+use serialize::{Deserialize, DeserializeCtx, Serialize};
+use source::TextSpan;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, prelude::*};
+use std::rc::Rc;
+
+/// The compact form of an [`InstructionKind`], which is what is evaluated and written to compiled
+/// files. Anything which isn't a small number is stored in the [`Chunk`] the op is part of, and
+/// referenced by its index.
+///
+/// See [`InstructionKind`] for what each op does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    BinaryAdd,
+    BinarySubtract,
+    BinaryMultiply,
+    BinaryDivide,
+    BinaryMod,
+    BinaryPower,
+    BinaryOr,
+    BinaryAnd,
+    UnaryPositive,
+    UnaryNegative,
+    UnaryNot,
+    CompareLT,
+    CompareLE,
+    CompareGT,
+    CompareGE,
+    CompareEQ,
+    CompareNE,
+    Pop,
+    /// Push the constant at index `constant` of the chunk.
+    Push {
+        constant: u32,
+    },
+    /// Store into the variable named by index `ident` of the chunk.
+    Store {
+        ident: u32,
+        declaration: bool,
+    },
+    /// Load the variable named by index `ident` of the chunk.
+    Load {
+        ident: u32,
+    },
+    GetIndex,
+    SetIndex,
+    JumpTo {
+        target: u32,
+    },
+    PopJumpIfTrue {
+        target: u32,
+    },
+    CallFunction {
+        num_args: u32,
+    },
+    MakeList {
+        len: u32,
+    },
+    MakeObject {
+        len: u32,
+    },
+    MakeRange,
+    /// Push the scope at index `scope` of the chunk.
+    PushVar {
+        scope: u32,
+    },
+    PopVar,
+    MakeIter,
+    ForIter {
+        target: u32,
+    },
+    ListAppend {
+        depth: u32,
+    },
+    ObjectInsert {
+        depth: u32,
+    },
+    BinaryIn,
+    LoadLocal {
+        depth: u32,
+        slot: u32,
+    },
+    StoreLocal {
+        depth: u32,
+        slot: u32,
+        declaration: bool,
+    },
+    TailCallFunction {
+        num_args: u32,
+    },
+    PopJumpIfFalse {
+        target: u32,
+    },
+    /// Store into the variable named by index `ident` of the chunk, popping the value.
+    PopStore {
+        ident: u32,
+        declaration: bool,
+    },
+    PopStoreLocal {
+        depth: u32,
+        slot: u32,
+        declaration: bool,
+    },
+    SetNestedIndex {
+        depth: u32,
+    },
+
+    // The ops below are specialized forms of the generic arithmetic and comparison ops, for
+    // operands of the types in their name. They are never lowered or written to files, instead the
+    // evaluator replaces a generic op with one of them once the op has been evaluated with the same
+    // types of operands enough times in a row, see `Chunk::record_types`. If a specialized op is
+    // evaluated with operands of other types, it is replaced with the generic op again.
+    AddIntInt,
+    SubIntInt,
+    MulIntInt,
+    AddFloatFloat,
+    SubFloatFloat,
+    MulFloatFloat,
+    LtIntInt,
+    LeIntInt,
+    GtIntInt,
+    GeIntInt,
+}
+
+impl Op {
+    /// The generic op a specialized op was made from, or the op itself if it isn't specialized.
+    pub fn generic(self) -> Op {
+        match self {
+            Op::AddIntInt | Op::AddFloatFloat => Op::BinaryAdd,
+            Op::SubIntInt | Op::SubFloatFloat => Op::BinarySubtract,
+            Op::MulIntInt | Op::MulFloatFloat => Op::BinaryMultiply,
+            Op::LtIntInt => Op::CompareLT,
+            Op::LeIntInt => Op::CompareLE,
+            Op::GtIntInt => Op::CompareGT,
+            Op::GeIntInt => Op::CompareGE,
+            op => op,
+        }
+    }
+}
+
+/// The number of times in a row an op has to be evaluated with the same types of operands before
+/// it is specialized for them.
+const SPECIALIZE_AFTER: u8 = 8;
+
+/// The number of times an op can be deoptimized before it stops being specialized, so that an op
+/// which sees different types in turns doesn't keep on being specialized and deoptimized.
+const MAX_DEOPTS: u8 = 4;
+
+/// What the evaluator has seen of the operands of an op, see `Chunk::record_types`
+#[derive(Debug, Clone, Copy, Default)]
+struct Feedback {
+    /// The specialized op for the types of operands the op was last evaluated with
+    specialized: Option<Op>,
+    /// The number of times in a row the op has been evaluated with operands of those types
+    count: u8,
+    deopts: u8,
+}
+
+/// Finalized bytecode, in the form it is evaluated in. The values, identifiers and scopes used by
+/// the ops are kept in pools, and the spans are kept in a side table since they are only needed
+/// when reporting errors.
+///
+/// A chunk is made from lowered bytecode with [`finalize_bytecode`](super::finalize_bytecode), and
+/// can be turned back into bytecode with [`Chunk::to_bytecode`] to be inspected.
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    /// The ops are in cells, so that the evaluator can specialize them while the chunk is shared
+    pub ops: Vec<Cell<Op>>,
+    pub constants: Vec<Value>,
+    pub idents: Vec<Rc<str>>,
+    pub scopes: Vec<Rc<Scope>>,
+    /// The offset of the first op of each run of ops with the same span, along with the span
+    spans: Vec<(u32, TextSpan)>,
+    /// The types of operands each op has been evaluated with, used to specialize it
+    feedback: Vec<Cell<Feedback>>,
+    /// The inline cache of each op, only used by `GetIndex` and `SetIndex`
+    caches: Vec<InlineCache>,
+}
+
+fn index(i: usize) -> u32 {
+    u32::try_from(i).expect("Bytecode is too large to be encoded")
+}
+
+impl Chunk {
+    /// Encodes bytecode whose labels have already been resolved.
+    pub(super) fn encode(bytecode: Bytecode) -> Self {
+        let mut chunk = Chunk {
+            ops: Vec::with_capacity(bytecode.len()),
+            ..Default::default()
+        };
+        // Identifiers are often repeated, so each one is only stored once
+        let mut chunk_idents = Vec::new();
+        let mut ident_indices = HashMap::new();
+
+        for instr in bytecode {
+            let mut ident = |ident: Rc<str>| {
+                *ident_indices.entry(ident).or_insert_with_key(|ident| {
+                    chunk_idents.push(Rc::clone(ident));
+                    index(chunk_idents.len() - 1)
+                })
+            };
+
+            let op = match instr.kind {
+                InstructionKind::BinaryAdd => Op::BinaryAdd,
+                InstructionKind::BinarySubtract => Op::BinarySubtract,
+                InstructionKind::BinaryMultiply => Op::BinaryMultiply,
+                InstructionKind::BinaryDivide => Op::BinaryDivide,
+                InstructionKind::BinaryMod => Op::BinaryMod,
+                InstructionKind::BinaryPower => Op::BinaryPower,
+                InstructionKind::BinaryOr => Op::BinaryOr,
+                InstructionKind::BinaryAnd => Op::BinaryAnd,
+                InstructionKind::UnaryPositive => Op::UnaryPositive,
+                InstructionKind::UnaryNegative => Op::UnaryNegative,
+                InstructionKind::UnaryNot => Op::UnaryNot,
+                InstructionKind::CompareLT => Op::CompareLT,
+                InstructionKind::CompareLE => Op::CompareLE,
+                InstructionKind::CompareGT => Op::CompareGT,
+                InstructionKind::CompareGE => Op::CompareGE,
+                InstructionKind::CompareEQ => Op::CompareEQ,
+                InstructionKind::CompareNE => Op::CompareNE,
+                InstructionKind::Pop => Op::Pop,
+                InstructionKind::Push { value } => {
+                    chunk.constants.push(value);
+                    Op::Push {
+                        constant: index(chunk.constants.len() - 1),
+                    }
+                }
+                InstructionKind::Store {
+                    ident: name,
+                    declaration,
+                } => Op::Store {
+                    ident: ident(name),
+                    declaration,
+                },
+                InstructionKind::Load { ident: name } => Op::Load { ident: ident(name) },
+                InstructionKind::GetIndex => Op::GetIndex,
+                InstructionKind::SetIndex => Op::SetIndex,
+                InstructionKind::JumpTo { target } => Op::JumpTo {
+                    target: index(target),
+                },
+                InstructionKind::PopJumpIfTrue { target } => Op::PopJumpIfTrue {
+                    target: index(target),
+                },
+                InstructionKind::CallFunction { num_args } => Op::CallFunction {
+                    num_args: index(num_args),
+                },
+                InstructionKind::Label { .. } => {
+                    unreachable!("Labels are removed before the bytecode is encoded")
+                }
+                InstructionKind::MakeList { len } => Op::MakeList { len: index(len) },
+                InstructionKind::MakeObject { len } => Op::MakeObject { len: index(len) },
+                InstructionKind::MakeRange => Op::MakeRange,
+                InstructionKind::PushVar { scope } => {
+                    chunk.scopes.push(scope);
+                    Op::PushVar {
+                        scope: index(chunk.scopes.len() - 1),
+                    }
+                }
+                InstructionKind::PopVar => Op::PopVar,
+                InstructionKind::MakeIter => Op::MakeIter,
+                InstructionKind::ForIter { target } => Op::ForIter {
+                    target: index(target),
+                },
+                InstructionKind::ListAppend { depth } => Op::ListAppend {
+                    depth: index(depth),
+                },
+                InstructionKind::ObjectInsert { depth } => Op::ObjectInsert {
+                    depth: index(depth),
+                },
+                InstructionKind::BinaryIn => Op::BinaryIn,
+                InstructionKind::LoadLocal { depth, slot } => Op::LoadLocal {
+                    depth: index(depth),
+                    slot: index(slot),
+                },
+                InstructionKind::StoreLocal {
+                    depth,
+                    slot,
+                    declaration,
+                } => Op::StoreLocal {
+                    depth: index(depth),
+                    slot: index(slot),
+                    declaration,
+                },
+                InstructionKind::TailCallFunction { num_args } => Op::TailCallFunction {
+                    num_args: index(num_args),
+                },
+                InstructionKind::PopJumpIfFalse { target } => Op::PopJumpIfFalse {
+                    target: index(target),
+                },
+                InstructionKind::PopStore {
+                    ident: name,
+                    declaration,
+                } => Op::PopStore {
+                    ident: ident(name),
+                    declaration,
+                },
+                InstructionKind::PopStoreLocal {
+                    depth,
+                    slot,
+                    declaration,
+                } => Op::PopStoreLocal {
+                    depth: index(depth),
+                    slot: index(slot),
+                    declaration,
+                },
+                InstructionKind::SetNestedIndex { depth } => Op::SetNestedIndex {
+                    depth: index(depth),
+                },
+            };
+
+            if chunk.spans.last().map(|(_, span)| span) != Some(&instr.span) {
+                chunk.spans.push((index(chunk.ops.len()), instr.span));
+            }
+            chunk.ops.push(Cell::new(op));
+        }
+
+        chunk.idents = chunk_idents;
+        chunk.feedback = vec![Cell::default(); chunk.ops.len()];
+        chunk.caches = vec![InlineCache::default(); chunk.ops.len()];
+        chunk
+    }
+
+    /// Decodes the chunk back into bytecode, which is easier to read and compare.
+    pub fn to_bytecode(&self) -> Bytecode {
+        self.ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| Instruction::new(self.decode(op.get()), self.span(i)))
+            .collect()
+    }
+
+    fn decode(&self, op: Op) -> InstructionKind {
+        match op {
+            Op::BinaryAdd => InstructionKind::BinaryAdd,
+            Op::BinarySubtract => InstructionKind::BinarySubtract,
+            Op::BinaryMultiply => InstructionKind::BinaryMultiply,
+            Op::BinaryDivide => InstructionKind::BinaryDivide,
+            Op::BinaryMod => InstructionKind::BinaryMod,
+            Op::BinaryPower => InstructionKind::BinaryPower,
+            Op::BinaryOr => InstructionKind::BinaryOr,
+            Op::BinaryAnd => InstructionKind::BinaryAnd,
+            Op::UnaryPositive => InstructionKind::UnaryPositive,
+            Op::UnaryNegative => InstructionKind::UnaryNegative,
+            Op::UnaryNot => InstructionKind::UnaryNot,
+            Op::CompareLT => InstructionKind::CompareLT,
+            Op::CompareLE => InstructionKind::CompareLE,
+            Op::CompareGT => InstructionKind::CompareGT,
+            Op::CompareGE => InstructionKind::CompareGE,
+            Op::CompareEQ => InstructionKind::CompareEQ,
+            Op::CompareNE => InstructionKind::CompareNE,
+            Op::Pop => InstructionKind::Pop,
+            Op::Push { constant } => InstructionKind::Push {
+                value: self.constants[constant as usize].clone(),
+            },
+            Op::Store { ident, declaration } => InstructionKind::Store {
+                ident: Rc::clone(&self.idents[ident as usize]),
+                declaration,
+            },
+            Op::Load { ident } => InstructionKind::Load {
+                ident: Rc::clone(&self.idents[ident as usize]),
+            },
+            Op::GetIndex => InstructionKind::GetIndex,
+            Op::SetIndex => InstructionKind::SetIndex,
+            Op::JumpTo { target } => InstructionKind::JumpTo {
+                target: target as usize,
+            },
+            Op::PopJumpIfTrue { target } => InstructionKind::PopJumpIfTrue {
+                target: target as usize,
+            },
+            Op::CallFunction { num_args } => InstructionKind::CallFunction {
+                num_args: num_args as usize,
+            },
+            Op::MakeList { len } => InstructionKind::MakeList { len: len as usize },
+            Op::MakeObject { len } => InstructionKind::MakeObject { len: len as usize },
+            Op::MakeRange => InstructionKind::MakeRange,
+            Op::PushVar { scope } => InstructionKind::PushVar {
+                scope: Rc::clone(&self.scopes[scope as usize]),
+            },
+            Op::PopVar => InstructionKind::PopVar,
+            Op::MakeIter => InstructionKind::MakeIter,
+            Op::ForIter { target } => InstructionKind::ForIter {
+                target: target as usize,
+            },
+            Op::ListAppend { depth } => InstructionKind::ListAppend {
+                depth: depth as usize,
+            },
+            Op::ObjectInsert { depth } => InstructionKind::ObjectInsert {
+                depth: depth as usize,
+            },
+            Op::BinaryIn => InstructionKind::BinaryIn,
+            Op::LoadLocal { depth, slot } => InstructionKind::LoadLocal {
+                depth: depth as usize,
+                slot: slot as usize,
+            },
+            Op::StoreLocal {
+                depth,
+                slot,
+                declaration,
+            } => InstructionKind::StoreLocal {
+                depth: depth as usize,
+                slot: slot as usize,
+                declaration,
+            },
+            Op::TailCallFunction { num_args } => InstructionKind::TailCallFunction {
+                num_args: num_args as usize,
+            },
+            Op::PopJumpIfFalse { target } => InstructionKind::PopJumpIfFalse {
+                target: target as usize,
+            },
+            Op::PopStore { ident, declaration } => InstructionKind::PopStore {
+                ident: Rc::clone(&self.idents[ident as usize]),
+                declaration,
+            },
+            Op::PopStoreLocal {
+                depth,
+                slot,
+                declaration,
+            } => InstructionKind::PopStoreLocal {
+                depth: depth as usize,
+                slot: slot as usize,
+                declaration,
+            },
+            Op::SetNestedIndex { depth } => InstructionKind::SetNestedIndex {
+                depth: depth as usize,
+            },
+            specialized => self.decode(specialized.generic()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// The span of source code the op at `offset` was lowered from.
+    pub fn span(&self, offset: usize) -> TextSpan {
+        let run = self
+            .spans
+            .partition_point(|(start, _)| *start as usize <= offset);
+        self.spans[run - 1].1.clone()
+    }
+
+    /// Records the types of the operands the op at `offset` was just evaluated with, as the
+    /// specialized op for them, or `None` if there is no specialized op for them. Once an op has
+    /// been evaluated with operands of the same types enough times in a row, it is replaced with
+    /// the specialized op, unless it has already been deoptimized too often.
+    pub fn record_types(&self, offset: usize, specialized: Option<Op>) {
+        let cell = &self.feedback[offset];
+        let mut feedback = cell.get();
+        if feedback.deopts >= MAX_DEOPTS {
+            return;
+        }
+
+        match specialized {
+            Some(op) if feedback.specialized == Some(op) => {
+                feedback.count += 1;
+                if feedback.count >= SPECIALIZE_AFTER {
+                    self.ops[offset].set(op);
+                    feedback.count = 0;
+                }
+            }
+            _ => {
+                feedback.specialized = specialized;
+                feedback.count = u8::from(specialized.is_some());
+            }
+        }
+        cell.set(feedback);
+    }
+
+    /// Replaces the specialized op at `offset` with the generic op it was made from, since it was
+    /// evaluated with operands of types it isn't made for.
+    pub fn deoptimize(&self, offset: usize) {
+        let op = &self.ops[offset];
+        op.set(op.get().generic());
+
+        let cell = &self.feedback[offset];
+        let mut feedback = cell.get();
+        feedback.deopts += 1;
+        cell.set(feedback);
+    }
+
+    /// The inline cache of the op at `offset`, which remembers where the property it accesses was
+    /// last found.
+    pub fn cache(&self, offset: usize) -> &InlineCache {
+        &self.caches[offset]
+    }
+}
+
+impl Serialize for Op {
+    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
+        let (tag, operands): (u8, &[u32]) = match *self {
+            Op::BinaryAdd => (0, &[]),
+            Op::BinarySubtract => (1, &[]),
+            Op::BinaryMultiply => (2, &[]),
+            Op::BinaryDivide => (3, &[]),
+            Op::BinaryMod => (4, &[]),
+            Op::BinaryPower => (5, &[]),
+            Op::BinaryOr => (6, &[]),
+            Op::BinaryAnd => (7, &[]),
+            Op::UnaryPositive => (8, &[]),
+            Op::UnaryNegative => (9, &[]),
+            Op::UnaryNot => (10, &[]),
+            Op::CompareLT => (11, &[]),
+            Op::CompareLE => (12, &[]),
+            Op::CompareGT => (13, &[]),
+            Op::CompareGE => (14, &[]),
+            Op::CompareEQ => (15, &[]),
+            Op::CompareNE => (16, &[]),
+            Op::Pop => (17, &[]),
+            Op::Push { constant } => (18, &[constant]),
+            Op::Store { ident, declaration } => {
+                buf.write_all(&[19])?;
+                ident.serialize(buf)?;
+                declaration.serialize(buf)?;
+                return Ok(6);
+            }
+            Op::Load { ident } => (20, &[ident]),
+            Op::GetIndex => (21, &[]),
+            Op::SetIndex => (22, &[]),
+            Op::JumpTo { target } => (23, &[target]),
+            Op::PopJumpIfTrue { target } => (24, &[target]),
+            Op::CallFunction { num_args } => (25, &[num_args]),
+            Op::MakeList { len } => (27, &[len]),
+            Op::MakeObject { len } => (28, &[len]),
+            Op::MakeRange => (29, &[]),
+            Op::PushVar { scope } => (30, &[scope]),
+            Op::PopVar => (31, &[]),
+            Op::MakeIter => (32, &[]),
+            Op::ForIter { target } => (33, &[target]),
+            Op::ListAppend { depth } => (34, &[depth]),
+            Op::ObjectInsert { depth } => (35, &[depth]),
+            Op::BinaryIn => (36, &[]),
+            Op::LoadLocal { depth, slot } => (37, &[depth, slot]),
+            Op::StoreLocal {
+                depth,
+                slot,
+                declaration,
+            } => {
+                buf.write_all(&[38])?;
+                depth.serialize(buf)?;
+                slot.serialize(buf)?;
+                declaration.serialize(buf)?;
+                return Ok(10);
+            }
+            Op::TailCallFunction { num_args } => (39, &[num_args]),
+            Op::PopJumpIfFalse { target } => (40, &[target]),
+            Op::PopStore { ident, declaration } => {
+                buf.write_all(&[41])?;
+                ident.serialize(buf)?;
+                declaration.serialize(buf)?;
+                return Ok(6);
+            }
+            Op::PopStoreLocal {
+                depth,
+                slot,
+                declaration,
+            } => {
+                buf.write_all(&[42])?;
+                depth.serialize(buf)?;
+                slot.serialize(buf)?;
+                declaration.serialize(buf)?;
+                return Ok(10);
+            }
+            Op::SetNestedIndex { depth } => (43, &[depth]),
+            // Specialization only lasts as long as the program is running
+            specialized => return specialized.generic().serialize(buf),
+        };
+
+        buf.write_all(&[tag])?;
+        for operand in operands {
+            operand.serialize(buf)?;
+        }
+        Ok(1 + 4 * operands.len())
+    }
+}
+
+impl Deserialize for Op {
+    fn deserialize<R: BufRead>(data: &mut R) -> io::Result<Self> {
+        let tag = u8::deserialize(data)?;
+
+        Ok(match tag {
+            0 => Op::BinaryAdd,
+            1 => Op::BinarySubtract,
+            2 => Op::BinaryMultiply,
+            3 => Op::BinaryDivide,
+            4 => Op::BinaryMod,
+            5 => Op::BinaryPower,
+            6 => Op::BinaryOr,
+            7 => Op::BinaryAnd,
+            8 => Op::UnaryPositive,
+            9 => Op::UnaryNegative,
+            10 => Op::UnaryNot,
+            11 => Op::CompareLT,
+            12 => Op::CompareLE,
+            13 => Op::CompareGT,
+            14 => Op::CompareGE,
+            15 => Op::CompareEQ,
+            16 => Op::CompareNE,
+            17 => Op::Pop,
+            18 => Op::Push {
+                constant: u32::deserialize(data)?,
+            },
+            19 => Op::Store {
+                ident: u32::deserialize(data)?,
+                declaration: bool::deserialize(data)?,
+            },
+            20 => Op::Load {
+                ident: u32::deserialize(data)?,
+            },
+            21 => Op::GetIndex,
+            22 => Op::SetIndex,
+            23 => Op::JumpTo {
+                target: u32::deserialize(data)?,
+            },
+            24 => Op::PopJumpIfTrue {
+                target: u32::deserialize(data)?,
+            },
+            25 => Op::CallFunction {
+                num_args: u32::deserialize(data)?,
+            },
+            27 => Op::MakeList {
+                len: u32::deserialize(data)?,
+            },
+            28 => Op::MakeObject {
+                len: u32::deserialize(data)?,
+            },
+            29 => Op::MakeRange,
+            30 => Op::PushVar {
+                scope: u32::deserialize(data)?,
+            },
+            31 => Op::PopVar,
+            32 => Op::MakeIter,
+            33 => Op::ForIter {
+                target: u32::deserialize(data)?,
+            },
+            34 => Op::ListAppend {
+                depth: u32::deserialize(data)?,
+            },
+            35 => Op::ObjectInsert {
+                depth: u32::deserialize(data)?,
+            },
+            36 => Op::BinaryIn,
+            37 => Op::LoadLocal {
+                depth: u32::deserialize(data)?,
+                slot: u32::deserialize(data)?,
+            },
+            38 => Op::StoreLocal {
+                depth: u32::deserialize(data)?,
+                slot: u32::deserialize(data)?,
+                declaration: bool::deserialize(data)?,
+            },
+            39 => Op::TailCallFunction {
+                num_args: u32::deserialize(data)?,
+            },
+            40 => Op::PopJumpIfFalse {
+                target: u32::deserialize(data)?,
+            },
+            41 => Op::PopStore {
+                ident: u32::deserialize(data)?,
+                declaration: bool::deserialize(data)?,
+            },
+            42 => Op::PopStoreLocal {
+                depth: u32::deserialize(data)?,
+                slot: u32::deserialize(data)?,
+                declaration: bool::deserialize(data)?,
+            },
+            43 => Op::SetNestedIndex {
+                depth: u32::deserialize(data)?,
+            },
+            n => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Tag of {} is not a valid instruction tag", n),
+                ))
+            }
+        })
+    }
+}
+
+impl Serialize for Chunk {
+    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
+        let mut written = self.ops.len().serialize(buf)?;
+        for op in self.ops.iter() {
+            written += op.get().serialize(buf)?;
+        }
+        written += self.constants.serialize(buf)?;
+        written += self.idents.serialize(buf)?;
+
+        written += self.scopes.len().serialize(buf)?;
+        for scope in self.scopes.iter() {
+            written += scope.id.serialize(buf)?;
+        }
+
+        written += self.spans.len().serialize(buf)?;
+        for (offset, span) in self.spans.iter() {
+            written += offset.serialize(buf)?;
+            written += index(span.start()).serialize(buf)?;
+            written += index(span.len()).serialize(buf)?;
+        }
+
+        Ok(written)
+    }
+}
+
+impl DeserializeCtx<DeserializationContext> for Chunk {
+    fn deserialize_with_context<R: BufRead>(
+        data: &mut R,
+        ctx: &mut DeserializationContext,
+    ) -> io::Result<Self> {
+        let ops: Vec<Op> = Vec::deserialize(data)?;
+        let constants = Vec::deserialize_with_context(data, ctx)?;
+        let idents = Vec::deserialize_with_context(data, ctx)?;
+
+        let num_scopes = usize::deserialize(data)?;
+        let mut scopes = Vec::with_capacity(num_scopes);
+        for _ in 0..num_scopes {
+            scopes.push(ctx.get_scope(usize::deserialize(data)?));
+        }
+
+        let num_spans = usize::deserialize(data)?;
+        let mut spans = Vec::with_capacity(num_spans);
+        for _ in 0..num_spans {
+            let offset = u32::deserialize(data)?;
+            let start = u32::deserialize(data)? as usize;
+            let len = u32::deserialize(data)? as usize;
+            spans.push((offset, TextSpan::new(start, len)));
+        }
+
+        Ok(Chunk {
+            feedback: vec![Cell::default(); ops.len()],
+            caches: vec![InlineCache::default(); ops.len()],
+            ops: ops.into_iter().map(Cell::new).collect(),
+            constants,
+            idents,
+            scopes,
+            spans,
+        })
+    }
+}
@@ -1,12 +1,11 @@
 use crate::scope::Scope;
 use crate::value::Value;
-use crate::DeserializationContext;
-use serialize::{Deserialize, DeserializeCtx, Serialize};
 use source::TextSpan;
-use std::io::{self, prelude::*};
 use std::rc::Rc;
 
+mod chunk;
 mod print_bytecode;
+pub use chunk::{Chunk, Op};
 pub use print_bytecode::print_bytecode;
 
 #[cfg(test)]
@@ -27,27 +26,6 @@ impl Instruction {
     }
 }
 
-impl Serialize for Instruction {
-    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
-        let bytes_read = self.kind.serialize(buf)?;
-        self.span.start().serialize(buf)?;
-        self.span.len().serialize(buf)?;
-        Ok(bytes_read + 16)
-    }
-}
-
-impl DeserializeCtx<DeserializationContext> for Instruction {
-    fn deserialize_with_context<R: BufRead>(
-        data: &mut R,
-        ctx: &mut DeserializationContext,
-    ) -> io::Result<Self> {
-        let kind = InstructionKind::deserialize_with_context(data, ctx)?;
-        let span_start = usize::deserialize(data)?;
-        let span_len = usize::deserialize(data)?;
-        Ok(Instruction::new(kind, TextSpan::new(span_start, span_len)))
-    }
-}
-
 // somehow need to allow this to be compiled for other crates running tests
 // #[cfg(test)]
 impl From<InstructionKind> for Instruction {
@@ -57,7 +35,8 @@ impl From<InstructionKind> for Instruction {
 }
 
 /// Representation of a single unit of the low level intermediate bytecode - a [`Vec`](std::vec::Vec)
-/// of [`Instruction`]. The bytecode is executed along side a stack of [`Value`]s.
+/// of [`Instruction`]. The bytecode is executed along side a stack of [`Value`]s, once it has been
+/// finalized into a [`Chunk`].
 ///
 /// For all documentation of various instructions, assume the stack is in the form `[a, b, c, ...]`
 /// where `a` is the top of the stack.
@@ -151,19 +130,24 @@ pub enum InstructionKind {
     ///
     /// stack = `[a[b], c, d, ...]`
     GetIndex,
-    /// Take 3 values from the stack, and stores the third into first with index second.
+    /// Take 3 values from the stack, and stores the third into first with index second, then push
+    /// the first. Strings can't be changed, so for a string a new string is pushed instead.
     ///
-    /// stack = `[d, ...]`
+    /// stack = `[a, d, ...]`
     /// a[b] = c
     SetIndex,
-    /// Jump to a particular label
-    JumpTo { label: LabelNumber },
-    /// Pop the top value of the stack, then jump to a label if it is true.
-    PopJumpIfTrue { label: LabelNumber },
+    /// Jump to the instruction at `target`.
+    ///
+    /// The lowerer first sets `target` to a label number, which is replaced with the index of the
+    /// instruction after the label by [`finalize_bytecode`].
+    JumpTo { target: usize },
+    /// Pop the top value of the stack, then jump to the instruction at `target` if it is true.
+    PopJumpIfTrue { target: usize },
     /// Take the top of the stack, and call it as a function, popping the next `num_args` values of
     /// the stack and supplying them as arguments to the function.
     CallFunction { num_args: usize },
-    /// Label to jump to
+    /// Label to jump to. Labels are only used while lowering, and are removed by
+    /// [`finalize_bytecode`].
     Label { number: LabelNumber },
     /// Take the top <len> elements of the stack and push a List on to the stack.
     MakeList { len: usize },
@@ -179,165 +163,131 @@ pub enum InstructionKind {
     PushVar { scope: Rc<Scope> },
     /// Pop the top variable stack
     PopVar,
+    /// Take the top of the stack and push an iterator over it, which is the value to iterate over
+    /// followed by the index of the next element. Objects are iterated as `[key, value]` pairs.
+    ///
+    /// stack = `[0, iter(a), b, c, ...]`
+    MakeIter,
+    /// Advance the iterator on the top of the stack. If it is exhausted, pop the iterator and jump
+    /// to the instruction at `target`, otherwise increment the index and push the next element.
+    ///
+    /// stack = `[b[a], a + 1, b, c, ...]` or `[c, ...]`
+    ForIter { target: usize },
+    /// Pop the top of the stack and push it to the list `depth` values below it.
+    ///
+    /// stack = `[b, c, ...]`
+    /// list.push(a)
+    ListAppend { depth: usize },
+    /// Pop the top 2 values of the stack and insert them into the object `depth` values below
+    /// them, with the first as the key and the second as the value.
+    ///
+    /// stack = `[c, d, ...]`
+    /// object[a] = b
+    ObjectInsert { depth: usize },
+    /// Take 2 values of the stack, check if the first is in the second and push the result to the
+    /// stack.
+    ///
+    /// stack = `[a in b, c, d, ...]`
+    BinaryIn,
+    /// Load the value in a slot of the scope `depth` parents above the current one on to the
+    /// stack.
+    LoadLocal { depth: usize, slot: usize },
+    /// Store the top of the stack into a slot of the scope `depth` parents above the current one,
+    /// without popping it.
+    ///
+    /// stack = `[a, b, c, ...]`
+    /// slot = a
+    StoreLocal {
+        depth: usize,
+        slot: usize,
+        declaration: bool,
+    },
+    /// Same as `CallFunction`, but the call is the last thing the current function does, so the
+    /// frame of the current function is reused for the called function instead of a new one
+    /// being pushed.
+    TailCallFunction { num_args: usize },
+    /// Pop the top value of the stack, then jump to the instruction at `target` if it is false.
+    /// The same as a `UnaryNot` followed by a `PopJumpIfTrue`.
+    PopJumpIfFalse { target: usize },
+    /// Pop the top of the stack and store it into a variable. The same as a `Store` followed by a
+    /// `Pop`.
+    ///
+    /// stack = `[b, c, d, ...]`
+    /// ident = a
+    PopStore { ident: Rc<str>, declaration: bool },
+    /// Pop the top of the stack and store it into a slot of the scope `depth` parents above the
+    /// current one. The same as a `StoreLocal` followed by a `Pop`.
+    ///
+    /// stack = `[b, c, d, ...]`
+    /// slot = a
+    PopStoreLocal {
+        depth: usize,
+        slot: usize,
+        declaration: bool,
+    },
+    /// Take the top `depth` + 2 values of the stack, and store the last into the first indexed by
+    /// each of the values between them in turn. Every value along the way is stored back into the
+    /// value it was indexed from, so strings are replaced with the new strings, and the first value
+    /// is pushed back.
+    ///
+    /// stack = `[a, e, ...]`
+    /// a[b][c] = d
+    SetNestedIndex { depth: usize },
 }
 
-impl Serialize for InstructionKind {
-    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
-        match self {
-            InstructionKind::BinaryAdd => buf.write(&[0]),
-            InstructionKind::BinarySubtract => buf.write(&[1]),
-            InstructionKind::BinaryMultiply => buf.write(&[2]),
-            InstructionKind::BinaryDivide => buf.write(&[3]),
-            InstructionKind::BinaryMod => buf.write(&[4]),
-            InstructionKind::BinaryPower => buf.write(&[5]),
-            InstructionKind::BinaryOr => buf.write(&[6]),
-            InstructionKind::BinaryAnd => buf.write(&[7]),
-            InstructionKind::UnaryPositive => buf.write(&[8]),
-            InstructionKind::UnaryNegative => buf.write(&[9]),
-            InstructionKind::UnaryNot => buf.write(&[10]),
-            InstructionKind::CompareLT => buf.write(&[11]),
-            InstructionKind::CompareLE => buf.write(&[12]),
-            InstructionKind::CompareGT => buf.write(&[13]),
-            InstructionKind::CompareGE => buf.write(&[14]),
-            InstructionKind::CompareEQ => buf.write(&[15]),
-            InstructionKind::CompareNE => buf.write(&[16]),
-            InstructionKind::Pop => buf.write(&[17]),
-            InstructionKind::Push { value } => {
-                buf.write_all(&[18])?;
-                let bytes_written = value.serialize(buf)?;
-                Ok(bytes_written + 1)
-            }
-            InstructionKind::Store { ident, declaration } => {
-                buf.write_all(&[19])?;
-                Rc::serialize(&ident, buf)?;
-                declaration.serialize(buf)?;
-                Ok(10)
-            }
-            InstructionKind::Load { ident } => {
-                buf.write_all(&[20])?;
-                Rc::serialize(&ident, buf)?;
-                Ok(9)
-            }
-            InstructionKind::GetIndex => buf.write(&[21]),
-            InstructionKind::SetIndex => buf.write(&[22]),
-            InstructionKind::JumpTo { label } => {
-                buf.write_all(&[23])?;
-                label.serialize(buf)?;
-                Ok(9)
-            }
-            InstructionKind::PopJumpIfTrue { label } => {
-                buf.write_all(&[24])?;
-                label.serialize(buf)?;
-                Ok(9)
-            }
-            InstructionKind::CallFunction { num_args } => {
-                buf.write_all(&[25])?;
-                num_args.serialize(buf)?;
-                Ok(9)
-            }
-            InstructionKind::Label { number } => {
-                buf.write_all(&[26])?;
-                number.serialize(buf)?;
-                Ok(9)
-            }
-            InstructionKind::MakeList { len } => {
-                buf.write_all(&[27])?;
-                len.serialize(buf)?;
-                Ok(9)
-            }
-            InstructionKind::MakeObject { len } => {
-                buf.write_all(&[28])?;
-                len.serialize(buf)?;
-                Ok(9)
-            }
-            InstructionKind::MakeRange => buf.write(&[29]),
-            InstructionKind::PushVar { scope } => {
-                buf.write_all(&[30])?;
-                Ok(1 + scope.id.serialize(buf)?)
+/// Replaces the label numbers of jumps with the index of the instruction they jump to, and removes
+/// the labels so they don't have to be stepped over when the bytecode is run. The result is encoded
+/// into a [`Chunk`].
+///
+/// Function bodies are not finalized, since they are finalized when the function is lowered.
+///
+/// # Examples
+/// ```
+/// use vm::{finalize_bytecode, InstructionKind};
+///
+/// let chunk = finalize_bytecode(vec![
+///     InstructionKind::JumpTo { target: 0 }.into(),
+///     InstructionKind::Pop.into(),
+///     InstructionKind::Label { number: 0 }.into(),
+///     InstructionKind::PopVar.into(),
+/// ]);
+///
+/// let kinds: Vec<_> = chunk.to_bytecode().into_iter().map(|instr| instr.kind).collect();
+/// assert_eq!(
+///     kinds,
+///     vec![
+///         InstructionKind::JumpTo { target: 2 },
+///         InstructionKind::Pop,
+///         InstructionKind::PopVar,
+///     ]
+/// );
+/// ```
+pub fn finalize_bytecode(bytecode: Bytecode) -> Chunk {
+    let mut labels = Vec::new();
+    let mut len = 0;
+    for instr in bytecode.iter() {
+        if let InstructionKind::Label { number } = instr.kind {
+            if number >= labels.len() {
+                labels.resize(number + 1, usize::MAX);
             }
-            InstructionKind::PopVar => buf.write(&[31]),
+            labels[number] = len;
+        } else {
+            len += 1;
         }
     }
-}
-
-impl DeserializeCtx<DeserializationContext> for InstructionKind {
-    fn deserialize_with_context<R: BufRead>(
-        data: &mut R,
-        ctx: &mut DeserializationContext,
-    ) -> io::Result<Self> {
-        let mut tag = 0;
-        data.read_exact(std::slice::from_mut(&mut tag))?;
 
-        Ok(match tag {
-            0 => InstructionKind::BinaryAdd,
-            1 => InstructionKind::BinarySubtract,
-            2 => InstructionKind::BinaryMultiply,
-            3 => InstructionKind::BinaryDivide,
-            4 => InstructionKind::BinaryMod,
-            5 => InstructionKind::BinaryPower,
-            6 => InstructionKind::BinaryOr,
-            7 => InstructionKind::BinaryAnd,
-            8 => InstructionKind::UnaryPositive,
-            9 => InstructionKind::UnaryNegative,
-            10 => InstructionKind::UnaryNot,
-            11 => InstructionKind::CompareLT,
-            12 => InstructionKind::CompareLE,
-            13 => InstructionKind::CompareGT,
-            14 => InstructionKind::CompareGE,
-            15 => InstructionKind::CompareEQ,
-            16 => InstructionKind::CompareNE,
-            17 => InstructionKind::Pop,
-            18 => {
-                let value = Value::deserialize_with_context(data, ctx)?;
-                InstructionKind::Push { value }
-            }
-            19 => InstructionKind::Store {
-                ident: Rc::deserialize_with_context(data, ctx)?,
-                declaration: bool::deserialize(data)?,
-            },
-            20 => InstructionKind::Load {
-                ident: Rc::deserialize_with_context(data, ctx)?,
-            },
-            21 => InstructionKind::GetIndex,
-            22 => InstructionKind::SetIndex,
-            23 => {
-                let label = usize::deserialize(data)?;
-                InstructionKind::JumpTo { label }
-            }
-            24 => {
-                let label = usize::deserialize(data)?;
-                InstructionKind::PopJumpIfTrue { label }
-            }
-            25 => {
-                let num_args = usize::deserialize(data)?;
-                InstructionKind::CallFunction { num_args }
-            }
-            26 => {
-                let number = usize::deserialize(data)?;
-                InstructionKind::Label { number }
-            }
-            27 => {
-                let len = usize::deserialize(data)?;
-                InstructionKind::MakeList { len }
-            }
-            28 => {
-                let len = usize::deserialize(data)?;
-                InstructionKind::MakeObject { len }
-            }
-            29 => InstructionKind::MakeRange,
-            30 => {
-                let id = usize::deserialize(data)?;
-                InstructionKind::PushVar {
-                    scope: ctx.get_scope(id),
-                }
-            }
-            31 => InstructionKind::PopVar,
-            n => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Tag of {} is not a valid instruction tag", n),
-                ))
-            }
-        })
+    let mut finalized = Vec::with_capacity(len);
+    for mut instr in bytecode {
+        match &mut instr.kind {
+            InstructionKind::Label { .. } => continue,
+            InstructionKind::JumpTo { target }
+            | InstructionKind::PopJumpIfTrue { target }
+            | InstructionKind::PopJumpIfFalse { target }
+            | InstructionKind::ForIter { target } => *target = labels[*target],
+            _ => {}
+        }
+        finalized.push(instr);
     }
+
+    Chunk::encode(finalized)
 }
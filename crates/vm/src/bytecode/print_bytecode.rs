@@ -1,9 +1,11 @@
-use super::{Instruction, InstructionKind};
+use super::{Chunk, InstructionKind};
 use crossterm::{queue, style, Result};
 use std::io::prelude::*;
 
-pub fn print_bytecode(bytecode: &[Instruction]) -> Result<()> {
+pub fn print_bytecode(chunk: &Chunk) -> Result<()> {
     let stdout = &mut std::io::stdout();
+    // The decoded instructions hold their values and scopes, which are easier to read than indices
+    let bytecode = chunk.to_bytecode();
 
     if bytecode.is_empty() {
         return Ok(());
@@ -16,25 +18,29 @@ pub fn print_bytecode(bytecode: &[Instruction]) -> Result<()> {
         l /= 10;
     }
 
-    let mut labels = Vec::new();
-
-    for (i, instr) in bytecode.iter().enumerate() {
-        if let InstructionKind::Label { number } = instr.kind {
-            if number >= labels.len() {
-                labels.resize(number + 1, usize::MAX);
-            }
-            labels[number] = i;
+    // The instructions which are jumped to are marked, so that the loops and branches are easier
+    // to follow
+    let mut is_target = vec![false; bytecode.len() + 1];
+    for instr in bytecode.iter() {
+        match instr.kind {
+            InstructionKind::JumpTo { target }
+            | InstructionKind::PopJumpIfTrue { target }
+            | InstructionKind::PopJumpIfFalse { target }
+            | InstructionKind::ForIter { target } => is_target[target] = true,
+            _ => {}
         }
     }
 
     for (i, instr) in bytecode.iter().enumerate() {
         queue!(
             stdout,
+            style::SetForegroundColor(style::Color::Yellow),
+            style::Print(if is_target[i] { ">> " } else { "   " }),
             style::SetForegroundColor(style::Color::DarkGreen),
             style::Print(format!("{: >w$}\t", i, w = w)),
             style::ResetColor,
         )?;
-        print_instr(&instr.kind, stdout, &labels[..])?;
+        print_instr(&instr.kind, stdout)?;
         stdout.write_all(b"\n")?;
     }
 
@@ -43,11 +49,7 @@ pub fn print_bytecode(bytecode: &[Instruction]) -> Result<()> {
     Ok(())
 }
 
-fn print_instr(
-    instr: &InstructionKind,
-    stdout: &mut std::io::Stdout,
-    labels: &[usize],
-) -> Result<()> {
+fn print_instr(instr: &InstructionKind, stdout: &mut std::io::Stdout) -> Result<()> {
     match instr {
         InstructionKind::BinaryAdd => queue!(stdout, style::Print("BinaryAdd\t\t")),
         InstructionKind::BinarySubtract => queue!(stdout, style::Print("BinarySubtract\t\t")),
@@ -84,22 +86,18 @@ fn print_instr(
         ),
         InstructionKind::GetIndex => queue!(stdout, style::Print("GetIndex\t\t")),
         InstructionKind::SetIndex => queue!(stdout, style::Print("SetIndex\t\t")),
-        InstructionKind::JumpTo { label } => queue!(
+        InstructionKind::JumpTo { target } => queue!(
             stdout,
             style::Print("JumpTo\t\t\t"),
-            style::SetForegroundColor(style::Color::Yellow),
-            style::Print(format!("label: {}\t\t", label)),
             style::SetForegroundColor(style::Color::DarkGreen),
-            style::Print(format!("instr: {}", labels[*label])),
+            style::Print(format!("target: {}", target)),
             style::ResetColor,
         ),
-        InstructionKind::PopJumpIfTrue { label } => queue!(
+        InstructionKind::PopJumpIfTrue { target } => queue!(
             stdout,
             style::Print("PopJumpIfTrue\t\t"),
-            style::SetForegroundColor(style::Color::Yellow),
-            style::Print(format!("label: {}\t\t", label)),
             style::SetForegroundColor(style::Color::DarkGreen),
-            style::Print(format!("instr: {}", labels[*label])),
+            style::Print(format!("target: {}", target)),
             style::ResetColor,
         ),
         InstructionKind::CallFunction { num_args } => queue!(
@@ -136,6 +134,76 @@ fn print_instr(
             }
         ),
         InstructionKind::PopVar => queue!(stdout, style::Print("PopVar\t\t\t")),
+        InstructionKind::MakeIter => queue!(stdout, style::Print("MakeIter\t\t")),
+        InstructionKind::ForIter { target } => queue!(
+            stdout,
+            style::Print("ForIter\t\t\t"),
+            style::SetForegroundColor(style::Color::DarkGreen),
+            style::Print(format!("target: {}", target)),
+            style::ResetColor,
+        ),
+        InstructionKind::ListAppend { depth } => queue!(
+            stdout,
+            style::Print("ListAppend\t\t"),
+            style::Print(format!("depth: {}", depth))
+        ),
+        InstructionKind::ObjectInsert { depth } => queue!(
+            stdout,
+            style::Print("ObjectInsert\t\t"),
+            style::Print(format!("depth: {}", depth))
+        ),
+        InstructionKind::BinaryIn => queue!(stdout, style::Print("BinaryIn\t\t")),
+        InstructionKind::LoadLocal { depth, slot } => queue!(
+            stdout,
+            style::Print("LoadLocal\t\t"),
+            style::Print(format!("depth: {}\tslot: {}", depth, slot))
+        ),
+        InstructionKind::StoreLocal {
+            depth,
+            slot,
+            declaration,
+        } => queue!(
+            stdout,
+            style::Print("StoreLocal\t\t"),
+            style::Print(format!(
+                "declaration: {}\tdepth: {}\tslot: {}",
+                declaration, depth, slot
+            ))
+        ),
+        InstructionKind::TailCallFunction { num_args } => queue!(
+            stdout,
+            style::Print("TailCallFunction\t"),
+            style::Print(format!("args: {}", num_args))
+        ),
+        InstructionKind::PopJumpIfFalse { target } => queue!(
+            stdout,
+            style::Print("PopJumpIfFalse\t\t"),
+            style::SetForegroundColor(style::Color::DarkGreen),
+            style::Print(format!("target: {}", target)),
+            style::ResetColor,
+        ),
+        InstructionKind::PopStore { declaration, ident } => queue!(
+            stdout,
+            style::Print("PopStore\t\t"),
+            style::Print(format!("declaration: {}\tident: {}", declaration, ident)),
+        ),
+        InstructionKind::PopStoreLocal {
+            depth,
+            slot,
+            declaration,
+        } => queue!(
+            stdout,
+            style::Print("PopStoreLocal\t\t"),
+            style::Print(format!(
+                "declaration: {}\tdepth: {}\tslot: {}",
+                declaration, depth, slot
+            ))
+        ),
+        InstructionKind::SetNestedIndex { depth } => queue!(
+            stdout,
+            style::Print("SetNestedIndex		"),
+            style::Print(format!("depth: {}", depth))
+        ),
     }
 }
 
@@ -171,6 +239,7 @@ fn print_value(value: &crate::value::Value, stdout: &mut std::io::Stdout) -> Res
             style::Print(format!("{:?}", value)),
             style::ResetColor
         ),
+        Type::Map | Type::Set => queue!(stdout, style::Print(format!("{:?}", value))),
         Type::List => {
             queue!(stdout, style::Print("["))?;
             for v in value.to_ref_list().iter() {
@@ -1,239 +1,439 @@
 use super::*;
 use crate::test_helpers::*;
+use crate::DeserializationContext;
+use serialize::{Deserialize, DeserializeCtx, Serialize};
+use std::cell::Cell;
 use std::rc::Rc;
-use std::thread_local;
 
-thread_local! {
-    static IDENT: Rc<str> = "ident".into();
-}
-
-fn id(rc: &Rc<str>) -> usize {
-    Rc::as_ptr(rc) as *const u8 as usize
-}
-
-fn test_serialize(instr: InstructionKind, expected_bytes: Vec<u8>) {
-    let mut context = DeserializationContext::new(1, 1, None);
-    context.add_scope(0, None);
-    IDENT.with(|ident| context.add_ident(id(ident), Rc::clone(ident)));
+fn test_serialize(op: Op, expected_bytes: Vec<u8>) {
     let mut buf = Vec::new();
-    assert_eq!(instr.serialize(&mut buf).unwrap(), expected_bytes.len());
+    assert_eq!(op.serialize(&mut buf).unwrap(), expected_bytes.len());
     assert_eq!(buf[..expected_bytes.len()], expected_bytes[..]);
-    assert_eq!(
-        InstructionKind::deserialize_with_context(&mut &expected_bytes[..], &mut context).unwrap(),
-        instr
-    );
+    assert_eq!(Op::deserialize(&mut &expected_bytes[..]).unwrap(), op);
 }
 
 #[test]
 fn serialize_instr_add() {
-    test_serialize(InstructionKind::BinaryAdd, vec![0]);
+    test_serialize(Op::BinaryAdd, vec![0]);
 }
 
 #[test]
 fn serialize_instr_sub() {
-    test_serialize(InstructionKind::BinarySubtract, vec![1]);
+    test_serialize(Op::BinarySubtract, vec![1]);
 }
 
 #[test]
 fn serialize_instr_mult() {
-    test_serialize(InstructionKind::BinaryMultiply, vec![2]);
+    test_serialize(Op::BinaryMultiply, vec![2]);
 }
 
 #[test]
 fn serialize_instr_div() {
-    test_serialize(InstructionKind::BinaryDivide, vec![3]);
+    test_serialize(Op::BinaryDivide, vec![3]);
 }
 
 #[test]
 fn serialize_instr_mod() {
-    test_serialize(InstructionKind::BinaryMod, vec![4]);
+    test_serialize(Op::BinaryMod, vec![4]);
 }
 
 #[test]
 fn serialize_instr_pow() {
-    test_serialize(InstructionKind::BinaryPower, vec![5]);
+    test_serialize(Op::BinaryPower, vec![5]);
 }
 
 #[test]
 fn serialize_instr_or() {
-    test_serialize(InstructionKind::BinaryOr, vec![6]);
+    test_serialize(Op::BinaryOr, vec![6]);
 }
 
 #[test]
 fn serialize_instr_and() {
-    test_serialize(InstructionKind::BinaryAnd, vec![7]);
+    test_serialize(Op::BinaryAnd, vec![7]);
 }
 
 #[test]
 fn serialize_instr_unary_plus() {
-    test_serialize(InstructionKind::UnaryPositive, vec![8]);
+    test_serialize(Op::UnaryPositive, vec![8]);
 }
 
 #[test]
 fn serialize_instr_unary_minus() {
-    test_serialize(InstructionKind::UnaryNegative, vec![9]);
+    test_serialize(Op::UnaryNegative, vec![9]);
 }
 
 #[test]
 fn serialize_instr_unary_not() {
-    test_serialize(InstructionKind::UnaryNot, vec![10]);
+    test_serialize(Op::UnaryNot, vec![10]);
 }
 
 #[test]
 fn serialize_instr_lt() {
-    test_serialize(InstructionKind::CompareLT, vec![11]);
+    test_serialize(Op::CompareLT, vec![11]);
 }
 
 #[test]
 fn serialize_instr_le() {
-    test_serialize(InstructionKind::CompareLE, vec![12]);
+    test_serialize(Op::CompareLE, vec![12]);
 }
 
 #[test]
 fn serialize_instr_gt() {
-    test_serialize(InstructionKind::CompareGT, vec![13]);
+    test_serialize(Op::CompareGT, vec![13]);
 }
 
 #[test]
 fn serialize_instr_ge() {
-    test_serialize(InstructionKind::CompareGE, vec![14]);
+    test_serialize(Op::CompareGE, vec![14]);
 }
 
 #[test]
 fn serialize_instr_eq() {
-    test_serialize(InstructionKind::CompareEQ, vec![15]);
+    test_serialize(Op::CompareEQ, vec![15]);
 }
 
 #[test]
 fn serialize_instr_ne() {
-    test_serialize(InstructionKind::CompareNE, vec![16]);
+    test_serialize(Op::CompareNE, vec![16]);
 }
 
 #[test]
 fn serialize_instr_pop() {
-    test_serialize(InstructionKind::Pop, vec![17]);
+    test_serialize(Op::Pop, vec![17]);
 }
 
-// Only tests one, since more in depth testing of value writing is done in
-// `vm/src/value/serialize.rs`
 #[test]
 fn serialize_instr_push() {
-    test_serialize(
-        InstructionKind::Push { value: i(2) },
-        vec![18, 1, 0, 2, 0, 0, 0, 0, 0, 0, 0],
-    );
+    test_serialize(Op::Push { constant: 2 }, vec![18, 2, 0, 0, 0]);
 }
 
 #[test]
 fn serialize_instr_store() {
-    let test_store = |declaration| {
-        IDENT.with(|ident| {
-            let mut bytes = vec![19];
-            bytes.extend(id(ident).to_le_bytes().iter());
-            bytes.push(if declaration { 1 } else { 0 });
-            test_serialize(
-                InstructionKind::Store {
-                    ident: Rc::clone(ident),
-                    declaration,
-                },
-                bytes,
-            );
-        });
-    };
-
-    test_store(true);
-    test_store(false);
+    test_serialize(
+        Op::Store {
+            ident: 3,
+            declaration: true,
+        },
+        vec![19, 3, 0, 0, 0, 1],
+    );
+    test_serialize(
+        Op::Store {
+            ident: 3,
+            declaration: false,
+        },
+        vec![19, 3, 0, 0, 0, 0],
+    );
 }
 
 #[test]
 fn serialize_instr_load() {
-    IDENT.with(|ident| {
-        let mut bytes = vec![20];
-        bytes.extend(id(ident).to_le_bytes().iter());
-
-        test_serialize(
-            InstructionKind::Load {
-                ident: Rc::clone(ident),
-            },
-            bytes,
-        );
-    });
+    test_serialize(Op::Load { ident: 3 }, vec![20, 3, 0, 0, 0]);
 }
 
 #[test]
 fn serialize_instr_get_index() {
-    test_serialize(InstructionKind::GetIndex, vec![21]);
+    test_serialize(Op::GetIndex, vec![21]);
 }
 
 #[test]
 fn serialize_instr_set_index() {
-    test_serialize(InstructionKind::SetIndex, vec![22]);
+    test_serialize(Op::SetIndex, vec![22]);
 }
 
 #[test]
 fn serialize_instr_jump() {
-    test_serialize(
-        InstructionKind::JumpTo { label: 314 },
-        vec![23, 58, 1, 0, 0, 0, 0, 0, 0],
-    );
+    test_serialize(Op::JumpTo { target: 314 }, vec![23, 58, 1, 0, 0]);
 }
 
 #[test]
 fn serialize_instr_pop_jump() {
-    test_serialize(
-        InstructionKind::PopJumpIfTrue { label: 213 },
-        vec![24, 213, 0, 0, 0, 0, 0, 0, 0],
-    );
+    test_serialize(Op::PopJumpIfTrue { target: 213 }, vec![24, 213, 0, 0, 0]);
 }
 
 #[test]
 fn serialize_instr_call_fn() {
-    test_serialize(
-        InstructionKind::CallFunction { num_args: 123 },
-        vec![25, 123, 0, 0, 0, 0, 0, 0, 0],
-    );
+    test_serialize(Op::CallFunction { num_args: 123 }, vec![25, 123, 0, 0, 0]);
 }
 
 #[test]
-fn serialize_instr_label() {
+fn serialize_instr_tail_call_fn() {
     test_serialize(
-        InstructionKind::Label { number: 812 },
-        vec![26, 44, 3, 0, 0, 0, 0, 0, 0],
+        Op::TailCallFunction { num_args: 123 },
+        vec![39, 123, 0, 0, 0],
     );
 }
 
 #[test]
 fn serialize_instr_make_list() {
+    test_serialize(Op::MakeList { len: 31 }, vec![27, 31, 0, 0, 0]);
+}
+
+#[test]
+fn serialize_instr_make_object() {
+    test_serialize(Op::MakeObject { len: 6 }, vec![28, 6, 0, 0, 0]);
+}
+
+#[test]
+fn serialize_instr_make_range() {
+    test_serialize(Op::MakeRange, vec![29]);
+}
+
+#[test]
+fn serialize_instr_push_var() {
+    test_serialize(Op::PushVar { scope: 1 }, vec![30, 1, 0, 0, 0]);
+}
+
+#[test]
+fn serialize_instr_pop_var() {
+    test_serialize(Op::PopVar, vec![31]);
+}
+
+#[test]
+fn serialize_instr_make_iter() {
+    test_serialize(Op::MakeIter, vec![32]);
+}
+
+#[test]
+fn serialize_instr_for_iter() {
+    test_serialize(Op::ForIter { target: 300 }, vec![33, 44, 1, 0, 0]);
+}
+
+#[test]
+fn serialize_instr_list_append() {
+    test_serialize(Op::ListAppend { depth: 2 }, vec![34, 2, 0, 0, 0]);
+}
+
+#[test]
+fn serialize_instr_object_insert() {
+    test_serialize(Op::ObjectInsert { depth: 2 }, vec![35, 2, 0, 0, 0]);
+}
+
+#[test]
+fn serialize_instr_in() {
+    test_serialize(Op::BinaryIn, vec![36]);
+}
+
+#[test]
+fn serialize_instr_load_local() {
     test_serialize(
-        InstructionKind::MakeList { len: 31 },
-        vec![27, 31, 0, 0, 0, 0, 0, 0, 0],
+        Op::LoadLocal { depth: 1, slot: 2 },
+        vec![37, 1, 0, 0, 0, 2, 0, 0, 0],
     );
 }
 
 #[test]
-fn serialize_instr_make_object() {
+fn serialize_instr_store_local() {
     test_serialize(
-        InstructionKind::MakeObject { len: 6 },
-        vec![28, 6, 0, 0, 0, 0, 0, 0, 0],
+        Op::StoreLocal {
+            depth: 1,
+            slot: 2,
+            declaration: true,
+        },
+        vec![38, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    );
+    test_serialize(
+        Op::StoreLocal {
+            depth: 1,
+            slot: 2,
+            declaration: false,
+        },
+        vec![38, 1, 0, 0, 0, 2, 0, 0, 0, 0],
     );
 }
 
 #[test]
-fn serialize_instr_make_range() {
-    test_serialize(InstructionKind::MakeRange, vec![29]);
+fn serialize_instr_pop_jump_if_false() {
+    test_serialize(Op::PopJumpIfFalse { target: 7 }, vec![40, 7, 0, 0, 0]);
 }
 
 #[test]
-fn serialize_instr_push_var() {
+fn serialize_instr_pop_store() {
     test_serialize(
-        InstructionKind::PushVar {
-            scope: Rc::new(Scope::new(0, None)),
+        Op::PopStore {
+            ident: 3,
+            declaration: false,
         },
-        vec![30, 0, 0, 0, 0, 0, 0, 0, 0],
+        vec![41, 3, 0, 0, 0, 0],
     );
 }
 
 #[test]
-fn serialize_instr_pop_var() {
-    test_serialize(InstructionKind::PopVar, vec![31]);
+fn serialize_instr_pop_store_local() {
+    test_serialize(
+        Op::PopStoreLocal {
+            depth: 0,
+            slot: 4,
+            declaration: true,
+        },
+        vec![42, 0, 0, 0, 0, 4, 0, 0, 0, 1],
+    );
+}
+
+#[test]
+fn serialize_instr_set_nested_index() {
+    test_serialize(Op::SetNestedIndex { depth: 2 }, vec![43, 2, 0, 0, 0]);
+}
+
+#[test]
+fn finalize_resolves_labels() {
+    let bytecode = finalize_bytecode(vec![
+        InstructionKind::Label { number: 1 }.into(),
+        InstructionKind::ForIter { target: 2 }.into(),
+        InstructionKind::PopJumpIfTrue { target: 1 }.into(),
+        InstructionKind::JumpTo { target: 1 }.into(),
+        InstructionKind::Label { number: 0 }.into(),
+        InstructionKind::Pop.into(),
+        InstructionKind::JumpTo { target: 0 }.into(),
+        InstructionKind::Label { number: 2 }.into(),
+    ]);
+
+    let kinds: Vec<_> = bytecode
+        .to_bytecode()
+        .into_iter()
+        .map(|instr| instr.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            InstructionKind::ForIter { target: 5 },
+            InstructionKind::PopJumpIfTrue { target: 0 },
+            InstructionKind::JumpTo { target: 0 },
+            InstructionKind::Pop,
+            InstructionKind::JumpTo { target: 3 },
+        ]
+    );
+}
+
+#[test]
+fn ops_are_small() {
+    assert!(std::mem::size_of::<Op>() <= 12);
+}
+
+fn chunk_bytecode() -> Bytecode {
+    let ident: Rc<str> = "ident".into();
+    vec![
+        Instruction::new(
+            InstructionKind::PushVar {
+                scope: Rc::new(Scope::new(0, None)),
+            },
+            TextSpan::new(0, 10),
+        ),
+        Instruction::new(InstructionKind::Push { value: i(1) }, TextSpan::new(0, 1)),
+        Instruction::new(
+            InstructionKind::Store {
+                ident: Rc::clone(&ident),
+                declaration: true,
+            },
+            TextSpan::new(0, 1),
+        ),
+        Instruction::new(InstructionKind::Load { ident }, TextSpan::new(5, 5)),
+        Instruction::new(InstructionKind::PopVar, TextSpan::new(0, 10)),
+    ]
+}
+
+#[test]
+fn finalize_encodes_chunk() {
+    let chunk = finalize_bytecode(chunk_bytecode());
+
+    assert_eq!(
+        chunk.ops.iter().map(Cell::get).collect::<Vec<_>>(),
+        vec![
+            Op::PushVar { scope: 0 },
+            Op::Push { constant: 0 },
+            Op::Store {
+                ident: 0,
+                declaration: true
+            },
+            Op::Load { ident: 0 },
+            Op::PopVar,
+        ]
+    );
+    assert_eq!(chunk.constants, vec![i(1)]);
+    assert_eq!(chunk.idents, vec!["ident".into()]);
+    assert_eq!(chunk.scopes.len(), 1);
+
+    assert_eq!(chunk.span(0), TextSpan::new(0, 10));
+    assert_eq!(chunk.span(1), TextSpan::new(0, 1));
+    assert_eq!(chunk.span(2), TextSpan::new(0, 1));
+    assert_eq!(chunk.span(3), TextSpan::new(5, 5));
+    assert_eq!(chunk.span(4), TextSpan::new(0, 10));
+
+    assert_eq!(chunk.to_bytecode(), chunk_bytecode());
+}
+
+#[test]
+fn serialize_chunk() {
+    let chunk = finalize_bytecode(chunk_bytecode());
+    let mut buf = Vec::new();
+    assert_eq!(chunk.serialize(&mut buf).unwrap(), buf.len());
+
+    let mut context = DeserializationContext::new(1, 1, None);
+    context.add_scope(0, None);
+    let ident = &chunk.idents[0];
+    context.add_ident(Rc::as_ptr(ident) as *const u8 as usize, Rc::clone(ident));
+
+    let deserialized = Chunk::deserialize_with_context(&mut &buf[..], &mut context).unwrap();
+    assert_eq!(deserialized.to_bytecode(), chunk_bytecode());
+}
+
+#[test]
+fn specialize_ops() {
+    let chunk = finalize_bytecode(vec![
+        InstructionKind::BinaryAdd.into(),
+        InstructionKind::CompareLT.into(),
+    ]);
+
+    // A different type resets the count
+    for _ in 0..7 {
+        chunk.record_types(0, Some(Op::AddIntInt));
+    }
+    chunk.record_types(0, None);
+    chunk.record_types(0, Some(Op::AddIntInt));
+    assert_eq!(chunk.ops[0].get(), Op::BinaryAdd);
+
+    for _ in 0..7 {
+        chunk.record_types(0, Some(Op::AddIntInt));
+    }
+    assert_eq!(chunk.ops[0].get(), Op::AddIntInt);
+    assert_eq!(chunk.ops[1].get(), Op::CompareLT);
+
+    // Specialized ops are only seen by the evaluator
+    let bytecode: Vec<_> = chunk.to_bytecode().into_iter().map(|i| i.kind).collect();
+    assert_eq!(
+        bytecode,
+        vec![InstructionKind::BinaryAdd, InstructionKind::CompareLT]
+    );
+    let mut buf = Vec::new();
+    chunk.serialize(&mut buf).unwrap();
+    let mut context = DeserializationContext::new(0, 0, None);
+    let deserialized = Chunk::deserialize_with_context(&mut &buf[..], &mut context).unwrap();
+    assert_eq!(deserialized.ops[0].get(), Op::BinaryAdd);
+
+    chunk.deoptimize(0);
+    assert_eq!(chunk.ops[0].get(), Op::BinaryAdd);
+
+    // Only the same types in a row are counted
+    for _ in 0..8 {
+        chunk.record_types(0, Some(Op::AddIntInt));
+        chunk.record_types(0, Some(Op::AddFloatFloat));
+    }
+    assert_eq!(chunk.ops[0].get(), Op::BinaryAdd);
+}
+
+#[test]
+fn stop_specializing_after_deopts() {
+    let chunk = finalize_bytecode(vec![InstructionKind::BinaryAdd.into()]);
+
+    for _ in 0..4 {
+        for _ in 0..8 {
+            chunk.record_types(0, Some(Op::AddIntInt));
+        }
+        assert_eq!(chunk.ops[0].get(), Op::AddIntInt);
+        chunk.deoptimize(0);
+    }
+
+    for _ in 0..8 {
+        chunk.record_types(0, Some(Op::AddIntInt));
+    }
+    assert_eq!(chunk.ops[0].get(), Op::BinaryAdd);
 }
@@ -6,14 +6,14 @@ use std::rc::Rc;
 
 pub struct DeserializationContext {
     global: Option<Rc<Scope>>,
-    scopes: Vec<Rc<Scope>>,
+    scopes: HashMap<usize, Rc<Scope>>,
     idents: HashMap<usize, Rc<str>>,
 }
 
 impl DeserializationContext {
     pub fn new(num_scopes: usize, num_idents: usize, global: Option<Rc<Scope>>) -> Self {
         Self {
-            scopes: Vec::with_capacity(num_scopes),
+            scopes: HashMap::with_capacity(num_scopes),
             idents: HashMap::with_capacity(num_idents),
             global,
         }
@@ -24,17 +24,15 @@ impl DeserializationContext {
         // id, and so will be deserialized in order of their id
         // While generating scopes, the children blocks always come after their parent, hence their
         // id must be greater that their parent. Thus when adding a child, its parent must already
-        // be added
-        self.scopes.push(Rc::new(Scope::new(
-            id,
-            parent_id
-                .map(|id| Rc::clone(&self.scopes[id]))
-                .or_else(|| self.global.clone()),
-        )))
+        // be added. Scopes which were optimized out leave gaps in the ids
+        let parent = parent_id
+            .map(|id| Rc::clone(&self.scopes[&id]))
+            .or_else(|| self.global.clone());
+        self.scopes.insert(id, Rc::new(Scope::new(id, parent)));
     }
 
     pub fn get_scope(&self, id: usize) -> Rc<Scope> {
-        Rc::clone(&self.scopes[id])
+        Rc::clone(&self.scopes[&id])
     }
 
     pub fn add_ident(&mut self, id: usize, ident: Rc<str>) {
@@ -1,84 +1,45 @@
-use super::{Function, FunctionType};
-use crate::bytecode::{Bytecode, InstructionKind};
+use crate::bytecode::{Chunk, Op};
 use crate::scope::Scope;
-use crate::value::Value;
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct AnilangFn {
     pub args: Vec<Rc<str>>,
-    pub body: Bytecode,
+    /// The bytecode of the function, which is shared by every copy of the function and never
+    /// changed after lowering
+    pub body: Rc<Chunk>,
+    /// The scope the function was declared in. Every call creates a new scope for the body, whose
+    /// parent is this one
+    pub parent: Option<Rc<Scope>>,
 }
 
 impl AnilangFn {
-    pub fn new(args: Vec<Rc<str>>, body: Bytecode) -> Self {
-        Self { args, body }
+    pub fn new(args: Vec<Rc<str>>, body: Chunk) -> Self {
+        let mut f = Self {
+            args,
+            body: Rc::new(body),
+            parent: None,
+        };
+        if !f.body.is_empty() {
+            f.parent = f.scope().parent().cloned();
+        }
+        f
     }
 
+    /// The scope of the function's body as it was lowered. Calls don't evaluate in this scope, it
+    /// only gives the id and parent of the scope each call creates.
     pub fn scope(&self) -> &Rc<Scope> {
-        match &self.body[0].kind {
-            InstructionKind::PushVar { scope } => scope,
+        match self.body.ops[0].get() {
+            Op::PushVar { scope } => &self.body.scopes[scope as usize],
             _ => unreachable!("Function body must start with a PushVar"),
         }
     }
 
-    pub fn set_scope(&mut self, scope: Rc<Scope>) {
-        if let InstructionKind::PushVar { scope: old_scope } = &mut self.body[0].kind {
-            *old_scope = scope;
-        } else {
-            unreachable!("Function body must start with a PushVar");
-        }
-    }
-
-    pub fn duplicate_body(&self) -> Bytecode {
-        if self.body.is_empty() {
-            return Vec::new();
-        }
-
-        let delta = self.scope().id;
-
-        let mut body = self.body.clone();
-        let mut scopes = Vec::new();
-
-        // Duplicate scopes, so that no conflict happens
-        for instr in body.iter_mut() {
-            match &mut instr.kind {
-                InstructionKind::PushVar { scope } => {
-                    let new_scope = if delta == scope.id {
-                        Rc::new(scope.duplicate())
-                    } else {
-                        Rc::new(Scope::new(
-                            scope.id,
-                            scope.parent_id().map(|id| Rc::clone(&scopes[id - delta])),
-                        ))
-                    };
-                    scopes.push(Rc::clone(&new_scope));
-                    *scope = new_scope;
-                }
-                InstructionKind::Push {
-                    value: Value::Function(rc_fn),
-                } => {
-                    if let Some(func) = rc_fn.as_anilang_fn() {
-                        let mut f = AnilangFn::new(func.args.clone(), func.duplicate_body());
-                        let new_scope = Rc::new(Scope::new(
-                            f.scope().id,
-                            f.scope()
-                                .parent_id()
-                                .map(|id| Rc::clone(&scopes[id - delta])),
-                        ));
-
-                        f.set_scope(new_scope);
-                        let mut f = Function::new(FunctionType::AnilangFn(f));
-                        f.this = rc_fn.this.clone();
-
-                        *rc_fn = Rc::new(f);
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        body
+    /// Creates the scope for a call of the function, which the arguments are then stored in.
+    pub fn call_scope(&self) -> Rc<Scope> {
+        let scope = Rc::new(Scope::new(self.scope().id, self.parent.clone()));
+        crate::gc::track_scope(&scope);
+        scope
     }
 }
 
@@ -1,4 +1,5 @@
-use crate::bytecode::Bytecode;
+use crate::bytecode::{finalize_bytecode, Bytecode};
+use crate::scope::Scope;
 use std::rc::Rc;
 
 mod anilang_fn;
@@ -24,9 +25,10 @@ impl Function {
         }
     }
 
+    /// Creates an anilang function from a body which hasn't been finalized yet
     pub fn anilang_fn(args: Vec<Rc<str>>, body: Bytecode) -> Self {
         Self {
-            fn_type: FunctionType::AnilangFn(AnilangFn::new(args, body)),
+            fn_type: FunctionType::AnilangFn(AnilangFn::new(args, finalize_bytecode(body))),
             this: None,
         }
     }
@@ -62,6 +64,23 @@ impl Function {
         self.this = Some(this);
         self
     }
+
+    /// Returns a copy of an anilang function which is declared in `scope`, so that its calls can
+    /// access the variables of that scope. Returns `None` if it is a native function, or already
+    /// declared in `scope`.
+    pub fn declared_in(&self, scope: &Rc<Scope>) -> Option<Self> {
+        let f = self.as_anilang_fn()?;
+        if f.parent.as_ref().is_some_and(|p| Rc::ptr_eq(p, scope)) {
+            return None;
+        }
+
+        let mut f = f.clone();
+        f.parent = Some(Rc::clone(scope));
+        Some(Self {
+            fn_type: FunctionType::AnilangFn(f),
+            this: self.this.clone(),
+        })
+    }
 }
 
 /// Representation of pointer to function which can be executed
@@ -98,7 +117,7 @@ impl fmt::Display for FunctionType {
 use crate::value::Value;
 impl From<FunctionType> for Value {
     fn from(fn_type: FunctionType) -> Value {
-        Value::Function(Rc::new(Function::new(fn_type)))
+        Value::new_function(Function::new(fn_type))
     }
 }
 
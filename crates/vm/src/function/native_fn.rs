@@ -1,8 +1,6 @@
 use crate::types::Type;
-use crate::value::{ErrorKind, Result, Value};
-use std::cell::RefCell;
+use crate::value::{ErrorKind, Map, Object, Result, Set, Value};
 use std::io::{self, prelude::*};
-use std::rc::Rc;
 
 pub type NativeFn = fn(Vec<Value>) -> Result<Value>;
 
@@ -43,7 +41,7 @@ pub fn input(args: Vec<Value>) -> Result<Value> {
     let new_len = s.trim_end_matches(|c| c == '\n' || c == '\r').len();
     s.truncate(new_len);
 
-    Ok(Value::String(Rc::new(RefCell::new(s))))
+    Ok(Value::String(s.into()))
 }
 
 pub fn push(mut args: Vec<Value>) -> Result<Value> {
@@ -94,6 +92,137 @@ pub fn pop(args: Vec<Value>) -> Result<Value> {
     }
 }
 
+/// Creates a map, which is empty, or has the `[key, value]` pairs of the value given, so
+/// `map({a: 1})` has the same keys and values as the object
+pub fn map(args: Vec<Value>) -> Result<Value> {
+    let mut map = Map::new();
+    for pair in iter_arg(args)? {
+        match pair {
+            Value::List(ref l) if l.borrow().len() == 2 => {
+                let l = l.borrow();
+                map.insert(l[0].to_key()?, l[1].clone());
+            }
+            _ => {
+                return Err(ErrorKind::Other {
+                    message: format!("Expected a pair of [key, value], got {:?}", pair),
+                })
+            }
+        }
+    }
+
+    Ok(Value::new_map(map))
+}
+
+/// Creates a set, which is empty, or has the elements of the value given
+pub fn set(args: Vec<Value>) -> Result<Value> {
+    let set = iter_arg(args)?
+        .map(|v| v.to_key())
+        .collect::<Result<Set>>()?;
+    Ok(Value::new_set(set))
+}
+
+/// The elements of the only argument of `map` and `set`, see `Value::into_iterable`
+fn iter_arg(args: Vec<Value>) -> Result<impl Iterator<Item = Value>> {
+    if args.len() > 1 {
+        return Err(ErrorKind::IncorrectArgCount {
+            expected: 1,
+            got: args.len(),
+        });
+    }
+
+    let iterable = match args.into_iter().next() {
+        Some(arg) => arg.into_iterable()?,
+        None => Value::new_list(vec![]),
+    };
+
+    Ok((0..).map_while(move |i| iterable.iter_at(i)))
+}
+
+pub fn add(mut args: Vec<Value>) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(ErrorKind::IncorrectArgCount {
+            expected: 2,
+            got: args.len(),
+        });
+    }
+
+    let to_add = args.pop().unwrap().to_key()?;
+
+    match &args[0] {
+        Value::Set(s) => {
+            s.borrow_mut().insert(to_add);
+            Ok(Value::Null)
+        }
+        _ => Err(ErrorKind::IncorrectType {
+            got: args[0].type_(),
+            expected: Type::Set.into(),
+        }),
+    }
+}
+
+/// Removes a key from a map, returning its value, or an element from a set, returning whether it
+/// was in the set. The order of the rest of the keys is kept.
+pub fn remove(mut args: Vec<Value>) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(ErrorKind::IncorrectArgCount {
+            expected: 2,
+            got: args.len(),
+        });
+    }
+
+    let to_remove = args.pop().unwrap();
+
+    match &args[0] {
+        Value::Map(m) => m
+            .borrow_mut()
+            .shift_remove(&to_remove.to_key()?)
+            .ok_or(ErrorKind::KeyNotFound { key: to_remove }),
+        Value::Set(s) => Ok(Value::Bool(
+            to_remove
+                .to_key()
+                .is_ok_and(|k| s.borrow_mut().shift_remove(&k)),
+        )),
+        _ => Err(ErrorKind::IncorrectType {
+            got: args[0].type_(),
+            expected: Type::Map | Type::Set,
+        }),
+    }
+}
+
+/// Applies `op` to the two sets in `args`, giving a new set
+fn set_op(args: Vec<Value>, op: impl FnOnce(&Set, &Set) -> Set) -> Result<Value> {
+    if args.len() != 2 {
+        return Err(ErrorKind::IncorrectArgCount {
+            expected: 2,
+            got: args.len(),
+        });
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::Set(l), Value::Set(r)) => Ok(Value::new_set(op(&l.borrow(), &r.borrow()))),
+        (Value::Set(_), r) => Err(ErrorKind::IncorrectType {
+            got: r.type_(),
+            expected: Type::Set.into(),
+        }),
+        (l, _) => Err(ErrorKind::IncorrectType {
+            got: l.type_(),
+            expected: Type::Set.into(),
+        }),
+    }
+}
+
+pub fn union(args: Vec<Value>) -> Result<Value> {
+    set_op(args, |l, r| l.union(r).cloned().collect())
+}
+
+pub fn intersection(args: Vec<Value>) -> Result<Value> {
+    set_op(args, |l, r| l.intersection(r).cloned().collect())
+}
+
+pub fn difference(args: Vec<Value>) -> Result<Value> {
+    set_op(args, |l, r| l.difference(r).cloned().collect())
+}
+
 pub fn assert(args: Vec<Value>) -> Result<Value> {
     for arg in args {
         if !bool::from(&arg) {
@@ -105,3 +234,20 @@ pub fn assert(args: Vec<Value>) -> Result<Value> {
 
     Ok(Value::Null)
 }
+
+/// Runs the cycle collector, and returns an object with its statistics, see `vm/src/gc.rs`
+pub fn gc(args: Vec<Value>) -> Result<Value> {
+    if !args.is_empty() {
+        return Err(ErrorKind::IncorrectArgCount {
+            expected: 0,
+            got: args.len(),
+        });
+    }
+
+    let stats = crate::gc::collect();
+    let mut object = Object::new();
+    object.insert("collected", Value::Int(stats.collected as i64));
+    object.insert("tracked", Value::Int(stats.tracked as i64));
+    object.insert("collections", Value::Int(stats.collections as i64));
+    Ok(Value::new_object(object))
+}
@@ -0,0 +1,402 @@
+//! Collection of reference cycles.
+//!
+//! Values are reference counted, so a list, object, map or closure which ends up referencing
+//! itself, like `o` in `let o = {}; o.self = o`, is never freed by reference counting alone. Every
+//! list, object, map, function and scope created while a program runs is tracked here, and
+//! [`collect`] frees the ones which can't be reached anymore.
+//!
+//! The collector doesn't need to know the roots of the VM. For every tracked container, the
+//! references to it from other tracked containers are subtracted from its reference count, and
+//! whatever is left must be a reference from somewhere else, like the value stack, a scope of the
+//! evaluator or a constant. Containers with such references, and everything reachable from them,
+//! are alive. The rest are garbage, and are emptied so that reference counting frees them.
+
+use crate::function::Function;
+use crate::scope::Scope;
+use crate::value::{List, Map, Object, Ref, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// The least number of containers to track before a collection is run automatically.
+const MIN_THRESHOLD: usize = 10_000;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap {
+            tracked: Vec::new(),
+            threshold: MIN_THRESHOLD,
+            collections: 0,
+            collected: 0,
+        })
+    };
+}
+
+struct Heap {
+    tracked: Vec<Tracked>,
+    /// A collection is run once this many containers are tracked, so that collections get less
+    /// frequent as more containers stay alive
+    threshold: usize,
+    collections: usize,
+    collected: usize,
+}
+
+/// Statistics about the collector, see [`collect`] and [`stats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// The number of containers tracked, including ones which have been freed since the last
+    /// collection
+    pub tracked: usize,
+    /// The number of containers freed by the last collection
+    pub collected: usize,
+    /// The number of collections run so far
+    pub collections: usize,
+}
+
+enum Tracked {
+    List(Weak<RefCell<List>>),
+    Object(Weak<RefCell<Object>>),
+    Map(Weak<RefCell<Map>>),
+    Function(Weak<Function>),
+    Scope(Weak<Scope>),
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<Container> {
+        Some(match self {
+            Tracked::List(l) => Container::List(l.upgrade()?),
+            Tracked::Object(o) => Container::Object(o.upgrade()?),
+            Tracked::Map(m) => Container::Map(m.upgrade()?),
+            Tracked::Function(f) => Container::Function(f.upgrade()?),
+            Tracked::Scope(s) => Container::Scope(s.upgrade()?),
+        })
+    }
+}
+
+enum Container {
+    List(Ref<List>),
+    Object(Ref<Object>),
+    Map(Ref<Map>),
+    Function(Rc<Function>),
+    Scope(Rc<Scope>),
+}
+
+impl Container {
+    fn downgrade(&self) -> Tracked {
+        match self {
+            Container::List(l) => Tracked::List(Rc::downgrade(l)),
+            Container::Object(o) => Tracked::Object(Rc::downgrade(o)),
+            Container::Map(m) => Tracked::Map(Rc::downgrade(m)),
+            Container::Function(f) => Tracked::Function(Rc::downgrade(f)),
+            Container::Scope(s) => Tracked::Scope(Rc::downgrade(s)),
+        }
+    }
+
+    fn addr(&self) -> usize {
+        match self {
+            Container::List(l) => Rc::as_ptr(l) as *const () as usize,
+            Container::Object(o) => Rc::as_ptr(o) as *const () as usize,
+            Container::Map(m) => Rc::as_ptr(m) as *const () as usize,
+            Container::Function(f) => Rc::as_ptr(f) as *const () as usize,
+            Container::Scope(s) => Rc::as_ptr(s) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Container::List(l) => Rc::strong_count(l),
+            Container::Object(o) => Rc::strong_count(o),
+            Container::Map(m) => Rc::strong_count(m),
+            Container::Function(f) => Rc::strong_count(f),
+            Container::Scope(s) => Rc::strong_count(s),
+        }
+    }
+
+    /// Calls `f` with the address of every container this one references. Returns false if the
+    /// contents are being modified, so they can't be looked at.
+    fn for_each_child(&self, mut f: impl FnMut(usize)) -> bool {
+        match self {
+            Container::List(l) => match l.try_borrow() {
+                Ok(l) => l.iter().for_each(|v| visit(v, &mut f)),
+                Err(_) => return false,
+            },
+            Container::Object(o) => match o.try_borrow() {
+                Ok(o) => o.iter().for_each(|(_, v)| visit(v, &mut f)),
+                Err(_) => return false,
+            },
+            // Keys can't reference anything, so only the values are visited
+            Container::Map(m) => match m.try_borrow() {
+                Ok(m) => m.values().for_each(|v| visit(v, &mut f)),
+                Err(_) => return false,
+            },
+            Container::Function(func) => {
+                if let Some(this) = func.this() {
+                    visit(this, &mut f);
+                }
+                if let Some(parent) = func.as_anilang_fn().and_then(|func| func.parent.as_ref()) {
+                    f(Rc::as_ptr(parent) as *const () as usize);
+                }
+            }
+            Container::Scope(s) => {
+                s.for_each_value(|v| visit(v, &mut f));
+                if let Some(parent) = s.parent() {
+                    f(Rc::as_ptr(parent) as *const () as usize);
+                }
+            }
+        }
+        true
+    }
+
+    /// Removes the values stored in the container, returning them so that they are dropped once
+    /// all the garbage has been emptied.
+    fn clear(&self, removed: &mut Vec<Value>) {
+        match self {
+            Container::List(l) => {
+                if let Ok(mut l) = l.try_borrow_mut() {
+                    removed.append(&mut l);
+                }
+            }
+            Container::Object(o) => {
+                if let Ok(mut o) = o.try_borrow_mut() {
+                    let o = std::mem::take(&mut *o);
+                    removed.extend(o.iter().map(|(_, v)| v.clone()));
+                }
+            }
+            Container::Map(m) => {
+                if let Ok(mut m) = m.try_borrow_mut() {
+                    removed.extend(m.drain(..).map(|(_, v)| v));
+                }
+            }
+            // Functions can't be changed after they are created, so they can only be part of a
+            // cycle which goes through a list, object, map or scope
+            Container::Function(_) => {}
+            Container::Scope(s) => s.clear(),
+        }
+    }
+}
+
+/// Calls `f` with the address of the container `value` references, if it references one
+fn visit(value: &Value, f: &mut impl FnMut(usize)) {
+    match value {
+        Value::List(l) => f(Rc::as_ptr(l) as *const () as usize),
+        Value::Object(o) => f(Rc::as_ptr(o) as *const () as usize),
+        Value::Map(m) => f(Rc::as_ptr(m) as *const () as usize),
+        Value::Function(func) => f(Rc::as_ptr(func) as *const () as usize),
+        _ => {}
+    }
+}
+
+fn track_container(tracked: Tracked) {
+    let collect_now = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.tracked.push(tracked);
+        heap.tracked.len() >= heap.threshold
+    });
+
+    if collect_now {
+        collect();
+    }
+}
+
+/// Tracks a list, object, map or function so that it is freed by [`collect`] if it is only referenced
+/// by a cycle. Values of other types can't reference anything, so they are ignored.
+pub fn track(value: &Value) {
+    match value {
+        Value::List(l) => track_container(Tracked::List(Rc::downgrade(l))),
+        Value::Object(o) => track_container(Tracked::Object(Rc::downgrade(o))),
+        Value::Map(m) => track_container(Tracked::Map(Rc::downgrade(m))),
+        Value::Function(f) => track_container(Tracked::Function(Rc::downgrade(f))),
+        _ => {}
+    }
+}
+
+/// Tracks a scope created while evaluating, see [`track`]
+pub fn track_scope(scope: &Rc<Scope>) {
+    track_container(Tracked::Scope(Rc::downgrade(scope)))
+}
+
+/// Frees all the tracked containers which can't be reached from anything other than cycles of
+/// tracked containers.
+pub fn collect() -> Stats {
+    let tracked = HEAP.with(|heap| std::mem::take(&mut heap.borrow_mut().tracked));
+    let containers: Vec<_> = tracked.iter().filter_map(Tracked::upgrade).collect();
+    drop(tracked);
+
+    let index: HashMap<_, _> = containers
+        .iter()
+        .enumerate()
+        .map(|(i, container)| (container.addr(), i))
+        .collect();
+
+    // The number of references to each container from outside the tracked containers. One of the
+    // references is the one in `containers`
+    let mut external: Vec<_> = containers.iter().map(|c| c.strong_count() - 1).collect();
+    let mut reachable = vec![false; containers.len()];
+    for (i, container) in containers.iter().enumerate() {
+        let traced = container.for_each_child(|addr| {
+            if let Some(&child) = index.get(&addr) {
+                external[child] -= 1;
+            }
+        });
+
+        // Whatever is modifying the container is using it, and the references it has weren't
+        // subtracted so its children will stay alive as well
+        if !traced {
+            reachable[i] = true;
+        }
+    }
+
+    let mut stack: Vec<_> = (0..containers.len())
+        .filter(|&i| reachable[i] || external[i] > 0)
+        .collect();
+    for &i in stack.iter() {
+        reachable[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        containers[i].for_each_child(|addr| {
+            if let Some(&child) = index.get(&addr) {
+                if !reachable[child] {
+                    reachable[child] = true;
+                    stack.push(child);
+                }
+            }
+        });
+    }
+
+    let mut removed = Vec::new();
+    let mut survivors = Vec::new();
+    let mut collected = 0;
+    for (container, reachable) in containers.iter().zip(reachable) {
+        if reachable {
+            survivors.push(container.downgrade());
+        } else {
+            container.clear(&mut removed);
+            collected += 1;
+        }
+    }
+
+    // Dropping the removed values and the last references to the garbage frees it
+    drop(removed);
+    drop(containers);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        // Anything tracked while collecting is kept as well
+        survivors.append(&mut heap.tracked);
+        heap.tracked = survivors;
+        heap.threshold = MIN_THRESHOLD.max(heap.tracked.len() * 2);
+        heap.collections += 1;
+        heap.collected = collected;
+        heap.stats()
+    })
+}
+
+/// The statistics of the collector, without running a collection.
+pub fn stats() -> Stats {
+    HEAP.with(|heap| heap.borrow().stats())
+}
+
+impl Heap {
+    fn stats(&self) -> Stats {
+        Stats {
+            tracked: self.tracked.len(),
+            collected: self.collected,
+            collections: self.collections,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn collected_by(f: impl FnOnce()) -> usize {
+        collect();
+        f();
+        collect().collected
+    }
+
+    #[test]
+    fn collects_self_referencing_list() {
+        let list = l(vec![i(0)]);
+        let weak = Rc::downgrade(&list.clone().into_rc_list());
+        list.clone().into_rc_list().borrow_mut().push(list.clone());
+
+        assert_eq!(collected_by(|| drop(list)), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn collects_object_cycles() {
+        let a = o(vec![]);
+        let b = o(vec![("a", a.clone())]);
+        a.clone().set_at(s("b"), b.clone()).unwrap();
+        let weak_a = Rc::downgrade(&a.clone().into_rc_obj());
+        let weak_b = Rc::downgrade(&b.clone().into_rc_obj());
+
+        assert_eq!(collected_by(|| drop((a, b))), 2);
+        assert!(weak_a.upgrade().is_none());
+        assert!(weak_b.upgrade().is_none());
+    }
+
+    #[test]
+    fn collects_map_cycles() {
+        let map = m(vec![]);
+        map.clone().set_at(i(0), map.clone()).unwrap();
+        let weak = Rc::downgrade(&map.clone().into_rc_map());
+
+        assert_eq!(collected_by(|| drop(map)), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn collects_closures() {
+        let scope = Rc::new(Scope::new(1, None));
+        track_scope(&scope);
+        let f = Value::new_function(
+            Function::anilang_fn(vec![], vec![])
+                .declared_in(&scope)
+                .unwrap(),
+        );
+        scope.declare("f".into(), f).unwrap();
+        let weak = Rc::downgrade(&scope);
+
+        assert_eq!(collected_by(|| drop(scope)), 2);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn keeps_reachable_cycles() {
+        let list = l(vec![]);
+        list.clone().into_rc_list().borrow_mut().push(list.clone());
+        let holder = o(vec![("list", list.clone())]);
+        drop(list);
+
+        assert_eq!(collected_by(|| {}), 0);
+
+        let list = holder.to_ref_obj().get("list").unwrap().clone();
+        assert_eq!(list.to_ref_list().len(), 1);
+
+        // Only the list is in a cycle, the object is freed as soon as it is dropped
+        drop(list);
+        assert_eq!(collected_by(|| drop(holder)), 1);
+    }
+
+    #[test]
+    fn keeps_children_of_borrowed_containers() {
+        let list = l(vec![]);
+        list.clone().into_rc_list().borrow_mut().push(list.clone());
+        let weak = Rc::downgrade(&list.clone().into_rc_list());
+        let holder = l(vec![list]);
+
+        let holder_rc = holder.clone().into_rc_list();
+        let guard = holder_rc.borrow_mut();
+        assert_eq!(collected_by(|| {}), 0);
+        assert!(weak.upgrade().is_some());
+        drop(guard);
+
+        assert_eq!(collected_by(|| drop((holder, holder_rc))), 1);
+        assert!(weak.upgrade().is_none());
+    }
+}
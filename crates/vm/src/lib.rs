@@ -1,11 +1,15 @@
 mod bytecode;
 mod deser_ctx;
 pub mod function;
+pub mod gc;
 mod scope;
 pub mod types;
 pub mod value;
 
-pub use bytecode::{print_bytecode, Bytecode, Instruction, InstructionKind, LabelNumber};
+pub use bytecode::{
+    finalize_bytecode, print_bytecode, Bytecode, Chunk, Instruction, InstructionKind, LabelNumber,
+    Op,
+};
 pub use deser_ctx::DeserializationContext;
 pub use scope::Scope;
 pub use types::Type;
@@ -5,6 +5,9 @@ use std::rc::Rc;
 
 /// A wrapper around `HashMap` to provide scoping functions.
 ///
+/// Variables which the lowerer can resolve are stored in slots instead, which are indexed directly
+/// and don't need to be looked up by name.
+///
 /// It is only needed to be manually created when some global variables are to be provided while
 /// evaluating, otherwise, the lowerer automatically creates the required scopes for each block.
 ///
@@ -18,11 +21,16 @@ use std::rc::Rc;
 /// // Idents stored as Rc<str>
 /// scope.declare("variable".into(), Value::Int(123));
 /// assert_eq!(scope.try_get_value("variable"), Some(Value::Int(123)));
+///
+/// assert_eq!(scope.get_slot(0), None);
+/// scope.set_slot(0, Value::Int(456));
+/// assert_eq!(scope.get_slot(0), Some(Value::Int(456)));
 /// ```
 #[derive(Default, Debug)]
 pub struct Scope {
     pub id: usize,
     vars: UnsafeCell<HashMap<Rc<str>, value::Value>>,
+    slots: UnsafeCell<Vec<Option<value::Value>>>,
     parent: Option<Rc<Scope>>,
 }
 
@@ -41,23 +49,16 @@ impl Scope {
         Self {
             id,
             vars: Default::default(),
+            slots: Default::default(),
             parent,
         }
     }
 
-    pub fn duplicate(&self) -> Self {
-        Self {
-            id: self.id,
-            vars: Default::default(),
-            parent: self.parent.clone(),
-        }
-    }
-
     /////////////////////       UNSAFETY        /////////////////////
 
     // SAFETY: The safety is very similar to the safety of `std::cell::Cell`. Since no references
     // are given to the underlying data inside the HashMap, it can be mutated safely through a
-    // shared reference. Also since this is !Sync, data races are not an issue.
+    // shared reference. The same holds for the slots. Also since this is !Sync, data races are not an issue.
     //
     // Comparison to Cell<T>: The principle which a cell works on is that no references are possible
     // since the only way value is accessible is to copy the whole value. Similarly, here no
@@ -72,6 +73,28 @@ impl Scope {
         vars.insert(key, value);
     }
 
+    fn clear_vars(&self) {
+        let vars = unsafe { &mut *self.vars.get() };
+        vars.clear();
+    }
+
+    fn slots(&self) -> &Vec<Option<value::Value>> {
+        unsafe { &*self.slots.get() }
+    }
+
+    fn insert_slot(&self, slot: usize, value: value::Value) {
+        let slots = unsafe { &mut *self.slots.get() };
+        if slot >= slots.len() {
+            slots.resize(slot + 1, None);
+        }
+        slots[slot] = Some(value);
+    }
+
+    fn clear_slots(&self) {
+        let slots = unsafe { &mut *self.slots.get() };
+        slots.clear();
+    }
+
     /////////////////////////////////////////////////////////////////
 
     /// Creates the variable `key` with value `value` in this scope.
@@ -112,7 +135,56 @@ impl Scope {
         }
     }
 
+    /// Returns a copy of the value stored in the slot, if it has been set.
+    pub fn get_slot(&self, slot: usize) -> Option<value::Value> {
+        // The value must be cloned so that the safety argument holds
+        self.slots().get(slot).cloned().flatten()
+    }
+
+    /// Whether a value has been stored in the slot.
+    pub fn has_slot(&self, slot: usize) -> bool {
+        matches!(self.slots().get(slot), Some(Some(_)))
+    }
+
+    /// Stores the value in the slot, growing the slots if needed.
+    pub fn set_slot(&self, slot: usize, value: value::Value) {
+        self.insert_slot(slot, value);
+    }
+
+    /// Returns the scope `depth` parents above this one.
+    pub fn ancestor(&self, depth: usize) -> &Scope {
+        let mut scope = self;
+        for _ in 0..depth {
+            scope = scope
+                .parent
+                .as_ref()
+                .expect("Slot depth must be within the scope's parents");
+        }
+        scope
+    }
+
+    /// Calls `f` with every value stored in this scope, used to find the values a scope references
+    /// while collecting cycles. `f` must not change this scope.
+    pub(crate) fn for_each_value(&self, mut f: impl FnMut(&value::Value)) {
+        for value in self.vars().values() {
+            f(value);
+        }
+        for value in self.slots().iter().flatten() {
+            f(value);
+        }
+    }
+
+    /// Removes all the variables declared in this scope, so that it can be entered again.
+    pub fn clear(&self) {
+        self.clear_vars();
+        self.clear_slots();
+    }
+
     pub fn parent_id(&self) -> Option<usize> {
         self.parent.as_ref().map(|p| p.id)
     }
+
+    pub fn parent(&self) -> Option<&Rc<Scope>> {
+        self.parent.as_ref()
+    }
 }
@@ -1,11 +1,14 @@
-use crate::value::{Object, Value};
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::value::{Object, Str, Value};
 
 pub fn i(i: i64) -> Value {
     Value::Int(i)
 }
 
+/// An int from its digits, which is a big int if it doesn't fit in an `i64`
+pub fn big(i: &str) -> Value {
+    Value::from_big_int(i.parse().unwrap())
+}
+
 pub fn f(f: f64) -> Value {
     Value::Float(f)
 }
@@ -15,23 +18,35 @@ pub fn b(b: bool) -> Value {
 }
 
 pub fn s(s: &str) -> Value {
-    Value::String(Rc::new(RefCell::new(s.to_owned())))
+    Value::String(Str::from(s))
 }
 
 pub fn l(l: Vec<Value>) -> Value {
-    Value::List(Rc::new(RefCell::new(l)))
+    Value::new_list(l)
 }
 
 pub fn o(o: Vec<(&str, Value)>) -> Value {
     let mut obj = Object::new();
     for (k, v) in o {
-        obj.insert(k.to_owned(), v);
+        obj.insert(k, v);
     }
-    Value::Object(Rc::new(RefCell::new(obj)))
+    Value::new_object(obj)
+}
+
+pub fn m(m: Vec<(Value, Value)>) -> Value {
+    Value::new_map(
+        m.into_iter()
+            .map(|(k, v)| (k.to_key().unwrap(), v))
+            .collect(),
+    )
+}
+
+pub fn st(s: Vec<Value>) -> Value {
+    Value::new_set(s.iter().map(|v| v.to_key().unwrap()).collect())
 }
 
 pub fn r(s: i64, e: i64) -> Value {
-    Value::Range(s, e)
+    Value::new_range(s, e)
 }
 
 pub fn func() -> Value {
@@ -22,21 +22,23 @@ pub enum Cast {
 /// assert!(expected.contains(Type::Int));
 /// assert!(expected.contains(Type::Float));
 /// ```
-/// Also since Type is `#[repr(u8)]`, it is only one byte in size, therefore multiple types can be
-/// stored in just one byte, rather than storing multiple types in a `Vec` or array
+/// Also since Type is `#[repr(u16)]`, it is only two bytes in size, therefore multiple types can be
+/// stored in just two bytes, rather than storing multiple types in a `Vec` or array
 #[derive(Copy, Clone, Debug, PartialEq, Eq, BitFlags)]
 #[rustfmt::skip]
 #[repr(u16)]
 pub enum Type {
-    Int      = 0b000000001,
-    Float    = 0b000000010,
-    String   = 0b000000100,
-    List     = 0b000001000,
-    Object   = 0b000010000,
-    Range    = 0b000100000,
-    Bool     = 0b001000000,
-    Function = 0b010000000,
-    Null     = 0b100000000,
+    Int      = 0b00000000001,
+    Float    = 0b00000000010,
+    String   = 0b00000000100,
+    List     = 0b00000001000,
+    Object   = 0b00000010000,
+    Range    = 0b00000100000,
+    Bool     = 0b00001000000,
+    Function = 0b00010000000,
+    Null     = 0b00100000000,
+    Map      = 0b01000000000,
+    Set      = 0b10000000000,
 }
 
 impl Type {
@@ -51,20 +53,41 @@ impl Type {
             _ => Cast::Explicit,
         }
     }
+
+    /// Gets the type from the name it is written as in type annotations, this is the same as the
+    /// name it is displayed as.
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "list" => Type::List,
+            "object" => Type::Object,
+            "range" => Type::Range,
+            "bool" => Type::Bool,
+            "function" => Type::Function,
+            "null" => Type::Null,
+            "map" => Type::Map,
+            "set" => Type::Set,
+            _ => return None,
+        })
+    }
 }
 
 impl From<u16> for Type {
     fn from(value: u16) -> Type {
         match value {
-            0b000000001 => Type::Int,
-            0b000000010 => Type::Float,
-            0b000000100 => Type::String,
-            0b000001000 => Type::List,
-            0b000010000 => Type::Object,
-            0b000100000 => Type::Range,
-            0b001000000 => Type::Bool,
-            0b010000000 => Type::Function,
-            0b100000000 => Type::Null,
+            0b00000000001 => Type::Int,
+            0b00000000010 => Type::Float,
+            0b00000000100 => Type::String,
+            0b00000001000 => Type::List,
+            0b00000010000 => Type::Object,
+            0b00000100000 => Type::Range,
+            0b00001000000 => Type::Bool,
+            0b00010000000 => Type::Function,
+            0b00100000000 => Type::Null,
+            0b01000000000 => Type::Map,
+            0b10000000000 => Type::Set,
             n => panic!(
                 "Invalid u16 {}, this method is only meant to be called with valid tags.",
                 n
@@ -89,6 +112,8 @@ impl fmt::Display for Type {
                 Type::Bool => "bool",
                 Type::Function => "function",
                 Type::Null => "null",
+                Type::Map => "map",
+                Type::Set => "set",
             }
         )
     }
@@ -119,11 +144,13 @@ impl Value {
     pub fn type_(&self) -> Type {
         match self {
             Value::Bool(_) => Type::Bool,
-            Value::Int(_) => Type::Int,
+            Value::Int(_) | Value::BigInt(_) => Type::Int,
             Value::Float(_) => Type::Float,
             Value::String(_) => Type::String,
             Value::List(_) => Type::List,
             Value::Object(_) => Type::Object,
+            Value::Map(_) => Type::Map,
+            Value::Set(_) => Type::Set,
             Value::Range(..) => Type::Range,
             Value::Function(_) => Type::Function,
             Value::Null => Type::Null,
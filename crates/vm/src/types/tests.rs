@@ -146,6 +146,7 @@ fn bitflag_type_to_string() {
         "string | range | function"
     );
     assert_eq!(&BitFlags::from(Type::Object).to_string(), "object");
+    assert_eq!(&(Type::Set | Type::Map).to_string(), "map | set");
 }
 
 use crate::test_helpers::*;
@@ -157,6 +158,8 @@ fn get_correct_type() {
     assert_eq!(s("hello").type_(), Type::String);
     assert_eq!(l(vec![i(0), f(2.0), b(true)]).type_(), Type::List);
     assert_eq!(o(vec![]).type_(), Type::Object);
+    assert_eq!(m(vec![]).type_(), Type::Map);
+    assert_eq!(st(vec![]).type_(), Type::Set);
     assert_eq!(r(0, 1).type_(), Type::Range);
     assert_eq!(b(true).type_(), Type::Bool);
     assert_eq!(func().type_(), Type::Function);
@@ -208,3 +211,13 @@ fn try_cast_fail() {
     assert_eq!(func().try_cast(Type::Float).err().unwrap(), Cast::Explicit);
     assert_eq!(n().try_cast(Type::Int).err().unwrap(), Cast::Explicit);
 }
+
+#[test]
+fn type_from_name() {
+    for t in BitFlags::<Type>::all().iter() {
+        assert_eq!(Type::from_name(&t.to_string()), Some(t));
+    }
+
+    assert_eq!(Type::from_name("any"), None);
+    assert_eq!(Type::from_name("Int"), None);
+}
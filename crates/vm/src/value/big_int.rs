@@ -0,0 +1,91 @@
+use super::Value;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// impl for ints, which are kept in an `i64` while they fit in one, and promoted to a `BigInt` when
+/// they don't. A `Value::BigInt` never holds a value which fits in an `i64`, so every int has only
+/// one representation, and equal ints always have the same variant.
+impl Value {
+    /// The int with the value of `i`, which is only a `Value::BigInt` if it doesn't fit in an `i64`
+    pub fn from_big_int(i: BigInt) -> Value {
+        match i.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::BigInt(Rc::new(i)),
+        }
+    }
+
+    /// Should only be called for Value::Int or Value::BigInt
+    pub fn to_big_int(&self) -> BigInt {
+        match self {
+            Value::Int(i) => BigInt::from(*i),
+            Value::BigInt(i) => BigInt::clone(i),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Applies an arithmetic operation to two ints. `small` is tried first if both fit in an
+    /// `i64`, and returns `None` if the result would overflow, in which case `big` is used.
+    ///
+    /// Should only be called for Value::Int or Value::BigInt
+    pub(super) fn int_op(
+        &self,
+        right: &Value,
+        small: impl FnOnce(i64, i64) -> Option<i64>,
+        big: impl FnOnce(BigInt, BigInt) -> BigInt,
+    ) -> Value {
+        if let (&Value::Int(left), &Value::Int(right)) = (self, right) {
+            if let Some(result) = small(left, right) {
+                return Value::Int(result);
+            }
+        }
+
+        Value::from_big_int(big(self.to_big_int(), right.to_big_int()))
+    }
+
+    /// Compares two ints.
+    ///
+    /// Should only be called for Value::Int or Value::BigInt
+    pub(super) fn cmp_int(&self, right: &Value) -> Ordering {
+        match (self, right) {
+            (Value::Int(left), Value::Int(right)) => left.cmp(right),
+            (Value::BigInt(left), Value::BigInt(right)) => left.cmp(right),
+            _ => self.to_big_int().cmp(&right.to_big_int()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    #[test]
+    fn only_promotes_when_needed() {
+        assert_eq!(Value::from_big_int(BigInt::from(5)), i(5));
+        assert!(matches!(big("9223372036854775807"), Value::Int(i64::MAX)));
+        assert!(matches!(big("-9223372036854775808"), Value::Int(i64::MIN)));
+        assert!(matches!(big("9223372036854775808"), Value::BigInt(_)));
+    }
+
+    #[test]
+    fn int_op_promotes_on_overflow() {
+        let add = |l: &Value, r: &Value| l.int_op(r, i64::checked_add, |l, r| l + r);
+
+        assert_eq!(add(&i(1), &i(2)), i(3));
+        assert_eq!(add(&i(i64::MAX), &i(1)), big("9223372036854775808"));
+        assert_eq!(add(&big("9223372036854775808"), &i(-1)), i(i64::MAX));
+    }
+
+    #[test]
+    fn cmp_ints() {
+        assert_eq!(i(1).cmp_int(&i(2)), Ordering::Less);
+        assert_eq!(big("-9223372036854775809").cmp_int(&i(0)), Ordering::Less);
+        assert_eq!(
+            big("9223372036854775808").cmp_int(&big("9223372036854775809")),
+            Ordering::Less
+        );
+        assert_eq!(i(0).cmp_int(&big("9223372036854775808")), Ordering::Less);
+    }
+}
@@ -1,4 +1,5 @@
 use super::Value;
+use std::cmp::Ordering;
 use std::rc::Rc;
 
 /// Only `PartialEq` can be implemented, since `f64` does not support `Eq`, and Null is not equal
@@ -18,16 +19,12 @@ impl PartialEq for Value {
         };
 
         match l {
-            Value::Int(l) => l == r.into(),
+            Value::Int(_) | Value::BigInt(_) => l.cmp_int(&r) == Ordering::Equal,
             Value::Float(l) => l == r.into(),
             Value::Bool(l) => l == r.into(),
-            Value::Range(s, e) => (s..e) == r.into(),
-            Value::String(ref l_rc) => {
-                // Easy to check if both are references to the same string, otherwise check if the
-                // actual strings are equal
-                Rc::ptr_eq(&l_rc, &r.clone().into_rc_str())
-                    || l_rc.borrow().as_str() == r.to_ref_str().as_str()
-            }
+            Value::Range(ref range) => **range == r.into(),
+            // Checks if both are the same part of the same string before comparing the text
+            Value::String(ref l) => l == r.to_ref_str(),
             Value::List(ref l_rc) => {
                 // Easy to check if both are references to the same list, otherwise check if the
                 // actual elements are equal
@@ -39,6 +36,13 @@ impl PartialEq for Value {
                 // actual elements are equal
                 Rc::ptr_eq(&l_rc, &r.clone().into_rc_obj()) || l_rc.borrow().eq(&*r.to_ref_obj())
             }
+            // Maps and sets are equal if they have the same keys, in any order, like objects
+            Value::Map(ref l_rc) => {
+                Rc::ptr_eq(l_rc, &r.clone().into_rc_map()) || *l_rc.borrow() == *r.to_ref_map()
+            }
+            Value::Set(ref l_rc) => {
+                Rc::ptr_eq(l_rc, &r.clone().into_rc_set()) || *l_rc.borrow() == *r.to_ref_set()
+            }
             // Functions are only equal if they are references to the same definition, the actual
             // args and function body are not considered.
             Value::Function(ref l) => Rc::ptr_eq(l, &r.into_rc_fn()),
@@ -64,13 +68,13 @@ impl PartialOrd for Value {
         };
 
         match l {
-            Value::Int(l) => l.partial_cmp(&r.into()),
+            Value::Int(_) | Value::BigInt(_) => Some(l.cmp_int(&r)),
             Value::Float(l) => l.partial_cmp(&r.into()),
             Value::Bool(l) => l.partial_cmp(&r.into()),
-            Value::String(ref l) => l.borrow().as_str().partial_cmp(r.to_ref_str().as_str()),
+            Value::String(ref l) => l.partial_cmp(r.to_ref_str()),
             Value::List(ref l) => l.borrow()[..].partial_cmp(&r.to_ref_list()[..]),
-            // No way to compare objects
-            Value::Object(_) => None,
+            // No way to compare objects, maps or sets
+            Value::Object(_) | Value::Map(_) | Value::Set(_) => None,
             // There is no real way to compare Ranges
             Value::Range(..) => None,
             // Functions have no ordering as they are just a container for a `BlockNode`
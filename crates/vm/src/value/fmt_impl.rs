@@ -7,7 +7,7 @@ use std::fmt;
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Value::String(ref s) => write!(f, "{}", s.borrow()),
+            Value::String(ref s) => write!(f, "{}", s),
             Value::List(ref l) => {
                 let l = l.borrow();
                 // Arbitrary number after which it should be pretty printed in multiple lines
@@ -26,9 +26,28 @@ impl fmt::Display for Value {
                     write!(f, "{:#?}", o)
                 }
             }
-            Value::Range(s, e) => write!(f, "{} -> {}", s, e),
+            Value::Map(ref m) => {
+                let m = m.borrow();
+                // Arbitrary number after which it should be pretty printed in multiple lines
+                if m.len() < 3 {
+                    write!(f, "map {:?}", m)
+                } else {
+                    write!(f, "map {:#?}", m)
+                }
+            }
+            Value::Set(ref s) => {
+                let s = s.borrow();
+                // Arbitrary number after which it should be pretty printed in multiple lines
+                if s.len() < 8 {
+                    write!(f, "set {:?}", s)
+                } else {
+                    write!(f, "set {:#?}", s)
+                }
+            }
+            Value::Range(range) => write!(f, "{} -> {}", range.start, range.end),
             Value::Function(ref func) => write!(f, "{}", func),
             Value::Int(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
             Value::Float(fl) => write!(f, "{}", fl),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Null => write!(f, "null"),
@@ -40,7 +59,6 @@ impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::String(ref s) => {
-                let s = &s.borrow();
                 // while printing quotes must be escaped to avoid confusion
                 if s.contains('\'') && !s.contains('"') {
                     write!(f, "\"{}\"", s)
@@ -56,10 +74,13 @@ impl fmt::Debug for Value {
                     write!(f, "'")
                 }
             }
-            Value::List(_) | Value::Object(_) => write!(f, "{}", self),
-            Value::Range(s, e) => write!(f, "{}..{}", s, e),
+            Value::List(_) | Value::Object(_) | Value::Map(_) | Value::Set(_) => {
+                write!(f, "{}", self)
+            }
+            Value::Range(range) => write!(f, "{}..{}", range.start, range.end),
             Value::Function(ref func) => write!(f, "{}", func),
             Value::Int(i) => write!(f, "{:?}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
             Value::Float(fl) => write!(f, "{:?}", fl),
             Value::Bool(b) => write!(f, "{:?}", b),
             Value::Null => write!(f, "null"),
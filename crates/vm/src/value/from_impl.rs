@@ -9,35 +9,37 @@
 ///     _ => unreachable!()
 /// }
 /// ```
-use super::{Function, List, Object, Value};
+use super::{Function, List, Map, Object, Set, Str, Value};
+use num_traits::{Signed, ToPrimitive};
 use std::cell::{Ref, RefCell};
 use std::rc::Rc;
 
 impl From<Value> for i64 {
-    /// Should only be called for Value::Int
+    /// Should only be called for Value::Int or Value::BigInt
     fn from(val: Value) -> i64 {
-        match val {
-            Value::Int(i) => i,
-            _ => unreachable!(),
-        }
+        i64::from(&val)
     }
 }
 
 impl From<&Value> for i64 {
-    /// Should only be called for &Value::Int
+    /// Should only be called for &Value::Int or &Value::BigInt. Big ints don't fit in an `i64`, so
+    /// they saturate to `i64::MIN` or `i64::MAX`
     fn from(val: &Value) -> i64 {
         match val {
             Value::Int(i) => *i,
+            Value::BigInt(i) if i.is_negative() => i64::MIN,
+            Value::BigInt(_) => i64::MAX,
             _ => unreachable!(),
         }
     }
 }
 
 impl From<Value> for f64 {
-    /// Should only be called for Value::Int or Value::Float
+    /// Should only be called for Value::Int, Value::BigInt or Value::Float
     fn from(val: Value) -> f64 {
         match val {
             Value::Int(i) => i as f64,
+            Value::BigInt(i) => i.to_f64().unwrap(),
             Value::Float(f) => f,
             _ => unreachable!(),
         }
@@ -45,10 +47,11 @@ impl From<Value> for f64 {
 }
 
 impl From<&Value> for f64 {
-    /// Should only be called for &Value::Int or &Value::Float
+    /// Should only be called for &Value::Int, &Value::BigInt or &Value::Float
     fn from(val: &Value) -> f64 {
         match val {
             Value::Int(i) => *i as f64,
+            Value::BigInt(i) => i.to_f64().unwrap(),
             Value::Float(f) => *f,
             _ => unreachable!(),
         }
@@ -61,12 +64,16 @@ impl From<&Value> for f64 {
 impl From<Value> for bool {
     fn from(val: Value) -> bool {
         match val {
-            Value::String(s) => s.borrow().len() != 0,
+            Value::String(s) => !s.is_empty(),
             Value::List(l) => l.borrow().len() != 0,
             Value::Object(o) => o.borrow().len() != 0,
+            Value::Map(m) => !m.borrow().is_empty(),
+            Value::Set(s) => !s.borrow().is_empty(),
             // A range is considered truthy as long as it doesn't have a length of zero
-            Value::Range(s, e) => s != e,
+            Value::Range(range) => range.start != range.end,
             Value::Int(i) => i != 0,
+            // Big ints are never zero
+            Value::BigInt(_) => true,
             Value::Float(f) => f.abs() > f64::EPSILON,
             Value::Bool(b) => b,
             // Function objects are truthy, but the returned after calling a Function need not be
@@ -81,12 +88,15 @@ impl From<Value> for bool {
 impl From<&Value> for bool {
     fn from(val: &Value) -> bool {
         match val {
-            Value::String(s) => s.borrow().len() != 0,
+            Value::String(s) => !s.is_empty(),
             Value::List(l) => l.borrow().len() != 0,
             Value::Object(o) => o.borrow().len() != 0,
+            Value::Map(m) => !m.borrow().is_empty(),
+            Value::Set(s) => !s.borrow().is_empty(),
             // A range is considered truthy as long as it doesn't have a length of zero
-            Value::Range(s, e) => s != e,
+            Value::Range(range) => range.start != range.end,
             Value::Int(i) => i != &0,
+            Value::BigInt(_) => true,
             // f64 comparisons are not completely accurate, so check if it is within the threshold
             Value::Float(f) => f.abs() > f64::EPSILON,
             Value::Bool(b) => *b,
@@ -100,7 +110,7 @@ use std::ops::Range;
 impl From<Value> for Range<i64> {
     fn from(val: Value) -> Range<i64> {
         match val {
-            Value::Range(s, e) => s..e,
+            Value::Range(range) => (*range).clone(),
             _ => unreachable!(),
         }
     }
@@ -109,29 +119,23 @@ impl From<Value> for Range<i64> {
 impl From<&Value> for Range<i64> {
     fn from(val: &Value) -> Range<i64> {
         match val {
-            Value::Range(s, e) => *s..*e,
+            Value::Range(range) => (**range).clone(),
             _ => unreachable!(),
         }
     }
 }
 
 impl Value {
-    pub fn into_rc_str(self) -> Rc<RefCell<String>> {
+    pub fn into_str(self) -> Str {
         match self {
             Value::String(s) => s,
             _ => unreachable!(),
         }
     }
 
-    pub fn into_str(self) -> String {
-        Rc::try_unwrap(self.into_rc_str())
-            .map(RefCell::into_inner)
-            .unwrap_or_else(|rc| rc.borrow().as_str().to_owned())
-    }
-
-    pub fn to_ref_str(&self) -> Ref<String> {
+    pub fn to_ref_str(&self) -> &Str {
         match self {
-            Value::String(ref s) => s.borrow(),
+            Value::String(ref s) => s,
             _ => unreachable!(),
         }
     }
@@ -170,6 +174,34 @@ impl Value {
             _ => unreachable!(),
         }
     }
+
+    pub fn into_rc_map(self) -> Rc<RefCell<Map>> {
+        match self {
+            Value::Map(m) => m,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn to_ref_map(&self) -> Ref<'_, Map> {
+        match self {
+            Value::Map(ref m) => m.borrow(),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn into_rc_set(self) -> Rc<RefCell<Set>> {
+        match self {
+            Value::Set(s) => s,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn to_ref_set(&self) -> Ref<'_, Set> {
+        match self {
+            Value::Set(ref s) => s.borrow(),
+            _ => unreachable!(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -211,6 +243,12 @@ mod tests {
         assert_eq!(bool::from(o(vec![("hello", i(0))])), true);
         assert_eq!(bool::from(o(vec![])), false);
 
+        assert!(bool::from(m(vec![(i(0), i(0))])));
+        assert!(!bool::from(m(vec![])));
+
+        assert!(bool::from(st(vec![i(0)])));
+        assert!(!bool::from(st(vec![])));
+
         assert_eq!(bool::from(func()), true);
         assert_eq!(bool::from(n()), false);
     }
@@ -222,7 +260,6 @@ mod tests {
 
     #[test]
     fn val_to_ref_string() {
-        assert_eq!(s("s").into_rc_str().borrow().as_str(), "s");
         assert_eq!(s("s").to_ref_str().as_str(), "s");
     }
 
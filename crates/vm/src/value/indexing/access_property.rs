@@ -1,71 +1,105 @@
 use crate::function::{native, Function, NativeFn};
-use crate::value::{ErrorKind, Ref, Result, Value};
-use std::rc::Rc;
+use crate::value::{ErrorKind, InlineCache, Key, Result, Str, Value};
 
 impl Value {
-    pub fn get_property(self, p: Ref<String>) -> Result<Value> {
+    /// Object properties are looked up using `cache`, see `InlineCache`
+    pub fn get_property(self, p: Str, cache: &InlineCache) -> Result<Value> {
         let err = |val, property| Err(ErrorKind::InvalidProperty { val, property });
 
-        let property = p.borrow();
+        let property = p.as_str();
 
         match &self {
-            Value::String(s) => match property.as_str() {
-                "len" => Ok(Value::Int(s.borrow().len() as i64)),
-                _ => err(self, Rc::clone(&p)),
+            Value::String(s) => match property {
+                "len" => Ok(Value::Int(s.len() as i64)),
+                _ => err(self, p.clone()),
             },
-            Value::List(l) => match property.as_str() {
+            Value::List(l) => match property {
                 "len" => Ok(Value::Int(l.borrow().len() as i64)),
                 "push" => Ok(make_fn(self, native::push)),
                 "pop" => Ok(make_fn(self, native::pop)),
-                _ => err(self, Rc::clone(&p)),
+                _ => err(self, p.clone()),
             },
             Value::Object(o) => {
-                if let Some(val) = o.borrow().get(property.as_str()) {
+                if let Some(val) = o.borrow().get_cached(property, cache) {
                     return Ok(val.clone());
                 }
 
-                err(self, Rc::clone(&p))
+                err(self, p.clone())
             }
-            Value::Range(s, e) => match property.as_str() {
-                "start" => Ok(Value::Int(*s)),
-                "end" => Ok(Value::Int(*e)),
-                _ => err(self, Rc::clone(&p)),
+            // The properties of a map are looked up before its keys, so they can't be hidden by a
+            // key with the same name
+            Value::Map(m) => match property {
+                "len" => Ok(Value::Int(m.borrow().len() as i64)),
+                "remove" => Ok(make_fn(self, native::remove)),
+                _ => match m.borrow().get(&Key::String(p.clone())) {
+                    Some(val) => Ok(val.clone()),
+                    None => Err(ErrorKind::KeyNotFound {
+                        key: Value::String(p),
+                    }),
+                },
+            },
+            Value::Set(s) => match property {
+                "len" => Ok(Value::Int(s.borrow().len() as i64)),
+                "add" => Ok(make_fn(self, native::add)),
+                "remove" => Ok(make_fn(self, native::remove)),
+                "union" => Ok(make_fn(self, native::union)),
+                "intersection" => Ok(make_fn(self, native::intersection)),
+                "difference" => Ok(make_fn(self, native::difference)),
+                _ => err(self, p.clone()),
             },
-            Value::Function(_) => match property.as_str() {
+            Value::Range(range) => match property {
+                "start" => Ok(Value::Int(range.start)),
+                "end" => Ok(Value::Int(range.end)),
+                _ => err(self, p.clone()),
+            },
+            Value::Function(_) => match property {
                 "call" => Ok(self),
-                _ => err(self, Rc::clone(&p)),
+                _ => err(self, p.clone()),
             },
             _ => unreachable!(),
         }
     }
 
-    pub fn set_property(self, p: Ref<String>, value: Value) -> Result<Value> {
+    /// Object properties are looked up using `cache`, see `InlineCache`
+    pub fn set_property(self, p: Str, value: Value, cache: &InlineCache) -> Result<Value> {
         let err_invalid = |val, property| Err(ErrorKind::InvalidProperty { val, property });
         let err_readonly = |val, property| Err(ErrorKind::ReadonlyProperty { val, property });
 
-        let property = p.borrow();
+        let property = p.as_str();
 
         match &self {
-            Value::String(_) => match property.as_str() {
-                "len" => err_readonly(self, Rc::clone(&p)),
-                _ => err_invalid(self, Rc::clone(&p)),
+            Value::String(_) => match property {
+                "len" => err_readonly(self, p.clone()),
+                _ => err_invalid(self, p.clone()),
             },
-            Value::List(_) => match property.as_str() {
-                "len" | "push" | "pop" => err_readonly(self, Rc::clone(&p)),
-                _ => err_invalid(self, Rc::clone(&p)),
+            Value::List(_) => match property {
+                "len" | "push" | "pop" => err_readonly(self, p.clone()),
+                _ => err_invalid(self, p.clone()),
             },
             Value::Object(o) => {
-                drop(property);
-                o.borrow_mut().insert(copy_str(p), value.clone());
-                Ok(value)
+                o.borrow_mut().insert_cached(property, value, cache);
+                Ok(self)
             }
-            Value::Range(..) => match property.as_str() {
-                "start" | "end" => err_readonly(self, Rc::clone(&p)),
-                _ => err_invalid(self, Rc::clone(&p)),
+            Value::Map(m) => match property {
+                "len" | "remove" => err_readonly(self, p.clone()),
+                _ => {
+                    m.borrow_mut().insert(Key::String(p), value);
+                    Ok(self)
+                }
             },
-            Value::Function(_) => match property.as_str() {
-                "call" => err_readonly(self, Rc::clone(&p)),
-                _ => err_invalid(self, Rc::clone(&p)),
+            Value::Set(_) => match property {
+                "len" | "add" | "remove" | "union" | "intersection" | "difference" => {
+                    err_readonly(self, p.clone())
+                }
+                _ => err_invalid(self, p.clone()),
+            },
+            Value::Range(..) => match property {
+                "start" | "end" => err_readonly(self, p.clone()),
+                _ => err_invalid(self, p.clone()),
+            },
+            Value::Function(_) => match property {
+                "call" => err_readonly(self, p.clone()),
+                _ => err_invalid(self, p.clone()),
             },
             _ => unreachable!(),
         }
@@ -73,11 +107,5 @@ impl Value {
 }
 
 fn make_fn(this: Value, native_fn: NativeFn) -> Value {
-    Value::Function(Rc::new(Function::native_fn(native_fn).with_this(this)))
-}
-
-fn copy_str(string: Ref<String>) -> String {
-    Rc::try_unwrap(string)
-        .map(std::cell::RefCell::into_inner)
-        .unwrap_or_else(|string| string.borrow().as_str().to_owned())
+    Value::new_function(Function::native_fn(native_fn).with_this(this))
 }
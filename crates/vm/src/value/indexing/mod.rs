@@ -1,7 +1,6 @@
-use super::{ErrorKind, Result, Value};
+use super::{ErrorKind, InlineCache, Key, Result, Value};
 use crate::types::Type;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::ops::Range;
 
 mod access_property;
 
@@ -49,6 +48,32 @@ fn normalise_index_len(index: i64, len: i64) -> Result<usize> {
     }
 }
 
+/// The byte range of the characters of `s` selected by `index`, which is either an int or a range
+/// of character indices
+fn char_range(s: &str, index: Value) -> Result<Range<usize>> {
+    let len = s.chars().count() as i64;
+    let (start, end) = match index {
+        Value::Int(index) => {
+            let i = normalise_index(index, len)?;
+            (i, i + 1)
+        }
+        Value::Range(range) => {
+            let start = normalise_index(range.start, len)?;
+            (start, normalise_index_len(range.end, len)?)
+        }
+        _ => unreachable!("Unindexable type should be caught by earlier check"),
+    };
+
+    let mut chars = s.char_indices().skip(start);
+    let start_byte = chars.next().unwrap().0;
+    let end_byte = match end.checked_sub(start + 1) {
+        Some(n) => chars.nth(n).map(|c| c.0).unwrap_or(s.len()),
+        None => start_byte,
+    };
+
+    Ok(start_byte..end_byte)
+}
+
 /// impl for index operations
 impl Value {
     /// Property access is equivalent to indexing by strings
@@ -56,12 +81,22 @@ impl Value {
         match self.type_() {
             Type::String if (Type::Int | Type::Range | Type::String).contains(index_type) => true,
             Type::List if (Type::Int | Type::Range | Type::String).contains(index_type) => true,
-            Type::Function | Type::Range | Type::Object if index_type == Type::String => true,
+            Type::Map if Key::types().contains(index_type) => true,
+            Type::Function | Type::Range | Type::Object | Type::Set
+                if index_type == Type::String =>
+            {
+                true
+            }
             _ => false,
         }
     }
 
     pub fn get_at(self, index: Value) -> Result<Value> {
+        self.get_at_cached(index, &InlineCache::default())
+    }
+
+    /// Same as `get_at`, except if this is an object, the property is looked up using `cache`
+    pub fn get_at_cached(self, index: Value, cache: &InlineCache) -> Result<Value> {
         if !self.indexable(index.type_()) {
             return Err(ErrorKind::Unindexable {
                 val_t: self.type_(),
@@ -70,35 +105,28 @@ impl Value {
         }
 
         if let Value::String(index) = index {
-            return self.get_property(index);
+            return self.get_property(index, cache);
         }
 
-        match self {
-            Value::String(s) => {
-                let s = s.borrow();
-                let s = match index {
-                    Value::Int(index) => {
-                        let i = normalise_index(index, s.chars().count() as i64)?;
-                        String::from(s.chars().nth(i).unwrap())
-                    }
-                    Value::Range(start, end) => {
-                        let len = s.chars().count() as i64;
-
-                        let start_i = normalise_index(start, len)?;
-                        let mut chars = s.char_indices().skip(start_i);
-                        let start = chars.next().unwrap().0;
-
-                        let end = chars
-                            .nth(normalise_index_len(end, len)? - start_i - 1)
-                            .map(|c| c.0)
-                            .unwrap_or_else(|| s.len());
+        if let Value::Map(m) = &self {
+            let key = index.to_key()?;
+            return m
+                .borrow()
+                .get(&key)
+                .cloned()
+                .ok_or(ErrorKind::KeyNotFound { key: index });
+        }
 
-                        String::from(&s[start..end])
-                    }
-                    _ => unreachable!("Unindexable type should be caught by earlier check"),
-                };
+        // A big int is out of range of anything, which saturating it to an `i64` still is
+        let index = match index {
+            Value::BigInt(_) => Value::Int(i64::from(&index)),
+            index => index,
+        };
 
-                Ok(Value::String(Rc::new(RefCell::new(s))))
+        match self {
+            Value::String(s) => {
+                let range = char_range(&s, index)?;
+                Ok(Value::String(s.slice(range)))
             }
             Value::List(l) => {
                 let l = l.borrow();
@@ -108,11 +136,11 @@ impl Value {
 
                         Ok(l[i].clone())
                     }
-                    Value::Range(s, e) => {
-                        let s = normalise_index(s, l.len() as i64)?;
-                        let e = normalise_index_len(e, l.len() as i64)?;
+                    Value::Range(range) => {
+                        let s = normalise_index(range.start, l.len() as i64)?;
+                        let e = normalise_index_len(range.end, l.len() as i64)?;
 
-                        Ok(Value::List(Rc::new(RefCell::new(Vec::from(&l[s..e])))))
+                        Ok(Value::new_list(Vec::from(&l[s..e])))
                     }
                     _ => unreachable!("Unindexable type should be caught by earlier check"),
                 }
@@ -121,7 +149,33 @@ impl Value {
         }
     }
 
+    /// Sets `value` at the last of `indices` of the value found by indexing this with the rest of
+    /// `indices` in turn, like `self[indices[0]][indices[1]] = value`. Each updated value is set
+    /// back into the value it was indexed from, since setting an index of a string creates a new
+    /// string, and the updated value of `self` is returned.
+    pub fn set_at_nested(self, mut indices: Vec<Value>, value: Value) -> Result<Value> {
+        let last = indices.pop().expect("Expect at least one index");
+
+        let mut indexed = vec![self];
+        for index in indices.iter() {
+            let v = indexed.last().unwrap().clone().get_at(index.clone())?;
+            indexed.push(v);
+        }
+
+        let mut value = indexed.pop().unwrap().set_at(last, value)?;
+        for index in indices.into_iter().rev() {
+            value = indexed.pop().unwrap().set_at(index, value)?;
+        }
+
+        Ok(value)
+    }
+
     pub fn set_at(self, index: Value, value: Value) -> Result<Value> {
+        self.set_at_cached(index, value, &InlineCache::default())
+    }
+
+    /// Same as `set_at`, except if this is an object, the property is looked up using `cache`
+    pub fn set_at_cached(self, index: Value, value: Value, cache: &InlineCache) -> Result<Value> {
         if !self.indexable(index.type_()) {
             return Err(ErrorKind::Unindexable {
                 val_t: self.type_(),
@@ -130,9 +184,20 @@ impl Value {
         }
 
         if let Value::String(index) = index {
-            return self.set_property(index, value);
+            return self.set_property(index, value, cache);
         }
 
+        if let Value::Map(m) = &self {
+            m.borrow_mut().insert(index.to_key()?, value);
+            return Ok(self);
+        }
+
+        // A big int is out of range of anything, which saturating it to an `i64` still is
+        let index = match index {
+            Value::BigInt(_) => Value::Int(i64::from(&index)),
+            index => index,
+        };
+
         match &self {
             Value::String(s) => {
                 let value = value
@@ -142,37 +207,15 @@ impl Value {
                         expected: Type::String.into(),
                     })?;
 
-                let (start_i, end_i) = match index {
-                    Value::Int(index) => {
-                        let s = s.borrow();
-                        let i = normalise_index(index, s.chars().count() as i64)?;
-
-                        let mut chars = s.char_indices().skip(i);
-                        (
-                            chars.next().unwrap().0,
-                            chars.next().map(|c| c.0).unwrap_or_else(|| s.len()),
-                        )
-                    }
-                    Value::Range(start, end) => {
-                        let s = s.borrow();
-                        let len = s.chars().count() as i64;
-                        let start = normalise_index(start, len)?;
-
-                        let mut chars = s.char_indices().skip(start);
-
-                        (
-                            chars.next().unwrap().0,
-                            chars
-                                .nth(normalise_index_len(end, len)? - start - 1)
-                                .map(|c| c.0)
-                                .unwrap_or_else(|| s.len()),
-                        )
-                    }
-                    _ => unreachable!("Unindexable type should be caught by earlier check"),
-                };
+                // Strings are immutable, so a new string is created with the part replaced
+                let range = char_range(s, index)?;
+                let mut new =
+                    String::with_capacity(s.len() - range.len() + value.to_ref_str().len());
+                new.push_str(&s[..range.start]);
+                new.push_str(value.to_ref_str());
+                new.push_str(&s[range.end..]);
 
-                s.borrow_mut()
-                    .replace_range(start_i..end_i, value.to_ref_str().as_str());
+                return Ok(Value::String(new.into()));
             }
             Value::List(l) => match index {
                 Value::Int(index) => {
@@ -180,7 +223,8 @@ impl Value {
 
                     l.borrow_mut()[i] = value;
                 }
-                Value::Range(s, e) => {
+                Value::Range(range) => {
+                    let (s, e) = (range.start, range.end);
                     let value =
                         value
                             .try_cast(Type::List)
@@ -227,4 +271,54 @@ impl Value {
 
         Ok(self)
     }
+
+    /// Checks whether `value` is in this value
+    ///
+    /// - `list` - `value` is equal to one of the elements
+    /// - `object` - `value` is one of the keys
+    /// - `map` - `value` is one of the keys, a value which can't be a key is never in a map
+    /// - `set` - `value` is one of the elements, a value which can't be a key is never in a set
+    /// - `string` - `value` is a substring
+    /// - `range` - `value` is an int such that `start <= value < end`
+    pub fn contains(&self, value: Value) -> Result<Value> {
+        let expected = |t: Type| {
+            Err(ErrorKind::IncorrectLeftType {
+                got: value.type_(),
+                expected: t.into(),
+            })
+        };
+
+        match self {
+            Value::List(l) => Ok(Value::Bool(l.borrow().contains(&value))),
+            Value::Object(o) => match value {
+                Value::String(ref k) => Ok(Value::Bool(o.borrow().contains_key(k))),
+                _ => expected(Type::String),
+            },
+            Value::Map(m) => Ok(Value::Bool(
+                value.to_key().is_ok_and(|k| m.borrow().contains_key(&k)),
+            )),
+            Value::Set(s) => Ok(Value::Bool(
+                value.to_key().is_ok_and(|k| s.borrow().contains(&k)),
+            )),
+            Value::String(s) => match value {
+                Value::String(ref sub) => Ok(Value::Bool(s.contains(sub.as_str()))),
+                _ => expected(Type::String),
+            },
+            Value::Range(range) => match value {
+                Value::Int(i) => Ok(Value::Bool(range.contains(&i))),
+                // Ranges only hold `i64`s
+                Value::BigInt(_) => Ok(Value::Bool(false)),
+                _ => expected(Type::Int),
+            },
+            _ => Err(ErrorKind::IncorrectRightType {
+                got: self.type_(),
+                expected: Type::List
+                    | Type::Object
+                    | Type::Map
+                    | Type::Set
+                    | Type::String
+                    | Type::Range,
+            }),
+        }
+    }
 }
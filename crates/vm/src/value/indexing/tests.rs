@@ -7,7 +7,7 @@ fn err_ior(index: i64, len: i64) -> Result<Value> {
 
 fn test_invalid_prop(v: Value, prop: &str) {
     let p = s(prop);
-    let prop_str = p.clone().into_rc_str();
+    let prop_str = p.clone().into_str();
     assert_eq!(
         v.clone().get_at(p),
         Err(ErrorKind::InvalidProperty {
@@ -19,7 +19,7 @@ fn test_invalid_prop(v: Value, prop: &str) {
 
 fn test_invalid_prop_set(v: Value, prop: &str, set: Value) {
     let p = s(prop);
-    let prop_str = p.clone().into_rc_str();
+    let prop_str = p.clone().into_str();
     assert_eq!(
         v.clone().set_at(p, set),
         Err(ErrorKind::InvalidProperty {
@@ -31,7 +31,7 @@ fn test_invalid_prop_set(v: Value, prop: &str, set: Value) {
 
 fn test_readonly_prop(v: Value, prop: &str, set: Value) {
     let p = s(prop);
-    let prop_str = p.clone().into_rc_str();
+    let prop_str = p.clone().into_str();
     assert_eq!(
         v.clone().set_at(p, set),
         Err(ErrorKind::ReadonlyProperty {
@@ -50,6 +50,11 @@ fn indexable_valid() {
     assert!(o(vec![]).indexable(Type::String));
     assert!(r(0, 1).indexable(Type::String));
     assert!(func().indexable(Type::String));
+
+    for t in [Type::Int, Type::Bool, Type::String, Type::List] {
+        assert!(m(vec![]).indexable(t));
+    }
+    assert!(st(vec![]).indexable(Type::String));
 }
 
 #[test]
@@ -83,6 +88,18 @@ fn indexable_invalid() {
     for value in &values[..4] {
         assert!(!value.indexable(Type::String));
     }
+
+    for t in [
+        Type::Float,
+        Type::Range,
+        Type::Object,
+        Type::Null,
+        Type::Map,
+    ] {
+        assert!(!m(vec![]).indexable(t));
+    }
+    assert!(!st(vec![]).indexable(Type::Int));
+    assert!(!st(vec![]).indexable(Type::List));
 }
 
 #[test]
@@ -128,6 +145,31 @@ fn get_at_valid() {
 
     let f = func();
     assert_eq!(f.clone().get_at(s("call")).unwrap(), f);
+
+    let map = m(vec![
+        (i(1), s("int")),
+        (big("9223372036854775808"), s("big int")),
+        (b(true), s("bool")),
+        (l(vec![i(1), s("a")]), s("list")),
+        (s("len"), s("key")),
+    ]);
+    assert_eq!(map.clone().get_at(i(1)), Ok(s("int")));
+    assert_eq!(
+        map.clone().get_at(big("9223372036854775808")),
+        Ok(s("big int"))
+    );
+    assert_eq!(map.clone().get_at(b(true)), Ok(s("bool")));
+    assert_eq!(map.clone().get_at(l(vec![i(1), s("a")])), Ok(s("list")));
+    // Properties are looked up before keys
+    assert_eq!(map.clone().get_at(s("len")), Ok(i(5)));
+    assert!(matches!(map.get_at(s("remove")), Ok(Value::Function(_))));
+    assert_eq!(m(vec![(i(1), n())]).get_at(s("len")), Ok(i(1)));
+
+    let set = st(vec![i(1), i(2)]);
+    assert_eq!(set.clone().get_at(s("len")), Ok(i(2)));
+    for method in ["add", "remove", "union", "intersection", "difference"] {
+        assert!(set.clone().get_at(s(method)).is_ok());
+    }
 }
 
 #[test]
@@ -147,6 +189,26 @@ fn get_at_invalid() {
 
     test_invalid_prop(r(0, 1), "unknown_property");
     test_invalid_prop(func(), "unknown_property");
+    test_invalid_prop(st(vec![]), "unknown_property");
+
+    let map = m(vec![(i(1), n())]);
+    assert_eq!(
+        map.clone().get_at(i(2)),
+        Err(ErrorKind::KeyNotFound { key: i(2) })
+    );
+    assert_eq!(
+        map.clone().get_at(s("unknown_property")),
+        Err(ErrorKind::KeyNotFound {
+            key: s("unknown_property")
+        })
+    );
+    assert_eq!(
+        map.get_at(l(vec![f(1.0)])),
+        Err(ErrorKind::IncorrectType {
+            got: Type::Float,
+            expected: Key::types()
+        })
+    );
 }
 
 #[test]
@@ -214,13 +276,103 @@ fn set_at_valid() {
     );
 
     let obj = o(vec![("already_exists", n())]);
-    assert_eq!(obj.clone().set_at(s("already_exists"), i(1)).unwrap(), i(1));
-    assert_eq!(obj.clone().set_at(s("doesnt_exist"), i(2)).unwrap(), i(2));
+    assert_eq!(obj.clone().set_at(s("already_exists"), i(1)).unwrap(), obj);
+    assert_eq!(obj.clone().set_at(s("doesnt_exist"), i(2)).unwrap(), obj);
 
     let obj = obj.to_ref_obj();
 
     assert_eq!(*obj.get("already_exists").unwrap(), i(1));
     assert_eq!(*obj.get("doesnt_exist").unwrap(), i(2));
+
+    let map = m(vec![(i(1), n())]);
+    assert_eq!(map.clone().set_at(i(1), s("a")).unwrap(), map);
+    assert_eq!(map.clone().set_at(l(vec![b(false)]), s("b")).unwrap(), map);
+    assert_eq!(map.clone().set_at(s("key"), s("c")).unwrap(), map);
+    assert_eq!(
+        map,
+        m(vec![
+            (i(1), s("a")),
+            (l(vec![b(false)]), s("b")),
+            (s("key"), s("c"))
+        ])
+    );
+    // The properties can't be assigned to
+    test_readonly_prop(map.clone(), "len", s("d"));
+    test_readonly_prop(map, "remove", s("d"));
+}
+
+#[test]
+fn set_at_nested_valid() {
+    let list = l(vec![s("hello"), o(vec![("s", s("world"))])]);
+    assert_eq!(
+        list.clone()
+            .set_at_nested(vec![i(0), i(0)], s("j"))
+            .unwrap(),
+        list
+    );
+    assert_eq!(
+        list.clone()
+            .set_at_nested(vec![i(1), s("s"), r(0, 1)], s("W"))
+            .unwrap(),
+        list
+    );
+    assert_eq!(list, l(vec![s("jello"), o(vec![("s", s("World"))])]));
+
+    // A string by itself is replaced with a new string
+    let string = s("hello");
+    assert_eq!(
+        string.clone().set_at_nested(vec![i(1)], s("a")).unwrap(),
+        s("hallo")
+    );
+    assert_eq!(string, s("hello"));
+}
+
+#[test]
+fn set_at_nested_invalid() {
+    assert!(l(vec![i(0)]).set_at_nested(vec![i(0), i(0)], i(1)).is_err());
+    assert!(l(vec![l(vec![])])
+        .set_at_nested(vec![i(1), i(0)], i(1))
+        .is_err());
+}
+
+#[test]
+fn cached_property_access() {
+    let cache = InlineCache::default();
+    let a = o(vec![("x", i(1)), ("y", i(2))]);
+    let b = o(vec![("x", i(3)), ("y", i(4))]);
+    let c = o(vec![("y", i(5)), ("x", i(6))]);
+
+    // Objects with the same shape share the cached slot, objects of other shapes replace it
+    for _ in 0..2 {
+        assert_eq!(a.clone().get_at_cached(s("y"), &cache), Ok(i(2)));
+        assert_eq!(b.clone().get_at_cached(s("y"), &cache), Ok(i(4)));
+        assert_eq!(c.clone().get_at_cached(s("y"), &cache), Ok(i(5)));
+    }
+
+    assert_eq!(c.clone().set_at_cached(s("x"), i(7), &cache), Ok(c.clone()));
+    assert_eq!(a.clone().set_at_cached(s("x"), i(8), &cache), Ok(a.clone()));
+    assert_eq!(c.clone().set_at_cached(s("z"), i(9), &cache), Ok(c.clone()));
+    assert_eq!(c.clone().get_at_cached(s("x"), &cache), Ok(i(7)));
+    assert_eq!(a.clone().get_at_cached(s("x"), &cache), Ok(i(8)));
+    assert_eq!(c.clone().get_at_cached(s("z"), &cache), Ok(i(9)));
+    assert!(a.get_at_cached(s("z"), &cache).is_err());
+}
+
+#[test]
+fn large_objects() {
+    let obj = o(vec![]);
+    for k in 0..100 {
+        obj.clone().set_at(s(&k.to_string()), i(k)).unwrap();
+    }
+
+    let cache = InlineCache::default();
+    assert_eq!(obj.to_ref_obj().len(), 100);
+    for k in 0..100 {
+        assert_eq!(
+            obj.clone().get_at_cached(s(&k.to_string()), &cache),
+            Ok(i(k))
+        );
+    }
 }
 
 #[test]
@@ -249,4 +401,91 @@ fn set_at_invalid() {
     test_readonly_prop(r(0, 1), "start", n());
     test_readonly_prop(r(0, 1), "end", n());
     test_readonly_prop(func(), "call", n());
+    for method in [
+        "len",
+        "add",
+        "remove",
+        "union",
+        "intersection",
+        "difference",
+    ] {
+        test_readonly_prop(st(vec![]), method, n());
+    }
+    test_invalid_prop_set(st(vec![]), "unknown_property", n());
+
+    assert_eq!(
+        m(vec![]).set_at(o(vec![]), n()),
+        Err(ErrorKind::Unindexable {
+            val_t: Type::Map,
+            index_t: Type::Object
+        })
+    );
+}
+
+#[test]
+fn contains_valid() {
+    let list = l(vec![i(1), s("a"), l(vec![])]);
+    assert_eq!(list.contains(i(1)), Ok(b(true)));
+    assert_eq!(list.contains(f(1.0)), Ok(b(true)));
+    assert_eq!(list.contains(s("a")), Ok(b(true)));
+    assert_eq!(list.contains(l(vec![])), Ok(b(true)));
+    assert_eq!(list.contains(i(2)), Ok(b(false)));
+    assert_eq!(list.contains(n()), Ok(b(false)));
+
+    let object = o(vec![("a", i(1))]);
+    assert_eq!(object.contains(s("a")), Ok(b(true)));
+    assert_eq!(object.contains(s("b")), Ok(b(false)));
+
+    let string = s("hello");
+    assert_eq!(string.contains(s("ell")), Ok(b(true)));
+    assert_eq!(string.contains(s("")), Ok(b(true)));
+    assert_eq!(string.contains(s("hi")), Ok(b(false)));
+
+    let range = r(0, 3);
+    assert_eq!(range.contains(i(0)), Ok(b(true)));
+    assert_eq!(range.contains(i(2)), Ok(b(true)));
+    assert_eq!(range.contains(i(3)), Ok(b(false)));
+    assert_eq!(range.contains(i(-1)), Ok(b(false)));
+
+    let map = m(vec![(i(1), n()), (l(vec![s("a")]), n())]);
+    assert_eq!(map.contains(i(1)), Ok(b(true)));
+    assert_eq!(map.contains(l(vec![s("a")])), Ok(b(true)));
+    assert_eq!(map.contains(s("a")), Ok(b(false)));
+    assert_eq!(map.contains(f(1.0)), Ok(b(false)));
+
+    let set = st(vec![b(true), s("a")]);
+    assert_eq!(set.contains(b(true)), Ok(b(true)));
+    assert_eq!(set.contains(s("a")), Ok(b(true)));
+    assert_eq!(set.contains(i(1)), Ok(b(false)));
+    assert_eq!(set.contains(o(vec![])), Ok(b(false)));
+}
+
+#[test]
+fn contains_invalid() {
+    let err_l = |got, expected: Type| {
+        Err(ErrorKind::IncorrectLeftType {
+            got,
+            expected: expected.into(),
+        })
+    };
+    assert_eq!(o(vec![]).contains(i(0)), err_l(Type::Int, Type::String));
+    assert_eq!(s("a").contains(l(vec![])), err_l(Type::List, Type::String));
+    assert_eq!(r(0, 1).contains(f(0.0)), err_l(Type::Float, Type::Int));
+
+    let err_r = |got| {
+        Err(ErrorKind::IncorrectRightType {
+            got,
+            expected: Type::List
+                | Type::Object
+                | Type::Map
+                | Type::Set
+                | Type::String
+                | Type::Range,
+        })
+    };
+    assert_eq!(i(0).contains(i(0)), err_r(Type::Int));
+    assert_eq!(f(0.0).contains(i(0)), err_r(Type::Float));
+    assert_eq!(b(true).contains(i(0)), err_r(Type::Bool));
+    assert_eq!(func().contains(s("call")), err_r(Type::Function));
+    assert_eq!(n().contains(i(0)), err_r(Type::Null));
 }
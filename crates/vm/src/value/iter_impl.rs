@@ -0,0 +1,61 @@
+use super::{ErrorKind, Result, Str, Value};
+use crate::types::Type;
+
+/// impl for iterating over values
+impl Value {
+    /// Converts the value into something which can be iterated over with `iter_at`. Lists and
+    /// ranges can already be iterated over, strings are split into characters, objects and maps are
+    /// converted into a list of `[key, value]` pairs, and sets into a list of their elements.
+    pub fn into_iterable(self) -> Result<Value> {
+        match self {
+            Value::List(_) | Value::Range(..) => Ok(self),
+            // The characters are slices of the string, so its text isn't copied
+            Value::String(s) => Ok(Value::new_list(
+                s.char_indices()
+                    .map(|(i, c)| Value::String(s.slice(i..i + c.len_utf8())))
+                    .collect(),
+            )),
+            Value::Object(o) => Ok(Value::new_list(
+                o.borrow()
+                    .iter()
+                    .map(|(k, v)| Value::new_list(vec![Value::String(Str::from(k)), v.clone()]))
+                    .collect(),
+            )),
+            Value::Map(m) => Ok(Value::new_list(
+                m.borrow()
+                    .iter()
+                    .map(|(k, v)| Value::new_list(vec![k.to_value(), v.clone()]))
+                    .collect(),
+            )),
+            Value::Set(s) => Ok(Value::new_list(
+                s.borrow().iter().map(|k| k.to_value()).collect(),
+            )),
+            _ => Err(ErrorKind::IncorrectType {
+                got: self.type_(),
+                expected: Type::List
+                    | Type::Range
+                    | Type::String
+                    | Type::Object
+                    | Type::Map
+                    | Type::Set,
+            }),
+        }
+    }
+
+    /// Gets the element at `index` of a value returned by `into_iterable`, or `None` if there are
+    /// no more elements.
+    pub fn iter_at(&self, index: usize) -> Option<Value> {
+        match self {
+            Value::List(l) => l.borrow().get(index).cloned(),
+            Value::Range(range) => {
+                let v = range.start.checked_add(index as i64)?;
+                if v < range.end {
+                    Some(Value::Int(v))
+                } else {
+                    None
+                }
+            }
+            _ => unreachable!("Value must be made iterable through `into_iterable`"),
+        }
+    }
+}
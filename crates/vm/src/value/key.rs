@@ -0,0 +1,191 @@
+use super::{BigInt, ErrorKind, List, Result, Str, Value};
+use crate::types::Type;
+use crate::DeserializationContext;
+use enumflags2::BitFlags;
+use serialize::{DeserializeCtx, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, prelude::*};
+use std::rc::Rc;
+
+/// A value which can be the key of a map or an element of a set. Keys are hashed, so they can't
+/// change, and only ints, bools, strings and lists of keys can be keys. A list is used as a tuple,
+/// it is copied into the key, so changing the list afterwards doesn't change the key, and a new
+/// list is created every time the key is converted back into a value.
+///
+/// Equal keys are equal values, and since a big int never fits in an `i64`, an int is equal to
+/// another only if they are both `Key::Int` or both `Key::BigInt`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Bool(bool),
+    String(Str),
+    List(Rc<[Key]>),
+}
+
+impl Key {
+    /// The types of values which can be keys
+    pub fn types() -> BitFlags<Type> {
+        Type::Int | Type::Bool | Type::String | Type::List
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Int(i) => Value::Int(*i),
+            Key::BigInt(i) => Value::BigInt(Rc::clone(i)),
+            Key::Bool(b) => Value::Bool(*b),
+            Key::String(s) => Value::String(s.clone()),
+            Key::List(l) => Value::new_list(l.iter().map(Key::to_value).collect()),
+        }
+    }
+}
+
+impl Value {
+    /// The key equal to this value, or an error if it can't be hashed. Lists are copied, see `Key`
+    pub fn to_key(&self) -> Result<Key> {
+        self.to_key_in(&mut Vec::new())
+    }
+
+    /// Same as `to_key`, where `lists` are the lists being copied which this value is in, so that a
+    /// list which contains itself is an error instead of being copied forever
+    fn to_key_in(&self, lists: &mut Vec<*const RefCell<List>>) -> Result<Key> {
+        Ok(match self {
+            Value::Int(i) => Key::Int(*i),
+            Value::BigInt(i) => Key::BigInt(Rc::clone(i)),
+            Value::Bool(b) => Key::Bool(*b),
+            Value::String(s) => Key::String(s.clone()),
+            Value::List(l) => {
+                if lists.contains(&Rc::as_ptr(l)) {
+                    return Err(ErrorKind::Other {
+                        message: "A list which contains itself can't be hashed".to_owned(),
+                    });
+                }
+
+                lists.push(Rc::as_ptr(l));
+                let key = l
+                    .borrow()
+                    .iter()
+                    .map(|v| v.to_key_in(lists))
+                    .collect::<Result<_>>();
+                lists.pop();
+                Key::List(key?)
+            }
+            _ => {
+                return Err(ErrorKind::IncorrectType {
+                    got: self.type_(),
+                    expected: Key::types(),
+                })
+            }
+        })
+    }
+}
+
+/// Printed the same as the value it is equal to
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Int(i) => write!(f, "{}", i),
+            Key::BigInt(i) => write!(f, "{}", i),
+            Key::Bool(b) => write!(f, "{}", b),
+            Key::String(s) => write!(f, "{:?}", Value::String(s.clone())),
+            Key::List(l) => f.debug_list().entries(l.iter()).finish(),
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
+        self.to_value().serialize(buf)
+    }
+}
+
+impl DeserializeCtx<DeserializationContext> for Key {
+    fn deserialize_with_context<R: BufRead>(
+        data: &mut R,
+        ctx: &mut DeserializationContext,
+    ) -> io::Result<Key> {
+        Value::deserialize_with_context(data, ctx)?
+            .to_key()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Expected a hashable value"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(key: &Key) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_values_are_equal_keys() {
+        let x = s("key").to_key().unwrap();
+        let y = Value::String(Str::intern("key")).to_key().unwrap();
+        assert_eq!(x, y);
+        assert_eq!(hash(&x), hash(&y));
+
+        let x = (big("9223372036854775808") - i(1))
+            .unwrap()
+            .to_key()
+            .unwrap();
+        assert_eq!(x, Key::Int(i64::MAX));
+
+        let x = l(vec![i(1), s("a")]).to_key().unwrap();
+        let y = l(vec![i(1), s("a")]).to_key().unwrap();
+        assert_eq!(x, y);
+        assert_eq!(hash(&x), hash(&y));
+
+        assert_ne!(i(1).to_key().unwrap(), b(true).to_key().unwrap());
+        assert_ne!(i(1).to_key().unwrap(), s("1").to_key().unwrap());
+    }
+
+    #[test]
+    fn only_hashable_values_are_keys() {
+        assert!(f(1.0).to_key().is_err());
+        assert!(o(vec![]).to_key().is_err());
+        assert!(n().to_key().is_err());
+        assert_eq!(
+            l(vec![i(1), r(0, 1)]).to_key().unwrap_err(),
+            ErrorKind::IncorrectType {
+                got: Type::Range,
+                expected: Key::types(),
+            }
+        );
+    }
+
+    #[test]
+    fn lists_containing_themselves_are_not_keys() {
+        let list = l(vec![i(1)]);
+        list.clone().into_rc_list().borrow_mut().push(list.clone());
+        assert!(list.to_key().is_err());
+
+        let outer = l(vec![l(vec![list.clone()])]);
+        assert!(outer.to_key().is_err());
+
+        // The same list can be in a key more than once
+        let inner = l(vec![i(1)]);
+        assert_eq!(
+            l(vec![inner.clone(), inner]).to_key().unwrap().to_value(),
+            l(vec![l(vec![i(1)]), l(vec![i(1)])])
+        );
+    }
+
+    #[test]
+    fn lists_are_copied() {
+        let list = l(vec![i(1)]);
+        let key = list.to_key().unwrap();
+        list.clone().into_rc_list().borrow_mut().push(i(2));
+        assert_eq!(key.to_value(), l(vec![i(1)]));
+
+        let value = key.to_value();
+        value.clone().into_rc_list().borrow_mut().push(i(2));
+        assert_eq!(key.to_value(), l(vec![i(1)]));
+    }
+}
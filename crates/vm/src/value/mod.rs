@@ -3,19 +3,30 @@ use enumflags2::BitFlags;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+mod big_int;
 mod cmp_impl;
 mod fmt_impl;
 mod from_impl;
 mod indexing;
+mod iter_impl;
+mod key;
+mod object;
 mod serialize;
+mod string;
 
 #[cfg(test)]
 mod tests;
 
 use crate::function::Function;
+pub use key::Key;
+pub use num_bigint::BigInt;
+pub use object::{InlineCache, Object, Shape};
+pub use string::Str;
 
 pub type List = Vec<Value>;
-pub type Object = std::collections::HashMap<String, Value>;
+/// Maps and sets keep their keys in the order they were first added, like objects
+pub type Map = indexmap::IndexMap<Key, Value>;
+pub type Set = indexmap::IndexSet<Key>;
 pub type Ref<T> = Rc<RefCell<T>>;
 pub(crate) type Result<T> = std::result::Result<T, ErrorKind>;
 
@@ -25,14 +36,16 @@ pub enum ErrorKind {
     IncorrectType { got: Type, expected: BitFlags<Type> },
     IncorrectLeftType { got: Type, expected: BitFlags<Type> },
     IncorrectRightType { got: Type, expected: BitFlags<Type> },
-    OutOfBounds { got: i64, start: i64, end: i64 },
+    OutOfBounds { got: Value, start: i64, end: i64 },
     IndexOutOfRange { index: i64, len: i64 },
     Unindexable { val_t: Type, index_t: Type },
     CannotCompare { left: Type, right: Type },
     IncorrectArgCount { got: usize, expected: usize },
-    InvalidProperty { val: Value, property: Ref<String> },
-    ReadonlyProperty { val: Value, property: Ref<String> },
+    InvalidProperty { val: Value, property: Str },
+    KeyNotFound { key: Value },
+    ReadonlyProperty { val: Value, property: Str },
     DivideByZero,
+    StackOverflow { depth: usize },
     Other { message: String },
 }
 
@@ -40,27 +53,37 @@ pub enum ErrorKind {
 /// the element
 #[derive(Clone)]
 pub enum Value {
-    /// `String`s are expensive to copy, so a `Rc` is used, copying the reference to the String,
-    /// and not the string itself. `Rc<T>` however gives only immutable access to the inner `T`,
-    /// so instead of directly using `Rc<String>`, we use `Rc<RefCell<String>>` to provide mutable
-    /// strings.
-    String(Ref<String>),
+    /// Strings are expensive to copy, so the text is kept in a `Rc`, copying the reference to the
+    /// text and not the text itself. Strings can't be changed, see `vm/src/value/string.rs`.
+    String(Str),
     /// `Vec`s are expensive to copy, so a `Rc` is used, copying the reference to the List, and not
     /// the list itself. `Rc<T>` however gives only immutable access to the inner `T`, so instead
     /// of directly using `Rc<Vec>`, we use `Rc<RefCell<Vec>>` to provide mutable lists.
     List(Ref<List>),
-    /// `HashMap`s are expensive to copy so a `Rc` is used, copying the reference to the Object, and
+    /// `Object`s are expensive to copy so a `Rc` is used, copying the reference to the Object, and
     /// not the object itself. `Rc<T>` however gives only immutable access to the inner `T`, so
-    /// instead of directly using `Rc<HashMap>`, we use `Rc<RefCell<HashMap>>` to provide mutable
-    /// objects.
+    /// instead of directly using `Rc<Object>`, we use `Rc<RefCell<Object>>` to provide mutable
+    /// objects. See `vm/src/value/object.rs` for how objects store their keys.
     Object(Ref<Object>),
+    /// Maps are like objects, except their keys can be any value which is a `Key`, see
+    /// `vm/src/value/key.rs`. They are expensive to copy and can be changed, so they are placed in
+    /// a `Rc<RefCell>`
+    Map(Ref<Map>),
+    /// A set of `Key`s, which is expensive to copy and can be changed, so it is placed in a
+    /// `Rc<RefCell>`
+    Set(Ref<Set>),
     /// A pointer to a function, see `vm/src/function/mod.rs` for more information, function
     /// objects are expensive to copy, so are placed in a `Rc`.
     Function(Rc<Function>),
-    /// A range value, easy to copy, so it is not placed in a `Rc`
-    Range(i64, i64),
+    /// A range value. It is twice as big as the other values, so it is placed in a `Rc` to keep
+    /// every `Value` 16 bytes. Ranges can't be changed, so they don't need a `RefCell`
+    Range(Rc<std::ops::Range<i64>>),
     /// A primitive integer type, easy to copy, so is not placed in a `Rc`
     Int(i64),
+    /// An integer which doesn't fit in an `i64`, which ints are promoted to when they overflow. Its
+    /// type is `Type::Int`, see `vm/src/value/big_int.rs`. It can't be changed, so it is placed in
+    /// a `Rc` without a `RefCell`
+    BigInt(Rc<BigInt>),
     /// A primitive float type, easy to copy, so is not placed in a `Rc`
     Float(f64),
     /// A primitive bool type, easy to copy, so is not placed in a `Rc`
@@ -71,6 +94,44 @@ pub enum Value {
     Null,
 }
 
+/// impl for creating values which can reference other values. They are tracked by the cycle
+/// collector, see `vm/src/gc.rs`
+impl Value {
+    pub fn new_list(list: List) -> Value {
+        let value = Value::List(Rc::new(RefCell::new(list)));
+        crate::gc::track(&value);
+        value
+    }
+
+    pub fn new_object(object: Object) -> Value {
+        let value = Value::Object(Rc::new(RefCell::new(object)));
+        crate::gc::track(&value);
+        value
+    }
+
+    pub fn new_map(map: Map) -> Value {
+        let value = Value::Map(Rc::new(RefCell::new(map)));
+        crate::gc::track(&value);
+        value
+    }
+
+    /// Keys can't reference other values, so sets aren't tracked
+    pub fn new_set(set: Set) -> Value {
+        Value::Set(Rc::new(RefCell::new(set)))
+    }
+
+    pub fn new_function(function: Function) -> Value {
+        let value = Value::Function(Rc::new(function));
+        crate::gc::track(&value);
+        value
+    }
+
+    /// Ranges can't reference other values, so they aren't tracked
+    pub fn new_range(start: i64, end: i64) -> Value {
+        Value::Range(Rc::new(start..end))
+    }
+}
+
 // Also see `vm/src/types/mod.rs` for type impls &
 //          `vm/src/value/from_impl.rs` for to base type impls
 
@@ -79,7 +140,7 @@ impl Value {
     /// Unary Plus +<num>
     pub fn plus(self) -> Result<Value> {
         match self {
-            Value::Int(_) => Ok(self),
+            Value::Int(_) | Value::BigInt(_) => Ok(self),
             Value::Float(_) => Ok(self),
             _ => Err(ErrorKind::IncorrectType {
                 got: self.type_(),
@@ -96,7 +157,11 @@ impl Neg for Value {
 
     fn neg(self) -> Result<Self> {
         match self {
-            Value::Int(i) => Ok(Value::Int(-i)),
+            Value::Int(i) => Ok(match i.checked_neg() {
+                Some(i) => Value::Int(i),
+                None => Value::from_big_int(-BigInt::from(i)),
+            }),
+            Value::BigInt(i) => Ok(Value::from_big_int(-BigInt::clone(&i))),
             Value::Float(f) => Ok(Value::Float(-f)),
             _ => Err(ErrorKind::IncorrectType {
                 got: self.type_(),
@@ -133,9 +198,17 @@ impl Value {
                 expected: right.type_().into(),
             })?;
 
-        match left {
-            Value::Int(start) => Ok(Value::Range(start, i64::from(right))),
-            _ => Err(ErrorKind::IncorrectType {
+        match (left, right) {
+            (Value::Int(start), Value::Int(end)) => Ok(Value::new_range(start, end)),
+            // Ranges only hold `i64`s
+            (got @ Value::BigInt(_), _) | (_, got @ Value::BigInt(_)) => {
+                Err(ErrorKind::OutOfBounds {
+                    got,
+                    start: i64::MIN,
+                    end: i64::MAX,
+                })
+            }
+            (_, right) => Err(ErrorKind::IncorrectType {
                 got: right.type_(),
                 expected: Type::Int.into(),
             }),
@@ -168,25 +241,16 @@ impl Add for Value {
             })?;
 
         match left {
-            Value::Int(left) => Ok(Value::Int(left + i64::from(right))),
+            Value::Int(_) | Value::BigInt(_) => {
+                Ok(left.int_op(&right, i64::checked_add, |l, r| l + r))
+            }
             Value::Float(left) => Ok(Value::Float(left + f64::from(right))),
             Value::String(left) => {
-                let right = right.into_rc_str();
-
-                Ok(Value::String(if Rc::strong_count(&left) == 1 {
-                    left.borrow_mut().push_str(&right.borrow());
-                    left
-                } else if Rc::strong_count(&right) == 1 {
-                    right.borrow_mut().push_str(&left.borrow());
-                    right
-                } else {
-                    let l = left.borrow();
-                    let r = right.borrow();
-                    let mut s = String::with_capacity(l.len() + r.len());
-                    s += &l;
-                    s += &r;
-                    Rc::new(RefCell::new(s))
-                }))
+                let right = right.to_ref_str();
+                let mut s = String::with_capacity(left.len() + right.len());
+                s += &left;
+                s += right;
+                Ok(Value::String(s.into()))
             }
             Value::List(left) => {
                 let right = right.into_rc_list();
@@ -234,7 +298,9 @@ impl Sub for Value {
             })?;
 
         match left {
-            Value::Int(left) => Ok(Value::Int(left - i64::from(right))),
+            Value::Int(_) | Value::BigInt(_) => {
+                Ok(left.int_op(&right, i64::checked_sub, |l, r| l - r))
+            }
             Value::Float(left) => Ok(Value::Float(left - f64::from(right))),
             _ => Err(ErrorKind::IncorrectLeftType {
                 got: self.type_(),
@@ -264,7 +330,9 @@ impl Mul for Value {
             })?;
 
         match left {
-            Value::Int(left) => Ok(Value::Int(left * i64::from(right))),
+            Value::Int(_) | Value::BigInt(_) => {
+                Ok(left.int_op(&right, i64::checked_mul, |l, r| l * r))
+            }
             Value::Float(left) => Ok(Value::Float(left * f64::from(right))),
             _ => Err(ErrorKind::IncorrectLeftType {
                 got: self.type_(),
@@ -294,12 +362,12 @@ impl Div for Value {
             })?;
 
         match left {
-            Value::Int(left) => {
-                let right: i64 = right.into();
-                if right == 0 {
+            Value::Int(_) | Value::BigInt(_) => {
+                if let Value::Int(0) = right {
                     Err(ErrorKind::DivideByZero)
                 } else {
-                    Ok(Value::Int(left / right))
+                    // Only `i64::MIN / -1` overflows
+                    Ok(left.int_op(&right, i64::checked_div, |l, r| l / r))
                 }
             }
             Value::Float(left) => {
@@ -338,12 +406,11 @@ impl Rem for Value {
             })?;
 
         match left {
-            Value::Int(left) => {
-                let right: i64 = right.into();
-                if right == 0 {
+            Value::Int(_) | Value::BigInt(_) => {
+                if let Value::Int(0) = right {
                     Err(ErrorKind::DivideByZero)
                 } else {
-                    Ok(Value::Int(left % right))
+                    Ok(left.int_op(&right, i64::checked_rem, |l, r| l % r))
                 }
             }
             Value::Float(left) => {
@@ -380,17 +447,25 @@ impl Value {
             })?;
 
         match left {
-            Value::Int(left) => {
-                let right: i64 = right.into();
-                if right > u32::MAX as i64 || right.is_negative() {
-                    Err(ErrorKind::OutOfBounds {
-                        got: right,
-                        start: 0,
-                        end: u32::MAX as i64,
-                    })
-                } else {
-                    Ok(Value::Int(left.pow(right as u32)))
-                }
+            Value::Int(_) | Value::BigInt(_) => {
+                let exponent = match right {
+                    Value::Int(right) if (0..=u32::MAX as i64).contains(&right) => right as u32,
+                    _ => {
+                        return Err(ErrorKind::OutOfBounds {
+                            got: right,
+                            start: 0,
+                            end: u32::MAX as i64,
+                        })
+                    }
+                };
+
+                Ok(match left {
+                    Value::Int(left) => match left.checked_pow(exponent) {
+                        Some(result) => Value::Int(result),
+                        None => Value::from_big_int(BigInt::from(left).pow(exponent)),
+                    },
+                    _ => Value::from_big_int(left.to_big_int().pow(exponent)),
+                })
             }
             Value::Float(left) => Ok(Value::Float(left.powf(right.into()))),
             _ => Err(ErrorKind::IncorrectLeftType {
@@ -0,0 +1,300 @@
+use super::Value;
+use crate::DeserializationContext;
+use serialize::{Deserialize, DeserializeCtx, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, prelude::*};
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Objects with more keys than this stop sharing shapes and keep the slots of their keys
+/// themselves instead. Every shape has its own copy of the slots of its keys, and objects this big
+/// are usually used as dictionaries with keys that other objects don't have anyway.
+const MAX_SHAPE_LEN: usize = 32;
+
+static NEXT_SHAPE_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static EMPTY_SHAPE: Rc<Shape> = Rc::new(Shape::new(Vec::new(), HashMap::new()));
+}
+
+/// The layout of an object: its keys, in the order they were added, and the slot the value of each
+/// key is kept in. Objects which had the same keys added in the same order share a shape, so all
+/// the objects made by the same object literal or interface constructor have the same shape. An
+/// `InlineCache` uses this to find a property without hashing it.
+#[derive(Debug)]
+pub struct Shape {
+    /// Unique to this shape, so that caches don't need to keep shapes alive to compare them
+    id: usize,
+    keys: Vec<Rc<str>>,
+    slots: HashMap<Rc<str>, usize>,
+    /// The shapes made by adding a key to this shape. They are only kept while an object has them
+    transitions: RefCell<HashMap<Rc<str>, Weak<Shape>>>,
+}
+
+impl Shape {
+    fn new(keys: Vec<Rc<str>>, slots: HashMap<Rc<str>, usize>) -> Self {
+        Self {
+            id: NEXT_SHAPE_ID.fetch_add(1, Ordering::Relaxed),
+            keys,
+            slots,
+            transitions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The shape with `key` added after the keys of this shape.
+    fn with_key(&self, key: &str) -> Rc<Shape> {
+        if let Some(shape) = self.transitions.borrow().get(key).and_then(Weak::upgrade) {
+            return shape;
+        }
+
+        let key: Rc<str> = key.into();
+        let mut keys = self.keys.clone();
+        keys.push(Rc::clone(&key));
+        let mut slots = self.slots.clone();
+        slots.insert(Rc::clone(&key), self.keys.len());
+
+        let shape = Rc::new(Shape::new(keys, slots));
+        let mut transitions = self.transitions.borrow_mut();
+        // The shapes of freed objects leave their transitions behind, so they are removed before
+        // the map grows, which keeps it at most about twice as big as the shapes still in use
+        if transitions.len() == transitions.capacity() {
+            transitions.retain(|_, shape| shape.strong_count() > 0);
+        }
+        transitions.insert(key, Rc::downgrade(&shape));
+        shape
+    }
+}
+
+/// Remembers the shape of the last object a property was found in, and the slot it was found in.
+/// Each `GetIndex` and `SetIndex` op has its own cache, so a property access which always sees
+/// objects of the same shape only has to compare the key it is looking for with the key in the
+/// cached slot.
+#[derive(Debug, Clone, Default)]
+pub struct InlineCache(Cell<Option<(usize, usize)>>);
+
+/// A map of keys to values, which keeps the keys in the order they were added, so objects are
+/// always printed, iterated and serialized in the same order. Small objects share their keys with
+/// other objects of the same `Shape`, and only store their values.
+#[derive(Clone)]
+pub struct Object {
+    storage: Storage,
+}
+
+#[derive(Clone)]
+enum Storage {
+    Shaped {
+        shape: Rc<Shape>,
+        values: Vec<Value>,
+    },
+    /// The same as a shape, except the keys and their slots are only used by this object
+    Dictionary {
+        keys: Vec<Rc<str>>,
+        slots: HashMap<Rc<str>, usize>,
+        values: Vec<Value>,
+    },
+}
+
+impl Default for Object {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Object {
+    pub fn new() -> Self {
+        Self {
+            storage: Storage::Shaped {
+                shape: EMPTY_SHAPE.with(Rc::clone),
+                values: Vec::new(),
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Shaped { values, .. } | Storage::Dictionary { values, .. } => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.get_cached(key, &InlineCache::default())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of `key`, adding it to the object if it doesn't have it.
+    pub fn insert(&mut self, key: &str, value: Value) {
+        self.insert_cached(key, value, &InlineCache::default())
+    }
+
+    /// Same as `get`, except the slot the key is in is taken from `cache` if the object has the
+    /// shape it remembers, and stored in it otherwise.
+    pub fn get_cached(&self, key: &str, cache: &InlineCache) -> Option<&Value> {
+        match &self.storage {
+            Storage::Shaped { shape, values } => Some(&values[slot(shape, key, cache)?]),
+            Storage::Dictionary { slots, values, .. } => Some(&values[*slots.get(key)?]),
+        }
+    }
+
+    /// Same as `insert`, except the slot of a key the object already has is found like in
+    /// `get_cached`.
+    pub fn insert_cached(&mut self, key: &str, value: Value, cache: &InlineCache) {
+        match &mut self.storage {
+            Storage::Shaped { shape, values } => {
+                if let Some(slot) = slot(shape, key, cache) {
+                    values[slot] = value;
+                } else if values.len() < MAX_SHAPE_LEN {
+                    *shape = shape.with_key(key);
+                    values.push(value);
+                } else {
+                    let mut keys = shape.keys.clone();
+                    let mut slots = shape.slots.clone();
+                    let mut values = std::mem::take(values);
+                    push_key(&mut keys, &mut slots, key);
+                    values.push(value);
+                    self.storage = Storage::Dictionary {
+                        keys,
+                        slots,
+                        values,
+                    };
+                }
+            }
+            Storage::Dictionary {
+                keys,
+                slots,
+                values,
+            } => {
+                if let Some(&slot) = slots.get(key) {
+                    values[slot] = value;
+                } else {
+                    push_key(keys, slots, key);
+                    values.push(value);
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        let (keys, values) = match &self.storage {
+            Storage::Shaped { shape, values } => (&shape.keys, values),
+            Storage::Dictionary { keys, values, .. } => (keys, values),
+        };
+        Iter(keys.iter().zip(values.iter()))
+    }
+}
+
+/// Adds `key` after the last of `keys`, with the next slot
+fn push_key(keys: &mut Vec<Rc<str>>, slots: &mut HashMap<Rc<str>, usize>, key: &str) {
+    let key: Rc<str> = key.into();
+    slots.insert(Rc::clone(&key), keys.len());
+    keys.push(key);
+}
+
+fn slot(shape: &Shape, key: &str, cache: &InlineCache) -> Option<usize> {
+    if let Some((id, slot)) = cache.0.get() {
+        if id == shape.id && *shape.keys[slot] == *key {
+            return Some(slot);
+        }
+    }
+
+    let slot = *shape.slots.get(key)?;
+    cache.0.set(Some((shape.id, slot)));
+    Some(slot)
+}
+
+/// Iterator over the keys and values of an object in the order the keys were added, see
+/// `Object::iter`
+pub struct Iter<'a>(std::iter::Zip<std::slice::Iter<'a, Rc<str>>, std::slice::Iter<'a, Value>>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, v)| (&**k, v))
+    }
+}
+
+/// Objects are equal if they have the same keys with equal values, whatever the order of the keys
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(k, v)| other.get(k).is_some_and(|other_v| v == other_v))
+    }
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for Object {
+    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
+        let mut bytes_written = self.len().serialize(buf)?;
+        for (k, v) in self.iter() {
+            bytes_written += k.serialize(buf)?;
+            bytes_written += v.serialize(buf)?;
+        }
+        Ok(bytes_written)
+    }
+}
+
+impl DeserializeCtx<DeserializationContext> for Object {
+    fn deserialize_with_context<R: BufRead>(
+        data: &mut R,
+        ctx: &mut DeserializationContext,
+    ) -> io::Result<Self> {
+        let len = usize::deserialize(data)?;
+        let mut object = Object::new();
+        for _ in 0..len {
+            let k = String::deserialize(data)?;
+            let v = Value::deserialize_with_context(data, ctx)?;
+            object.insert(&k, v);
+        }
+        Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions_of_freed_shapes_are_removed() {
+        let empty = EMPTY_SHAPE.with(Rc::clone);
+        for i in 0..1000 {
+            let mut object = Object::new();
+            object.insert(&format!("key{}", i), Value::Null);
+        }
+        assert!(empty.transitions.borrow().len() <= 4);
+
+        // Transitions to shapes which are still used are kept
+        let objects = (0..1000)
+            .map(|i| {
+                let mut object = Object::new();
+                object.insert(&format!("key{}", i), Value::Null);
+                object
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            empty
+                .transitions
+                .borrow()
+                .values()
+                .filter(|shape| shape.strong_count() > 0)
+                .count(),
+            objects.len()
+        );
+        assert!(empty.transitions.borrow().len() <= 2 * objects.len());
+    }
+}
@@ -1,14 +1,22 @@
-use super::Value;
-use crate::function::Function;
+use super::{BigInt, Key, Map, Object, Set, Str, Value};
+use crate::bytecode::Chunk;
+use crate::function::{AnilangFn, Function};
 use crate::types::Type;
 use crate::DeserializationContext;
 use serialize::{Deserialize, DeserializeCtx, Serialize};
-use std::cell::RefCell;
 use std::io::{self, prelude::*};
-use std::rc::Rc;
+
+/// Big ints have the same type as ints, but are stored differently, so they have a tag of their own
+/// which isn't the tag of any type
+const BIG_INT_TAG: u16 = 0x8000;
 
 impl Serialize for Value {
     fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
+        if let Value::BigInt(i) = self {
+            BIG_INT_TAG.serialize(buf)?;
+            return Ok(2 + i.to_signed_bytes_le().serialize(buf)?);
+        }
+
         (self.type_() as u16).serialize(buf)?;
         match self {
             Value::Int(i) => {
@@ -23,14 +31,31 @@ impl Serialize for Value {
                 b.serialize(buf)?;
                 Ok(3)
             }
-            Value::Range(s, e) => {
-                s.serialize(buf)?;
-                e.serialize(buf)?;
+            Value::Range(range) => {
+                range.start.serialize(buf)?;
+                range.end.serialize(buf)?;
                 Ok(18)
             }
             Value::List(l) => Ok(2 + l.borrow().serialize(buf)?),
-            Value::String(s) => Ok(2 + s.borrow().serialize(buf)?),
+            Value::String(s) => Ok(2 + s.serialize(buf)?),
             Value::Object(o) => Ok(2 + o.borrow().serialize(buf)?),
+            Value::Map(m) => {
+                let m = m.borrow();
+                let mut bytes_written = 2 + m.len().serialize(buf)?;
+                for (k, v) in m.iter() {
+                    bytes_written += k.serialize(buf)?;
+                    bytes_written += v.serialize(buf)?;
+                }
+                Ok(bytes_written)
+            }
+            Value::Set(s) => {
+                let s = s.borrow();
+                let mut bytes_written = 2 + s.len().serialize(buf)?;
+                for k in s.iter() {
+                    bytes_written += k.serialize(buf)?;
+                }
+                Ok(bytes_written)
+            }
             Value::Function(f) => {
                 let f = f
                     .as_anilang_fn()
@@ -38,6 +63,7 @@ impl Serialize for Value {
                 Ok(2 + f.args.serialize(buf)? + f.body.serialize(buf)?)
             }
             Value::Null => Ok(2),
+            Value::BigInt(_) => unreachable!(),
         }
     }
 }
@@ -48,6 +74,10 @@ impl DeserializeCtx<DeserializationContext> for Value {
         ctx: &mut DeserializationContext,
     ) -> io::Result<Value> {
         let tag = u16::deserialize(data)?;
+        if tag == BIG_INT_TAG {
+            let bytes = Vec::<u8>::deserialize(data)?;
+            return Ok(Value::from_big_int(BigInt::from_signed_bytes_le(&bytes)));
+        }
 
         Ok(match Type::from(tag) {
             Type::Int => Value::Int(i64::deserialize(data)?),
@@ -56,21 +86,35 @@ impl DeserializeCtx<DeserializationContext> for Value {
             Type::Range => {
                 let s = i64::deserialize(data)?;
                 let e = i64::deserialize(data)?;
-                Value::Range(s, e)
+                Value::new_range(s, e)
+            }
+            Type::List => Value::new_list(Vec::deserialize_with_context(data, ctx)?),
+            Type::String => Value::String(Str::deserialize(data)?),
+            Type::Object => Value::new_object(Object::deserialize_with_context(data, ctx)?),
+            Type::Map => {
+                let len = usize::deserialize(data)?;
+                let mut map = Map::with_capacity(len);
+                for _ in 0..len {
+                    let k = Key::deserialize_with_context(data, ctx)?;
+                    let v = Value::deserialize_with_context(data, ctx)?;
+                    map.insert(k, v);
+                }
+                Value::new_map(map)
+            }
+            Type::Set => {
+                let len = usize::deserialize(data)?;
+                let mut set = Set::with_capacity(len);
+                for _ in 0..len {
+                    set.insert(Key::deserialize_with_context(data, ctx)?);
+                }
+                Value::new_set(set)
             }
-            Type::List => Value::List(Rc::new(RefCell::new(Vec::deserialize_with_context(
-                data, ctx,
-            )?))),
-            Type::String => Value::String(Rc::new(RefCell::new(String::deserialize(data)?))),
-            Type::Object => Value::Object(Rc::new(RefCell::new(
-                std::collections::HashMap::deserialize_with_context(data, ctx)?,
-            ))),
             Type::Function => {
                 let args = Vec::deserialize_with_context(data, ctx)?;
-                let body = Vec::deserialize_with_context(data, ctx)?;
+                let body = Chunk::deserialize_with_context(data, ctx)?;
 
                 // Note native functions cannot be serialized, so the function has to be a AnilangFn
-                Value::Function(Rc::new(Function::anilang_fn(args, body)))
+                Value::new_function(Function::new(AnilangFn::new(args, body).into()))
             }
             Type::Null => Value::Null,
         })
@@ -117,7 +161,7 @@ mod tests {
                     let f = f.as_anilang_fn().unwrap();
                     let df = df.as_anilang_fn().unwrap();
                     assert_eq!(df.args, f.args);
-                    assert_eq!(df.body, f.body);
+                    assert_eq!(df.body.to_bytecode(), f.body.to_bytecode());
                 }
                 dv => panic!("Expected function, got {}", dv),
             },
@@ -130,6 +174,18 @@ mod tests {
         test_serialize(i(12), vec![1, 0, 12, 0, 0, 0, 0, 0, 0, 0]);
     }
 
+    #[test]
+    fn big_int_serialize() {
+        test_serialize(
+            Value::from_big_int(BigInt::from(u64::MAX) + 1),
+            vec![0, 128, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+        test_serialize(
+            Value::from_big_int(-BigInt::from(u64::MAX) - 1),
+            vec![0, 128, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255],
+        );
+    }
+
     #[test]
     fn float_serialize() {
         test_serialize(f(2.71), vec![2, 0, 174, 71, 225, 122, 20, 174, 5, 64]);
@@ -175,6 +231,33 @@ mod tests {
         );
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn map_serialize() {
+        test_serialize(
+            m(vec![(l(vec![i(1)]), s("a"))]),
+            vec![
+                0, 2, 1, 0, 0, 0, 0, 0, 0, 0,   // Map tag + len
+                8, 0, 1, 0, 0, 0, 0, 0, 0, 0,   // key list tag + len
+                1, 0, 1, 0, 0, 0, 0, 0, 0, 0,   // int 1
+                4, 0, b'a', b'\0',              // value string
+            ],
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn set_serialize() {
+        test_serialize(
+            st(vec![b(false), s("a")]),
+            vec![
+                0, 4, 2, 0, 0, 0, 0, 0, 0, 0,   // Set tag + len
+                64, 0, 0,                       // bool false
+                4, 0, b'a', b'\0',              // string
+            ],
+        );
+    }
+
     #[test]
     fn range_serialize() {
         test_serialize(
@@ -226,26 +309,25 @@ mod tests {
         bytes.extend(ident_b_id.to_le_bytes().iter());
 
         bytes.extend([
-            5, 0, 0, 0, 0, 0, 0, 0, // Length of Instructions
-            // Instruction 0
-            30, 0, 0, 0, 0, 0, 0, 0, 0, // Tag + scope id (PushVar)
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Span
-            // Instruction 1
-            20, // Tag Load
+            5, 0, 0, 0, 0, 0, 0, 0, // Length of ops
+            30, 0, 0, 0, 0, // PushVar scope 0
+            20, 0, 0, 0, 0, // Load ident 0
+            20, 1, 0, 0, 0, // Load ident 1
+            0, // BinaryAdd
+            31, // PopVar
+            0, 0, 0, 0, 0, 0, 0, 0, // Length of constants
+            2, 0, 0, 0, 0, 0, 0, 0, // Length of idents
         ].iter());
 
+        // Idents in the order they are first used
         bytes.extend(ident_b_id.to_le_bytes().iter());
-        bytes.extend([
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Span
-            // Instruction 2
-            20, // Tag
-        ].iter());
-
         bytes.extend(ident_a_id.to_le_bytes().iter());
+
         bytes.extend([
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Span
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Instruction 3 - Tag + Span
-            31, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Instruction 4 - Tag + Span
+            1, 0, 0, 0, 0, 0, 0, 0, // Length of scopes
+            0, 0, 0, 0, 0, 0, 0, 0, // Scope id
+            1, 0, 0, 0, 0, 0, 0, 0, // Length of spans, every op has the same span
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Offset, span start and span length
         ].iter());
 
         test_serialize(f, bytes);
@@ -0,0 +1,198 @@
+use serialize::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, prelude::*};
+use std::ops::{Deref, Range};
+use std::rc::Rc;
+
+thread_local! {
+    /// The text of every string which has been interned, see `Str::intern`
+    static INTERNED: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// An immutable string. Since the text of a string never changes, a slice of a string refers to
+/// the text of the string it was sliced from instead of copying it, and strings can be compared
+/// and hashed by their text. Assigning to an index of a string creates a new string.
+#[derive(Clone)]
+pub struct Str(Rc<Slice>);
+
+struct Slice {
+    text: Rc<str>,
+    /// The byte range of `text` which is part of the string
+    range: Range<usize>,
+}
+
+impl Str {
+    /// Returns a string with the same text as `text`, which shares it with every other interned
+    /// string with the same text. String literals and property names are interned, so that the
+    /// same text isn't stored many times and equal strings can be compared without looking at
+    /// their text.
+    pub fn intern(text: &str) -> Self {
+        INTERNED.with(|interned| {
+            let mut interned = interned.borrow_mut();
+            let text = match interned.get(text) {
+                Some(text) => Rc::clone(text),
+                None => {
+                    let text: Rc<str> = text.into();
+                    interned.insert(Rc::clone(&text));
+                    text
+                }
+            };
+            Str::from(text)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0.text[self.0.range.clone()]
+    }
+
+    /// The part of the string in the byte range `range`, which shares the text of this string.
+    ///
+    /// Panics if `range` is out of bounds or not on character boundaries, like indexing a `str`.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        // Check the range is valid
+        let _ = &self.as_str()[range.clone()];
+
+        let start = self.0.range.start;
+        Str(Rc::new(Slice {
+            text: Rc::clone(&self.0.text),
+            range: (start + range.start)..(start + range.end),
+        }))
+    }
+
+    /// Whether both strings are the same part of the same text, in which case they are equal
+    /// without having to compare the text.
+    pub fn ptr_eq(&self, other: &Str) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+            || (Rc::ptr_eq(&self.0.text, &other.0.text) && self.0.range == other.0.range)
+    }
+}
+
+impl Deref for Str {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Str {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<Rc<str>> for Str {
+    fn from(text: Rc<str>) -> Self {
+        let range = 0..text.len();
+        Str(Rc::new(Slice { text, range }))
+    }
+}
+
+impl From<&str> for Str {
+    fn from(text: &str) -> Self {
+        Str::from(Rc::<str>::from(text))
+    }
+}
+
+impl From<String> for Str {
+    fn from(text: String) -> Self {
+        Str::from(Rc::<str>::from(text))
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Str) -> bool {
+        self.ptr_eq(other) || self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Str {}
+
+impl PartialOrd for Str {
+    fn partial_cmp(&self, other: &Str) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Str {
+    fn cmp(&self, other: &Str) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+/// Hashes the same as the `str` it contains, so that it can be looked up by a `&str`
+impl Hash for Str {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Str {
+    fn serialize<W: Write>(&self, buf: &mut W) -> io::Result<usize> {
+        self.as_str().serialize(buf)
+    }
+}
+
+impl Deserialize for Str {
+    fn deserialize<R: BufRead>(data: &mut R) -> io::Result<Self> {
+        Ok(Str::from(String::deserialize(data)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_share_text() {
+        let s = Str::from("hello world");
+        let world = s.slice(6..11);
+        assert_eq!(world.as_str(), "world");
+        assert!(Rc::ptr_eq(&s.0.text, &world.0.text));
+
+        let or = world.slice(1..3);
+        assert_eq!(or.as_str(), "or");
+        assert!(or.ptr_eq(&s.slice(7..9)));
+        assert_eq!(or, Str::from("or"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_out_of_bounds() {
+        Str::from("hello").slice(3..6);
+    }
+
+    #[test]
+    fn interned_strings_share_text() {
+        let a = Str::intern("interned");
+        let b = Str::intern(&String::from("interned"));
+        assert!(a.ptr_eq(&b));
+        assert!(!a.ptr_eq(&Str::from("interned")));
+        assert_eq!(a, Str::from("interned"));
+    }
+
+    #[test]
+    fn hash_as_str() {
+        let mut set = HashSet::new();
+        set.insert(Str::from("a b").slice(2..3));
+        assert!(set.contains("b"));
+        assert!(set.contains(&Str::intern("b")));
+    }
+}
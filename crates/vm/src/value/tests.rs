@@ -21,7 +21,7 @@ fn err_ir(g: Type, e: BitFlags<Type>) -> Result<Value> {
 
 fn err_eb(got: i64) -> Result<Value> {
     Err(ErrorKind::OutOfBounds {
-        got,
+        got: i(got),
         start: 0,
         end: u32::MAX as i64,
     })
@@ -317,6 +317,51 @@ fn binary_pow_invalid() {
         i(10).pow(i(u32::MAX as i64 + 5)),
         err_eb(u32::MAX as i64 + 5)
     );
+    assert_eq!(
+        i(10).pow(big("10000000000000000000")),
+        Err(ErrorKind::OutOfBounds {
+            got: big("10000000000000000000"),
+            start: 0,
+            end: u32::MAX as i64,
+        })
+    );
+}
+
+#[test]
+fn int_overflow_promotes() {
+    let max = i(i64::MAX);
+    let min = i(i64::MIN);
+
+    assert_eq!(max.clone() + i(1), Ok(big("9223372036854775808")));
+    assert_eq!(min.clone() - i(1), Ok(big("-9223372036854775809")));
+    assert_eq!(max.clone() * i(2), Ok(big("18446744073709551614")));
+    assert_eq!(min.clone() / i(-1), Ok(big("9223372036854775808")));
+    assert_eq!(min.clone() % i(-1), Ok(i(0)));
+    assert_eq!(-min.clone(), Ok(big("9223372036854775808")));
+    assert_eq!(i(2).pow(i(64)), Ok(big("18446744073709551616")));
+
+    // Results which fit in an `i64` again are demoted
+    assert_eq!(big("9223372036854775808") - i(1), Ok(max.clone()));
+    assert_eq!(-big("9223372036854775808"), Ok(min));
+    assert_eq!(big("18446744073709551616") / i(4), Ok(i(1 << 62)));
+    assert_eq!(
+        big("18446744073709551616") / i(0),
+        Err(ErrorKind::DivideByZero)
+    );
+
+    assert_eq!(
+        big("18446744073709551616") + f(1.0),
+        Ok(f(18446744073709551616.0))
+    );
+    assert!(big("18446744073709551616") > max);
+    assert_eq!(
+        big("18446744073709551616").range_to(i(0)),
+        Err(ErrorKind::OutOfBounds {
+            got: big("18446744073709551616"),
+            start: i64::MIN,
+            end: i64::MAX,
+        })
+    );
 }
 
 #[test]
@@ -408,6 +453,11 @@ fn binary_eq() {
         l(vec![i(0), f(2.0), b(true)])
     );
     assert_eq!(o(vec![("key", s("value"))]), o(vec![("key", s("value"))]),);
+    assert_eq!(
+        m(vec![(i(1), s("a")), (s("b"), i(2))]),
+        m(vec![(s("b"), i(2)), (i(1), s("a"))])
+    );
+    assert_eq!(st(vec![i(1), b(true)]), st(vec![b(true), i(1)]));
     assert_eq!(r(0, 1), r(0, 1));
     assert_eq!(b(true), b(true));
     assert_eq!(b(false), b(false));
@@ -428,6 +478,9 @@ fn binary_ne() {
         l(vec![s("world"), f(2.0), b(true)]),
     );
     assert_ne!(o(vec![("key", s("value"))]), o(vec![]),);
+    assert_ne!(m(vec![(i(1), s("a"))]), m(vec![(i(1), s("b"))]));
+    assert_ne!(m(vec![(i(1), s("a"))]), o(vec![("1", s("a"))]));
+    assert_ne!(st(vec![i(1)]), st(vec![b(true)]));
     assert_ne!(r(0, 1), r(2, 3));
     assert_ne!(b(true), b(false));
     assert_ne!(b(false), b(true));
@@ -491,3 +544,84 @@ fn binary_ge() {
     assert!(b(true) >= b(false));
     assert!(b(false) >= b(false));
 }
+
+#[test]
+fn into_iterable_valid() {
+    assert_eq!(l(vec![i(1)]).into_iterable(), Ok(l(vec![i(1)])));
+    assert_eq!(r(0, 3).into_iterable(), Ok(r(0, 3)));
+    assert_eq!(s("ab").into_iterable(), Ok(l(vec![s("a"), s("b")])));
+    assert_eq!(
+        o(vec![("a", i(1))]).into_iterable(),
+        Ok(l(vec![l(vec![s("a"), i(1)])]))
+    );
+    assert_eq!(
+        m(vec![(i(1), s("a")), (l(vec![b(true)]), n())]).into_iterable(),
+        Ok(l(vec![
+            l(vec![i(1), s("a")]),
+            l(vec![l(vec![b(true)]), n()])
+        ]))
+    );
+    assert_eq!(
+        st(vec![i(2), s("a")]).into_iterable(),
+        Ok(l(vec![i(2), s("a")]))
+    );
+}
+
+#[test]
+fn into_iterable_invalid() {
+    let e = Type::List | Type::Range | Type::String | Type::Object | Type::Map | Type::Set;
+    assert_eq!(i(0).into_iterable(), err_ite(Type::Int, e));
+    assert_eq!(f(0.0).into_iterable(), err_ite(Type::Float, e));
+    assert_eq!(b(true).into_iterable(), err_ite(Type::Bool, e));
+    assert_eq!(n().into_iterable(), err_ite(Type::Null, e));
+}
+
+#[test]
+fn objects_keep_key_order() {
+    let keys: Vec<_> = (0..40).rev().map(|k| k.to_string()).collect();
+    for len in [3, 40] {
+        let object = o(keys[..len].iter().map(|k| (k.as_str(), n())).collect());
+        let expected: Vec<_> = keys[..len].iter().map(|k| s(k)).collect();
+
+        let iterated: Vec<_> = object.to_ref_obj().iter().map(|(k, _)| s(k)).collect();
+        assert_eq!(iterated, expected);
+
+        let printed = object.to_string();
+        assert!(printed.find("\"39\"").unwrap() < printed.find("\"38\"").unwrap());
+    }
+
+    // Setting a key which exists doesn't move it
+    let object = o(vec![("b", i(1)), ("a", i(2))]);
+    object.clone().set_at(s("b"), i(3)).unwrap();
+    assert_eq!(object.to_string(), r#"{"b": 3, "a": 2}"#);
+}
+
+#[test]
+fn print_maps_and_sets() {
+    assert_eq!(
+        m(vec![(i(1), s("a")), (l(vec![b(true), s("b")]), n())]).to_string(),
+        "map {1: 'a', [true, 'b']: null}"
+    );
+    assert_eq!(st(vec![i(2), s("a"), i(1)]).to_string(), "set {2, 'a', 1}");
+    assert_eq!(m(vec![]).to_string(), "map {}");
+}
+
+#[test]
+fn iter_at() {
+    let list = l(vec![i(1), i(2)]);
+    assert_eq!(list.iter_at(0), Some(i(1)));
+    assert_eq!(list.iter_at(1), Some(i(2)));
+    assert_eq!(list.iter_at(2), None);
+
+    let range = r(3, 5);
+    assert_eq!(range.iter_at(0), Some(i(3)));
+    assert_eq!(range.iter_at(1), Some(i(4)));
+    assert_eq!(range.iter_at(2), None);
+    assert_eq!(r(5, 3).iter_at(0), None);
+}
+
+#[test]
+fn value_size() {
+    // Every value on the stack takes this much space, so it should stay small
+    assert_eq!(std::mem::size_of::<Value>(), 16);
+}